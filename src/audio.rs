@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+
+use crate::config::Config;
+
+/// Volume of sound effects at a sim speed, full up to 1x and silent from
+/// `sfx_mute_speed` on
+pub fn sfx_volume(config: &Config, sim_speed: f32) -> f32 {
+    let range = config.sfx_mute_speed - 1.0;
+    if range <= 0.0 {
+        return if sim_speed <= 1.0 { 1.0 } else { 0.0 };
+    }
+
+    let t = ((sim_speed - 1.0) / range).clamp(0.0, 1.0);
+    // smoothstep, so the volume doesn't jump at either end
+    1.0 - t * t * (3.0 - 2.0 * t)
+}
+
+/// Volume of the music, which ducks a bit while lots is happening
+pub fn music_volume(config: &Config, burst: bool) -> f32 {
+    if burst {
+        config.music_duck
+    } else {
+        1.0
+    }
+}
+
+/// Notices when many events happen within a short window
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BurstDetector {
+    /// Times and counts of the events still in the window, oldest first
+    events: VecDeque<(f32, usize)>,
+    /// Sum of the counts in `events`
+    count: usize,
+}
+
+impl BurstDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `count` events that happened at `time`
+    pub fn record(&mut self, time: f32, count: usize) {
+        if count > 0 {
+            self.events.push_back((time, count));
+            self.count += count;
+        }
+    }
+
+    /// Whether at least `burst_events` happened within the last
+    /// `burst_window` seconds before `time`
+    pub fn burst(&mut self, config: &Config, time: f32) -> bool {
+        while let Some(&(oldest, count)) = self.events.front() {
+            if time - oldest > config.burst_window {
+                self.events.pop_front();
+                self.count -= count;
+            } else {
+                break;
+            }
+        }
+        self.count >= config.burst_events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_fade_out_smoothly_with_speed() {
+        let config = Config {
+            sfx_mute_speed: 5.0,
+            ..Config::default()
+        };
+        assert_eq!(sfx_volume(&config, 0.5), 1.0);
+        assert_eq!(sfx_volume(&config, 1.0), 1.0);
+        assert_eq!(sfx_volume(&config, 3.0), 0.5);
+        assert_eq!(sfx_volume(&config, 5.0), 0.0);
+        assert_eq!(sfx_volume(&config, 50.0), 0.0);
+        assert!(sfx_volume(&config, 2.0) > 0.5);
+
+        // muting right above 1x is a step
+        let config = Config {
+            sfx_mute_speed: 1.0,
+            ..Config::default()
+        };
+        assert_eq!(sfx_volume(&config, 1.0), 1.0);
+        assert_eq!(sfx_volume(&config, 1.01), 0.0);
+    }
+
+    #[test]
+    fn bursts_are_counted_over_the_window() {
+        let config = Config {
+            burst_window: 1.0,
+            burst_events: 10,
+            ..Config::default()
+        };
+        let mut bursts = BurstDetector::new();
+        bursts.record(0.0, 6);
+        bursts.record(0.5, 0);
+        assert!(!bursts.burst(&config, 0.5));
+        bursts.record(0.8, 4);
+        assert!(bursts.burst(&config, 0.8));
+        assert_eq!(music_volume(&config, true), config.music_duck);
+        // the first six fall out of the window
+        assert!(!bursts.burst(&config, 1.5));
+        assert_eq!(bursts.count, 4);
+        assert_eq!(music_volume(&config, false), 1.0);
+    }
+}
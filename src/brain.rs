@@ -0,0 +1,252 @@
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::creature::{Creature, Kind, DIR_COUNT, INPUT_COUNT, LUNGE_OUTPUT};
+use crate::data::{Entity, GameData};
+use crate::error::{Error, Result};
+use crate::nn::{Activation, Desired, Inputs, Network, Outputs};
+
+/// Shape of the networks new creatures get
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrainConfig {
+    /// Sizes of the layers between the inputs and the outputs
+    pub hidden_layers: Vec<usize>,
+    /// Replaces `hidden_layers` for carnivores if set
+    pub carnivore_hidden_layers: Option<Vec<usize>>,
+    pub activation: Activation,
+    /// Recurrent networks see their own last output
+    pub recurrent: bool,
+}
+
+impl Default for BrainConfig {
+    fn default() -> Self {
+        Self {
+            hidden_layers: vec![24, 20],
+            carnivore_hidden_layers: None,
+            activation: Activation::Sigmoid,
+            recurrent: true,
+        }
+    }
+}
+
+/// Everything that decides the shape of the network of one kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrainSpec {
+    pub input_len: usize,
+    pub output_len: usize,
+    pub hidden: Vec<usize>,
+}
+
+impl BrainSpec {
+    /// Every layer from the inputs to the outputs
+    pub fn layers(&self) -> Vec<usize> {
+        let mut layers = vec![self.input_len];
+        layers.extend(&self.hidden);
+        layers.push(self.output_len);
+        layers
+    }
+}
+
+impl BrainConfig {
+    /// The shape of the networks of a kind, both kinds see the same way but
+    /// carnivores have a lunge output after the directions
+    pub fn spec(&self, kind: Kind) -> BrainSpec {
+        let hidden = match (kind, &self.carnivore_hidden_layers) {
+            (Kind::Carnivorous, Some(hidden)) => hidden.clone(),
+            _ => self.hidden_layers.clone(),
+        };
+        let output_len = match kind {
+            Kind::Vegan => DIR_COUNT,
+            Kind::Carnivorous => LUNGE_OUTPUT + 1,
+        };
+        BrainSpec {
+            input_len: INPUT_COUNT,
+            output_len,
+            hidden,
+        }
+    }
+
+    /// Whether a network, say from a save, could have been made by `new_for`
+    /// for a creature of `kind`
+    pub fn matches(&self, network: &Network, kind: Kind) -> bool {
+        network.layers() == self.spec(kind).layers()
+            && network.activation() == self.activation
+            && network.recurrent() == self.recurrent
+    }
+}
+
+/// A fresh, untrained network for a creature of `kind`, calibrated if
+/// `calibrate_brains` is on
+///
+/// Every network that isn't inherited or loaded comes from here, so this is
+/// the only place calibration happens.
+pub fn new_for<R: Rng>(config: &Config, kind: Kind, rng: &mut R) -> Network {
+    let brain = &config.brain;
+    let mut network = Network::new(
+        &brain.spec(kind).layers(),
+        brain.activation,
+        brain.recurrent,
+        rng,
+    );
+    if config.calibrate_brains {
+        network.calibrate(DIR_COUNT, config.calibration_samples, rng);
+    }
+    network
+}
+
+/// Makes sure the network of a creature fits its inputs and outputs, so a
+/// mismatch is reported by name instead of deep inside nalgebra
+pub fn check_shape(data: &GameData, e: Entity) -> Result<()> {
+    let network = &data[e.component::<Network>()];
+    let inputs = data[e.component::<Inputs>()].input.len();
+    let outputs = data[e.component::<Outputs>()].output.len();
+    let desired = data[e.component::<Desired>()].desired.len();
+    let expected = (network.input_len(), network.output_len());
+    if (inputs, outputs) == expected && desired == expected.1 {
+        return Ok(());
+    }
+
+    Err(Error::ShapeMismatch(format!(
+        "creature {} ({:?}) has a network from {} inputs to {} outputs, but {} inputs, {} outputs and {} desired outputs",
+        e.idx,
+        data[e.component::<Creature>()].kind,
+        expected.0,
+        expected.1,
+        inputs,
+        outputs,
+        desired
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::{DVector, Vector2};
+
+    use crate::creature::Genome;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    #[test]
+    fn networks_match_the_config_they_were_made_with() {
+        let config = Config {
+            brain: BrainConfig {
+                hidden_layers: vec![6],
+                activation: Activation::Tanh,
+                recurrent: false,
+                ..BrainConfig::default()
+            },
+            ..Config::default()
+        };
+        let network = new_for(&config, Kind::Vegan, &mut SimRng::new(1));
+        assert_eq!(network.layers(), vec![INPUT_COUNT, 6, DIR_COUNT]);
+        assert!(config.brain.matches(&network, Kind::Vegan));
+
+        let changed = [
+            BrainConfig {
+                hidden_layers: vec![7],
+                ..config.brain.clone()
+            },
+            BrainConfig {
+                activation: Activation::Sigmoid,
+                ..config.brain.clone()
+            },
+            BrainConfig {
+                recurrent: true,
+                ..config.brain.clone()
+            },
+        ];
+        for brain in &changed {
+            assert!(!brain.matches(&network, Kind::Vegan), "{:?}", brain);
+        }
+    }
+
+    #[test]
+    fn each_kind_gets_its_own_shape() {
+        let mut config = Config::default();
+        config.brain.carnivore_hidden_layers = Some(vec![5, 5]);
+        let vegan = config.brain.spec(Kind::Vegan);
+        let carnivore = config.brain.spec(Kind::Carnivorous);
+        assert_eq!(vegan.layers(), vec![INPUT_COUNT, 24, 20, DIR_COUNT]);
+        assert_eq!(
+            carnivore.layers(),
+            vec![INPUT_COUNT, 5, 5, LUNGE_OUTPUT + 1]
+        );
+
+        let network = new_for(&config, Kind::Carnivorous, &mut SimRng::new(1));
+        assert!(config.brain.matches(&network, Kind::Carnivorous));
+        assert!(!config.brain.matches(&network, Kind::Vegan));
+    }
+
+    #[test]
+    fn mismatched_shapes_are_named() {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = new_for(&data.config, Kind::Vegan, &mut rng);
+        let origin = Vector2::new(0.0, 0.0);
+        let e = spawn::creature_with(&mut data, origin, Kind::Vegan, &genome, brain);
+        assert!(check_shape(&data, e).is_ok());
+
+        data[e.component::<Inputs>()] = Inputs::new(INPUT_COUNT - 1);
+        match check_shape(&data, e) {
+            Err(Error::ShapeMismatch(message)) => {
+                assert!(message.starts_with(&format!("creature {} (Vegan)", e.idx)))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    /// Mean of each direction output over `samples` random inputs
+    fn mean_outputs(network: &Network, samples: usize, rng: &mut SimRng) -> DVector<f32> {
+        let mut mean = DVector::zeros(DIR_COUNT);
+        for _ in 0..samples {
+            let input = DVector::from_fn(network.input_len(), |_, _| rng.gen::<f32>());
+            mean += network.clone().feedforward(&input).rows(0, DIR_COUNT);
+        }
+        mean / samples as f32
+    }
+
+    #[test]
+    fn calibrated_networks_favor_no_direction() {
+        let mut config = Config {
+            calibration_samples: 512,
+            ..Config::default()
+        };
+        config.brain.recurrent = false;
+        let spread = |calibrate_brains| {
+            let config = Config {
+                calibrate_brains,
+                ..config.clone()
+            };
+            let network = new_for(&config, Kind::Vegan, &mut SimRng::new(6));
+            let mean = mean_outputs(&network, 1000, &mut SimRng::new(7));
+            mean.max() - mean.min()
+        };
+        assert!(spread(false) > 0.2, "{}", spread(false));
+        assert!(spread(true) < 0.1, "{}", spread(true));
+    }
+
+    #[test]
+    fn inherited_networks_arent_calibrated() {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(8);
+        let inherited = new_for(&data.config, Kind::Vegan, &mut rng);
+        data.config.calibrate_brains = true;
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let origin = Vector2::new(0.0, 0.0);
+        let e = spawn::creature_with(&mut data, origin, Kind::Vegan, &genome, inherited.clone());
+        assert_eq!(data[e.component::<Network>()], inherited);
+
+        // and fresh ones only move their last biases
+        let calibrated = new_for(&data.config, Kind::Vegan, &mut SimRng::new(9));
+        data.config.calibrate_brains = false;
+        let fresh = new_for(&data.config, Kind::Vegan, &mut SimRng::new(9));
+        assert_ne!(calibrated, fresh);
+        assert_eq!(calibrated.weight_stats(), fresh.weight_stats());
+    }
+}
@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// A way to cut down on work while updates don't fit in `frame_budget`,
+/// taken in the order of `degradation_ladder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Degradation {
+    /// Networks stop learning from their desired outputs
+    NoTraining,
+    /// Creatures cast every other ray each frame and remember the rest
+    HalfRays,
+}
+
+impl Degradation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Degradation::NoTraining => "no training",
+            Degradation::HalfRays => "half rays",
+        }
+    }
+}
+
+/// A step up or down the ladder, for logging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Degraded(Degradation),
+    Restored(Degradation),
+}
+
+/// Keeps track of how long updates have been over or well under
+/// `frame_budget` and climbs the degradation ladder accordingly
+///
+/// Climbing takes `degrade_after` seconds over budget in a row, coming back
+/// down takes `restore_after` seconds under `restore_headroom` of it, and
+/// anything in between resets both, so the level doesn't flap at the edge.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Budget {
+    /// Steps of the ladder in effect, counted from its start
+    level: usize,
+    over: f32,
+    under: f32,
+    /// Flips every frame while rays are halved, picks which half gets cast
+    parity: usize,
+}
+
+impl Budget {
+    /// Records an update that took `spent` seconds for a frame of `real`
+    /// seconds, returns the step taken if there was one
+    pub fn record(&mut self, config: &Config, spent: f32, real: f32) -> Option<Step> {
+        let ladder = &config.degradation_ladder;
+        self.level = self.level.min(ladder.len());
+        let budget = config.frame_budget;
+        if budget <= 0.0 {
+            // turned off, everything comes back at once
+            *self = Self::default();
+            return None;
+        }
+
+        if spent > budget {
+            self.over += real;
+            self.under = 0.0;
+        } else if spent <= budget * config.restore_headroom {
+            self.under += real;
+            self.over = 0.0;
+        } else {
+            self.over = 0.0;
+            self.under = 0.0;
+        }
+
+        if self.over >= config.degrade_after && self.level < ladder.len() {
+            self.over = 0.0;
+            self.level += 1;
+            return Some(Step::Degraded(ladder[self.level - 1]));
+        }
+        if self.under >= config.restore_after && self.level > 0 {
+            self.under = 0.0;
+            self.level -= 1;
+            return Some(Step::Restored(ladder[self.level]));
+        }
+        None
+    }
+
+    /// The degradations in effect, in the order they were taken
+    pub fn active<'a>(&self, config: &'a Config) -> &'a [Degradation] {
+        let ladder = &config.degradation_ladder;
+        &ladder[..self.level.min(ladder.len())]
+    }
+
+    pub fn is_active(&self, config: &Config, degradation: Degradation) -> bool {
+        self.active(config).contains(&degradation)
+    }
+
+    /// Which half of the rays to cast this frame, `None` for all of them,
+    /// alternates between calls while rays are halved
+    pub fn ray_parity(&mut self, config: &Config) -> Option<usize> {
+        if !self.is_active(config, Degradation::HalfRays) {
+            return None;
+        }
+        self.parity = 1 - self.parity;
+        Some(self.parity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            frame_budget: 0.01,
+            degrade_after: 1.0,
+            restore_after: 2.0,
+            restore_headroom: 0.5,
+            degradation_ladder: vec![Degradation::NoTraining, Degradation::HalfRays],
+            ..Config::default()
+        }
+    }
+
+    /// Records `frames` updates of `spent` seconds in 0.1s frames, returns
+    /// the steps taken
+    fn run(budget: &mut Budget, config: &Config, spent: f32, frames: usize) -> Vec<Step> {
+        (0..frames)
+            .filter_map(|_| budget.record(config, spent, 0.1))
+            .collect()
+    }
+
+    #[test]
+    fn degrades_in_ladder_order_and_restores_in_reverse() {
+        let config = config();
+        let mut budget = Budget::default();
+        let degraded = run(&mut budget, &config, 0.02, 30);
+        assert_eq!(
+            degraded,
+            vec![
+                Step::Degraded(Degradation::NoTraining),
+                Step::Degraded(Degradation::HalfRays),
+            ]
+        );
+        assert_eq!(budget.active(&config), &config.degradation_ladder[..]);
+
+        let restored = run(&mut budget, &config, 0.001, 60);
+        assert_eq!(
+            restored,
+            vec![
+                Step::Restored(Degradation::HalfRays),
+                Step::Restored(Degradation::NoTraining),
+            ]
+        );
+        assert!(budget.active(&config).is_empty());
+    }
+
+    #[test]
+    fn waits_for_a_whole_stretch_over_budget() {
+        let config = config();
+        let mut budget = Budget::default();
+        assert!(run(&mut budget, &config, 0.02, 9).is_empty());
+        assert_eq!(
+            run(&mut budget, &config, 0.02, 2),
+            vec![Step::Degraded(Degradation::NoTraining)]
+        );
+    }
+
+    #[test]
+    fn the_band_in_between_resets_both_timers() {
+        let config = config();
+        let mut budget = Budget::default();
+        run(&mut budget, &config, 0.02, 10);
+        assert!(budget.is_active(&config, Degradation::NoTraining));
+        // alternating over budget and inside the band never adds up
+        for _ in 0..100 {
+            assert_eq!(budget.record(&config, 0.02, 0.1), None);
+            assert_eq!(budget.record(&config, 0.008, 0.1), None);
+        }
+        // neither does alternating well under and inside the band
+        for _ in 0..100 {
+            assert_eq!(budget.record(&config, 0.001, 0.1), None);
+            assert_eq!(budget.record(&config, 0.008, 0.1), None);
+        }
+        assert_eq!(budget.active(&config), &[Degradation::NoTraining][..]);
+    }
+
+    #[test]
+    fn turning_the_budget_off_restores_everything() {
+        let mut config = config();
+        let mut budget = Budget::default();
+        run(&mut budget, &config, 0.02, 30);
+        config.frame_budget = 0.0;
+        assert_eq!(budget.record(&config, 0.02, 0.1), None);
+        assert!(budget.active(&config).is_empty());
+    }
+
+    #[test]
+    fn ray_parity_alternates_only_while_halved() {
+        let config = config();
+        let mut budget = Budget::default();
+        assert_eq!(budget.ray_parity(&config), None);
+        run(&mut budget, &config, 0.02, 30);
+        let a = budget.ray_parity(&config);
+        let b = budget.ray_parity(&config);
+        assert!(a.is_some() && b.is_some() && a != b);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::collision::Body;
+use crate::creature::*;
+use crate::data::{Entity, GameData, Has};
+use crate::nn::{Desired, Experience, Inputs, Network, Outputs};
+use crate::CREATURE_COUNT;
+
+/// Names of the components a creature needs but doesn't have
+fn missing_creature(data: &GameData, e: Entity) -> Vec<&'static str> {
+    [
+        ("Position", data.has(e.component::<Position>())),
+        ("Velocity", data.has(e.component::<Velocity>())),
+        ("Direction", data.has(e.component::<Direction>())),
+        ("Body", data.has(e.component::<Body>())),
+        ("Genome", data.has(e.component::<Genome>())),
+        ("Network", data.has(e.component::<Network>())),
+        ("Inputs", data.has(e.component::<Inputs>())),
+        ("Outputs", data.has(e.component::<Outputs>())),
+        ("Desired", data.has(e.component::<Desired>())),
+        ("Experience", data.has(e.component::<Experience>())),
+    ]
+    .iter()
+    .filter(|(_, has)| !has)
+    .map(|(name, _)| *name)
+    .collect()
+}
+
+/// Checks that a creature has everything it needs, in the right sizes
+fn check_creature(data: &GameData, e: Entity, problems: &mut Vec<String>) {
+    if !data.has(e.component::<Creature>()) {
+        problems.push(format!("creature {} has no Creature", e.idx));
+        return;
+    }
+
+    let missing = missing_creature(data, e);
+    if !missing.is_empty() {
+        problems.push(format!("creature {} has no {}", e.idx, missing.join(", ")));
+        return;
+    }
+
+    let kind = data[e.component::<Creature>()].kind;
+    let spec = data.config.brain.spec(kind);
+    let inputs = data[e.component::<Inputs>()].input.len();
+    if inputs != spec.input_len {
+        problems.push(format!(
+            "creature {} has {} inputs instead of {}",
+            e.idx, inputs, spec.input_len
+        ));
+    }
+    let outputs = data[e.component::<Outputs>()].output.len();
+    if outputs != spec.output_len {
+        problems.push(format!(
+            "creature {} has {} outputs instead of {}",
+            e.idx, outputs, spec.output_len
+        ));
+    }
+    let desired = data[e.component::<Desired>()].desired.len();
+    if desired != spec.output_len {
+        problems.push(format!(
+            "creature {} has {} desired outputs instead of {}",
+            e.idx, desired, spec.output_len
+        ));
+    }
+    let layers = data[e.component::<Network>()].layers();
+    if layers.first() != Some(&spec.input_len) || layers.last() != Some(&spec.output_len) {
+        problems.push(format!(
+            "creature {} has a network with layers {:?}, which doesn't take {} inputs to {} outputs",
+            e.idx, layers, spec.input_len, spec.output_len
+        ));
+    }
+}
+
+/// Checks that a piece of food has everything it needs
+fn check_food(data: &GameData, e: Entity, problems: &mut Vec<String>) {
+    let missing: Vec<_> = [
+        ("Food", data.has(e.component::<Food>())),
+        ("Position", data.has(e.component::<Position>())),
+        ("Body", data.has(e.component::<Body>())),
+    ]
+    .iter()
+    .filter(|(_, has)| !has)
+    .map(|(name, _)| *name)
+    .collect();
+    if !missing.is_empty() {
+        problems.push(format!("food {} has no {}", e.idx, missing.join(", ")));
+    }
+}
+
+/// Describes everything wrong with the world, empty if it's all fine
+pub fn validate(data: &GameData, creatures: &[Entity], foods: &[Entity]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for name in data.short_storages() {
+        problems.push(format!(
+            "storage {} doesn't have a slot for all {} entities",
+            name,
+            data.entity_count()
+        ));
+    }
+    if !problems.is_empty() {
+        // indexing would panic
+        return problems;
+    }
+
+    for &e in creatures {
+        if e.idx >= data.entity_count() {
+            problems.push(format!("creature {} doesn't exist", e.idx));
+        } else {
+            check_creature(data, e, &mut problems);
+        }
+    }
+    for &e in foods {
+        if e.idx >= data.entity_count() {
+            problems.push(format!("food {} doesn't exist", e.idx));
+        } else {
+            check_food(data, e, &mut problems);
+        }
+    }
+    problems
+}
+
+/// Like `validate`, but also checks what only holds right after spawning
+pub fn validate_fresh(data: &GameData, creatures: &[Entity], foods: &[Entity]) -> Vec<String> {
+    let mut problems = validate(data, creatures, foods);
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    if creatures.len() != CREATURE_COUNT {
+        problems.push(format!(
+            "there are {} creatures instead of {}",
+            creatures.len(),
+            CREATURE_COUNT
+        ));
+    }
+    let expected = (CREATURE_COUNT as f32 * data.config.carnivore_ratio) as usize;
+    let carnivores = creatures
+        .iter()
+        .filter(|e| data[e.component::<Creature>()].kind == Kind::Carnivorous)
+        .count();
+    if carnivores != expected {
+        problems.push(format!(
+            "there are {} carnivores instead of {}",
+            carnivores, expected
+        ));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::Config;
+    use crate::data::Remove;
+    use crate::GameState;
+
+    fn fresh() -> GameState {
+        let config = Config {
+            seed: 5,
+            chronicle: false,
+            ..Config::default()
+        };
+        let mut game = GameState::empty(0, config);
+        game.populate_with(Vec::new());
+        game
+    }
+
+    #[test]
+    fn a_fresh_world_is_fine() {
+        let game = fresh();
+        let problems = validate_fresh(&game.data, &game.creatures, &game.foods);
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn broken_worlds_are_described() {
+        let mut game = fresh();
+        let (a, b) = (game.creatures[0], game.creatures[1]);
+        Remove::<Genome>::remove(&mut game.data, a);
+        game.data[b.component::<Inputs>()] = Inputs::new(INPUT_COUNT + 1);
+        let food = game.foods[0];
+        Remove::<Body>::remove(&mut game.data, food);
+        let missing = Entity {
+            idx: game.data.entity_count(),
+        };
+        game.creatures.push(missing);
+
+        let problems = validate(&game.data, &game.creatures, &game.foods);
+        assert_eq!(
+            problems,
+            vec![
+                format!("creature {} has no Genome", a.idx),
+                format!(
+                    "creature {} has {} inputs instead of {}",
+                    b.idx,
+                    INPUT_COUNT + 1,
+                    INPUT_COUNT
+                ),
+                format!("creature {} doesn't exist", missing.idx),
+                format!("food {} has no Body", food.idx),
+            ]
+        );
+        // a fine world can still be short of creatures
+        let problems = validate_fresh(&game.data, &game.creatures[2..4], &[]);
+        assert_eq!(
+            problems[0],
+            format!("there are 2 creatures instead of {}", CREATURE_COUNT)
+        );
+    }
+}
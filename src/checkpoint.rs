@@ -0,0 +1,119 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where rollovers write checkpoints and `--resume-latest` looks for them
+pub const CHECKPOINT_DIR: &str = "checkpoints";
+
+/// The checkpoint written at the start of `generation` in `dir`
+pub fn path<P: AsRef<Path>>(dir: P, generation: usize) -> PathBuf {
+    dir.as_ref().join(format!("ckpt_gen{}.bin", generation))
+}
+
+/// The generation a checkpoint file name is for
+fn generation(name: &str) -> Option<usize> {
+    name.strip_prefix("ckpt_gen")?
+        .strip_suffix(".bin")?
+        .parse()
+        .ok()
+}
+
+/// Every checkpoint in `dir` with its generation, oldest first, nothing if
+/// there's no such directory
+pub fn list<P: AsRef<Path>>(dir: P) -> io::Result<Vec<(usize, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut checkpoints = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let found = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(generation);
+        if let Some(generation) = found {
+            checkpoints.push((generation, path));
+        }
+    }
+    checkpoints.sort();
+    Ok(checkpoints)
+}
+
+/// The newest checkpoint in `dir`
+pub fn latest<P: AsRef<Path>>(dir: P) -> io::Result<Option<PathBuf>> {
+    Ok(list(dir)?.pop().map(|(_, path)| path))
+}
+
+/// Writes `bytes` next to `path` first and moves them over it after, so a
+/// crash halfway leaves the old checkpoint rather than half of a new one
+pub fn write<P: AsRef<Path>>(path: P, bytes: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("partial");
+    fs::write(&partial, bytes)?;
+    fs::rename(&partial, path)
+}
+
+/// Removes all but the `keep` newest checkpoints in `dir`, returns how many
+/// went
+pub fn prune<P: AsRef<Path>>(dir: P, keep: usize) -> io::Result<usize> {
+    let checkpoints = list(dir)?;
+    let old = checkpoints.len().saturating_sub(keep);
+    for (_, path) in &checkpoints[..old] {
+        fs::remove_file(path)?;
+    }
+    Ok(old)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ldjam-46-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn names_round_trip() {
+        let name = path("checkpoints", 12);
+        let name = name.file_name().unwrap().to_str().unwrap();
+        assert_eq!(generation(name), Some(12));
+        assert_eq!(generation("ckpt_gen12.partial"), None);
+        assert_eq!(generation("gen_12.bin"), None);
+    }
+
+    #[test]
+    fn pruning_keeps_the_newest() {
+        let dir = temp_dir("prune");
+        // out of order and past 9, so sorting by name would get it wrong
+        for &generation in &[10, 2, 9, 11] {
+            write(path(&dir, generation), &[generation as u8]).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), b"not a checkpoint").unwrap();
+
+        assert_eq!(prune(&dir, 2).unwrap(), 2);
+        let left: Vec<_> = list(&dir).unwrap().into_iter().map(|(g, _)| g).collect();
+        assert_eq!(left, vec![10, 11]);
+        assert_eq!(latest(&dir).unwrap(), Some(path(&dir, 11)));
+        assert!(dir.join("notes.txt").exists());
+        // keeping more than there are removes nothing
+        assert_eq!(prune(&dir, 5).unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_dir_has_no_checkpoints() {
+        let dir = temp_dir("missing");
+        assert!(list(&dir).unwrap().is_empty());
+        assert_eq!(latest(&dir).unwrap(), None);
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::creature::{Creature, Kind, Position};
+use crate::data::{Entity, GameData};
+
+pub const CHRONICLE_PATH: &str = "chronicle.jsonl";
+/// Records are written out once this many have piled up
+pub const CHRONICLE_BUFFER: usize = 64;
+/// Most position samples kept per creature, older ones get dropped
+pub const MAX_TRACE: usize = 256;
+
+/// Why a creature stopped living
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cause {
+    Starved,
+    /// Eaten by the carnivore with this entity index
+    Eaten {
+        by: usize,
+    },
+    /// Made room for children under the population cap
+    Culled,
+    /// Had values `sanitize_system` couldn't fix
+    Despawned,
+    /// Killed from the console
+    Killed,
+    /// Was still alive when the generation ended
+    Survived,
+}
+
+pub const CAUSE_NAMES: [&str; 6] = [
+    "starved",
+    "eaten",
+    "culled",
+    "despawned",
+    "killed",
+    "survived",
+];
+
+impl Cause {
+    /// Position in `CAUSE_NAMES`, being eaten counts the same whoever did it
+    pub fn index(self) -> usize {
+        match self {
+            Cause::Starved => 0,
+            Cause::Eaten { .. } => 1,
+            Cause::Culled => 2,
+            Cause::Despawned => 3,
+            Cause::Killed => 4,
+            Cause::Survived => 5,
+        }
+    }
+}
+
+/// Everything about the life of one creature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub generation: usize,
+    pub entity: usize,
+    pub kind: Kind,
+    /// Time into the generation it was born, 0.0 for the first ones
+    pub born: f32,
+    pub died: f32,
+    pub cause: Cause,
+    pub life: f32,
+    pub eaten: usize,
+    pub children: usize,
+    pub fitness: f32,
+    /// Every `chronicle_interval` seconds, time and position
+    pub trace: Vec<(f32, f32, f32)>,
+    /// Generation the seed bank froze it in, for creatures revived from it
+    #[serde(default)]
+    pub seed: Option<usize>,
+}
+
+/// Birth time and trace of a creature that's still alive
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+struct Life {
+    born: f32,
+    trace: Vec<(f32, f32, f32)>,
+    seed: Option<usize>,
+}
+
+/// Keeps track of the creatures of a generation while `config.chronicle` is
+/// on, records stay in memory only until they're flushed
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Chronicle {
+    pub generation: usize,
+    /// Time into the generation, kept up to date by the game
+    pub time: f32,
+    last_sample: f32,
+    living: HashMap<usize, Life>,
+    records: Vec<Record>,
+}
+
+impl Chronicle {
+    pub fn birth(&mut self, e: Entity) {
+        let life = Life {
+            born: self.time,
+            trace: Vec::new(),
+            seed: None,
+        };
+        self.living.insert(e.idx, life);
+    }
+
+    /// Notes that a creature born already came from a seed frozen in
+    /// `generation`, see `seedbank::SeedBank::revive`
+    pub fn revived(&mut self, e: Entity, generation: usize) {
+        if let Some(life) = self.living.get_mut(&e.idx) {
+            life.seed = Some(generation);
+        }
+    }
+
+    /// Follows the living creatures to their new handles after
+    /// `GameData::compact`
+    pub fn remap(&mut self, map: &HashMap<Entity, Entity>) {
+        self.living = self
+            .living
+            .drain()
+            .filter_map(|(idx, life)| Some((map.get(&Entity { idx })?.idx, life)))
+            .collect();
+    }
+
+    /// Position samples of a living creature so far, oldest first
+    pub fn trace(&self, e: Entity) -> &[(f32, f32, f32)] {
+        self.living.get(&e.idx).map_or(&[], |life| &life.trace)
+    }
+
+    /// Whether enough records piled up to be worth writing
+    pub fn full(&self) -> bool {
+        self.records.len() >= CHRONICLE_BUFFER
+    }
+
+    /// Appends the buffered records to a JSON lines file
+    pub fn flush<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        for record in self.records.drain(..) {
+            let line = serde_json::to_string(&record)?;
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds a position sample for every creature if it's been long enough
+pub fn sample(data: &mut GameData, creatures: &[Entity]) {
+    let chronicle = &data.chronicle;
+    if !data.config.chronicle
+        || chronicle.time - chronicle.last_sample < data.config.chronicle_interval
+    {
+        return;
+    }
+
+    let time = chronicle.time;
+    let samples: Vec<_> = creatures
+        .iter()
+        .map(|e| (e.idx, data[e.component::<Position>()].position))
+        .collect();
+    let chronicle = &mut data.chronicle;
+    chronicle.last_sample = time;
+    for (idx, position) in samples {
+        if let Some(life) = chronicle.living.get_mut(&idx) {
+            if life.trace.len() >= MAX_TRACE {
+                life.trace.remove(0);
+            }
+            life.trace.push((time, position.x, position.y));
+        }
+    }
+}
+
+/// Records the end of a creature, has to be called before it's deleted
+pub fn death(data: &mut GameData, e: Entity, cause: Cause) {
+    let creature: Creature = data[e.component::<Creature>()];
+    data.mortality.record(creature.kind, cause);
+    if !data.config.chronicle {
+        return;
+    }
+
+    let fitness = creature.fitness(&data.config);
+    let chronicle = &mut data.chronicle;
+    let life = chronicle.living.remove(&e.idx).unwrap_or_default();
+    chronicle.records.push(Record {
+        generation: chronicle.generation,
+        entity: e.idx,
+        kind: creature.kind,
+        born: life.born,
+        died: chronicle.time,
+        cause,
+        life: creature.life,
+        eaten: creature.eaten,
+        children: creature.children,
+        fitness,
+        trace: life.trace,
+        seed: life.seed,
+    });
+}
+
+/// Number of creatures and their mean life for every cause of death
+pub fn summarize(records: &[Record]) -> Vec<(&'static str, usize, f32)> {
+    CAUSE_NAMES
+        .iter()
+        .enumerate()
+        .map(|(i, &cause)| {
+            let lives: Vec<_> = records
+                .iter()
+                .filter(|r| r.cause.index() == i)
+                .map(|r| r.life)
+                .collect();
+            let mean = if lives.is_empty() {
+                0.0
+            } else {
+                lives.iter().sum::<f32>() / lives.len() as f32
+            };
+            (cause, lives.len(), mean)
+        })
+        .collect()
+}
+
+/// Reads back the records written by `Chronicle::flush`
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Record>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    use nalgebra::Vector2;
+
+    use crate::brain;
+    use crate::creature::Genome;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    #[test]
+    fn a_life_is_written_and_read_back() {
+        let mut data = GameData::new();
+        data.config.chronicle = true;
+        data.config.chronicle_interval = 1.0;
+        data.config.continuous = false;
+        let mut rng = SimRng::new(1);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+        let origin = Vector2::new(10.0, 20.0);
+        let e = spawn::creature_with(&mut data, origin, Kind::Vegan, &genome, brain);
+
+        data.chronicle.time = 2.0;
+        data.chronicle.birth(e);
+        for step in 0..MAX_TRACE as u32 + 5 {
+            data.chronicle.time = 3.0 + step as f32;
+            sample(&mut data, &[e]);
+            // too soon for another sample
+            data.chronicle.time += 0.5;
+            sample(&mut data, &[e]);
+        }
+        let trace = data.chronicle.trace(e);
+        assert_eq!(trace.len(), MAX_TRACE);
+        assert_eq!(trace[0], (8.0, 10.0, 20.0));
+
+        data[e.component::<Creature>()].life = 4.0;
+        death(&mut data, e, Cause::Eaten { by: 7 });
+        assert!(data.chronicle.trace(e).is_empty());
+        let path = env::temp_dir().join(format!("ldjam-46-chronicle-{}.jsonl", process::id()));
+        let _ = fs::remove_file(&path);
+        data.chronicle.flush(&path).unwrap();
+        let records = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!((record.entity, record.born), (e.idx, 2.0));
+        assert_eq!(record.cause, Cause::Eaten { by: 7 });
+        assert_eq!(record.trace.len(), MAX_TRACE);
+        let summary = summarize(&records);
+        assert_eq!(summary[1], ("eaten", 1, 4.0));
+        assert_eq!(summary[0], ("starved", 0, 0.0));
+    }
+}
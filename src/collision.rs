@@ -1,34 +1,54 @@
 use std::f32;
 
-use ggez::timer;
-use ggez::{Context, GameResult};
+use ggez::GameResult;
 
-use nalgebra::{DVector, Vector2};
+use nalgebra::Vector2;
 
-use ordered_float::OrderedFloat;
+use rand::Rng;
 
-use rand::random;
+use serde::{Deserialize, Serialize};
 
+use crate::chronicle::{self, Cause};
+use crate::continuous;
 use crate::creature::*;
 use crate::data::{Entity, GameData, Has};
-use crate::nn::{Desired, Inputs, Outputs};
-use crate::{CARNIVORE_SPEED, HEIGHT, MAX_RADIUS, VEGAN_SPEED, WIDTH};
-
-pub const VIEW_DISTANCE: f32 = WIDTH;
-
+use crate::events::GameEvent;
+use crate::nn::{self, Experience, Inputs, Outputs};
+use crate::nutrients;
+use crate::profile_scope;
+use crate::resources::{DeltaTime, SimRng};
+use crate::{MAX_RADIUS, WORLD_HEIGHT, WORLD_WIDTH};
+
+/// Hits closer than this times the caster's radius are the caster standing
+/// inside something, like a newborn, and are ignored
+pub const RAYCAST_EPSILON: f32 = 0.1;
+/// How much of a ray a creature blocks, a whole one
+pub const CREATURE_OPACITY: f32 = 1.0;
+/// How much of a ray food blocks, a creature can see past a few pieces
+pub const FOOD_OPACITY: f32 = 0.3;
+
+/// A segment from `p1` to `p2`
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Ray {
-    p1: Vector2<f32>,
-    p2: Vector2<f32>,
+    pub p1: Vector2<f32>,
+    pub p2: Vector2<f32>,
+}
+
+impl Ray {
+    pub fn new(p1: Vector2<f32>, p2: Vector2<f32>) -> Self {
+        Self { p1, p2 }
+    }
 }
 
 /// Should be stored in an array of structs
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Body {
     pub radius: f32,
     pub mass: f32,
     pub rmass: f32,
     pub restitution: f32,
+    /// Rays stop once the bodies they pass through add up to 1.0
+    pub opacity: f32,
 }
 
 impl Body {
@@ -39,8 +59,74 @@ impl Body {
             mass,
             rmass,
             restitution,
+            opacity: CREATURE_OPACITY,
         }
     }
+
+    pub fn with_opacity(self, opacity: f32) -> Self {
+        Self { opacity, ..self }
+    }
+}
+
+/// Where a ray passed through a circle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// How far along the ray the center of the circle is, from 0.0 at `p1`
+    /// to 1.0 at `p2`
+    pub t: f32,
+    /// Distance from `p1` to the point of the ray closest to the center
+    pub distance: f32,
+}
+
+/// Whether a ray passes through a circle whose center lies along it
+pub fn ray_circle(ray: &Ray, center: Vector2<f32>, radius: f32) -> Option<RayHit> {
+    let b = ray.p2 - ray.p1;
+    let a = center - ray.p1;
+    let dot = a.dot(&b);
+    let len2 = b.magnitude_squared();
+    let t = dot / len2;
+    if !(0.0..1.0).contains(&t) {
+        return None;
+    }
+    let a1 = b * (dot / len2);
+    let a2 = a - a1;
+    if a2.magnitude_squared() > radius * radius {
+        return None;
+    }
+
+    Some(RayHit {
+        t,
+        distance: a1.magnitude(),
+    })
+}
+
+/// Normal from the first circle to the second and how deep they overlap,
+/// `None` if they don't touch
+///
+/// Circles at the same spot are pushed apart along the x axis.
+pub fn circle_circle_manifold(
+    p1: Vector2<f32>,
+    r1: f32,
+    p2: Vector2<f32>,
+    r2: f32,
+) -> Option<(Vector2<f32>, f32)> {
+    let n = p2 - p1;
+
+    let r = r2 + r1;
+    let r2 = r * r;
+
+    let dist2 = n.magnitude_squared();
+    if dist2 > r2 {
+        return None;
+    }
+
+    let dist = dist2.sqrt();
+
+    if dist > f32::EPSILON {
+        Some((n / dist, r - dist))
+    } else {
+        Some((Vector2::new(1.0, 0.0), r1))
+    }
 }
 
 /// Informs the physics engine of collisions
@@ -52,29 +138,45 @@ pub struct Manifold {
     penetration: f32,
 }
 
+/// A manifold as seen by the contact overlay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    pub a: Entity,
+    pub b: Entity,
+    /// Middle of the overlap
+    pub point: Vector2<f32>,
+    pub normal: Vector2<f32>,
+    pub penetration: f32,
+    /// Whether one of the two got eaten instead of bouncing off
+    pub eaten: bool,
+}
+
+/// The contacts of the last physics step, only collected when enabled
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Contacts {
+    pub enabled: bool,
+    pub list: Vec<Contact>,
+}
+
 /// Resolves a manifold generated with `gen_manifold`
 pub fn resolve(data: &mut GameData, m: &Manifold) {
-    let rv =
-        data[m.b.component::<Velocity>()].velocity - data[m.a.component::<Velocity>()].velocity;
-    let veln = rv.dot(&m.normal);
+    let a = data[m.a.component::<Body>()];
+    let b = data[m.b.component::<Body>()];
+
+    let (va, vb) = data.get_pair_mut::<Velocity>(m.a, m.b);
+    let veln = (vb.velocity - va.velocity).dot(&m.normal);
 
     if veln > 0.0 {
         return;
     }
 
-    let a = data[m.a.component::<Body>()];
-    let b = data[m.b.component::<Body>()];
-
     let e = a.restitution.min(b.restitution);
 
-    let mut j = -(1.0 + e) * veln;
-    j /= a.rmass + b.rmass;
-    let j = j;
-
+    let j = -(1.0 + e) * veln / (a.rmass + b.rmass);
     let impulse = m.normal * j;
 
-    data[m.a.component::<Velocity>()].velocity -= impulse * a.rmass;
-    data[m.b.component::<Velocity>()].velocity += impulse * b.rmass;
+    va.velocity -= impulse * a.rmass;
+    vb.velocity += impulse * b.rmass;
 }
 
 /// Corrects position using some pre-set PERCENT and SLOP
@@ -86,37 +188,19 @@ pub fn correct(data: &mut GameData, m: &Manifold) {
     let b = data[m.b.component::<Body>()];
 
     let correction = m.normal * (m.penetration - SLOP).max(0.0) / (a.rmass + b.rmass) * PERCENT;
-    data[m.a.component::<Position>()].position -= correction * a.rmass;
-    data[m.b.component::<Position>()].position += correction * b.rmass;
+    let (pa, pb) = data.get_pair_mut::<Position>(m.a, m.b);
+    pa.position -= correction * a.rmass;
+    pb.position += correction * b.rmass;
 }
 
+/// The manifold of two entities, see `circle_circle_manifold`
 pub fn gen_manifold(data: &mut GameData, a: Entity, b: Entity) -> Option<Manifold> {
-    let a_body = data[a.component::<Body>()];
-    let b_body = data[b.component::<Body>()];
-
-    let n = data[b.component::<Position>()].position - data[a.component::<Position>()].position;
-
-    let r = b_body.radius + a_body.radius;
-    let r2 = r * r;
-
-    let dist2 = n.magnitude_squared();
-    if dist2 > r2 {
-        return None;
-    }
-
-    let dist = dist2.sqrt();
-
-    let penetration;
-    let normal;
-
-    if dist > f32::EPSILON {
-        penetration = r - dist;
-        normal = n / dist;
-    } else {
-        penetration = a_body.radius;
-        normal = Vector2::new(1.0, 0.0);
-    }
-
+    let (normal, penetration) = circle_circle_manifold(
+        data[a.component::<Position>()].position,
+        data[a.component::<Body>()].radius,
+        data[b.component::<Position>()].position,
+        data[b.component::<Body>()].radius,
+    )?;
     Some(Manifold {
         a,
         b,
@@ -125,52 +209,72 @@ pub fn gen_manifold(data: &mut GameData, a: Entity, b: Entity) -> Option<Manifol
     })
 }
 
-pub fn raycast<I>(data: &GameData, ray: &Ray, this: Entity, entities: I) -> Option<(Entity, f32)>
+/// Every one of `entities` hit by a ray cast by `this` with its distance,
+/// closest first, see `ray_circle`
+pub fn raycast_all<I>(data: &GameData, ray: &Ray, this: Entity, entities: I) -> Vec<(Entity, f32)>
 where
     I: IntoIterator<Item = Entity>,
 {
-    let b = ray.p2 - ray.p1;
-    let mut result = None;
-    let mut min_dist = f32::INFINITY;
+    profile_scope!("raycast");
+    let min_hit = RAYCAST_EPSILON * data[this.component::<Body>()].radius;
+    let mut hits = Vec::new();
     for e in entities {
         if e == this {
             continue;
         }
 
-        let pos = data[e.component::<Position>()].position;
-        let radius = data[e.component::<Body>()].radius;
-        let a = pos - ray.p1;
-        let dot = a.dot(&b);
-        let len2 = b.magnitude_squared();
-        let t = dot / len2;
-        if t < 0.0 || t >= 1.0 {
-            continue;
-        }
-        let a1 = b * (dot / len2);
-        let a2 = a - a1;
-        if a2.magnitude_squared() > radius * radius {
-            continue;
+        // eaten earlier in the frame
+        let (pos, radius) = match (data.get::<Position>(e), data.get::<Body>(e)) {
+            (Some(position), Some(body)) => (position.position, body.radius),
+            _ => continue,
+        };
+        match ray_circle(ray, pos, radius) {
+            Some(hit) if hit.distance >= min_hit => hits.push((e, hit.distance)),
+            _ => {}
         }
+    }
+    // exact ties go to the lower index so iteration order doesn't matter
+    hits.sort_by(|(a, da), (b, db)| da.total_cmp(db).then(a.idx.cmp(&b.idx)));
+    hits
+}
 
-        let dist = a1.magnitude();
-        if dist < min_dist {
-            min_dist = dist;
-            result = Some(e);
+/// What a ray sees through the bodies in `hits`, which are sorted closest
+/// first: the most opaque body before the ray is used up, the closest one
+/// of those on ties
+pub fn occluder(data: &GameData, hits: &[(Entity, f32)]) -> Option<(Entity, f32)> {
+    let mut blocked = 0.0;
+    let mut result: Option<(Entity, f32)> = None;
+    for &(e, distance) in hits {
+        let opacity = data[e.component::<Body>()].opacity;
+        let stronger = match result {
+            Some((r, _)) => opacity > data[r.component::<Body>()].opacity,
+            None => true,
+        };
+        if stronger {
+            result = Some((e, distance));
+        }
+        blocked += opacity;
+        if blocked >= 1.0 {
+            break;
         }
     }
-    result.map(|r| (r, min_dist))
+    result
 }
 
-pub fn physics_system<I1, I2>(
-    ctx: &mut Context,
-    data: &mut GameData,
-    left: I1,
-    right: I2,
-) -> GameResult<()>
+/// Resolves collisions and moves everything by the `DeltaTime` of the step
+pub fn physics_system<I1, I2>(data: &mut GameData, left: I1, right: I2) -> GameResult<()>
 where
     I1: IntoIterator<Item = Entity> + Clone,
     I2: IntoIterator<Item = Entity> + Clone,
 {
+    profile_scope!("physics");
+    let DeltaTime(delta) = *data.resource::<DeltaTime>();
+    data.contacts.list.clear();
+    for a in left.clone() {
+        if data.has(a.component::<Creature>()) {
+            data[a.component::<Creature>()].crowding = 0;
+        }
+    }
     for a in left.clone() {
         for b in right.clone() {
             if a == b {
@@ -182,56 +286,148 @@ where
             }
 
             if let Some(m) = gen_manifold(data, a, b) {
+                if data.contacts.enabled {
+                    let radius = data[m.a.component::<Body>()].radius;
+                    let position = data[m.a.component::<Position>()].position;
+                    data.contacts.list.push(Contact {
+                        a: m.a,
+                        b: m.b,
+                        point: position + m.normal * (radius - m.penetration * 0.5),
+                        normal: m.normal,
+                        penetration: m.penetration,
+                        eaten: false,
+                    });
+                }
+
                 resolve(data, &m);
                 correct(data, &m);
 
                 if data.has(m.a.component::<Creature>()) && data.has(m.b.component::<Creature>()) {
                     let c1 = data[m.a.component::<Creature>()];
                     let c2 = data[m.b.component::<Creature>()];
+                    // the pair comes up again the other way around for `b`
+                    if c1.kind == c2.kind {
+                        data[m.a.component::<Creature>()].crowding += 1;
+                    }
                     match (c1.kind, c2.kind) {
                         (Kind::Vegan, Kind::Vegan) => {}
+                        // a carnivore that just ate only bumps into its prey,
+                        // which lives to notice it
+                        (Kind::Vegan, Kind::Carnivorous)
+                            if c2.eat_cooldown > 0.0 || c2.digesting() =>
+                        {
+                            data[m.a.component::<Creature>()].last_hit = data.config.startle_time;
+                            continue;
+                        }
+                        (Kind::Carnivorous, Kind::Vegan)
+                            if c1.eat_cooldown > 0.0 || c1.digesting() =>
+                        {
+                            data[m.b.component::<Creature>()].last_hit = data.config.startle_time;
+                            continue;
+                        }
                         (Kind::Vegan, Kind::Carnivorous) => {
-                            data[m.b.component::<Creature>()].hunger -= CARNIVORE_NUTRITION;
+                            data[m.b.component::<Creature>()].eat_cooldown =
+                                data.config.predation_cooldown;
+                            let digestion = data.config.digestion_duration;
+                            data[m.b.component::<Creature>()]
+                                .start_digesting(digestion, CARNIVORE_NUTRITION);
+                            data[m.b.component::<Creature>()].consumed += CARNIVORE_NUTRITION;
+                            data[m.b.component::<Creature>()].eaten += 1;
+                            chronicle::death(data, m.a, Cause::Eaten { by: m.b.idx });
+                            continuous::enshrine(data, m.a);
+                            nutrients::recycle(data, m.a);
+                            data.events.push(GameEvent::Ate {
+                                eater: m.b,
+                                eaten: m.a,
+                            });
                             data.delete(m.a);
                             data.lazy.remove(m.a);
                             continue;
                         }
                         (Kind::Carnivorous, Kind::Vegan) => {
-                            data[m.a.component::<Creature>()].hunger -= CARNIVORE_NUTRITION;
+                            data[m.a.component::<Creature>()].eat_cooldown =
+                                data.config.predation_cooldown;
+                            let digestion = data.config.digestion_duration;
+                            data[m.a.component::<Creature>()]
+                                .start_digesting(digestion, CARNIVORE_NUTRITION);
+                            data[m.a.component::<Creature>()].consumed += CARNIVORE_NUTRITION;
+                            data[m.a.component::<Creature>()].eaten += 1;
+                            chronicle::death(data, m.b, Cause::Eaten { by: m.a.idx });
+                            continuous::enshrine(data, m.b);
+                            nutrients::recycle(data, m.b);
+                            data.events.push(GameEvent::Ate {
+                                eater: m.a,
+                                eaten: m.b,
+                            });
                             data.delete(m.b);
                             data.lazy.remove(m.b);
                             continue;
                         }
                         (Kind::Carnivorous, Kind::Carnivorous) => {}
                     }
+                    // every pair is visited twice, only share on one of the visits
+                    if m.a.idx < m.b.idx {
+                        share(data, m.a, m.b, delta);
+                    }
                     if c1.timeout >= 0.0 || c2.timeout >= 0.0 {
                         continue;
                     }
 
-                    mate(ctx, data, m.a, m.b)?;
+                    mate(data, m.a, m.b);
                 } else if data.has(m.a.component::<Creature>()) && data.has(m.b.component::<Food>())
                 {
                     let c = data[m.a.component::<Creature>()];
-                    if c.kind == Kind::Carnivorous {
-                        continue;
-                    }
-                    data[m.a.component::<Creature>()].hunger -= VEGAN_NUTRITION;
+                    let food = data[m.b.component::<Food>()].nutrition;
+                    let nutrition = match c.kind {
+                        _ if c.eat_cooldown > 0.0 => continue,
+                        Kind::Vegan => food,
+                        Kind::Carnivorous if c.desperate(&data.config) => {
+                            food * data.config.scavenge_factor
+                        }
+                        Kind::Carnivorous => continue,
+                    };
+                    data[m.a.component::<Creature>()].hunger -= nutrition;
+                    data[m.a.component::<Creature>()].consumed += nutrition;
+                    data[m.a.component::<Creature>()].eaten += 1;
+                    data[m.a.component::<Creature>()].eat_cooldown = data.config.food_cooldown;
+                    data.events.push(GameEvent::Ate {
+                        eater: m.a,
+                        eaten: m.b,
+                    });
                     data.delete(m.b);
                     data.lazy.remove(m.b);
                 } else if data.has(m.a.component::<Food>()) && data.has(m.b.component::<Creature>())
                 {
                     let c = data[m.b.component::<Creature>()];
-                    if c.kind == Kind::Carnivorous {
-                        continue;
-                    }
-                    data[m.b.component::<Creature>()].hunger -= VEGAN_NUTRITION;
+                    let food = data[m.a.component::<Food>()].nutrition;
+                    let nutrition = match c.kind {
+                        _ if c.eat_cooldown > 0.0 => continue,
+                        Kind::Vegan => food,
+                        Kind::Carnivorous if c.desperate(&data.config) => {
+                            food * data.config.scavenge_factor
+                        }
+                        Kind::Carnivorous => continue,
+                    };
+                    data[m.b.component::<Creature>()].hunger -= nutrition;
+                    data[m.b.component::<Creature>()].consumed += nutrition;
+                    data[m.b.component::<Creature>()].eaten += 1;
+                    data[m.b.component::<Creature>()].eat_cooldown = data.config.food_cooldown;
+                    data.events.push(GameEvent::Ate {
+                        eater: m.b,
+                        eaten: m.a,
+                    });
                     data.delete(m.a);
                     data.lazy.remove(m.a);
                 }
             }
         }
     }
-    let delta = timer::duration_to_f64(timer::delta(ctx)) as f32;
+    let mut contacts = std::mem::take(&mut data.contacts.list);
+    for contact in &mut contacts {
+        contact.eaten =
+            !data.has(contact.a.component::<Body>()) || !data.has(contact.b.component::<Body>());
+    }
+    data.contacts.list = contacts;
     for a in left.clone() {
         if !data.has(a.component::<Velocity>()) || !data.has(a.component::<Position>()) {
             continue;
@@ -240,98 +436,434 @@ where
         let pos = &mut data[a.component::<Position>()].position;
         *pos += vel * delta;
         if pos.x < -MAX_RADIUS {
-            pos.x += WIDTH + MAX_RADIUS;
-        } else if pos.x > WIDTH + MAX_RADIUS {
-            pos.x -= WIDTH + MAX_RADIUS;
+            pos.x += WORLD_WIDTH + MAX_RADIUS;
+        } else if pos.x > WORLD_WIDTH + MAX_RADIUS {
+            pos.x -= WORLD_WIDTH + MAX_RADIUS;
         }
         if pos.y < -MAX_RADIUS {
-            pos.y += HEIGHT + MAX_RADIUS;
-        } else if pos.y > HEIGHT + MAX_RADIUS {
-            pos.y -= HEIGHT + MAX_RADIUS;
+            pos.y += WORLD_HEIGHT + MAX_RADIUS;
+        } else if pos.y > WORLD_HEIGHT + MAX_RADIUS {
+            pos.y -= WORLD_HEIGHT + MAX_RADIUS;
         }
     }
     Ok(())
 }
 
-pub fn input_system<I1, I2>(data: &mut GameData, creatures: I1, all: I2) -> GameResult<()>
+pub fn output_system<I>(data: &mut GameData, entities: I) -> GameResult<()>
 where
-    I1: IntoIterator<Item = Entity>,
-    I2: IntoIterator<Item = Entity> + Clone,
+    I: IntoIterator<Item = Entity>,
 {
-    for e in creatures {
-        let this = e;
-        let p1 = data[e.component::<Position>()].position;
-        let d = data[e.component::<Direction>()].direction;
-        let mut has_desired = false;
-        let mut desired = vec![0.0; DIR_COUNT];
-        let mut inputs = vec![1.0; RAY_COUNT * 2];
-        for i in 0..RAY_COUNT {
-            let f = i as f32 / (RAY_COUNT as f32 - 1.0);
-            let d = -FOV_2 * f + d + FOV_2 * f;
-            let (y, x) = d.sin_cos();
-            let p2 = Vector2::new(x, y) * VIEW_DISTANCE;
-            let ray = Ray { p1, p2 };
-            let result = raycast(data, &ray, e, all.clone());
-            if let Some((e, d)) = result {
-                let kind = match data[this.component::<Creature>()].kind {
-                    Kind::Vegan => {
-                        if data.has(e.component::<Food>()) {
-                            1.0
-                        } else if data[e.component::<Creature>()].kind == Kind::Vegan {
-                            0.7
-                        } else {
-                            -1.0
-                        }
-                    }
-                    Kind::Carnivorous => {
-                        if data.has(e.component::<Food>()) {
-                            0.0
-                        } else if data[e.component::<Creature>()].kind == Kind::Vegan {
-                            1.0
-                        } else {
-                            0.7
-                        }
-                    }
-                };
-
-                has_desired = true;
-                let j = i * (DIR_COUNT as f32 / RAY_COUNT as f32).round() as usize;
-                desired[j] = kind;
-                inputs[i * 2] = kind;
-                inputs[i * 2 + 1] = d / VIEW_DISTANCE;
+    let mut rng = data.take_resource::<SimRng>();
+    for e in entities {
+        // eaten earlier in the frame
+        let (output, mut creature, genome) = match (
+            data.get::<Outputs>(e),
+            data.get::<Creature>(e),
+            data.get::<Genome>(e),
+        ) {
+            (Some(outputs), Some(creature), Some(genome)) => (&outputs.output, *creature, *genome),
+            _ => continue,
+        };
+        if data.get::<Velocity>(e).is_none() || data.get::<Direction>(e).is_none() {
+            continue;
+        }
+        let kind = creature.kind;
+        // a creature with the wrong outputs is a bug, it is just skipped in
+        // release builds instead of taking the game down
+        let expected = data.config.brain.spec(kind).output_len;
+        if output.len() != expected {
+            let problem = format!(
+                "creature {} ({:?}) has {} outputs, expected {}",
+                e.idx,
+                kind,
+                output.len(),
+                expected
+            );
+            debug_assert!(false, "{}", problem);
+            eprintln!("{}", problem);
+            continue;
+        }
+        let lunge = output.get(LUNGE_OUTPUT).copied().unwrap_or(0.0);
+        let directions = output.rows(0, DIR_COUNT).into_owned();
+        let mut index = if data.config.sample_outputs {
+            nn::sample(&directions, genome.temperature, rng.gen())
+        } else {
+            nn::argmax(&directions)
+        };
+        if directions.iter().all(|x| *x == 1.0) {
+            index = rng.gen_range(0, DIR_COUNT);
+        }
+        if data.config.imprinting {
+            let capacity = data.config.imprint_samples;
+            let input = data.get::<Inputs>(e).map(|inputs| inputs.input.clone());
+            if let (Some(input), Some(experience)) = (input, data.get_mut::<Experience>(e)) {
+                experience.record(input, index, capacity);
             }
         }
-        if has_desired {
-            data[e.component::<Desired>()].desired = DVector::from_vec(desired);
+        let angle = (360.0 / DIR_COUNT as f32 * index as f32).to_radians();
+        let (y, x) = angle.sin_cos();
+        // vegans never lunge, whatever their network says
+        if creature.can_lunge() && lunge > data.config.lunge_threshold {
+            creature.start_lunge(&data.config);
+            data[e.component::<Creature>()] = creature;
         }
-        data[e.component::<Inputs>()].input = DVector::from_vec(inputs);
+        let speed = genome.speed * creature.speed_factor(&data.config);
+        data[e.component::<Velocity>()] = Velocity::new(x * speed, y * speed);
+        data[e.component::<Direction>()].direction = angle;
     }
+    data.insert_resource(rng);
     Ok(())
 }
 
-pub fn output_system<I>(data: &mut GameData, entities: I) -> GameResult<()>
-where
-    I: IntoIterator<Item = Entity>,
-{
-    for e in entities {
-        let output = &data[e.component::<Outputs>()].output;
-        let (mut index, _) = output
+#[cfg(test)]
+mod tests {
+    use nalgebra::DVector;
+
+    use super::*;
+    use crate::brain;
+    use crate::senses::heading_index;
+    use crate::spawn;
+
+    /// A carnivore and a vegan on top of each other at the center of a
+    /// fresh world
+    fn predator_and_prey() -> (GameData, Entity, Entity) {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let center = Vector2::new(WORLD_WIDTH * 0.5, WORLD_HEIGHT * 0.5);
+        let mut spawn = |data: &mut GameData, kind| {
+            let genome = Genome::random(kind, &mut rng);
+            let brain = brain::new_for(&data.config, kind, &mut rng);
+            spawn::creature_with(data, center, kind, &genome, brain)
+        };
+        let carnivore = spawn(&mut data, Kind::Carnivorous);
+        let vegan = spawn(&mut data, Kind::Vegan);
+        data.insert_resource(DeltaTime(0.01));
+        (data, carnivore, vegan)
+    }
+
+    #[test]
+    fn bumped_prey_gets_startled() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        data[carnivore.component::<Creature>()].eat_cooldown = 1.0;
+        let all = [carnivore, vegan];
+        physics_system(&mut data, all, all).unwrap();
+
+        assert!(data.has(vegan.component::<Creature>()));
+        assert_eq!(
+            data[vegan.component::<Creature>()].last_hit,
+            data.config.startle_time
+        );
+    }
+
+    #[test]
+    fn eating_food_is_an_event() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        data.delete(carnivore);
+        data.lazy.remove(carnivore);
+        data.commit();
+        let center = data[vegan.component::<Position>()].position;
+        let food = Food::plain(&data.config);
+        let food = spawn::food_at(&mut data, center, 10.0, food, &mut SimRng::new(2));
+        let all = [vegan, food];
+        physics_system(&mut data, all, all).unwrap();
+
+        let events: Vec<_> = data.drain_events().collect();
+        assert_eq!(
+            events,
+            vec![GameEvent::Ate {
+                eater: vegan,
+                eaten: food
+            }]
+        );
+        assert!(!data.has(food.component::<Food>()));
+        assert_eq!(data.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn eaten_prey_is_deleted() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        let all = [carnivore, vegan];
+        physics_system(&mut data, all, all).unwrap();
+
+        assert!(!data.has(vegan.component::<Creature>()));
+        assert_eq!(data[carnivore.component::<Creature>()].eaten, 1);
+        let events: Vec<_> = data.drain_events().collect();
+        assert_eq!(
+            events,
+            vec![GameEvent::Ate {
+                eater: carnivore,
+                eaten: vegan
+            }]
+        );
+    }
+
+    #[test]
+    fn one_kill_per_cooldown() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        let center = data[carnivore.component::<Position>()].position;
+        let mut rng = SimRng::new(3);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+        let other = spawn::creature_with(&mut data, center, Kind::Vegan, &genome, brain);
+        let all = [carnivore, vegan, other];
+        physics_system(&mut data, all, all).unwrap();
+
+        let alive = [vegan, other]
+            .iter()
+            .filter(|e| data.has(e.component::<Creature>()))
+            .count();
+        assert_eq!(alive, 1);
+        let creature = data[carnivore.component::<Creature>()];
+        assert_eq!(creature.eaten, 1);
+        assert_eq!(creature.eat_cooldown, data.config.predation_cooldown);
+    }
+
+    #[test]
+    fn digesting_carnivores_spare_prey() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        data[carnivore.component::<Creature>()].digestion = 1.0;
+        let all = [carnivore, vegan];
+        physics_system(&mut data, all, all).unwrap();
+        assert!(data.has(vegan.component::<Creature>()));
+
+        data[carnivore.component::<Creature>()].digestion = 0.0;
+        physics_system(&mut data, all, all).unwrap();
+        assert!(!data.has(vegan.component::<Creature>()));
+        let creature = data[carnivore.component::<Creature>()];
+        assert_eq!(creature.digestion, data.config.digestion_duration);
+        assert_eq!(creature.nutrition, CARNIVORE_NUTRITION);
+    }
+
+    /// A carnivore as hungry as `hunger` on top of a piece of plain food
+    fn carnivore_on_food(hunger: f32) -> (GameData, Entity, Entity) {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        data.delete(vegan);
+        data.lazy.remove(vegan);
+        data.commit();
+        data[carnivore.component::<Creature>()].hunger = hunger;
+        let center = data[carnivore.component::<Position>()].position;
+        let mut rng = SimRng::new(2);
+        let food = Food::plain(&data.config);
+        let food = spawn::food_at(&mut data, center, 10.0, food, &mut rng);
+        (data, carnivore, food)
+    }
+
+    #[test]
+    fn sated_carnivores_leave_food_alone() {
+        let (mut data, carnivore, food) = carnivore_on_food(0.0);
+        let all = [carnivore, food];
+        physics_system(&mut data, all, all).unwrap();
+
+        assert!(data.has(food.component::<Food>()));
+        assert_eq!(data[carnivore.component::<Creature>()].eaten, 0);
+    }
+
+    #[test]
+    fn desperate_carnivores_scavenge_for_less() {
+        let hunger = CARNIVORE_STARVE * 0.9;
+        let (mut data, carnivore, food) = carnivore_on_food(hunger);
+        assert!(data[carnivore.component::<Creature>()].desperate(&data.config));
+        let nutrition = data[food.component::<Food>()].nutrition;
+        let all = [carnivore, food];
+        physics_system(&mut data, all, all).unwrap();
+
+        assert!(!data.has(food.component::<Food>()));
+        let creature = data[carnivore.component::<Creature>()];
+        assert_eq!(creature.eaten, 1);
+        let expected = hunger - nutrition * data.config.scavenge_factor;
+        assert!((creature.hunger - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rays_skip_what_the_caster_stands_in_and_break_ties_by_index() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        let center = data[carnivore.component::<Position>()].position;
+        let mut rng = SimRng::new(2);
+        let food = Food::plain(&data.config);
+        // the same distance along the ray, one on either side of it
+        let above = spawn::food_at(
+            &mut data,
+            center + Vector2::new(50.0, 2.0),
+            5.0,
+            food,
+            &mut rng,
+        );
+        let below = spawn::food_at(
+            &mut data,
+            center + Vector2::new(50.0, -2.0),
+            5.0,
+            food,
+            &mut rng,
+        );
+        let ray = Ray::new(center, center + Vector2::new(100.0, 0.0));
+
+        let hits = raycast_all(&data, &ray, carnivore, vec![below, vegan, above, carnivore]);
+        let hit: Vec<_> = hits.iter().map(|&(e, _)| e).collect();
+        assert_eq!(hit, vec![above, below]);
+        assert_eq!(hits[0].1, hits[1].1);
+    }
+
+    #[test]
+    fn contacts_are_only_collected_when_enabled() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        data[vegan.component::<Position>()].position.x += 5.0;
+        data[carnivore.component::<Creature>()].eat_cooldown = 1.0;
+        let all = [carnivore, vegan];
+        physics_system(&mut data, all, all).unwrap();
+        assert!(data.contacts.list.is_empty());
+
+        data.contacts.enabled = true;
+        physics_system(&mut data, all, all).unwrap();
+        assert!(!data.contacts.list.is_empty());
+        assert!(data
+            .contacts
+            .list
             .iter()
-            .enumerate()
-            .max_by_key(|(_, x)| OrderedFloat::from(**x))
-            .unwrap();
-        if output.iter().all(|x| *x == 1.0) {
-            index = random::<usize>() % DIR_COUNT;
+            .all(|c| !c.eaten && c.penetration > 0.0));
+
+        data[carnivore.component::<Creature>()].eat_cooldown = 0.0;
+        physics_system(&mut data, all, all).unwrap();
+        assert!(!data.contacts.list.is_empty());
+        assert!(data.contacts.list.iter().all(|c| c.eaten));
+    }
+
+    #[test]
+    fn piled_up_kin_get_crowded() {
+        let (mut data, carnivore, vegan) = predator_and_prey();
+        data[carnivore.component::<Creature>()].eat_cooldown = 1.0;
+        let center = data[vegan.component::<Position>()].position;
+        let mut rng = SimRng::new(3);
+        let mut pile = vec![vegan];
+        for i in 1..4 {
+            let genome = Genome::random(Kind::Vegan, &mut rng);
+            let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+            let position = center + Vector2::new(i as f32, 0.0);
+            pile.push(spawn::creature_with(
+                &mut data,
+                position,
+                Kind::Vegan,
+                &genome,
+                brain,
+            ));
         }
-        let angle = (360.0 / DIR_COUNT as f32 * index as f32).to_radians();
-        let (y, x) = angle.sin_cos();
-        let new_velocity = match data[e.component::<Creature>()].kind {
-            Kind::Carnivorous => Velocity::new(x * CARNIVORE_SPEED, y * CARNIVORE_SPEED),
-            Kind::Vegan => Velocity::new(x * VEGAN_SPEED, y * VEGAN_SPEED),
+        let all: Vec<_> = pile.iter().copied().chain(Some(carnivore)).collect();
+        physics_system(&mut data, all.clone(), all).unwrap();
+
+        // the carnivore is in the pile too, but not of the same kind
+        for e in &pile {
+            assert_eq!(data[e.component::<Creature>()].crowding, 3);
+        }
+        assert_eq!(data[carnivore.component::<Creature>()].crowding, 0);
+
+        data.config.crowding_threshold = 1;
+        data.config.crowding_rate = 2.0;
+        let creature = data[vegan.component::<Creature>()];
+        assert_eq!(creature.crowding_hunger(&data.config), 4.0);
+        data.config.crowding_threshold = 5;
+        assert_eq!(creature.crowding_hunger(&data.config), 0.0);
+    }
+
+    #[test]
+    fn circles_overlap_along_the_line_between_them() {
+        let origin = Vector2::new(0.0, 0.0);
+        let (normal, penetration) =
+            circle_circle_manifold(origin, 2.0, Vector2::new(0.0, 3.0), 2.0).unwrap();
+        assert_eq!((normal, penetration), (Vector2::new(0.0, 1.0), 1.0));
+        assert_eq!(
+            circle_circle_manifold(origin, 2.0, Vector2::new(4.5, 0.0), 2.0),
+            None
+        );
+        assert_eq!(
+            circle_circle_manifold(origin, 2.0, origin, 3.0),
+            Some((Vector2::new(1.0, 0.0), 2.0))
+        );
+
+        // swapping the circles flips the normal and nothing else
+        let mut rng = SimRng::new(4);
+        for _ in 0..100 {
+            let p1 = Vector2::new(rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0));
+            let p2 = Vector2::new(rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0));
+            let (r1, r2) = (rng.gen_range(0.5, 4.0), rng.gen_range(0.5, 4.0));
+            let ab = circle_circle_manifold(p1, r1, p2, r2);
+            let ba = circle_circle_manifold(p2, r2, p1, r1);
+            match (ab, ba) {
+                (Some((n1, d1)), Some((n2, d2))) => {
+                    assert!((n1 + n2).magnitude() < 1e-5);
+                    assert!((d1 - d2).abs() < 1e-5 && d1 >= 0.0);
+                }
+                (None, None) => assert!((p2 - p1).magnitude() > r1 + r2 - 1e-5),
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn rays_only_hit_circles_ahead_of_them() {
+        let ray = Ray::new(Vector2::new(0.0, 0.0), Vector2::new(10.0, 0.0));
+        let hit = ray_circle(&ray, Vector2::new(4.0, 1.0), 2.0).unwrap();
+        assert_eq!((hit.t, hit.distance), (0.4, 4.0));
+        // too far to the side
+        assert_eq!(ray_circle(&ray, Vector2::new(4.0, 3.0), 2.0), None);
+        // behind the start and past the end
+        assert_eq!(ray_circle(&ray, Vector2::new(-1.0, 0.0), 2.0), None);
+        assert_eq!(ray_circle(&ray, Vector2::new(11.0, 0.0), 2.0), None);
+    }
+
+    #[test]
+    fn lunges_cost_hunger_and_wait_for_the_cooldown() {
+        let (mut data, carnivore, _) = predator_and_prey();
+        data.config.sample_outputs = false;
+        let mut output = DVector::zeros(LUNGE_OUTPUT + 1);
+        output[2] = 0.5;
+        output[LUNGE_OUTPUT] = 1.0;
+        data[carnivore.component::<Outputs>()] = Outputs { output };
+        output_system(&mut data, vec![carnivore]).unwrap();
+
+        let config = &data.config;
+        let creature = data[carnivore.component::<Creature>()];
+        assert_eq!(creature.lunge, config.lunge_duration);
+        assert_eq!(creature.lunge_cooldown, config.lunge_cooldown);
+        assert_eq!(creature.hunger, config.lunge_hunger);
+        let speed = data[carnivore.component::<Genome>()].speed * creature.speed_factor(config);
+        let velocity = data[carnivore.component::<Velocity>()].velocity;
+        assert!((velocity.magnitude() - speed).abs() < 1e-3);
+        assert_eq!(
+            heading_index(data[carnivore.component::<Direction>()].direction),
+            2
+        );
+
+        // the lunge is over, but the cooldown isn't
+        data[carnivore.component::<Creature>()].lunge = 0.0;
+        output_system(&mut data, vec![carnivore]).unwrap();
+        let creature = data[carnivore.component::<Creature>()];
+        assert_eq!(
+            (creature.lunge, creature.hunger),
+            (0.0, data.config.lunge_hunger)
+        );
+    }
+
+    #[test]
+    fn a_few_pieces_of_food_dont_hide_a_creature() {
+        let (mut data, carnivore, _) = predator_and_prey();
+        let mut rng = SimRng::new(2);
+        let food = Food::plain(&data.config);
+        let foods: Vec<_> = (0..4)
+            .map(|_| spawn::food_at(&mut data, Vector2::new(0.0, 0.0), 5.0, food, &mut rng))
+            .collect();
+        let hits = |count: usize| -> Vec<_> {
+            let mut hits: Vec<_> = foods[..count]
+                .iter()
+                .enumerate()
+                .map(|(i, &e)| (e, i as f32))
+                .collect();
+            hits.push((carnivore, 10.0));
+            hits
         };
-        let new_direction = angle;
-        data[e.component::<Velocity>()] = new_velocity;
-        data[e.component::<Direction>()].direction = new_direction;
+
+        assert_eq!(occluder(&data, &hits(0)), Some((carnivore, 10.0)));
+        assert_eq!(occluder(&data, &hits(3)), Some((carnivore, 10.0)));
+        // four of them add up to more than a whole ray
+        assert_eq!(occluder(&data, &hits(4)), Some((foods[0], 0.0)));
+        assert_eq!(occluder(&data, &[]), None);
     }
-    Ok(())
 }
@@ -0,0 +1,507 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::brain::BrainConfig;
+use crate::budget::Degradation;
+use crate::creature::{Fitness, M_CHANCE, M_MUTATION, VEGAN_NUTRITION};
+use crate::error::{Error, Result};
+use crate::events::EVENT_CAPACITY;
+use crate::keys::Keybindings;
+use crate::time;
+use crate::{CARNIVORE_RATIO, CREATURE_COUNT, DPI_FACTOR, FOOD_TIMEOUT, GEN_TIME};
+
+pub const CONFIG_PATH: &str = "config.toml";
+
+/// What happens when mating would push the population over `max_creatures`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapPolicy {
+    /// Only the children that fit are born
+    Skip,
+    /// The least fit creatures die to make room
+    Cull,
+}
+
+/// What the simulation does while the window is in the background
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusPolicy {
+    /// Nothing moves until the window gets focus back
+    Pause,
+    /// Updates only `unfocused_update_rate` times a second
+    Throttle,
+    /// Same as in the foreground
+    Continue,
+}
+
+/// Parameters that can be changed while the game is running
+///
+/// Lengths and speeds are in world units like the rest of the gameplay, the
+/// world is `WORLD_WIDTH` by `WORLD_HEIGHT` of them however big the window is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Chance for a gene to mutate when mating
+    pub mutation_chance: f32,
+    /// How far a mutated gene can stray from its parents
+    pub mutation_magnitude: f32,
+    /// Multiplies both mutation parameters every generation
+    pub mutation_decay: f32,
+    /// Lowest the mutation chance decays to
+    pub min_mutation_chance: f32,
+    /// Lowest the mutation magnitude decays to
+    pub min_mutation_magnitude: f32,
+    /// Looks diversity below which mutation heats back up, 0.0 turns this
+    /// off
+    pub reheat_diversity: f32,
+    /// Whether creatures retire one by one instead of the whole world rolling
+    /// over, generations then only split up stats and saves
+    pub continuous: bool,
+    /// Life after which a vegan retires in continuous mode
+    pub max_vegan_life: f32,
+    /// Life after which a carnivore retires in continuous mode
+    pub max_carnivore_life: f32,
+    /// Recruits per second that join a kind that's below its floor in
+    /// continuous mode
+    pub recruit_rate: f32,
+    /// Fraction of the starting count of each kind recruits keep it at
+    pub recruit_floor: f32,
+    /// Whether continuous mode holds every kind at its quota like a reseed
+    /// does, retiring the oldest of a kind over it and recruiting up to it
+    pub enforce_ratio: bool,
+    /// Parents further apart than this by `mating_distance` refuse to mate,
+    /// 0.0 turns this off
+    pub mating_threshold: f32,
+    /// Multiplies `mating_threshold` every generation
+    pub mating_threshold_decay: f32,
+    /// Elite weights smaller than this are zeroed when saving, 0.0 turns
+    /// this off
+    pub prune_threshold: f32,
+    /// Generations between two freezes of the seed bank, 0 turns it off, see
+    /// `seedbank::SeedBank`
+    pub seed_bank_interval: usize,
+    /// Creatures of every kind frozen each time
+    pub seed_bank_samples: usize,
+    /// Most seeds kept, new ones replace random old ones past that
+    pub seed_bank_size: usize,
+    /// Chance every generation that a mutated seed comes back instead of a
+    /// random founder or recruit
+    pub seed_revival_chance: f32,
+    /// Time between two batches of food
+    pub food_timeout: f32,
+    /// Multiplies how fast creatures get hungry
+    pub metabolism: f32,
+    /// Fraction of the starting creatures that are carnivores
+    pub carnivore_ratio: f32,
+    /// Multiplies the speed of every carnivore
+    pub carnivore_speed: f32,
+    /// Multiplies how fast the simulation runs
+    pub sim_speed: f32,
+    /// Seeds the rng of the simulation, 0 picks a new seed every run
+    pub seed: u64,
+    /// Longest real time a frame can cover, anything above is dropped, 0.0
+    /// turns this off
+    pub max_frame_delta: f32,
+    /// Frames `--trace` records before writing the trace
+    pub trace_frames: usize,
+    /// Real seconds an update should take at most, the game degrades while
+    /// it keeps taking longer, 0.0 turns this off, see `budget::Budget`
+    pub frame_budget: f32,
+    /// Seconds over budget in a row before the next degradation
+    pub degrade_after: f32,
+    /// Seconds well under budget in a row before the last degradation is
+    /// undone
+    pub restore_after: f32,
+    /// Fraction of the budget an update has to stay under to count towards
+    /// restoring
+    pub restore_headroom: f32,
+    /// Degradations in the order they're taken
+    pub degradation_ladder: Vec<Degradation>,
+    /// Fraction of `CARNIVORE_STARVE` after which carnivores will eat food
+    pub scavenge_threshold: f32,
+    /// Fraction of the usual nutrition a scavenging carnivore gets from food
+    pub scavenge_factor: f32,
+    /// How long a creature stays startled after a predator bumps into it
+    pub startle_time: f32,
+    /// Nutrition of plain food
+    pub food_nutrition: f32,
+    /// Nutrition of rich food
+    pub rich_food_nutrition: f32,
+    /// Fraction of spawned food that is rich
+    pub rich_food_ratio: f32,
+    /// Whether the dead give some of what they ate back as food pellets, see
+    /// `nutrients::recycle`
+    pub nutrient_cycling: bool,
+    /// Fraction of the nutrition a creature took in over its life that comes
+    /// back when it dies
+    pub recycled_fraction: f32,
+    /// Nutrition a pellet carries at most, more makes more pellets
+    pub pellet_nutrition: f32,
+    /// Most pellets a single death leaves behind, past that they get bigger
+    pub max_pellets: usize,
+    /// How far from the body pellets land
+    pub pellet_scatter: f32,
+    /// Number of patches rich food spawns in, none means no rich food
+    pub rich_patch_count: usize,
+    /// Radius of the patches rich food spawns in
+    pub rich_patch_radius: f32,
+    /// Time after eating prey during which a carnivore can't eat
+    pub predation_cooldown: f32,
+    /// Time after eating food during which a creature can't eat
+    pub food_cooldown: f32,
+    /// Fraction of the world around its center that things spawn in
+    pub spawn_area_fraction: f32,
+    /// Room new food has to leave between itself and any creature
+    pub food_clearance: f32,
+    /// How long no food spawns after a famine
+    pub famine_duration: f32,
+    /// Number of overlapping creatures of the same kind that costs nothing
+    pub crowding_threshold: usize,
+    /// Hunger per second for every overlapping creature above the threshold
+    pub crowding_rate: f32,
+    /// Standard deviation of the gaussian noise added to every input
+    pub input_noise: f32,
+    /// Chance for a ray to see nothing, like a broken sensor
+    pub ray_dropout: f32,
+    /// Degrees the rays of a vegan spread over, 0.0 points them all along
+    /// the heading
+    pub vegan_fov: f32,
+    /// Degrees the rays of a carnivore spread over
+    pub carnivore_fov: f32,
+    /// Sim seconds of a day and the night after it, 0.0 for no nights
+    pub day_length: f32,
+    /// View distances are multiplied by this at night
+    pub night_view: f32,
+    /// Whether every creature's life is written to the chronicle
+    pub chronicle: bool,
+    /// Seconds between position samples in the chronicle
+    pub chronicle_interval: f32,
+    /// Only every this many frames get drawn while drawing is throttled
+    pub render_divisor: usize,
+    /// Window pixels per world unit, the one setting that isn't in world
+    /// units, it only sizes the window and places the mouse
+    pub display_scale: f32,
+    /// Age at which creatures become fully fertile
+    pub prime_age_start: f32,
+    /// Age after which fertility declines
+    pub prime_age_end: f32,
+    /// How long fertility takes to decline from full to none outside the prime
+    pub fertility_falloff: f32,
+    /// Most creatures there can be at once
+    pub max_creatures: usize,
+    pub cap_policy: CapPolicy,
+    /// Lunge output above which a carnivore lunges
+    pub lunge_threshold: f32,
+    /// Multiplies the speed of a lunging carnivore
+    pub lunge_factor: f32,
+    /// How long a lunge lasts
+    pub lunge_duration: f32,
+    /// Time after the start of a lunge before the next one
+    pub lunge_cooldown: f32,
+    /// Hunger a lunge costs
+    pub lunge_hunger: f32,
+    /// How long a carnivore digests its prey, the nutrition is fed in over
+    /// this time and no more prey gets eaten meanwhile
+    pub digestion_duration: f32,
+    /// Multiplies the speed of a digesting carnivore
+    pub digestion_speed: f32,
+    /// What vegans are ranked by
+    pub vegan_fitness: Fitness,
+    /// What carnivores are ranked by
+    pub carnivore_fitness: Fitness,
+    /// Whether fitness is divided by the number of creatures with similar
+    /// looks when ranking them
+    pub fitness_sharing: bool,
+    /// Looks distance within which creatures share their fitness
+    pub sharing_radius: f32,
+    /// Weight of life for the weighted fitness
+    pub weight_life: f32,
+    /// Weight of the number of things eaten for the weighted fitness
+    pub weight_eaten: f32,
+    /// Weight of the number of children for the weighted fitness
+    pub weight_children: f32,
+    /// Whether the food spawn rate adapts to the number of vegans
+    pub adaptive_food: bool,
+    /// Number of vegans the adaptive food spawning aims for
+    pub target_vegans: usize,
+    /// How strongly the adaptive food spawning reacts each sim-second
+    pub adaptive_gain: f32,
+    /// Lowest food spawn rate multiplier, must be above zero
+    pub min_food_multiplier: f32,
+    /// Highest food spawn rate multiplier
+    pub max_food_multiplier: f32,
+    /// Number of species creatures get clustered into
+    pub species_count: usize,
+    /// Creatures whose colors are closer than this count as kin
+    pub kin_distance: f32,
+    /// Whether founders get palette hues their lineage keeps for good
+    pub lineage_colors: bool,
+    /// Fraction of the starve budget below which a creature will share food
+    pub share_max_hunger: f32,
+    /// Fraction of the starve budget the recipient must be hungrier by
+    pub share_gap: f32,
+    /// Hunger handed over per second of contact
+    pub share_rate: f32,
+    /// Fraction of the shared hunger that actually reaches the recipient
+    pub share_efficiency: f32,
+    /// Fraction at the end of the starve budget during which creatures are
+    /// weakened
+    pub weakened_band: f32,
+    /// Speed factor a weakened creature slows down to right before starving
+    pub weakened_speed: f32,
+    /// Target for a direction that got a creature closer to food, or minus
+    /// that if it got farther, 0.0 turns this off
+    pub shaping_weight: f32,
+    /// Whether newborns are trained to imitate the fitter parent before they
+    /// start out
+    pub imprinting: bool,
+    /// Training passes over the parent's samples a newborn gets
+    pub imprint_steps: usize,
+    /// Recent inputs and picked directions every creature remembers for
+    /// imprinting
+    pub imprint_samples: usize,
+    /// Whether fresh networks get their direction biases shifted so no
+    /// direction is favored from the start, see `Network::calibrate`
+    pub calibrate_brains: bool,
+    /// Random inputs the calibration averages over
+    pub calibration_samples: usize,
+    /// Whether directions are sampled by temperature instead of always
+    /// picking the strongest output
+    pub sample_outputs: bool,
+    /// Lowest temperature a creature can evolve
+    pub min_temperature: f32,
+    /// Highest temperature a creature can evolve
+    pub max_temperature: f32,
+    /// Sim speed from which sound effects are silent, they fade out from 1x
+    pub sfx_mute_speed: f32,
+    /// Music volume during a burst of events
+    pub music_duck: f32,
+    /// What the simulation does while the window doesn't have focus
+    pub unfocused: FocusPolicy,
+    /// Updates a second while the window doesn't have focus and `unfocused`
+    /// is `throttle`, below 1 / `max_frame_delta` some time gets clamped away
+    pub unfocused_update_rate: f32,
+    /// Fraction of the music volume left while the window doesn't have focus
+    pub unfocused_volume: f32,
+    /// Length of the window bursts are detected in, in real seconds
+    pub burst_window: f32,
+    /// Number of births and deaths within the window that make a burst
+    pub burst_events: usize,
+    /// Events queued at most between two drains, past that the oldest are
+    /// dropped and counted, changes take from the next generation on
+    pub event_capacity: usize,
+    /// Generations between two checkpoints of the whole world, 0 turns them
+    /// off, see `--resume-latest`
+    pub checkpoint_interval: usize,
+    /// Checkpoints kept, older ones are removed
+    pub checkpoint_keep: usize,
+    /// Tables have to stay the last fields, toml wants them after plain values
+    pub brain: BrainConfig,
+    pub keybindings: Keybindings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mutation_chance: M_CHANCE,
+            mutation_magnitude: M_MUTATION,
+            mutation_decay: 1.0,
+            min_mutation_chance: 0.01,
+            min_mutation_magnitude: 0.02,
+            reheat_diversity: 0.0,
+            continuous: false,
+            max_vegan_life: GEN_TIME,
+            max_carnivore_life: GEN_TIME * 2.0,
+            recruit_rate: time::rate(1.0),
+            recruit_floor: 0.5,
+            enforce_ratio: false,
+            mating_threshold: 0.0,
+            mating_threshold_decay: 1.0,
+            prune_threshold: 0.0,
+            seed_bank_interval: 5,
+            seed_bank_samples: 2,
+            seed_bank_size: 32,
+            seed_revival_chance: 0.25,
+            food_timeout: FOOD_TIMEOUT,
+            metabolism: 1.0,
+            carnivore_ratio: CARNIVORE_RATIO,
+            carnivore_speed: 1.0,
+            sim_speed: 1.0,
+            seed: 0,
+            max_frame_delta: 0.25,
+            trace_frames: 300,
+            frame_budget: 0.012,
+            degrade_after: 3.0,
+            restore_after: 5.0,
+            restore_headroom: 0.75,
+            degradation_ladder: vec![Degradation::NoTraining, Degradation::HalfRays],
+            scavenge_threshold: 0.75,
+            scavenge_factor: 0.25,
+            startle_time: time::duration(2.0),
+            food_nutrition: VEGAN_NUTRITION,
+            rich_food_nutrition: VEGAN_NUTRITION * 3.0,
+            rich_food_ratio: 0.1,
+            nutrient_cycling: false,
+            recycled_fraction: 0.5,
+            pellet_nutrition: VEGAN_NUTRITION * 0.25,
+            max_pellets: 8,
+            pellet_scatter: 120.0,
+            rich_patch_count: 2,
+            rich_patch_radius: 60.0,
+            predation_cooldown: time::duration(1.5),
+            food_cooldown: time::duration(0.25),
+            spawn_area_fraction: 1.0,
+            food_clearance: 20.0,
+            famine_duration: time::duration(10.0),
+            crowding_threshold: 2,
+            crowding_rate: 0.5,
+            input_noise: 0.0,
+            ray_dropout: 0.0,
+            vegan_fov: 0.0,
+            carnivore_fov: 0.0,
+            day_length: 0.0,
+            night_view: 0.5,
+            chronicle: false,
+            chronicle_interval: 1.0,
+            render_divisor: 30,
+            display_scale: DPI_FACTOR,
+            prime_age_start: 0.0,
+            prime_age_end: GEN_TIME,
+            fertility_falloff: GEN_TIME,
+            max_creatures: 5 * CREATURE_COUNT,
+            cap_policy: CapPolicy::Skip,
+            lunge_threshold: 0.9,
+            lunge_factor: 3.0,
+            lunge_duration: time::duration(0.5),
+            lunge_cooldown: time::duration(4.0),
+            lunge_hunger: time::duration(3.0),
+            digestion_duration: time::duration(3.0),
+            digestion_speed: 0.7,
+            vegan_fitness: Fitness::Life,
+            carnivore_fitness: Fitness::Eaten,
+            fitness_sharing: false,
+            sharing_radius: 0.1,
+            weight_life: 1.0,
+            weight_eaten: 1.0,
+            weight_children: 1.0,
+            adaptive_food: false,
+            target_vegans: CREATURE_COUNT - (CREATURE_COUNT as f32 * CARNIVORE_RATIO) as usize,
+            adaptive_gain: 0.1,
+            min_food_multiplier: 0.25,
+            max_food_multiplier: 4.0,
+            species_count: 4,
+            kin_distance: 0.2,
+            lineage_colors: false,
+            share_max_hunger: 0.25,
+            share_gap: 0.5,
+            share_rate: time::rate(1.0),
+            share_efficiency: 0.8,
+            weakened_band: 0.2,
+            weakened_speed: 0.3,
+            shaping_weight: 0.1,
+            imprinting: false,
+            imprint_steps: 20,
+            imprint_samples: 32,
+            calibrate_brains: false,
+            calibration_samples: 64,
+            sample_outputs: true,
+            min_temperature: 0.0,
+            max_temperature: 1.0,
+            sfx_mute_speed: 4.0,
+            music_duck: 0.6,
+            unfocused: FocusPolicy::Pause,
+            unfocused_update_rate: 5.0,
+            unfocused_volume: 0.2,
+            burst_window: 1.0,
+            burst_events: 20,
+            event_capacity: EVENT_CAPACITY,
+            checkpoint_interval: 0,
+            checkpoint_keep: 3,
+            brain: BrainConfig::default(),
+            keybindings: Keybindings::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Falls back to the defaults if there is no config file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_over(path, Self::default())
+    }
+
+    /// Like `load`, but settings the file leaves out come from `base` instead
+    /// of the defaults, see `presets`
+    pub fn load_over<P: AsRef<Path>>(path: P, base: Self) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(base);
+        }
+
+        let encoded = fs::read_to_string(path)?;
+        let mut merged = toml::Value::try_from(&base)?;
+        overlay(&mut merged, toml::from_str(&encoded)?);
+        let config: Config = merged.try_into()?;
+        for problem in config.keybindings.problems() {
+            eprintln!("keybindings: {}", problem);
+        }
+        Ok(config)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let encoded = toml::to_string(self)?;
+        fs::write(path, &encoded)?;
+        Ok(())
+    }
+
+    /// Sets a top level field by its name in the config file, `value` is
+    /// parsed as whatever type the field already has
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let mut table = match toml::Value::try_from(&*self)? {
+            toml::Value::Table(table) => table,
+            _ => return Err(Error::Serde("config isn't a table".to_owned())),
+        };
+        let field = table
+            .get_mut(name)
+            .ok_or_else(|| Error::Config(format!("no setting called {}", name)))?;
+        let bad = || Error::Config(format!("{} isn't a valid value for {}", value, name));
+        *field = match field {
+            toml::Value::Float(_) => toml::Value::Float(value.parse().map_err(|_| bad())?),
+            toml::Value::Integer(_) => toml::Value::Integer(value.parse().map_err(|_| bad())?),
+            toml::Value::Boolean(_) => toml::Value::Boolean(value.parse().map_err(|_| bad())?),
+            toml::Value::String(_) => toml::Value::String(value.to_owned()),
+            _ => {
+                return Err(Error::Config(format!(
+                    "{} can only be set in {}",
+                    name, CONFIG_PATH
+                )))
+            }
+        };
+        *self = toml::Value::Table(table).try_into().map_err(|err| {
+            Error::Config(format!(
+                "{} isn't a valid value for {}: {}",
+                value, name, err
+            ))
+        })?;
+        Ok(())
+    }
+}
+
+/// Writes everything in `top` over `bottom`, tables in both get merged
+/// field by field
+fn overlay(bottom: &mut toml::Value, top: toml::Value) {
+    match (bottom, top) {
+        (toml::Value::Table(bottom), toml::Value::Table(top)) => {
+            for (key, value) in top {
+                match bottom.get_mut(&key) {
+                    Some(field) => overlay(field, value),
+                    None => {
+                        bottom.insert(key, value);
+                    }
+                }
+            }
+        }
+        (bottom, top) => *bottom = top,
+    }
+}
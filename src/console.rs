@@ -0,0 +1,272 @@
+use ggez::graphics::{self, Color, DrawMode, DrawParam, Mesh, Rect, Text};
+use ggez::{Context, GameResult};
+
+use nalgebra::Vector2;
+
+use crate::creature::Kind;
+use crate::draw;
+use crate::{WIDTH, WORLD_PATH};
+
+/// Lines of output the console keeps, older ones get dropped
+pub const CONSOLE_LINES: usize = 12;
+
+/// Everything that can be typed into the console
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `spawn food <count>`
+    SpawnFood(usize),
+    /// `spawn vegan|carnivore <count> [at <x> <y>]`, random spots if there's
+    /// no position
+    SpawnCreatures {
+        kind: Kind,
+        count: usize,
+        at: Option<Vector2<f32>>,
+    },
+    /// `kill <id>`
+    Kill(usize),
+    /// `set <name> <value>`, see `Config::set`
+    Set(String, String),
+    /// `save`
+    Save,
+    /// `save world [<path>]`, `WORLD_PATH` if there's no path
+    SaveWorld(String),
+    /// `stats`
+    Stats,
+    /// `select <id>`
+    Select(usize),
+}
+
+fn number<T: std::str::FromStr>(word: Option<&str>, what: &str) -> Result<T, String> {
+    let word = word.ok_or_else(|| format!("missing {}", what))?;
+    word.parse()
+        .map_err(|_| format!("{} isn't a valid {}", word, what))
+}
+
+/// Parses a line typed into the console
+pub fn parse(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some("spawn") => {
+            let kind = match words.next() {
+                Some("food") => None,
+                Some("vegan") => Some(Kind::Vegan),
+                Some("carnivore") => Some(Kind::Carnivorous),
+                Some(other) => return Err(format!("can't spawn {}", other)),
+                None => return Err("spawn what? food, vegan or carnivore".to_owned()),
+            };
+            let count = number(words.next(), "count")?;
+            match kind {
+                None => Command::SpawnFood(count),
+                Some(kind) => {
+                    let at = match words.next() {
+                        Some("at") => Some(Vector2::new(
+                            number(words.next(), "x")?,
+                            number(words.next(), "y")?,
+                        )),
+                        Some(other) => return Err(format!("expected at, got {}", other)),
+                        None => None,
+                    };
+                    Command::SpawnCreatures { kind, count, at }
+                }
+            }
+        }
+        Some("kill") => Command::Kill(number(words.next(), "id")?),
+        Some("set") => {
+            let name = words.next().ok_or("missing setting")?;
+            let value = words.next().ok_or("missing value")?;
+            Command::Set(name.to_owned(), value.to_owned())
+        }
+        Some("save") => match words.next() {
+            Some("world") => Command::SaveWorld(words.next().unwrap_or(WORLD_PATH).to_owned()),
+            Some(other) => return Err(format!("can't save {}", other)),
+            None => Command::Save,
+        },
+        Some("stats") => Command::Stats,
+        Some("select") => Command::Select(number(words.next(), "id")?),
+        Some(other) => return Err(format!("unknown command {}", other)),
+        None => return Err("empty command".to_owned()),
+    };
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected {}", extra));
+    }
+    Ok(command)
+}
+
+/// Drop-down console at the top of the screen
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Console {
+    pub open: bool,
+    /// What's being typed
+    pub line: String,
+    /// Commands typed so far, oldest first
+    pub history: Vec<String>,
+    /// Position in `history` while going through it with up and down
+    pub recall: Option<usize>,
+    /// Commands and their results
+    pub output: Vec<String>,
+}
+
+impl Console {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Adds a line of output
+    pub fn print<S: Into<String>>(&mut self, line: S) {
+        if self.output.len() >= CONSOLE_LINES {
+            self.output.remove(0);
+        }
+        self.output.push(line.into());
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        self.line.push(c);
+    }
+
+    pub fn backspace(&mut self) {
+        self.line.pop();
+    }
+
+    /// Takes the typed line and remembers it, `None` if nothing was typed
+    pub fn submit(&mut self) -> Option<String> {
+        self.recall = None;
+        let line = std::mem::take(&mut self.line);
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        self.print(format!("> {}", line));
+        self.history.push(line.clone());
+        Some(line)
+    }
+
+    /// Brings back the command typed before the one shown
+    pub fn older(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let i = match self.recall {
+            Some(i) => i.saturating_sub(1),
+            None => self.history.len() - 1,
+        };
+        self.recall = Some(i);
+        self.line = self.history[i].clone();
+    }
+
+    /// Brings back the command typed after the one shown, or an empty line
+    /// after the last one
+    pub fn newer(&mut self) {
+        match self.recall {
+            Some(i) if i + 1 < self.history.len() => {
+                self.recall = Some(i + 1);
+                self.line = self.history[i + 1].clone();
+            }
+            Some(_) => {
+                self.recall = None;
+                self.line.clear();
+            }
+            None => {}
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        draw::screen_view(ctx)?;
+        const MARGIN: f32 = 4.0;
+
+        let mut lines = self.output.clone();
+        lines.push(format!("> {}_", self.line));
+        let text = Text::new(lines.join("\n"));
+        let (_, h) = text.dimensions(ctx);
+        let rect = Rect::new(0.0, 0.0, WIDTH, h as f32 + 2.0 * MARGIN);
+        let background =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), rect, Color::new(0.0, 0.0, 0.0, 0.85))?;
+        graphics::draw(ctx, &background, DrawParam::new())?;
+        graphics::draw(ctx, &text, DrawParam::new().dest([MARGIN, MARGIN]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_command_parses() {
+        let commands = [
+            ("spawn food 5", Command::SpawnFood(5)),
+            (
+                "spawn vegan 2",
+                Command::SpawnCreatures {
+                    kind: Kind::Vegan,
+                    count: 2,
+                    at: None,
+                },
+            ),
+            (
+                "  spawn carnivore 1 at 10 20.5 ",
+                Command::SpawnCreatures {
+                    kind: Kind::Carnivorous,
+                    count: 1,
+                    at: Some(Vector2::new(10.0, 20.5)),
+                },
+            ),
+            ("kill 7", Command::Kill(7)),
+            (
+                "set sim_speed 2",
+                Command::Set("sim_speed".to_owned(), "2".to_owned()),
+            ),
+            ("save", Command::Save),
+            ("save world", Command::SaveWorld(WORLD_PATH.to_owned())),
+            (
+                "save world there.bin",
+                Command::SaveWorld("there.bin".to_owned()),
+            ),
+            ("stats", Command::Stats),
+            ("select 3", Command::Select(3)),
+        ];
+        for (line, command) in commands.iter() {
+            assert_eq!(parse(line).as_ref(), Ok(command), "{}", line);
+        }
+    }
+
+    #[test]
+    fn bad_lines_say_whats_wrong() {
+        let errors = [
+            ("", "empty command"),
+            ("jump", "unknown command jump"),
+            ("spawn", "spawn what? food, vegan or carnivore"),
+            ("spawn trees 3", "can't spawn trees"),
+            ("spawn food", "missing count"),
+            ("spawn food lots", "lots isn't a valid count"),
+            ("spawn vegan 1 near 3 4", "expected at, got near"),
+            ("spawn vegan 1 at 3", "missing y"),
+            ("kill -1", "-1 isn't a valid id"),
+            ("set sim_speed", "missing value"),
+            ("save elites", "can't save elites"),
+            ("stats now", "unexpected now"),
+        ];
+        for (line, error) in errors.iter() {
+            assert_eq!(parse(line), Err(error.to_string()), "{}", line);
+        }
+    }
+
+    #[test]
+    fn history_goes_both_ways() {
+        let mut console = Console::default();
+        for line in &["stats", "   ", "kill 1"] {
+            line.chars().for_each(|c| console.type_char(c));
+            console.submit();
+        }
+        assert_eq!(console.history, vec!["stats", "kill 1"]);
+        assert_eq!(console.output, vec!["> stats", "> kill 1"]);
+
+        console.older();
+        assert_eq!(console.line, "kill 1");
+        console.older();
+        console.older();
+        assert_eq!(console.line, "stats");
+        console.newer();
+        assert_eq!(console.line, "kill 1");
+        console.newer();
+        assert_eq!((console.line.as_str(), console.recall), ("", None));
+    }
+}
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::creature::Kind;
+use crate::data::{Entity, GameData};
+use crate::save;
+use crate::{CREATURE_COUNT, TOP_COUNT};
+
+/// Life after which a creature of `kind` retires in continuous mode
+pub fn max_life(config: &Config, kind: Kind) -> f32 {
+    match kind {
+        Kind::Vegan => config.max_vegan_life,
+        Kind::Carnivorous => config.max_carnivore_life,
+    }
+}
+
+/// Offers a creature to the hall of fame in continuous mode, does nothing
+/// otherwise
+///
+/// Has to be called on every death, before the creature is deleted.
+pub fn enshrine(data: &mut GameData, e: Entity) {
+    if data.config.continuous {
+        let saved = save::saved(data, e);
+        data.hall_of_fame.offer(saved, TOP_COUNT, &data.config);
+    }
+}
+
+/// Creatures of `kind` every generation starts with
+pub fn quota(config: &Config, kind: Kind) -> usize {
+    let carnivores = (CREATURE_COUNT as f32 * config.carnivore_ratio) as usize;
+    match kind {
+        Kind::Vegan => CREATURE_COUNT - carnivores,
+        Kind::Carnivorous => carnivores,
+    }
+}
+
+/// Fewest creatures of `kind` recruiting keeps around in continuous mode,
+/// the whole quota with `enforce_ratio`
+pub fn floor(config: &Config, kind: Kind) -> usize {
+    let quota = quota(config, kind);
+    if config.enforce_ratio {
+        return quota;
+    }
+    (quota as f32 * config.recruit_floor).ceil() as usize
+}
+
+/// How many of the `count` creatures of `kind` have to retire to keep it at
+/// its quota, none unless `enforce_ratio` is on
+pub fn surplus(config: &Config, kind: Kind, count: usize) -> usize {
+    if !config.enforce_ratio {
+        return 0;
+    }
+    count.saturating_sub(quota(config, kind))
+}
+
+/// Trickles fresh creatures into kinds that fell below their floor
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Recruiter {
+    vegans: f32,
+    carnivores: f32,
+}
+
+impl Recruiter {
+    /// How many recruits of `kind` are due after `delta`, none while there
+    /// are `count` creatures of it and that's at least the floor
+    pub fn due(&mut self, config: &Config, kind: Kind, count: usize, delta: f32) -> usize {
+        let timer = match kind {
+            Kind::Vegan => &mut self.vegans,
+            Kind::Carnivorous => &mut self.carnivores,
+        };
+        let missing = floor(config, kind).saturating_sub(count);
+        if missing == 0 {
+            *timer = 0.0;
+            return 0;
+        }
+
+        *timer += delta * config.recruit_rate;
+        let due = (*timer as usize).min(missing);
+        // whatever couldn't be recruited doesn't pile up
+        *timer = (*timer - due as f32).min(1.0);
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chronicle::Cause;
+    use crate::creature::Creature;
+    use crate::GameState;
+
+    fn continuous() -> GameState {
+        let config = Config {
+            seed: 17,
+            chronicle: false,
+            frame_budget: 0.0,
+            continuous: true,
+            ..Config::default()
+        };
+        let mut game = GameState::empty(0, config);
+        game.populate_with(Vec::new());
+        game
+    }
+
+    #[test]
+    fn floors_are_a_share_of_the_starting_population() {
+        let config = Config {
+            carnivore_ratio: 0.1,
+            recruit_floor: 0.25,
+            ..Config::default()
+        };
+        assert_eq!(floor(&config, Kind::Vegan), 23);
+        assert_eq!(floor(&config, Kind::Carnivorous), 3);
+        let config = Config {
+            recruit_floor: 0.0,
+            ..config
+        };
+        assert_eq!(floor(&config, Kind::Vegan), 0);
+    }
+
+    #[test]
+    fn recruits_trickle_in_below_the_floor() {
+        let config = Config {
+            carnivore_ratio: 0.1,
+            recruit_floor: 0.5,
+            recruit_rate: 2.0,
+            ..Config::default()
+        };
+        let mut recruiter = Recruiter::default();
+        // at the floor nothing is due, however long it's been
+        assert_eq!(recruiter.due(&config, Kind::Carnivorous, 5, 100.0), 0);
+
+        assert_eq!(recruiter.due(&config, Kind::Carnivorous, 2, 0.25), 0);
+        assert_eq!(recruiter.due(&config, Kind::Carnivorous, 2, 0.25), 1);
+        // never more than are missing, and the rest doesn't pile up
+        assert_eq!(recruiter.due(&config, Kind::Carnivorous, 2, 10.0), 3);
+        assert_eq!(recruiter.due(&config, Kind::Carnivorous, 2, 0.25), 1);
+        // the kinds keep their own time
+        assert_eq!(recruiter.due(&config, Kind::Vegan, 45, 0.25), 0);
+    }
+
+    #[test]
+    fn the_old_retire_into_the_hall_of_fame() {
+        let mut game = continuous();
+        let e = game.creatures[0];
+        let kind = game.data[e.component::<Creature>()].kind;
+        game.data[e.component::<Creature>()].life = max_life(&game.data.config, kind) + 1.0;
+        game.step(1.0 / 60.0).unwrap();
+
+        assert!(!game.creatures.contains(&e));
+        let survived = match kind {
+            Kind::Vegan => game.data.mortality.vegans,
+            Kind::Carnivorous => game.data.mortality.carnivores,
+        };
+        assert_eq!(survived[Cause::Survived.index()], 1);
+        let hall = match kind {
+            Kind::Vegan => &game.data.hall_of_fame.vegans,
+            Kind::Carnivorous => &game.data.hall_of_fame.carnivores,
+        };
+        assert_eq!(hall.len(), 1);
+        assert!(hall[0].0.life > max_life(&game.data.config, kind));
+    }
+
+    #[test]
+    fn the_hall_of_fame_keeps_the_best_dead() {
+        let mut game = continuous();
+        let vegans: Vec<_> = game
+            .creatures
+            .iter()
+            .copied()
+            .filter(|e| game.data[e.component::<Creature>()].kind == Kind::Vegan)
+            .take(TOP_COUNT + 5)
+            .collect();
+        for (i, &e) in vegans.iter().enumerate() {
+            // ranks by `i` whichever fitness vegans use
+            let creature = &mut game.data[e.component::<Creature>()];
+            creature.life = i as f32;
+            creature.eaten = i;
+            creature.children = i;
+            enshrine(&mut game.data, e);
+        }
+        let eaten: Vec<_> = game
+            .data
+            .hall_of_fame
+            .vegans
+            .iter()
+            .map(|(creature, _, _)| creature.eaten)
+            .collect();
+        let best: Vec<_> = (5..TOP_COUNT + 5).rev().collect();
+        assert_eq!(eaten, best);
+        assert!(game.data.hall_of_fame.carnivores.is_empty());
+
+        // outside continuous mode deaths don't go anywhere
+        game.data.config.continuous = false;
+        game.data.hall_of_fame = Default::default();
+        enshrine(&mut game.data, vegans[0]);
+        assert!(game.data.hall_of_fame.vegans.is_empty());
+    }
+
+    #[test]
+    fn deaths_count_towards_the_epoch_they_happened_in() {
+        let mut game = continuous();
+        game.data.mortality.record(Kind::Vegan, Cause::Starved);
+        game.time = 1.0;
+        game.next_epoch();
+        assert_eq!(game.generation, 1);
+        assert_eq!(game.data.mortality, Default::default());
+        assert_eq!(game.data.chronicle.generation, 1);
+
+        let e = game.creatures[0];
+        game.kill(e).unwrap();
+        let deaths: usize = game.data.mortality.deaths().iter().map(|(_, n)| n).sum();
+        assert_eq!(deaths, 1);
+    }
+}
@@ -1,32 +1,105 @@
-use ggez::{Context, GameResult};
+use ggez::graphics::Color;
 
 use nalgebra::Vector2;
 
-use rand::random;
+use rand::Rng;
 
 use serde::{Deserialize, Serialize};
 
-use crate::collision::Body;
-use crate::data::{Entity, GameData, Insert};
-use crate::draw::Draw;
-use crate::mutate::Mutate;
-use crate::nn::{Desired, Inputs, Network, Outputs};
-use crate::TIME_FACTOR;
+use crate::brain;
+use crate::chronicle::{self, Cause};
+use crate::config::{CapPolicy, Config};
+use crate::continuous;
+use crate::data::{Entity, GameData};
+use crate::events::GameEvent;
+use crate::mutate::{self, Mutate, Rates};
+use crate::nn::{self, Experience, Network};
+use crate::nutrients;
+use crate::resources::SimRng;
+use crate::sanitize::MIN_MASS;
+use crate::selection;
+use crate::senses::VIEW_DISTANCE;
+use crate::spawn;
+use crate::species;
+use crate::time::{self, SimTime};
+use crate::{CARNIVORE_MAX_RADIUS, CARNIVORE_MIN_RADIUS, VEGAN_MAX_RADIUS, VEGAN_MIN_RADIUS};
 
 pub const VEGAN_CHILDREN: f32 = 3.0;
-pub const VEGAN_TIMEOUT: f32 = 15.0 / TIME_FACTOR;
+pub const VEGAN_TIMEOUT: f32 = time::duration(15.0);
 pub const CARNIVORE_CHILDREN: f32 = 1.0;
-pub const CARNIVORE_TIMEOUT: f32 = 40.0 / TIME_FACTOR;
-pub const FOV_2: f32 = 180.0;
+pub const CARNIVORE_TIMEOUT: f32 = time::duration(40.0);
 pub const RAY_COUNT: usize = 8;
+/// Inputs per ray: what was seen, how far away and how fast it's receding
+pub const RAY_CHANNELS: usize = 3;
+/// The ray inputs and then the self-state inputs
+pub const INPUT_COUNT: usize = RAY_COUNT * RAY_CHANNELS + 4;
+/// Index of the input telling a creature it was recently hit
+pub const STARTLE_INPUT: usize = RAY_COUNT * RAY_CHANNELS;
+/// Index of the input telling a creature it is about to starve
+pub const WEAKENED_INPUT: usize = RAY_COUNT * RAY_CHANNELS + 1;
+/// Index of the input telling a creature how much of its lunge is left
+pub const LUNGE_INPUT: usize = RAY_COUNT * RAY_CHANNELS + 2;
+/// Index of the input telling a carnivore how much of its digestion is left
+pub const DIGESTION_INPUT: usize = RAY_COUNT * RAY_CHANNELS + 3;
 pub const DIR_COUNT: usize = 16;
-pub const VEGAN_STARVE: f32 = 180.0 / TIME_FACTOR;
-pub const CARNIVORE_STARVE: f32 = 60.0 / TIME_FACTOR;
+/// Index of the output carnivores lunge with, right after the directions
+pub const LUNGE_OUTPUT: usize = DIR_COUNT;
+pub const VEGAN_STARVE: f32 = time::duration(180.0);
+pub const CARNIVORE_STARVE: f32 = time::duration(60.0);
 pub const VEGAN_NUTRITION: f32 = 2.0;
 pub const CARNIVORE_NUTRITION: f32 = 3.0;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Food;
+/// Number of evenly spaced hues founders of a lineage get
+pub const LINEAGE_PALETTE: usize = 12;
+
+/// Temperature creatures start out with before it evolves
+pub const TEMPERATURE: f32 = 0.1;
+
+/// Rich food stands out from the gray plain food
+pub const RICH_FOOD_COLOR: Color = Color {
+    r: 1.0,
+    g: 0.84,
+    b: 0.0,
+    a: 1.0,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Food {
+    /// How much hunger eating this takes away
+    pub nutrition: f32,
+    /// Rich food is rarer and only spawns in patches
+    pub rich: bool,
+}
+
+impl Food {
+    pub fn plain(config: &Config) -> Self {
+        Self {
+            nutrition: config.food_nutrition,
+            rich: false,
+        }
+    }
+
+    pub fn rich(config: &Config) -> Self {
+        Self {
+            nutrition: config.rich_food_nutrition,
+            rich: true,
+        }
+    }
+}
+
+/// What a creature is judged by when picking the best of a generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fitness {
+    /// Time spent living
+    Life,
+    /// Number of things eaten
+    Eaten,
+    /// Number of children had
+    Children,
+    /// Weighted sum of the others, using the weights from the config
+    Weighted,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Kind {
@@ -47,26 +120,328 @@ pub struct Creature {
     pub timeout: f32,
     /// This is the time spent living
     pub life: f32,
+    /// How many times this creature has eaten
+    pub eaten: usize,
+    /// How many children this creature has had
+    pub children: usize,
+    /// Cluster this creature was put in by `species::cluster`
+    pub species: usize,
+    /// Counts down from `startle_time` after a predator bumps into this creature
+    pub last_hit: f32,
+    /// Distance to the closest edible food seen last step, infinite if none
+    pub food_distance: f32,
+    /// Counts down after eating, nothing else gets eaten until it's gone
+    pub eat_cooldown: f32,
+    /// Number of creatures of the same kind overlapping this one
+    pub crowding: usize,
+    /// Counts down from `lunge_duration` while a carnivore lunges
+    pub lunge: f32,
+    /// Counts down after a lunge, the next one can't start until it's gone
+    pub lunge_cooldown: f32,
+    /// Counts down from `digestion_duration` after a carnivore eats prey
+    pub digestion: f32,
+    /// Nutrition of the prey being digested that hasn't been fed in yet
+    pub nutrition: f32,
+    /// Nutrition taken in over its whole life, see `nutrients::recycle`
+    pub consumed: f32,
+    /// Creatures of the same kind within `sharing_radius` when this one was
+    /// last ranked by `species::share`, counting itself
+    pub niche: usize,
 }
 
 impl Creature {
     pub fn new(kind: Kind) -> Self {
-        let timeout = match kind {
-            Kind::Vegan => VEGAN_TIMEOUT,
-            Kind::Carnivorous => CARNIVORE_TIMEOUT,
-        };
-
         Self {
             kind,
             hunger: 0.0,
-            timeout,
+            timeout: SimTime::mate_timeout(kind),
             life: 0.0,
+            eaten: 0,
+            children: 0,
+            species: 0,
+            last_hit: 0.0,
+            food_distance: f32::INFINITY,
+            eat_cooldown: 0.0,
+            crowding: 0,
+            lunge: 0.0,
+            lunge_cooldown: 0.0,
+            digestion: 0.0,
+            nutrition: 0.0,
+            consumed: 0.0,
+            niche: 1,
+        }
+    }
+
+    /// Hunger at which this creature starves to death
+    pub fn starve(&self) -> f32 {
+        SimTime::starve(self.kind)
+    }
+
+    /// Desperate carnivores will eat plain food
+    pub fn desperate(&self, config: &Config) -> bool {
+        self.kind == Kind::Carnivorous && self.hunger > self.starve() * config.scavenge_threshold
+    }
+
+    /// Extra hunger per second from being crowded by too many of its kind
+    pub fn crowding_hunger(&self, config: &Config) -> f32 {
+        self.crowding.saturating_sub(config.crowding_threshold) as f32 * config.crowding_rate
+    }
+
+    /// How far into the weakened band this creature is, from 0.0 before it
+    /// to 1.0 when it starves
+    pub fn weakness(&self, config: &Config) -> f32 {
+        let band = self.starve() * config.weakened_band;
+        if band <= 0.0 {
+            return 0.0;
+        }
+        let start = self.starve() - band;
+        ((self.hunger - start) / band).clamp(0.0, 1.0)
+    }
+
+    /// Multiplies the number of children, 1.0 within the prime age and
+    /// falling to 0.0 over `fertility_falloff` on either side of it
+    pub fn fertility(&self, config: &Config) -> f32 {
+        let outside = if self.life < config.prime_age_start {
+            config.prime_age_start - self.life
+        } else {
+            (self.life - config.prime_age_end).max(0.0)
+        };
+        if outside <= 0.0 {
+            return 1.0;
+        }
+        if config.fertility_falloff <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - outside / config.fertility_falloff).max(0.0)
+    }
+
+    /// Whether a lunge can start, only carnivores ever lunge
+    pub fn can_lunge(&self) -> bool {
+        self.kind == Kind::Carnivorous && self.lunge <= 0.0 && self.lunge_cooldown <= 0.0
+    }
+
+    /// Starts a lunge, which is paid for in hunger right away
+    pub fn start_lunge(&mut self, config: &Config) {
+        self.lunge = config.lunge_duration;
+        self.lunge_cooldown = config.lunge_cooldown;
+        self.hunger += config.lunge_hunger;
+    }
+
+    /// Whether prey is still being digested, no more prey gets eaten until
+    /// it's done
+    pub fn digesting(&self) -> bool {
+        self.digestion > 0.0
+    }
+
+    /// Starts digesting prey, its nutrition is fed in over `duration`
+    /// instead of all at once
+    pub fn start_digesting(&mut self, duration: f32, nutrition: f32) {
+        self.digestion = duration;
+        self.nutrition += nutrition;
+        if !self.digesting() {
+            self.hunger -= self.nutrition;
+            self.nutrition = 0.0;
+        }
+    }
+
+    /// Feeds in the share of the nutrition that's due `delta` seconds later,
+    /// whatever is left goes in when the digestion ends
+    pub fn digest(&mut self, delta: f32) {
+        if !self.digesting() {
+            return;
+        }
+
+        let step = delta.min(self.digestion);
+        let fed = self.nutrition * step / self.digestion;
+        self.hunger -= fed;
+        self.nutrition -= fed;
+        self.digestion -= step;
+        if !self.digesting() {
+            self.hunger -= self.nutrition;
+            self.nutrition = 0.0;
+        }
+    }
+
+    /// Multiplies the speed of this creature, weakened and digesting
+    /// creatures slow down, lunging ones speed up and carnivores go at
+    /// `carnivore_speed`
+    pub fn speed_factor(&self, config: &Config) -> f32 {
+        let lunge = if self.lunge > 0.0 {
+            config.lunge_factor
+        } else {
+            1.0
+        };
+        let digestion = if self.digesting() {
+            config.digestion_speed
+        } else {
+            1.0
+        };
+        let kind = match self.kind {
+            Kind::Vegan => 1.0,
+            Kind::Carnivorous => config.carnivore_speed,
+        };
+        (1.0 - self.weakness(config) * (1.0 - config.weakened_speed)) * lunge * digestion * kind
+    }
+
+    /// Used to rank creatures of the same kind against each other
+    pub fn fitness(&self, config: &Config) -> f32 {
+        let metric = match self.kind {
+            Kind::Vegan => config.vegan_fitness,
+            Kind::Carnivorous => config.carnivore_fitness,
+        };
+
+        match metric {
+            Fitness::Life => self.life,
+            Fitness::Eaten => self.eaten as f32,
+            Fitness::Children => self.children as f32,
+            Fitness::Weighted => self.weighted(config),
+        }
+    }
+
+    /// Fitness divided by the size of the niche, so crowded strategies rank
+    /// lower than rare ones
+    pub fn shared_fitness(&self, config: &Config) -> f32 {
+        self.fitness(config) / self.niche.max(1) as f32
+    }
+
+    /// Weighted sum of life, things eaten and children
+    pub fn weighted(&self, config: &Config) -> f32 {
+        self.life * config.weight_life
+            + self.eaten as f32 * config.weight_eaten
+            + self.children as f32 * config.weight_children
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Color")]
+pub(crate) struct ColorDef {
+    r: f32,
+    g: f32,
+    b: f32,
+    a: f32,
+}
+
+/// The heritable traits of a creature
+///
+/// Should be stored in an array of structs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Genome {
+    pub radius: f32,
+    pub mass: f32,
+    pub restitution: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+    /// Top speed of the creature
+    pub speed: f32,
+    /// How far the rays of the creature reach
+    pub view_distance: f32,
+    /// How randomly this creature picks a direction from its outputs,
+    /// 0.0 always picks the strongest one
+    pub temperature: f32,
+    /// Hue passed down unchanged from the founder of this lineage, only with
+    /// `lineage_colors`
+    pub lineage_hue: Option<f32>,
+}
+
+impl Genome {
+    /// A fresh genome for a creature of the first generation
+    pub fn random<R: Rng>(kind: Kind, rng: &mut R) -> Self {
+        let speed = SimTime::top_speed(kind);
+        let (min_radius, max_radius, color) = match kind {
+            Kind::Vegan => (
+                VEGAN_MIN_RADIUS,
+                VEGAN_MAX_RADIUS,
+                Color::new(0.0, rng.gen::<f32>(), rng.gen::<f32>() * 0.2, 1.0),
+            ),
+            Kind::Carnivorous => (
+                CARNIVORE_MIN_RADIUS,
+                CARNIVORE_MAX_RADIUS,
+                Color::new(rng.gen::<f32>(), 0.0, rng.gen::<f32>() * 0.2, 1.0),
+            ),
+        };
+
+        Self {
+            radius: min_radius + rng.gen::<f32>() * (max_radius - min_radius),
+            mass: rng.gen::<f32>(),
+            restitution: rng.gen::<f32>(),
+            color,
+            speed,
+            view_distance: VIEW_DISTANCE,
+            temperature: TEMPERATURE,
+            lineage_hue: None,
+        }
+    }
+
+    /// Makes this the genome of the `index`th founder of a lineage, which
+    /// gets its hue from the palette
+    pub fn founder(self, index: usize) -> Self {
+        let hue = (index % LINEAGE_PALETTE) as f32 / LINEAGE_PALETTE as f32;
+        Self {
+            color: mutate::from_hsv(hue, 0.8, 0.9),
+            lineage_hue: Some(hue),
+            ..self
+        }
+    }
+
+    /// Mixes the genomes of two parents and mutates the result
+    pub fn cross<R: Rng>(&self, other: &Self, config: &Config, rates: Rates, rng: &mut R) -> Self {
+        let Rates { chance, magnitude } = rates;
+        let temperature = self
+            .temperature
+            .mutate(&other.temperature, M_FACTOR, chance, magnitude, rng)
+            .max(config.min_temperature)
+            .min(config.max_temperature);
+        // children take the hue of the first parent, so mating across
+        // lineages doesn't muddy them
+        let color = match self.lineage_hue {
+            Some(hue) => mutate::mutate_shade(
+                self.color,
+                other.color,
+                hue,
+                M_FACTOR,
+                chance,
+                magnitude,
+                rng,
+            ),
+            None => self
+                .color
+                .mutate(&other.color, M_FACTOR, chance, magnitude, rng),
+        };
+        Self {
+            radius: self
+                .radius
+                .mutate(&other.radius, M_FACTOR, chance, magnitude, rng),
+            // a big enough mutation can flip the sign, which breaks impulses
+            mass: self
+                .mass
+                .mutate(&other.mass, M_FACTOR, chance, magnitude, rng)
+                .max(MIN_MASS),
+            restitution: self.restitution.mutate(
+                &other.restitution,
+                M_FACTOR,
+                chance,
+                magnitude,
+                rng,
+            ),
+            color,
+            speed: self
+                .speed
+                .mutate(&other.speed, M_FACTOR, chance, magnitude, rng),
+            view_distance: self.view_distance.mutate(
+                &other.view_distance,
+                M_FACTOR,
+                chance,
+                magnitude,
+                rng,
+            ),
+            temperature,
+            lineage_hue: self.lineage_hue,
         }
     }
 }
 
 /// Should be stored in an array of structs
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub position: Vector2<f32>,
 }
@@ -80,7 +455,7 @@ impl Position {
 }
 
 /// Should be stored in an array of structs
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Velocity {
     pub velocity: Vector2<f32>,
 }
@@ -94,7 +469,7 @@ impl Velocity {
 }
 
 /// Should be stored in an array of structs
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Direction {
     pub direction: f32,
 }
@@ -105,15 +480,163 @@ impl Direction {
     }
 }
 
+/// Euclidean distance between two colors, ignoring alpha
+pub fn color_distance(a: Color, b: Color) -> f32 {
+    let (dr, dg, db) = (a.r - b.r, a.g - b.g, a.b - b.b);
+    (dr * dr + dg * dg + db * db).sqrt()
+}
+
+/// How far apart two genomes are for kinship, only their hues count for
+/// lineages since shades drift
+pub fn kin_distance(a: &Genome, b: &Genome) -> f32 {
+    match (a.lineage_hue, b.lineage_hue) {
+        (Some(a), Some(b)) => {
+            let d = (a - b).abs();
+            d.min(1.0 - d)
+        }
+        _ => color_distance(a.color, b.color),
+    }
+}
+
+/// Difference of two values relative to their mean
+fn relative_difference(a: f32, b: f32) -> f32 {
+    let mean = (a.abs() + b.abs()) * 0.5;
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    (a - b).abs() / mean
+}
+
+/// How far apart two parents are for mating, from their bodies, hues and a
+/// sample of their network weights
+pub fn mating_distance(a: &Genome, b: &Genome, brain_a: &Network, brain_b: &Network) -> f32 {
+    let (hue_a, _, _) = mutate::hsv(a.color);
+    let (hue_b, _, _) = mutate::hsv(b.color);
+    let hue = (hue_a - hue_b).abs();
+    // hues wrap around, so they're at most half a turn apart
+    let hue = hue.min(1.0 - hue) * 2.0;
+    MATING_RADIUS_WEIGHT * relative_difference(a.radius, b.radius)
+        + MATING_SPEED_WEIGHT * relative_difference(a.speed, b.speed)
+        + MATING_VIEW_WEIGHT * relative_difference(a.view_distance, b.view_distance)
+        + MATING_HUE_WEIGHT * hue
+        + MATING_BRAIN_WEIGHT * brain_a.weight_distance(brain_b, MATING_WEIGHT_STRIDE)
+}
+
+/// Lets the less hungry of two similar looking creatures of the same kind
+/// feed the other one, some of the food is lost on the way
+pub fn share(data: &mut GameData, a: Entity, b: Entity, delta: f32) {
+    let (donor, recipient) =
+        if data[a.component::<Creature>()].hunger < data[b.component::<Creature>()].hunger {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+    let d = data[donor.component::<Creature>()];
+    let r = data[recipient.component::<Creature>()];
+    let config = &data.config;
+    if d.kind != r.kind
+        || d.hunger > d.starve() * config.share_max_hunger
+        || r.hunger - d.hunger < r.starve() * config.share_gap
+    {
+        return;
+    }
+
+    // kinship is about the genes, not about how the creature is drawn
+    let distance = kin_distance(
+        &data[donor.component::<Genome>()],
+        &data[recipient.component::<Genome>()],
+    );
+    if distance > config.kin_distance {
+        return;
+    }
+
+    let amount = config.share_rate * delta;
+    let received = amount * config.share_efficiency;
+    data[donor.component::<Creature>()].hunger += amount;
+    data[recipient.component::<Creature>()].hunger -= received;
+    data.events.push(GameEvent::Shared {
+        donor,
+        recipient,
+        amount,
+    });
+}
+
+/// Weights of the parts of `mating_distance`
+pub const MATING_RADIUS_WEIGHT: f32 = 1.0;
+pub const MATING_SPEED_WEIGHT: f32 = 1.0;
+pub const MATING_VIEW_WEIGHT: f32 = 1.0;
+pub const MATING_HUE_WEIGHT: f32 = 2.0;
+pub const MATING_BRAIN_WEIGHT: f32 = 1.0;
+/// Only every this many network weights are compared by `mating_distance`
+pub const MATING_WEIGHT_STRIDE: usize = 16;
+
 pub const M_FACTOR: f32 = 0.5;
 pub const M_CHANCE: f32 = 0.05;
 pub const M_MUTATION: f32 = 0.10;
 
-pub fn mate(ctx: &mut Context, data: &mut GameData, a: Entity, b: Entity) -> GameResult<()> {
-    let timeout = match data[a.component::<Creature>()].kind {
-        Kind::Vegan => VEGAN_TIMEOUT,
-        Kind::Carnivorous => CARNIVORE_TIMEOUT,
-    };
+/// How many of `children` fit under `max_creatures`, with `CapPolicy::Cull`
+/// the least fit creatures other than the parents die to fit more
+fn make_room(data: &mut GameData, a: Entity, b: Entity, children: usize) -> usize {
+    let count = data.creature_count();
+    let over = (count + children).saturating_sub(data.config.max_creatures);
+    if over == 0 {
+        return children;
+    }
+
+    let mut culled = 0;
+    if data.config.cap_policy == CapPolicy::Cull {
+        let candidates: Vec<_> = data
+            .living_creatures()
+            .filter(|&e| e != a && e != b)
+            .collect();
+        species::share(data, &candidates);
+        for (e, _) in selection::bottom_k_by(data, candidates, over, selection::shared_fitness) {
+            chronicle::death(data, e, Cause::Culled);
+            continuous::enshrine(data, e);
+            nutrients::recycle(data, e);
+            data.delete(e);
+            data.lazy.remove(e);
+            culled += 1;
+        }
+    }
+    children.saturating_sub(over - culled)
+}
+
+pub fn mate(data: &mut GameData, a: Entity, b: Entity) {
+    if !data.has_or_pending::<Creature>(a) || !data.has_or_pending::<Creature>(b) {
+        return;
+    }
+
+    // parents with differently shaped brains aren't the same species
+    let (kind_a, kind_b) = (
+        data[a.component::<Creature>()].kind,
+        data[b.component::<Creature>()].kind,
+    );
+    if kind_a != kind_b
+        || data[a.component::<Network>()].layers() != data[b.component::<Network>()].layers()
+    {
+        return;
+    }
+
+    let threshold = data.annealing.mating_threshold(&data.config);
+    if threshold > 0.0 {
+        let distance = mating_distance(
+            &data[a.component::<Genome>()],
+            &data[b.component::<Genome>()],
+            &data[a.component::<Network>()],
+            &data[b.component::<Network>()],
+        );
+        if distance > threshold {
+            // every pair is visited twice, only count it once
+            if a.idx < b.idx {
+                data.refused_matings += 1;
+            }
+            return;
+        }
+    }
+
+    let timeout = SimTime::mate_timeout(kind_a);
 
     data[a.component::<Creature>()].timeout = timeout;
     data[b.component::<Creature>()].timeout = timeout;
@@ -124,52 +647,430 @@ pub fn mate(ctx: &mut Context, data: &mut GameData, a: Entity, b: Entity) -> Gam
         Kind::Carnivorous => CARNIVORE_CHILDREN,
     };
 
-    let children = min_children + random::<f32>() * (max_children - min_children);
-    let children = children.round() as usize;
+    let fertility = data[a.component::<Creature>()].fertility(&data.config)
+        * data[b.component::<Creature>()].fertility(&data.config);
+    let roll = data.resource_mut::<SimRng>().gen::<f32>();
+    let children = min_children + roll * (max_children - min_children);
+    // rounds to no children at all for parents well past their prime
+    let children = (children * fertility).round() as usize;
+    let children = make_room(data, a, b, children);
+    data.events.push(GameEvent::Mated { a, b, children });
+    data[a.component::<Creature>()].children += children;
+    data[b.component::<Creature>()].children += children;
+    let mut rng = data.take_resource::<SimRng>();
     for _ in 0..children {
         let apos = data[a.component::<Position>()].position;
         let bpos = data[b.component::<Position>()].position;
-        let position = (apos + bpos) * 0.5;
-        let x = position[0];
-        let y = position[1];
-        let e = data.lazy.add_entity();
-        let radius = data[a.component::<Body>()].radius.mutate(
-            &data[b.component::<Body>()].radius,
-            M_FACTOR,
-            M_CHANCE,
-            M_MUTATION,
+        let rates = data.annealing.rates(&data.config);
+        let genome = data[a.component::<Genome>()].cross(
+            &data[b.component::<Genome>()],
+            &data.config,
+            rates,
+            &mut rng,
         );
-        let mass = data[a.component::<Body>()].mass.mutate(
-            &data[b.component::<Body>()].mass,
-            M_FACTOR,
-            M_CHANCE,
-            M_MUTATION,
-        );
-        let restitution = data[a.component::<Body>()].restitution.mutate(
-            &data[b.component::<Body>()].restitution,
-            M_FACTOR,
-            M_CHANCE,
-            M_MUTATION,
+        let position = spawn::clamp_to_world((apos + bpos) * 0.5, genome.radius);
+        let kind = data[a.component::<Creature>()].kind;
+        let mut brain = brain::new_for(&data.config, kind, &mut rng);
+        if data.config.imprinting {
+            let fitter = if selection::fitness(data, a) >= selection::fitness(data, b) {
+                a
+            } else {
+                b
+            };
+            let experience = &data[fitter.component::<Experience>()];
+            nn::imprint(&mut brain, experience, data.config.imprint_steps);
+        }
+        spawn::creature_with(&mut data.lazy, position, kind, &genome, brain);
+    }
+    data.insert_resource(rng);
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::DVector;
+
+    use super::*;
+
+    use crate::collision;
+    use crate::draw::{Appearance, Draw};
+    use crate::nn::{Desired, Inputs};
+    use crate::senses;
+
+    /// Two vegans with the same genes and the given hunger
+    fn kin(data: &mut GameData, hunger: [f32; 2]) -> [Entity; 2] {
+        let mut rng = SimRng::new(5);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let mut spawn = |x, hunger| {
+            let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+            let e = spawn::creature_with(data, Vector2::new(x, 100.0), Kind::Vegan, &genome, brain);
+            data[e.component::<Creature>()].hunger = hunger;
+            e
+        };
+        [spawn(100.0, hunger[0]), spawn(110.0, hunger[1])]
+    }
+
+    #[test]
+    fn the_sated_one_feeds_the_hungry_one() {
+        let mut data = GameData::new();
+        let starve = VEGAN_STARVE;
+        let [sated, hungry] = kin(&mut data, [0.0, starve * 0.9]);
+        // either order of the pair works
+        share(&mut data, hungry, sated, 1.0);
+        let config = &data.config;
+        assert_eq!(
+            data[sated.component::<Creature>()].hunger,
+            config.share_rate
         );
-        let color = data[a.component::<Draw>()].color.mutate(
-            &data[b.component::<Draw>()].color,
-            M_FACTOR,
-            M_CHANCE,
-            M_MUTATION,
+        let received = config.share_rate * config.share_efficiency;
+        let hunger = data[hungry.component::<Creature>()].hunger;
+        assert!((hunger - (starve * 0.9 - received)).abs() < 1e-3);
+        let amount = config.share_rate;
+        let events: Vec<_> = data.drain_events().collect();
+        assert_eq!(
+            events,
+            vec![GameEvent::Shared {
+                donor: sated,
+                recipient: hungry,
+                amount,
+            }]
         );
-        let kind = data[a.component::<Creature>()].kind;
-        data.lazy.insert(e, Creature::new(kind));
-        data.lazy.insert(e, Position::new(x, y));
-        data.lazy.insert(e, Velocity::new(0.0, 0.0));
-        data.lazy.insert(e, Direction::new(0.0));
-        data.lazy.insert(e, Body::new(radius, mass, restitution));
-        data.lazy.insert(e, Draw::creature(ctx, radius, color)?);
-        data.lazy
-            .insert(e, Network::new(&[RAY_COUNT * 2, 24, 20, DIR_COUNT]));
-        data.lazy.insert(e, Inputs::new(RAY_COUNT * 2));
-        data.lazy.insert(e, Outputs::new(DIR_COUNT));
-        data.lazy.insert(e, Desired::new(DIR_COUNT));
-    }
-
-    Ok(())
+    }
+
+    #[test]
+    fn only_sated_kin_share() {
+        let starve = VEGAN_STARVE;
+        let mut data = GameData::new();
+        // the donor is too hungry itself
+        let [a, b] = kin(&mut data, [starve * 0.3, starve * 0.95]);
+        // the gap is too small
+        let [c, d] = kin(&mut data, [0.0, starve * 0.4]);
+        // they don't look alike
+        let [e, f] = kin(&mut data, [0.0, starve * 0.9]);
+        data[e.component::<Genome>()].color = Color::new(1.0, 1.0, 1.0, 1.0);
+        data[f.component::<Genome>()].color = Color::new(0.0, 0.0, 0.0, 1.0);
+
+        let before: Vec<_> = [a, b, c, d, e, f]
+            .iter()
+            .map(|e| data[e.component::<Creature>()].hunger)
+            .collect();
+        for &(x, y) in &[(a, b), (c, d), (e, f)] {
+            share(&mut data, x, y, 1.0);
+        }
+        let after: Vec<_> = [a, b, c, d, e, f]
+            .iter()
+            .map(|e| data[e.component::<Creature>()].hunger)
+            .collect();
+        assert_eq!(before, after);
+        assert_eq!(data.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn creatures_weaken_before_they_starve() {
+        let config = Config::default();
+        let mut creature = Creature::new(Kind::Vegan);
+        let starve = creature.starve();
+        let weakness = |creature: &Creature, hunger| {
+            let creature = Creature {
+                hunger,
+                ..*creature
+            };
+            creature.weakness(&config)
+        };
+        assert_eq!(weakness(&creature, starve * 0.7), 0.0);
+        assert!((weakness(&creature, starve * 0.9) - 0.5).abs() < 1e-5);
+        assert_eq!(weakness(&creature, starve * 2.0), 1.0);
+
+        assert_eq!(creature.speed_factor(&config), 1.0);
+        creature.hunger = starve;
+        assert!((creature.speed_factor(&config) - config.weakened_speed).abs() < 1e-6);
+    }
+
+    #[test]
+    fn prey_is_digested_over_time() {
+        let config = Config::default();
+        let mut creature = Creature::new(Kind::Carnivorous);
+        let fast = creature.speed_factor(&config);
+        creature.start_digesting(2.0, 10.0);
+        assert!(creature.digesting());
+        assert!((creature.speed_factor(&config) - fast * config.digestion_speed).abs() < 1e-6);
+
+        creature.digest(0.5);
+        assert!((creature.hunger + 2.5).abs() < 1e-5);
+        // the last step runs past the end, only what's left goes in
+        creature.digest(0.7);
+        creature.digest(1.0);
+        assert!(!creature.digesting());
+        assert!((creature.hunger + 10.0).abs() < 1e-5);
+        assert_eq!(creature.nutrition, 0.0);
+        creature.digest(1.0);
+        assert!((creature.hunger + 10.0).abs() < 1e-5);
+
+        // without a duration it all goes in at once
+        let mut creature = Creature::new(Kind::Carnivorous);
+        creature.start_digesting(0.0, 10.0);
+        assert!(!creature.digesting());
+        assert_eq!(creature.hunger, -10.0);
+    }
+
+    #[test]
+    fn fertility_peaks_in_the_prime_of_life() {
+        let mut config = Config {
+            prime_age_start: 10.0,
+            prime_age_end: 20.0,
+            fertility_falloff: 4.0,
+            ..Config::default()
+        };
+        let at = |config: &Config, life| {
+            let creature = Creature {
+                life,
+                ..Creature::new(Kind::Vegan)
+            };
+            creature.fertility(config)
+        };
+        assert_eq!(at(&config, 15.0), 1.0);
+        assert_eq!(at(&config, 8.0), 0.5);
+        assert_eq!(at(&config, 21.0), 0.75);
+        assert_eq!(at(&config, 30.0), 0.0);
+        config.fertility_falloff = 0.0;
+        assert_eq!(at(&config, 20.0), 1.0);
+        assert_eq!(at(&config, 20.5), 0.0);
+    }
+
+    #[test]
+    fn parents_past_their_prime_have_no_children() {
+        let mut data = GameData::new();
+        data.config.prime_age_end = 1.0;
+        data.config.fertility_falloff = 1.0;
+        let [a, b] = kin(&mut data, [0.0, 0.0]);
+        for &e in &[a, b] {
+            data[e.component::<Creature>()].life = 100.0;
+        }
+        let count = data.creature_count();
+        mate(&mut data, a, b);
+
+        assert_eq!(data.creature_count(), count);
+        let timeout = SimTime::mate_timeout(Kind::Vegan);
+        for &e in &[a, b] {
+            let creature = data[e.component::<Creature>()];
+            assert_eq!((creature.children, creature.timeout), (0, timeout));
+        }
+    }
+
+    #[test]
+    fn children_are_ready_for_the_networks() {
+        let mut data = GameData::new();
+        let [a, b] = kin(&mut data, [0.0, 0.0]);
+        mate(&mut data, a, b);
+        let children = data.commit().added;
+        assert!(!children.is_empty());
+        for e in children {
+            let outputs = data[e.component::<Network>()].output_len();
+            assert_eq!(data[e.component::<Desired>()].desired.len(), outputs);
+            assert_eq!(data[e.component::<Position>()].position.x, 105.0);
+        }
+    }
+
+    #[test]
+    fn mating_distance_wraps_hues_around() {
+        let mut rng = SimRng::new(8);
+        let config = Config::default();
+        let brain = brain::new_for(&config, Kind::Vegan, &mut rng);
+        let mut a = Genome::random(Kind::Vegan, &mut rng);
+        let mut b = a;
+        assert_eq!(mating_distance(&a, &b, &brain, &brain), 0.0);
+
+        a.color = mutate::from_hsv(0.05, 0.8, 0.8);
+        b.color = mutate::from_hsv(0.95, 0.8, 0.8);
+        let wrapped = mating_distance(&a, &b, &brain, &brain);
+        b.color = mutate::from_hsv(0.25, 0.8, 0.8);
+        let apart = mating_distance(&a, &b, &brain, &brain);
+        assert!(wrapped < apart, "{} {}", wrapped, apart);
+        assert!((mating_distance(&b, &a, &brain, &brain) - apart).abs() < 1e-6);
+    }
+
+    #[test]
+    fn distant_parents_refuse_to_mate() {
+        let mut data = GameData::new();
+        // kin share their genes but not their brains
+        let [a, b] = kin(&mut data, [0.0, 0.0]);
+        data.config.mating_threshold = 1e-6;
+        mate(&mut data, a, b);
+        mate(&mut data, b, a);
+        assert!(data.commit().added.is_empty());
+        assert_eq!(data.refused_matings, 1);
+        assert_eq!(data[a.component::<Creature>()].children, 0);
+
+        // the threshold is scaled by its decay every generation
+        data.config.mating_threshold = 100.0;
+        data.config.mating_threshold_decay = 2.0;
+        data.annealing.generation = 3;
+        assert_eq!(data.annealing.mating_threshold(&data.config), 800.0);
+        mate(&mut data, a, b);
+        assert!(!data.commit().added.is_empty());
+    }
+
+    #[test]
+    fn a_full_world_skips_children() {
+        let mut data = GameData::new();
+        let [a, b] = kin(&mut data, [0.0, 0.0]);
+        data.config.max_creatures = data.creature_count();
+        data.config.cap_policy = CapPolicy::Skip;
+        mate(&mut data, a, b);
+        assert_eq!(data.creature_count(), 2);
+        assert_eq!(data[a.component::<Creature>()].children, 0);
+    }
+
+    #[test]
+    fn a_full_world_culls_the_least_fit() {
+        let mut data = GameData::new();
+        let [a, b] = kin(&mut data, [0.0, 0.0]);
+        let others = kin(&mut data, [0.0, 0.0]);
+        // the fitter of the other two goes last
+        let fitter = &mut data[others[1].component::<Creature>()];
+        fitter.life = 5.0;
+        fitter.eaten = 10;
+        data.config.max_creatures = data.creature_count();
+        data.config.cap_policy = CapPolicy::Cull;
+        mate(&mut data, a, b);
+
+        let children = data[a.component::<Creature>()].children;
+        assert!(children > 0);
+        assert_eq!(data.creature_count(), 4);
+        assert!(!data.has_or_pending::<Creature>(others[0]));
+        assert_eq!(data.has_or_pending::<Creature>(others[1]), children == 1);
+        assert!(data.has_or_pending::<Creature>(a) && data.has_or_pending::<Creature>(b));
+    }
+
+    #[test]
+    fn chosen_directions_are_remembered() {
+        let mut data = GameData::new();
+        let [a, b] = kin(&mut data, [0.0, 0.0]);
+        collision::output_system(&mut data, vec![a, b]).unwrap();
+        assert!(data[a.component::<Experience>()].samples.is_empty());
+
+        data.config.imprinting = true;
+        data.config.imprint_samples = 3;
+        for _ in 0..5 {
+            collision::output_system(&mut data, vec![a, b]).unwrap();
+        }
+        let samples = &data[a.component::<Experience>()].samples;
+        assert_eq!(samples.len(), 3);
+        let (input, direction) = samples.back().unwrap();
+        assert_eq!(input, &data[a.component::<Inputs>()].input);
+        let heading = data[a.component::<Direction>()].direction;
+        assert_eq!(senses::heading_index(heading), *direction);
+    }
+
+    /// Inputs for `network` and the direction it picks for each
+    fn teach(network: &Network, count: usize, rng: &mut SimRng) -> Vec<(DVector<f32>, usize)> {
+        (0..count)
+            .map(|_| {
+                let input = DVector::from_fn(network.input_len(), |_, _| rng.gen::<f32>());
+                let output = network.clone().feedforward(&input);
+                let direction = nn::argmax(&output.rows(0, DIR_COUNT).into_owned());
+                (input, direction)
+            })
+            .collect()
+    }
+
+    /// Share of `samples` `network` picks the same direction for
+    fn agreement(network: &Network, samples: &[(DVector<f32>, usize)]) -> f32 {
+        let agreed = samples
+            .iter()
+            .filter(|(input, direction)| {
+                let output = network.clone().feedforward(input);
+                nn::argmax(&output.rows(0, DIR_COUNT).into_owned()) == *direction
+            })
+            .count();
+        agreed as f32 / samples.len() as f32
+    }
+
+    #[test]
+    fn imprinted_children_pick_like_their_parent() {
+        let config = Config::default();
+        let mut rng = SimRng::new(12);
+        let parent = brain::new_for(&config, Kind::Vegan, &mut rng);
+        let mut child = brain::new_for(&config, Kind::Vegan, &mut rng);
+        let mut experience = Experience::default();
+        for (input, direction) in teach(&parent, 32, &mut rng) {
+            experience.record(input, direction, 32);
+        }
+        let held_out = teach(&parent, 200, &mut rng);
+
+        let before = agreement(&child, &held_out);
+        nn::imprint(&mut child, &experience, 320);
+        let after = agreement(&child, &held_out);
+        assert!(after > before, "{} isn't above {}", after, before);
+    }
+
+    #[test]
+    fn children_are_left_alone_without_imprinting() {
+        // the same mating twice, once with the parents remembering things
+        let child = |experienced: bool, imprinting: bool| {
+            let mut data = GameData::new();
+            data.config.imprinting = imprinting;
+            let [a, b] = kin(&mut data, [0.0, 0.0]);
+            if experienced {
+                let mut rng = SimRng::new(4);
+                let samples = teach(&data[a.component::<Network>()], 8, &mut rng);
+                for &e in &[a, b] {
+                    for (input, direction) in samples.iter().cloned() {
+                        data[e.component::<Experience>()].record(input, direction, 8);
+                    }
+                }
+            }
+            mate(&mut data, a, b);
+            let children = data.commit().added;
+            data[children[0].component::<Network>()].clone()
+        };
+        assert_eq!(child(true, false), child(false, false));
+        assert_ne!(child(true, true), child(false, true));
+    }
+
+    #[test]
+    fn colors_are_inherited_without_meshes() {
+        let mut data = GameData::new();
+        data.config.mutation_chance = 0.0;
+        data.config.min_mutation_chance = 0.0;
+        let [a, b] = kin(&mut data, [0.0, 0.0]);
+        let red = Color::new(0.9, 0.1, 0.1, 1.0);
+        let blue = Color::new(0.1, 0.1, 0.9, 1.0);
+        data[a.component::<Genome>()].color = red;
+        data[b.component::<Genome>()].color = blue;
+        mate(&mut data, a, b);
+        let children = data.commit().added;
+        assert!(!children.is_empty());
+        assert_eq!(data.iter::<(Draw,)>().count(), 0);
+
+        let blend = |a: f32, b: f32| a * M_FACTOR + b * (1.0 - M_FACTOR);
+        for child in children {
+            let color = data[child.component::<Genome>()].color;
+            assert!((color.r - blend(red.r, blue.r)).abs() < 1e-5);
+            assert!((color.b - blend(red.b, blue.b)).abs() < 1e-5);
+            assert_eq!(data[child.component::<Appearance>()].color, color);
+        }
+    }
+
+    #[test]
+    fn lineages_keep_the_first_parents_hue() {
+        let mut rng = SimRng::new(2);
+        let config = Config::default();
+        let a = Genome::random(Kind::Vegan, &mut rng).founder(1);
+        let b = Genome::random(Kind::Vegan, &mut rng).founder(3);
+        let rates = Rates {
+            chance: 1.0,
+            magnitude: 1.0,
+        };
+        let child = a.cross(&b, &config, rates, &mut rng);
+        assert_eq!(child.lineage_hue, a.lineage_hue);
+        let (hue, _, _) = mutate::hsv(child.color);
+        assert!((hue - a.lineage_hue.unwrap()).abs() < 1e-4);
+
+        // hues are compared around the color wheel
+        let first = a.founder(0);
+        let last = a.founder(LINEAGE_PALETTE - 1);
+        let step = 1.0 / LINEAGE_PALETTE as f32;
+        assert!((kin_distance(&first, &last) - step).abs() < 1e-5);
+        assert_eq!(kin_distance(&a.founder(2), &b.founder(2)), 0.0);
+    }
 }
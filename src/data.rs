@@ -1,19 +1,174 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::marker::PhantomData;
+use std::mem;
 use std::ops::{Index, IndexMut};
 
+use ggez::{Context, GameResult};
+
+use nalgebra::{DVector, Vector2};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chronicle::Chronicle;
 use crate::collision::*;
+use crate::config::Config;
 use crate::creature::*;
 use crate::draw::*;
+use crate::events::{EventQueue, GameEvent};
 use crate::lazy::*;
-use crate::nn::{Desired, Inputs, Network, Outputs};
+use crate::memory::{no_heap, MemoryReport, StorageReport};
+use crate::mortality::Mortality;
+use crate::mutate::Annealing;
+use crate::nn::{Desired, Experience, Inputs, Network, Outputs};
+use crate::nutrients::NutrientTally;
+use crate::paint::FoodGrid;
+use crate::resources::{Clock, DeltaTime, SimRng};
+use crate::save::Elites;
+
+/// Whether a container has a component, `C` is the kind of handle it takes
+pub trait Has<T, C = Component<T>> {
+    fn has(&self, c: C) -> bool;
+}
+
+/// Adds a component to an entity, `E` is the kind of entity handle the
+/// container hands out
+pub trait Insert<T, E = Entity> {
+    fn insert(&mut self, e: E, t: T);
+}
+
+/// Takes a component off an entity, the rest of the entity stays
+pub trait Remove<T> {
+    /// The removed component, `None` if the entity didn't have one
+    fn remove(&mut self, e: Entity) -> Option<T>;
+}
+
+/// Something there's one of per world rather than one per entity, see
+/// `GameData::resource`
+pub trait Resource<T> {
+    fn slot(&self) -> &T;
+    fn slot_mut(&mut self) -> &mut T;
+}
+
+/// The slots of one component for every entity, what `Query` looks things
+/// up in
+pub trait Storage<T> {
+    fn storage(&self) -> &Vec<Option<T>>;
+    fn storage_mut(&mut self) -> &mut Vec<Option<T>>;
+}
+
+/// A tuple of components looked up together by `GameData::iter` and
+/// `GameData::for_each_mut`, entities missing any of them are skipped
+pub trait Query: 'static {
+    type Item<'a>;
+    type ItemMut<'a>;
+    /// What `for_each_mut` takes out of `GameData` while it runs
+    type Storages;
+
+    fn fetch(data: &GameData, idx: usize) -> Option<Self::Item<'_>>;
+    fn take(data: &mut GameData) -> Self::Storages;
+    fn fetch_mut(storages: &mut Self::Storages, idx: usize) -> Option<Self::ItemMut<'_>>;
+    /// Puts the storages back, in the opposite order `take` took them so a
+    /// component listed twice gets its slots back
+    fn restore(data: &mut GameData, storages: Self::Storages);
+}
+
+impl<A: 'static> Query for (A,)
+where
+    GameData: Storage<A>,
+{
+    type Item<'a> = (&'a A,);
+    type ItemMut<'a> = (&'a mut A,);
+    type Storages = (Vec<Option<A>>,);
+
+    fn fetch(data: &GameData, idx: usize) -> Option<Self::Item<'_>> {
+        Some((Storage::<A>::storage(data)[idx].as_ref()?,))
+    }
+
+    fn take(data: &mut GameData) -> Self::Storages {
+        (mem::take(Storage::<A>::storage_mut(data)),)
+    }
+
+    fn fetch_mut(storages: &mut Self::Storages, idx: usize) -> Option<Self::ItemMut<'_>> {
+        Some((storages.0.get_mut(idx)?.as_mut()?,))
+    }
+
+    fn restore(data: &mut GameData, storages: Self::Storages) {
+        *Storage::<A>::storage_mut(data) = storages.0;
+    }
+}
+
+impl<A: 'static, B: 'static> Query for (A, B)
+where
+    GameData: Storage<A> + Storage<B>,
+{
+    type Item<'a> = (&'a A, &'a B);
+    type ItemMut<'a> = (&'a mut A, &'a mut B);
+    type Storages = (Vec<Option<A>>, Vec<Option<B>>);
+
+    fn fetch(data: &GameData, idx: usize) -> Option<Self::Item<'_>> {
+        Some((
+            Storage::<A>::storage(data)[idx].as_ref()?,
+            Storage::<B>::storage(data)[idx].as_ref()?,
+        ))
+    }
+
+    fn take(data: &mut GameData) -> Self::Storages {
+        let a = mem::take(Storage::<A>::storage_mut(data));
+        let b = mem::take(Storage::<B>::storage_mut(data));
+        (a, b)
+    }
+
+    fn fetch_mut(storages: &mut Self::Storages, idx: usize) -> Option<Self::ItemMut<'_>> {
+        let (a, b) = storages;
+        Some((a.get_mut(idx)?.as_mut()?, b.get_mut(idx)?.as_mut()?))
+    }
 
-pub trait Has<T> {
-    fn has(&self, c: Component<T>) -> bool;
+    fn restore(data: &mut GameData, storages: Self::Storages) {
+        let (a, b) = storages;
+        *Storage::<B>::storage_mut(data) = b;
+        *Storage::<A>::storage_mut(data) = a;
+    }
 }
 
-pub trait Insert<T> {
-    fn insert(&mut self, e: Entity, t: T);
+impl<A: 'static, B: 'static, C: 'static> Query for (A, B, C)
+where
+    GameData: Storage<A> + Storage<B> + Storage<C>,
+{
+    type Item<'a> = (&'a A, &'a B, &'a C);
+    type ItemMut<'a> = (&'a mut A, &'a mut B, &'a mut C);
+    type Storages = (Vec<Option<A>>, Vec<Option<B>>, Vec<Option<C>>);
+
+    fn fetch(data: &GameData, idx: usize) -> Option<Self::Item<'_>> {
+        Some((
+            Storage::<A>::storage(data)[idx].as_ref()?,
+            Storage::<B>::storage(data)[idx].as_ref()?,
+            Storage::<C>::storage(data)[idx].as_ref()?,
+        ))
+    }
+
+    fn take(data: &mut GameData) -> Self::Storages {
+        let a = mem::take(Storage::<A>::storage_mut(data));
+        let b = mem::take(Storage::<B>::storage_mut(data));
+        let c = mem::take(Storage::<C>::storage_mut(data));
+        (a, b, c)
+    }
+
+    fn fetch_mut(storages: &mut Self::Storages, idx: usize) -> Option<Self::ItemMut<'_>> {
+        let (a, b, c) = storages;
+        Some((
+            a.get_mut(idx)?.as_mut()?,
+            b.get_mut(idx)?.as_mut()?,
+            c.get_mut(idx)?.as_mut()?,
+        ))
+    }
+
+    fn restore(data: &mut GameData, storages: Self::Storages) {
+        let (a, b, c) = storages;
+        *Storage::<C>::storage_mut(data) = c;
+        *Storage::<B>::storage_mut(data) = b;
+        *Storage::<A>::storage_mut(data) = a;
+    }
 }
 
 /// A collection of all the components
@@ -32,7 +187,77 @@ pub struct GameData {
     inputs: Vec<Option<Inputs>>,
     outputs: Vec<Option<Outputs>>,
     desired: Vec<Option<Desired>>,
+    experiences: Vec<Option<Experience>>,
+    genomes: Vec<Option<Genome>>,
+    appearances: Vec<Option<Appearance>>,
     pub lazy: LazyUpdate,
+    pub config: Config,
+    pub contacts: Contacts,
+    pub chronicle: Chronicle,
+    pub annealing: Annealing,
+    pub mortality: Mortality,
+    /// Matings refused for parents too far apart, see `creature::mate`
+    pub refused_matings: usize,
+    /// Nutrition spawned and recycled this generation
+    pub nutrients: NutrientTally,
+    pub food_grid: FoodGrid,
+    /// Best creatures that died so far in continuous mode, see
+    /// `continuous::enshrine`
+    pub hall_of_fame: Elites,
+    /// What the systems did since the last `drain_events`, holds
+    /// `event_capacity` of them at most
+    pub events: EventQueue,
+    /// Slots emptied by `commit` since the last `compact`, see `hole_ratio`
+    holes: usize,
+    delta_time: DeltaTime,
+    clock: Clock,
+    rng: SimRng,
+}
+
+impl Default for GameData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a `GameData::commit` did, see `GameData::classify` for telling the
+/// added entities apart
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CommitResult {
+    /// In the order of their indices
+    pub added: Vec<Entity>,
+    pub removed: Vec<Entity>,
+}
+
+/// Everything in a `GameData` that can be written to disk, `Draw` is left out
+/// since meshes live on the gpu, `GameData::restore` builds them again from
+/// the appearances
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    entity: usize,
+    delete: HashSet<Entity>,
+    creatures: Vec<Option<Creature>>,
+    foods: Vec<Option<Food>>,
+    positions: Vec<Option<Position>>,
+    velocities: Vec<Option<Velocity>>,
+    directions: Vec<Option<Direction>>,
+    bodies: Vec<Option<Body>>,
+    nns: Vec<Option<Network>>,
+    inputs: Vec<Option<Inputs>>,
+    outputs: Vec<Option<Outputs>>,
+    desired: Vec<Option<Desired>>,
+    experiences: Vec<Option<Experience>>,
+    genomes: Vec<Option<Genome>>,
+    appearances: Vec<Option<Appearance>>,
+    config: Config,
+    chronicle: Chronicle,
+    annealing: Annealing,
+    mortality: Mortality,
+    refused_matings: usize,
+    nutrients: NutrientTally,
+    food_grid: FoodGrid,
+    hall_of_fame: Elites,
+    rng: SimRng,
 }
 
 impl GameData {
@@ -51,10 +276,90 @@ impl GameData {
             inputs: Vec::new(),
             outputs: Vec::new(),
             desired: Vec::new(),
+            experiences: Vec::new(),
+            genomes: Vec::new(),
+            appearances: Vec::new(),
             lazy: LazyUpdate::new(),
+            config: Config::default(),
+            contacts: Contacts::default(),
+            chronicle: Chronicle::default(),
+            annealing: Annealing::default(),
+            mortality: Mortality::default(),
+            refused_matings: 0,
+            nutrients: NutrientTally::default(),
+            food_grid: FoodGrid::default(),
+            hall_of_fame: Elites::default(),
+            events: EventQueue::default(),
+            holes: 0,
+            delta_time: DeltaTime::default(),
+            clock: Clock::default(),
+            rng: SimRng::default(),
         }
     }
 
+    /// An empty world with room for `n` entities in every storage and `n`
+    /// pending ones in the lazy update
+    pub fn with_capacity(n: usize) -> Self {
+        let mut data = Self::new();
+        data.reserve(n);
+        data.lazy.reserve(n);
+        data
+    }
+
+    /// Makes room for `additional` more entities in every storage, so a burst
+    /// of spawns reallocates at most once
+    pub fn reserve(&mut self, additional: usize) {
+        self.creatures.reserve(additional);
+        self.foods.reserve(additional);
+        self.positions.reserve(additional);
+        self.velocities.reserve(additional);
+        self.directions.reserve(additional);
+        self.bodies.reserve(additional);
+        self.draw.reserve(additional);
+        self.nns.reserve(additional);
+        self.inputs.reserve(additional);
+        self.outputs.reserve(additional);
+        self.desired.reserve(additional);
+        self.experiences.reserve(additional);
+        self.genomes.reserve(additional);
+        self.appearances.reserve(additional);
+    }
+
+    /// Empties the world back to what `new` gives, but keeps the config, the
+    /// food grid, the resources and the capacity of every storage, so filling
+    /// it again up to the same size doesn't allocate
+    pub fn clear(&mut self) {
+        self.entity = 0;
+        self.delete.clear();
+        self.creatures.clear();
+        self.foods.clear();
+        self.positions.clear();
+        self.velocities.clear();
+        self.directions.clear();
+        self.bodies.clear();
+        self.draw.clear();
+        self.nns.clear();
+        self.inputs.clear();
+        self.outputs.clear();
+        self.desired.clear();
+        self.experiences.clear();
+        self.genomes.clear();
+        self.appearances.clear();
+        self.lazy.clear();
+        // whether contacts are collected is up to the overlay
+        self.contacts.list.clear();
+        self.chronicle = Chronicle::default();
+        self.annealing = Annealing::default();
+        self.mortality = Mortality::default();
+        self.refused_matings = 0;
+        self.nutrients = NutrientTally::default();
+        self.hall_of_fame = Elites::default();
+        self.events.clear();
+        // a changed capacity takes from the next generation on
+        self.events.set_capacity(self.config.event_capacity);
+        self.holes = 0;
+    }
+
     pub fn add_entity(&mut self) -> Entity {
         self.creatures.push(None);
         self.foods.push(None);
@@ -67,38 +372,336 @@ impl GameData {
         self.inputs.push(None);
         self.outputs.push(None);
         self.desired.push(None);
+        self.experiences.push(None);
+        self.genomes.push(None);
+        self.appearances.push(None);
 
         let e = Entity { idx: self.entity };
         self.entity += 1;
         e
     }
 
+    /// Number of entities ever added, every index is below this
+    pub fn entity_count(&self) -> usize {
+        self.entity
+    }
+
+    /// The component of an entity, `None` if it doesn't have one or is
+    /// marked for deletion, like `Has`
+    pub fn get<T>(&self, e: Entity) -> Option<&T>
+    where
+        GameData: Storage<T>,
+    {
+        if self.delete.contains(&e) {
+            return None;
+        }
+        self.storage().get(e.idx)?.as_ref()
+    }
+
+    pub fn get_mut<T>(&mut self, e: Entity) -> Option<&mut T>
+    where
+        GameData: Storage<T>,
+    {
+        if self.delete.contains(&e) {
+            return None;
+        }
+        self.storage_mut().get_mut(e.idx)?.as_mut()
+    }
+
+    /// The components of two entities at once, like indexing twice, panics if
+    /// `a` and `b` are the same entity or either doesn't have one
+    pub fn get_pair_mut<T>(&mut self, a: Entity, b: Entity) -> (&mut T, &mut T)
+    where
+        GameData: Storage<T>,
+    {
+        assert_ne!(a, b, "can't borrow an entity's component twice");
+        let storage = self.storage_mut();
+        let (first, second) = (a.idx.min(b.idx), a.idx.max(b.idx));
+        let (left, right) = storage.split_at_mut(second);
+        let first = left[first].as_mut().expect("entity doesn't have component");
+        let second = right[0].as_mut().expect("entity doesn't have component");
+        if a.idx < b.idx {
+            (first, second)
+        } else {
+            (second, first)
+        }
+    }
+
+    /// Replaces the resource of type `T`
+    pub fn insert_resource<T>(&mut self, resource: T)
+    where
+        GameData: Resource<T>,
+    {
+        *self.slot_mut() = resource;
+    }
+
+    pub fn resource<T>(&self) -> &T
+    where
+        GameData: Resource<T>,
+    {
+        self.slot()
+    }
+
+    pub fn resource_mut<T>(&mut self) -> &mut T
+    where
+        GameData: Resource<T>,
+    {
+        self.slot_mut()
+    }
+
+    /// Takes a resource out so a system can use it while borrowing the rest
+    /// of the world, `insert_resource` puts it back
+    pub fn take_resource<T: Default>(&mut self) -> T
+    where
+        GameData: Resource<T>,
+    {
+        mem::take(self.slot_mut())
+    }
+
+    /// Whether an entity has a component, committed or waiting in `lazy`,
+    /// and isn't going to be removed by the next commit
+    ///
+    /// Handles past the committed entities are the pending ones, as
+    /// `pending_entity` gives them out.
+    pub fn has_or_pending<T>(&self, e: Entity) -> bool
+    where
+        GameData: Has<T>,
+        LazyUpdate: Has<T, PendingComponent<T>>,
+    {
+        if e.idx >= self.entity {
+            let pending = PendingEntity {
+                idx: e.idx - self.entity,
+            };
+            return pending.idx < self.lazy.entity && self.lazy.has(pending.component::<T>());
+        }
+        self.has(e.component::<T>()) && !self.lazy.remove.contains(&e)
+    }
+
+    /// The entity a pending one becomes at the next commit
+    pub fn pending_entity(&self, e: PendingEntity) -> Entity {
+        Entity {
+            idx: self.entity + e.idx,
+        }
+    }
+
+    /// Every committed creature that isn't marked for deletion
+    pub fn living_creatures(&self) -> impl Iterator<Item = Entity> + '_ {
+        (0..self.entity)
+            .map(|idx| Entity { idx })
+            .filter(move |e| self.has(e.component::<Creature>()))
+    }
+
+    /// Every committed entity that has all the components in `Q` and isn't
+    /// marked for deletion, in entity order
+    pub fn iter<Q: Query>(&self) -> impl Iterator<Item = (Entity, Q::Item<'_>)> + '_ {
+        (0..self.entity)
+            .map(|idx| Entity { idx })
+            .filter(move |e| !self.delete.contains(e))
+            .filter_map(move |e| Q::fetch(self, e.idx).map(|item| (e, item)))
+    }
+
+    /// Like `iter`, but `f` can change the components
+    ///
+    /// The storages in `Q` are taken out of `self` while this runs, so `f`
+    /// gets the rest of the world but must not index the components in `Q`
+    /// through it, and a component listed twice matches nothing.
+    pub fn for_each_mut<Q: Query, F>(&mut self, mut f: F)
+    where
+        F: FnMut(&GameData, Entity, Q::ItemMut<'_>),
+    {
+        let mut storages = Q::take(self);
+        for idx in 0..self.entity {
+            let e = Entity { idx };
+            if self.delete.contains(&e) {
+                continue;
+            }
+            if let Some(item) = Q::fetch_mut(&mut storages, idx) {
+                f(self, e, item);
+            }
+        }
+        Q::restore(self, storages);
+    }
+
+    /// Living creatures plus the ones waiting to be committed
+    pub fn creature_count(&self) -> usize {
+        let pending = self.lazy.creatures.iter().filter(|c| c.is_some()).count();
+        self.living_creatures().count() + pending
+    }
+
+    /// Storages that don't have a slot for every entity
+    pub fn short_storages(&self) -> Vec<&'static str> {
+        let lengths = [
+            ("creatures", self.creatures.len()),
+            ("foods", self.foods.len()),
+            ("positions", self.positions.len()),
+            ("velocities", self.velocities.len()),
+            ("directions", self.directions.len()),
+            ("bodies", self.bodies.len()),
+            ("draw", self.draw.len()),
+            ("nns", self.nns.len()),
+            ("inputs", self.inputs.len()),
+            ("outputs", self.outputs.len()),
+            ("desired", self.desired.len()),
+            ("experiences", self.experiences.len()),
+            ("genomes", self.genomes.len()),
+            ("appearances", self.appearances.len()),
+        ];
+        lengths
+            .iter()
+            .filter(|(_, len)| *len != self.entity)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Whether any storage has a row for the entity at `idx`, deleted ones
+    /// included until their rows are removed
+    fn occupied(&self, idx: usize) -> bool {
+        has_row(&self.creatures, idx)
+            || has_row(&self.foods, idx)
+            || has_row(&self.positions, idx)
+            || has_row(&self.velocities, idx)
+            || has_row(&self.directions, idx)
+            || has_row(&self.bodies, idx)
+            || has_row(&self.draw, idx)
+            || has_row(&self.nns, idx)
+            || has_row(&self.inputs, idx)
+            || has_row(&self.outputs, idx)
+            || has_row(&self.desired, idx)
+            || has_row(&self.experiences, idx)
+            || has_row(&self.genomes, idx)
+            || has_row(&self.appearances, idx)
+    }
+
+    /// Fraction of entity slots that no storage has a row for anymore
+    ///
+    /// This is counted as `commit` empties rows, so it's cheap enough to ask
+    /// every frame. Components taken off with `Remove` directly rather than
+    /// through the lazy update aren't seen until the next `compact`.
+    pub fn hole_ratio(&self) -> f32 {
+        if self.entity == 0 {
+            return 0.0;
+        }
+        self.holes as f32 / self.entity as f32
+    }
+
+    /// Counts the empty slots by looking at every storage
+    fn count_holes(&self) -> usize {
+        (0..self.entity).filter(|&idx| !self.occupied(idx)).count()
+    }
+
+    /// Moves every row down over the holes left by removed entities, keeping
+    /// their order and the capacity of every storage, returns the new handle
+    /// of every entity that still has rows
+    ///
+    /// The delete set, the pending lazy removals, the chronicle and the
+    /// contacts are remapped here, entity lists kept anywhere else have to be
+    /// remapped by whoever keeps them. Entities pending in the lazy update
+    /// get their indices at commit as always.
+    pub fn compact(&mut self) -> HashMap<Entity, Entity> {
+        let kept: Vec<_> = (0..self.entity).filter(|&idx| self.occupied(idx)).collect();
+        let map: HashMap<_, _> = kept
+            .iter()
+            .enumerate()
+            .map(|(new, &old)| (Entity { idx: old }, Entity { idx: new }))
+            .collect();
+        compact_storage(&mut self.creatures, &kept);
+        compact_storage(&mut self.foods, &kept);
+        compact_storage(&mut self.positions, &kept);
+        compact_storage(&mut self.velocities, &kept);
+        compact_storage(&mut self.directions, &kept);
+        compact_storage(&mut self.bodies, &kept);
+        compact_storage(&mut self.draw, &kept);
+        compact_storage(&mut self.nns, &kept);
+        compact_storage(&mut self.inputs, &kept);
+        compact_storage(&mut self.outputs, &kept);
+        compact_storage(&mut self.desired, &kept);
+        compact_storage(&mut self.experiences, &kept);
+        compact_storage(&mut self.genomes, &kept);
+        compact_storage(&mut self.appearances, &kept);
+        self.entity = kept.len();
+        self.holes = 0;
+
+        self.delete = self
+            .delete
+            .iter()
+            .filter_map(|e| map.get(e).copied())
+            .collect();
+        self.lazy.remap(&map);
+        self.chronicle.remap(&map);
+        self.contacts
+            .list
+            .retain_mut(|contact| match (map.get(&contact.a), map.get(&contact.b)) {
+                (Some(&a), Some(&b)) => {
+                    contact.a = a;
+                    contact.b = b;
+                    true
+                }
+                _ => false,
+            });
+        map
+    }
+
+    /// Counts, capacities and estimated bytes of every storage
+    pub fn memory_report(&self) -> MemoryReport {
+        let floats = |v: &DVector<f32>| v.len() * mem::size_of::<f32>();
+        let storages = vec![
+            StorageReport::of("creatures", &self.creatures, no_heap),
+            StorageReport::of("foods", &self.foods, no_heap),
+            StorageReport::of("positions", &self.positions, no_heap),
+            StorageReport::of("velocities", &self.velocities, no_heap),
+            StorageReport::of("directions", &self.directions, no_heap),
+            StorageReport::of("bodies", &self.bodies, no_heap),
+            // meshes live on the gpu
+            StorageReport::of("draw", &self.draw, no_heap),
+            StorageReport::of("nns", &self.nns, Network::parameter_bytes),
+            StorageReport::of("inputs", &self.inputs, |i| floats(&i.input)),
+            StorageReport::of("outputs", &self.outputs, |o| floats(&o.output)),
+            StorageReport::of("desired", &self.desired, |d| floats(&d.desired)),
+            StorageReport::of("experiences", &self.experiences, Experience::heap_bytes),
+            StorageReport::of("genomes", &self.genomes, no_heap),
+            StorageReport::of("appearances", &self.appearances, no_heap),
+            StorageReport::list("contacts", &self.contacts.list),
+        ];
+        MemoryReport {
+            entities: self.entity,
+            storages,
+        }
+    }
+
+    /// Takes the events queued since the last time, oldest first
+    pub fn drain_events(&mut self) -> impl Iterator<Item = GameEvent> + '_ {
+        self.events.drain()
+    }
+
     /// This does not immediately remove the entity, it only marks it for
-    /// deletion
+    /// deletion, components queued to come off it with `remove_later` are
+    /// dropped from the lazy update since the whole entity goes
     pub fn delete(&mut self, e: Entity) {
         self.delete.insert(e);
+        self.lazy.removed.retain(|&(removed, _)| removed != e);
     }
 
-    pub fn commit(&mut self) -> (Vec<Entity>, Vec<Entity>) {
-        let delta = self.lazy.entity;
-        let mut remove = Vec::new();
-        let result = (self.entity..self.entity + delta)
-            .map(|idx| Entity { idx })
-            .collect();
-        self.entity += self.lazy.entity;
-        self.lazy.entity = 0;
-        self.creatures.extend(self.lazy.creatures.drain(..));
-        self.foods.extend(self.lazy.foods.drain(..));
-        self.positions.extend(self.lazy.positions.drain(..));
-        self.velocities.extend(self.lazy.velocities.drain(..));
-        self.directions.extend(self.lazy.directions.drain(..));
-        self.bodies.extend(self.lazy.bodies.drain(..));
-        self.draw.extend(self.lazy.draw.drain(..));
-        self.nns.extend(self.lazy.nns.drain(..));
-        self.inputs.extend(self.lazy.inputs.drain(..));
-        self.outputs.extend(self.lazy.outputs.drain(..));
-        self.desired.extend(self.lazy.desired.drain(..));
-        for e in self.lazy.remove.drain(..) {
+    /// Applies the lazy update, removals first and then the new entities, so
+    /// removals only ever touch rows that were committed before
+    ///
+    /// An entity removed more than once in the same frame is only returned
+    /// once.
+    pub fn commit(&mut self) -> CommitResult {
+        for (e, remove) in std::mem::take(&mut self.lazy.removed) {
+            let occupied = self.occupied(e.idx);
+            remove(self, e);
+            // taking off the last component leaves a hole as well
+            if occupied && !self.occupied(e.idx) {
+                self.holes += 1;
+            }
+        }
+        let mut removed = Vec::new();
+        self.lazy.remove.sort_unstable_by_key(|e| e.idx);
+        self.lazy.remove.dedup();
+        for e in std::mem::take(&mut self.lazy.remove) {
+            if self.occupied(e.idx) {
+                self.holes += 1;
+            }
             self.creatures[e.idx] = None;
             self.foods[e.idx] = None;
             self.positions[e.idx] = None;
@@ -110,14 +713,491 @@ impl GameData {
             self.inputs[e.idx] = None;
             self.outputs[e.idx] = None;
             self.desired[e.idx] = None;
-            remove.push(e);
+            self.experiences[e.idx] = None;
+            self.genomes[e.idx] = None;
+            self.appearances[e.idx] = None;
+            removed.push(e);
+        }
+
+        let delta = self.lazy.entity;
+        let added = (self.entity..self.entity + delta)
+            .map(|idx| Entity { idx })
+            .collect();
+        self.entity += self.lazy.entity;
+        self.lazy.entity = 0;
+        self.creatures.append(&mut self.lazy.creatures);
+        self.foods.append(&mut self.lazy.foods);
+        self.positions.append(&mut self.lazy.positions);
+        self.velocities.append(&mut self.lazy.velocities);
+        self.directions.append(&mut self.lazy.directions);
+        self.bodies.append(&mut self.lazy.bodies);
+        self.draw.append(&mut self.lazy.draw);
+        self.nns.append(&mut self.lazy.nns);
+        self.inputs.append(&mut self.lazy.inputs);
+        self.outputs.append(&mut self.lazy.outputs);
+        self.desired.append(&mut self.lazy.desired);
+        self.experiences.append(&mut self.lazy.experiences);
+        self.genomes.append(&mut self.lazy.genomes);
+        self.appearances.append(&mut self.lazy.appearances);
+        CommitResult { added, removed }
+    }
+
+    /// Splits entities into creatures and foods, the ones that are neither
+    /// are left out
+    pub fn classify(&self, entities: &[Entity]) -> (Vec<Entity>, Vec<Entity>) {
+        let mut creatures = Vec::new();
+        let mut foods = Vec::new();
+        for &e in entities {
+            if self.has(e.component::<Creature>()) {
+                creatures.push(e);
+            } else if self.has(e.component::<Food>()) {
+                foods.push(e);
+            }
+        }
+        (creatures, foods)
+    }
+
+    /// Copies the world for saving, pending lazy updates aren't part of it so
+    /// this should be taken right after a `commit`
+    pub fn snapshot(&self) -> WorldSnapshot {
+        debug_assert!(
+            self.lazy.entity == 0 && self.lazy.remove.is_empty(),
+            "snapshot taken with lazy updates pending"
+        );
+        WorldSnapshot {
+            entity: self.entity,
+            delete: self.delete.clone(),
+            creatures: self.creatures.clone(),
+            foods: self.foods.clone(),
+            positions: self.positions.clone(),
+            velocities: self.velocities.clone(),
+            directions: self.directions.clone(),
+            bodies: self.bodies.clone(),
+            nns: self.nns.clone(),
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            desired: self.desired.clone(),
+            experiences: self.experiences.clone(),
+            genomes: self.genomes.clone(),
+            appearances: self.appearances.clone(),
+            config: self.config.clone(),
+            chronicle: self.chronicle.clone(),
+            annealing: self.annealing,
+            mortality: self.mortality,
+            refused_matings: self.refused_matings,
+            nutrients: self.nutrients,
+            food_grid: self.food_grid.clone(),
+            hall_of_fame: self.hall_of_fame.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Builds the world a snapshot was taken of, with a mesh for every entity
+    /// that has an `Appearance`
+    pub fn restore(ctx: &mut Context, snapshot: WorldSnapshot) -> GameResult<Self> {
+        let mut data = Self::from_snapshot(snapshot);
+        data.draw = data
+            .appearances
+            .iter()
+            .map(|appearance| appearance.map(|a| Draw::new(ctx, &a)).transpose())
+            .collect::<GameResult<_>>()?;
+        Ok(data)
+    }
+
+    /// Builds the world a snapshot was taken of without any meshes, for
+    /// comparing against or stepping where there's no window
+    pub fn from_snapshot(snapshot: WorldSnapshot) -> Self {
+        let draw = snapshot.appearances.iter().map(|_| None).collect();
+        let events = EventQueue::new(snapshot.config.event_capacity);
+        let mut data = Self {
+            entity: snapshot.entity,
+            delete: snapshot.delete,
+            creatures: snapshot.creatures,
+            foods: snapshot.foods,
+            positions: snapshot.positions,
+            velocities: snapshot.velocities,
+            directions: snapshot.directions,
+            bodies: snapshot.bodies,
+            draw,
+            nns: snapshot.nns,
+            inputs: snapshot.inputs,
+            outputs: snapshot.outputs,
+            desired: snapshot.desired,
+            experiences: snapshot.experiences,
+            genomes: snapshot.genomes,
+            appearances: snapshot.appearances,
+            lazy: LazyUpdate::new(),
+            config: snapshot.config,
+            contacts: Contacts::default(),
+            chronicle: snapshot.chronicle,
+            annealing: snapshot.annealing,
+            mortality: snapshot.mortality,
+            refused_matings: snapshot.refused_matings,
+            nutrients: snapshot.nutrients,
+            food_grid: snapshot.food_grid,
+            hall_of_fame: snapshot.hall_of_fame,
+            events,
+            holes: 0,
+            delta_time: DeltaTime::default(),
+            clock: Clock::default(),
+            rng: snapshot.rng,
+        };
+        // a saved world can have holes of its own, counted once here
+        data.holes = data.count_holes();
+        data
+    }
+}
+
+/// Whether `storage` has a row at `idx`
+fn has_row<T>(storage: &[Option<T>], idx: usize) -> bool {
+    storage.get(idx).is_some_and(Option::is_some)
+}
+
+/// Moves the rows at `kept`, in ascending order, to the front and drops the
+/// rest, rows that aren't kept are always empty so swapping them back is fine
+fn compact_storage<T>(storage: &mut Vec<Option<T>>, kept: &[usize]) {
+    for (new, &old) in kept.iter().enumerate() {
+        if old < storage.len() {
+            storage.swap(new, old);
+        }
+    }
+    storage.truncate(kept.len());
+}
+
+/// Position and velocity differences smaller than this are ignored by `diff`
+pub const DIFF_EPSILON: f32 = 1e-4;
+
+/// A single difference between two worlds found by `GameData::diff`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentDiff {
+    pub entity: Entity,
+    pub component: &'static str,
+    pub description: String,
+}
+
+impl fmt::Display for ComponentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "entity {} {}: {}",
+            self.entity.idx, self.component, self.description
+        )
+    }
+}
+
+impl GameData {
+    /// Compares two worlds entity by entity and component by component,
+    /// ordered by entity
+    pub fn diff(&self, other: &GameData) -> Vec<ComponentDiff> {
+        let mut diffs = Vec::new();
+        let vectors = |a: &DVector<f32>, b: &DVector<f32>| {
+            if a.nrows() != b.nrows() {
+                Some(format!("length {} vs {}", a.nrows(), b.nrows()))
+            } else {
+                let delta = (a - b).amax();
+                if delta > DIFF_EPSILON {
+                    Some(format!("max delta {}", delta))
+                } else {
+                    None
+                }
+            }
+        };
+        let debug = |a: &dyn fmt::Debug, b: &dyn fmt::Debug| Some(format!("{:?} vs {:?}", a, b));
+
+        diff_storage(
+            &mut diffs,
+            "creature",
+            &self.creatures,
+            &other.creatures,
+            unequal,
+        );
+        diff_storage(&mut diffs, "food", &self.foods, &other.foods, unequal);
+        diff_storage(
+            &mut diffs,
+            "position",
+            &self.positions,
+            &other.positions,
+            |a, b| {
+                if (a.position - b.position).magnitude() > DIFF_EPSILON {
+                    debug(&a.position, &b.position)
+                } else {
+                    None
+                }
+            },
+        );
+        diff_storage(
+            &mut diffs,
+            "velocity",
+            &self.velocities,
+            &other.velocities,
+            |a, b| {
+                if (a.velocity - b.velocity).magnitude() > DIFF_EPSILON {
+                    debug(&a.velocity, &b.velocity)
+                } else {
+                    None
+                }
+            },
+        );
+        diff_storage(
+            &mut diffs,
+            "direction",
+            &self.directions,
+            &other.directions,
+            |a, b| {
+                if (a.direction - b.direction).abs() > DIFF_EPSILON {
+                    debug(&a.direction, &b.direction)
+                } else {
+                    None
+                }
+            },
+        );
+        diff_storage(&mut diffs, "body", &self.bodies, &other.bodies, unequal);
+        diff_storage(&mut diffs, "network", &self.nns, &other.nns, |a, b| {
+            let delta = a.max_delta(b);
+            if delta > DIFF_EPSILON {
+                Some(format!("max weight delta {}", delta))
+            } else {
+                None
+            }
+        });
+        diff_storage(&mut diffs, "inputs", &self.inputs, &other.inputs, |a, b| {
+            vectors(&a.input, &b.input)
+        });
+        diff_storage(
+            &mut diffs,
+            "outputs",
+            &self.outputs,
+            &other.outputs,
+            |a, b| vectors(&a.output, &b.output),
+        );
+        diff_storage(
+            &mut diffs,
+            "desired",
+            &self.desired,
+            &other.desired,
+            |a, b| vectors(&a.desired, &b.desired),
+        );
+        diff_storage(
+            &mut diffs,
+            "experience",
+            &self.experiences,
+            &other.experiences,
+            |a, b| {
+                if a.samples.len() != b.samples.len() {
+                    Some(format!(
+                        "{} vs {} samples",
+                        a.samples.len(),
+                        b.samples.len()
+                    ))
+                } else {
+                    None
+                }
+            },
+        );
+        diff_storage(&mut diffs, "genome", &self.genomes, &other.genomes, unequal);
+        diff_storage(
+            &mut diffs,
+            "appearance",
+            &self.appearances,
+            &other.appearances,
+            unequal,
+        );
+
+        diffs.sort_by_key(|d| d.entity.idx);
+        diffs
+    }
+
+    /// A checksum of every storage that `diff` compares, under the same names,
+    /// for telling whether two worlds match without keeping both
+    ///
+    /// These are exact, where `diff` lets small float differences through.
+    pub fn checksums(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("creature", checksum(&self.creatures)),
+            ("food", checksum(&self.foods)),
+            ("position", checksum(&self.positions)),
+            ("velocity", checksum(&self.velocities)),
+            ("direction", checksum(&self.directions)),
+            ("body", checksum(&self.bodies)),
+            ("network", checksum(&self.nns)),
+            ("inputs", checksum(&self.inputs)),
+            ("outputs", checksum(&self.outputs)),
+            ("desired", checksum(&self.desired)),
+            ("experience", checksum(&self.experiences)),
+            ("genome", checksum(&self.genomes)),
+            ("appearance", checksum(&self.appearances)),
+        ]
+    }
+}
+
+/// FNV-1a over the encoded storage, which unlike the std hasher is the same
+/// on every version of Rust, so checksums can be kept in files
+fn checksum<T: Serialize>(storage: &[Option<T>]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    let encoded = bincode::serialize(storage).expect("storages always encode");
+    encoded.iter().fold(OFFSET, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+fn unequal<T: PartialEq + fmt::Debug>(a: &T, b: &T) -> Option<String> {
+    if a != b {
+        Some(format!("{:?} vs {:?}", a, b))
+    } else {
+        None
+    }
+}
+
+/// Pushes a `ComponentDiff` for every slot where `a` and `b` disagree, `same`
+/// describes the difference between two present components if there is one
+fn diff_storage<T, F>(
+    diffs: &mut Vec<ComponentDiff>,
+    component: &'static str,
+    a: &[Option<T>],
+    b: &[Option<T>],
+    same: F,
+) where
+    F: Fn(&T, &T) -> Option<String>,
+{
+    for idx in 0..a.len().max(b.len()) {
+        let description = match (
+            a.get(idx).and_then(Option::as_ref),
+            b.get(idx).and_then(Option::as_ref),
+        ) {
+            (None, None) => None,
+            (Some(_), None) => Some("missing in other".to_string()),
+            (None, Some(_)) => Some("missing in self".to_string()),
+            (Some(a), Some(b)) => same(a, b),
+        };
+        if let Some(description) = description {
+            diffs.push(ComponentDiff {
+                entity: Entity { idx },
+                component,
+                description,
+            });
         }
-        (result, remove)
     }
 }
 
+/// Everything a `GameData` holds for one entity, see `GameData::describe`
+///
+/// Networks and experiences are summed up, their contents would bury the
+/// rest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityDebug {
+    pub entity: Entity,
+    pub creature: Option<String>,
+    pub food: Option<String>,
+    pub position: Option<String>,
+    pub velocity: Option<String>,
+    pub direction: Option<String>,
+    pub body: Option<String>,
+    pub draw: Option<String>,
+    pub network: Option<String>,
+    pub inputs: Option<String>,
+    pub outputs: Option<String>,
+    pub desired: Option<String>,
+    pub experience: Option<String>,
+    pub genome: Option<String>,
+    pub appearance: Option<String>,
+    /// In the delete set, gone at the next commit
+    pub pending_delete: bool,
+    /// In `lazy.remove`, gone at the next commit
+    pub pending_remove: bool,
+}
+
+impl EntityDebug {
+    fn components(&self) -> [(&'static str, &Option<String>); 14] {
+        [
+            ("creature", &self.creature),
+            ("food", &self.food),
+            ("position", &self.position),
+            ("velocity", &self.velocity),
+            ("direction", &self.direction),
+            ("body", &self.body),
+            ("draw", &self.draw),
+            ("network", &self.network),
+            ("inputs", &self.inputs),
+            ("outputs", &self.outputs),
+            ("desired", &self.desired),
+            ("experience", &self.experience),
+            ("genome", &self.genome),
+            ("appearance", &self.appearance),
+        ]
+    }
+}
+
+impl fmt::Display for EntityDebug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "entity {}", self.entity.idx)?;
+        if self.pending_delete {
+            write!(f, " (pending delete)")?;
+        }
+        if self.pending_remove {
+            write!(f, " (pending lazy remove)")?;
+        }
+        let mut empty = true;
+        for (name, component) in self.components().iter() {
+            if let Some(component) = component {
+                write!(f, "\n  {}: {}", name, component)?;
+                empty = false;
+            }
+        }
+        if empty {
+            write!(f, "\n  no components")?;
+        }
+        Ok(())
+    }
+}
+
+impl GameData {
+    /// What `e` holds right now, including components of an entity that's
+    /// about to be deleted
+    pub fn describe(&self, e: Entity) -> EntityDebug {
+        let debug = |t: &dyn fmt::Debug| format!("{:?}", t);
+        let point = |v: &Vector2<f32>| format!("({}, {})", v.x, v.y);
+        let vector = |v: &DVector<f32>| format!("{:?}", v.as_slice());
+        EntityDebug {
+            entity: e,
+            creature: describe_row(&self.creatures, e.idx, |c| debug(c)),
+            food: describe_row(&self.foods, e.idx, |f| debug(f)),
+            position: describe_row(&self.positions, e.idx, |p| point(&p.position)),
+            velocity: describe_row(&self.velocities, e.idx, |v| point(&v.velocity)),
+            direction: describe_row(&self.directions, e.idx, |d| debug(&d.direction)),
+            body: describe_row(&self.bodies, e.idx, |b| debug(b)),
+            draw: describe_row(&self.draw, e.idx, |_| "mesh".to_owned()),
+            network: describe_row(&self.nns, e.idx, |n| {
+                format!(
+                    "layers {:?}, {:?}{}",
+                    n.layers(),
+                    n.activation(),
+                    if n.recurrent() { ", recurrent" } else { "" }
+                )
+            }),
+            inputs: describe_row(&self.inputs, e.idx, |i| vector(&i.input)),
+            outputs: describe_row(&self.outputs, e.idx, |o| vector(&o.output)),
+            desired: describe_row(&self.desired, e.idx, |d| vector(&d.desired)),
+            experience: describe_row(&self.experiences, e.idx, |x| {
+                format!("{} samples", x.samples.len())
+            }),
+            genome: describe_row(&self.genomes, e.idx, |g| debug(g)),
+            appearance: describe_row(&self.appearances, e.idx, |a| debug(a)),
+            pending_delete: self.delete.contains(&e),
+            pending_remove: self.lazy.remove.contains(&e),
+        }
+    }
+}
+
+fn describe_row<T, F>(storage: &[Option<T>], idx: usize, describe: F) -> Option<String>
+where
+    F: Fn(&T) -> String,
+{
+    storage.get(idx).and_then(Option::as_ref).map(describe)
+}
+
 /// And index into the SOAs representing entities
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Entity {
     pub idx: usize,
 }
@@ -131,10 +1211,11 @@ impl Entity {
     }
 }
 
-/// Used to index into the corresponding `Vec<T>` in a `GameData`
+/// Used to index into the corresponding `Vec<T>` in a `GameData` or a
+/// `LazyUpdate`
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Component<T> {
-    idx: usize,
+    pub(crate) idx: usize,
     _phantom: PhantomData<T>,
 }
 
@@ -172,6 +1253,22 @@ impl Insert<Creature> for GameData {
     }
 }
 
+impl Remove<Creature> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Creature> {
+        self.creatures[e.idx].take()
+    }
+}
+
+impl Storage<Creature> for GameData {
+    fn storage(&self) -> &Vec<Option<Creature>> {
+        &self.creatures
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Creature>> {
+        &mut self.creatures
+    }
+}
+
 impl Index<Component<Food>> for GameData {
     type Output = Food;
 
@@ -206,6 +1303,22 @@ impl Insert<Food> for GameData {
     }
 }
 
+impl Remove<Food> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Food> {
+        self.foods[e.idx].take()
+    }
+}
+
+impl Storage<Food> for GameData {
+    fn storage(&self) -> &Vec<Option<Food>> {
+        &self.foods
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Food>> {
+        &mut self.foods
+    }
+}
+
 impl Index<Component<Position>> for GameData {
     type Output = Position;
 
@@ -240,6 +1353,22 @@ impl Insert<Position> for GameData {
     }
 }
 
+impl Remove<Position> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Position> {
+        self.positions[e.idx].take()
+    }
+}
+
+impl Storage<Position> for GameData {
+    fn storage(&self) -> &Vec<Option<Position>> {
+        &self.positions
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Position>> {
+        &mut self.positions
+    }
+}
+
 impl Index<Component<Velocity>> for GameData {
     type Output = Velocity;
 
@@ -274,6 +1403,22 @@ impl Insert<Velocity> for GameData {
     }
 }
 
+impl Remove<Velocity> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Velocity> {
+        self.velocities[e.idx].take()
+    }
+}
+
+impl Storage<Velocity> for GameData {
+    fn storage(&self) -> &Vec<Option<Velocity>> {
+        &self.velocities
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Velocity>> {
+        &mut self.velocities
+    }
+}
+
 impl Index<Component<Direction>> for GameData {
     type Output = Direction;
 
@@ -308,6 +1453,22 @@ impl Insert<Direction> for GameData {
     }
 }
 
+impl Remove<Direction> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Direction> {
+        self.directions[e.idx].take()
+    }
+}
+
+impl Storage<Direction> for GameData {
+    fn storage(&self) -> &Vec<Option<Direction>> {
+        &self.directions
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Direction>> {
+        &mut self.directions
+    }
+}
+
 impl Index<Component<Body>> for GameData {
     type Output = Body;
 
@@ -342,6 +1503,22 @@ impl Insert<Body> for GameData {
     }
 }
 
+impl Remove<Body> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Body> {
+        self.bodies[e.idx].take()
+    }
+}
+
+impl Storage<Body> for GameData {
+    fn storage(&self) -> &Vec<Option<Body>> {
+        &self.bodies
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Body>> {
+        &mut self.bodies
+    }
+}
+
 impl Index<Component<Draw>> for GameData {
     type Output = Draw;
 
@@ -376,6 +1553,22 @@ impl Insert<Draw> for GameData {
     }
 }
 
+impl Remove<Draw> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Draw> {
+        self.draw[e.idx].take()
+    }
+}
+
+impl Storage<Draw> for GameData {
+    fn storage(&self) -> &Vec<Option<Draw>> {
+        &self.draw
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Draw>> {
+        &mut self.draw
+    }
+}
+
 impl Index<Component<Network>> for GameData {
     type Output = Network;
 
@@ -410,6 +1603,22 @@ impl Insert<Network> for GameData {
     }
 }
 
+impl Remove<Network> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Network> {
+        self.nns[e.idx].take()
+    }
+}
+
+impl Storage<Network> for GameData {
+    fn storage(&self) -> &Vec<Option<Network>> {
+        &self.nns
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Network>> {
+        &mut self.nns
+    }
+}
+
 impl Index<Component<Inputs>> for GameData {
     type Output = Inputs;
 
@@ -444,6 +1653,22 @@ impl Insert<Inputs> for GameData {
     }
 }
 
+impl Remove<Inputs> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Inputs> {
+        self.inputs[e.idx].take()
+    }
+}
+
+impl Storage<Inputs> for GameData {
+    fn storage(&self) -> &Vec<Option<Inputs>> {
+        &self.inputs
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Inputs>> {
+        &mut self.inputs
+    }
+}
+
 impl Index<Component<Outputs>> for GameData {
     type Output = Outputs;
 
@@ -478,6 +1703,22 @@ impl Insert<Outputs> for GameData {
     }
 }
 
+impl Remove<Outputs> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Outputs> {
+        self.outputs[e.idx].take()
+    }
+}
+
+impl Storage<Outputs> for GameData {
+    fn storage(&self) -> &Vec<Option<Outputs>> {
+        &self.outputs
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Outputs>> {
+        &mut self.outputs
+    }
+}
+
 impl Index<Component<Desired>> for GameData {
     type Output = Desired;
 
@@ -511,3 +1752,591 @@ impl Insert<Desired> for GameData {
         self.desired[e.idx] = Some(t);
     }
 }
+
+impl Remove<Desired> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Desired> {
+        self.desired[e.idx].take()
+    }
+}
+
+impl Storage<Desired> for GameData {
+    fn storage(&self) -> &Vec<Option<Desired>> {
+        &self.desired
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Desired>> {
+        &mut self.desired
+    }
+}
+
+impl Index<Component<Experience>> for GameData {
+    type Output = Experience;
+
+    fn index(&self, idx: Component<Experience>) -> &Self::Output {
+        self.experiences[idx.idx]
+            .as_ref()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl IndexMut<Component<Experience>> for GameData {
+    fn index_mut(&mut self, idx: Component<Experience>) -> &mut Self::Output {
+        self.experiences[idx.idx]
+            .as_mut()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl Has<Experience> for GameData {
+    fn has(&self, c: Component<Experience>) -> bool {
+        if self.delete.contains(&Entity { idx: c.idx }) {
+            return false;
+        }
+
+        self.experiences[c.idx].is_some()
+    }
+}
+
+impl Insert<Experience> for GameData {
+    fn insert(&mut self, e: Entity, t: Experience) {
+        self.experiences[e.idx] = Some(t);
+    }
+}
+
+impl Remove<Experience> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Experience> {
+        self.experiences[e.idx].take()
+    }
+}
+
+impl Storage<Experience> for GameData {
+    fn storage(&self) -> &Vec<Option<Experience>> {
+        &self.experiences
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Experience>> {
+        &mut self.experiences
+    }
+}
+
+impl Index<Component<Genome>> for GameData {
+    type Output = Genome;
+
+    fn index(&self, idx: Component<Genome>) -> &Self::Output {
+        self.genomes[idx.idx]
+            .as_ref()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl IndexMut<Component<Genome>> for GameData {
+    fn index_mut(&mut self, idx: Component<Genome>) -> &mut Self::Output {
+        self.genomes[idx.idx]
+            .as_mut()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl Has<Genome> for GameData {
+    fn has(&self, c: Component<Genome>) -> bool {
+        if self.delete.contains(&Entity { idx: c.idx }) {
+            return false;
+        }
+
+        self.genomes[c.idx].is_some()
+    }
+}
+
+impl Insert<Genome> for GameData {
+    fn insert(&mut self, e: Entity, t: Genome) {
+        self.genomes[e.idx] = Some(t);
+    }
+}
+
+impl Remove<Genome> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Genome> {
+        self.genomes[e.idx].take()
+    }
+}
+
+impl Storage<Genome> for GameData {
+    fn storage(&self) -> &Vec<Option<Genome>> {
+        &self.genomes
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Genome>> {
+        &mut self.genomes
+    }
+}
+
+impl Index<Component<Appearance>> for GameData {
+    type Output = Appearance;
+
+    fn index(&self, idx: Component<Appearance>) -> &Self::Output {
+        self.appearances[idx.idx]
+            .as_ref()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl IndexMut<Component<Appearance>> for GameData {
+    fn index_mut(&mut self, idx: Component<Appearance>) -> &mut Self::Output {
+        self.appearances[idx.idx]
+            .as_mut()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl Has<Appearance> for GameData {
+    fn has(&self, c: Component<Appearance>) -> bool {
+        if self.delete.contains(&Entity { idx: c.idx }) {
+            return false;
+        }
+
+        self.appearances[c.idx].is_some()
+    }
+}
+
+impl Insert<Appearance> for GameData {
+    fn insert(&mut self, e: Entity, t: Appearance) {
+        self.appearances[e.idx] = Some(t);
+    }
+}
+
+impl Remove<Appearance> for GameData {
+    fn remove(&mut self, e: Entity) -> Option<Appearance> {
+        self.appearances[e.idx].take()
+    }
+}
+
+impl Storage<Appearance> for GameData {
+    fn storage(&self) -> &Vec<Option<Appearance>> {
+        &self.appearances
+    }
+
+    fn storage_mut(&mut self) -> &mut Vec<Option<Appearance>> {
+        &mut self.appearances
+    }
+}
+
+impl Resource<DeltaTime> for GameData {
+    fn slot(&self) -> &DeltaTime {
+        &self.delta_time
+    }
+
+    fn slot_mut(&mut self) -> &mut DeltaTime {
+        &mut self.delta_time
+    }
+}
+
+impl Resource<Clock> for GameData {
+    fn slot(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn slot_mut(&mut self) -> &mut Clock {
+        &mut self.clock
+    }
+}
+
+impl Resource<SimRng> for GameData {
+    fn slot(&self) -> &SimRng {
+        &self.rng
+    }
+
+    fn slot_mut(&mut self) -> &mut SimRng {
+        &mut self.rng
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::brain;
+    use crate::collision;
+    use crate::lazy::RemoveLater;
+    use crate::nn;
+    use crate::senses;
+    use crate::spawn;
+
+    /// A world with `creatures` vegans and then `foods` foods, all committed,
+    /// spawned the same way every time
+    fn world(creatures: usize, foods: usize) -> (GameData, Vec<Entity>) {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let mut entities = Vec::new();
+        for i in 0..creatures {
+            let genome = Genome::random(Kind::Vegan, &mut rng);
+            let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+            let position = Vector2::new(100.0 + 50.0 * i as f32, 100.0);
+            entities.push(spawn::creature_with(
+                &mut data,
+                position,
+                Kind::Vegan,
+                &genome,
+                brain,
+            ));
+        }
+        for i in 0..foods {
+            let food = Food {
+                nutrition: 1.0,
+                rich: false,
+            };
+            let position = Vector2::new(100.0 + 50.0 * i as f32, 300.0);
+            entities.push(spawn::food_at(&mut data, position, 5.0, food, &mut rng));
+        }
+        (data, entities)
+    }
+
+    fn pending_vegan(data: &mut GameData) {
+        let mut rng = SimRng::new(2);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+        let position = Vector2::new(500.0, 500.0);
+        spawn::creature_with(&mut data.lazy, position, Kind::Vegan, &genome, brain);
+    }
+
+    #[test]
+    fn commit_removes_once_and_appends_after() {
+        let (mut data, entities) = world(2, 0);
+        let gone = entities[0];
+        data.delete(gone);
+        data.lazy.remove(gone);
+        data.lazy.remove(gone);
+        pending_vegan(&mut data);
+
+        let commit = data.commit();
+        assert_eq!(commit.removed, vec![gone]);
+        assert_eq!(commit.added, vec![Entity { idx: 2 }]);
+        assert!(data.creatures[gone.idx].is_none());
+        assert!(data.positions[gone.idx].is_none());
+        assert!(data.has(Entity { idx: 2 }.component::<Creature>()));
+        assert!(data.has(entities[1].component::<Creature>()));
+    }
+
+    #[test]
+    fn lazy_components_land_on_the_committed_rows() {
+        let (mut data, _) = world(2, 1);
+        let mut rng = SimRng::new(3);
+        let food = Food {
+            nutrition: 2.0,
+            rich: true,
+        };
+        let position = Vector2::new(700.0, 300.0);
+        spawn::food_at(&mut data.lazy, position, 4.0, food, &mut rng);
+        pending_vegan(&mut data);
+
+        let commit = data.commit();
+        assert_eq!(commit.added, vec![Entity { idx: 3 }, Entity { idx: 4 }]);
+        let [food, vegan] = [commit.added[0], commit.added[1]];
+        assert_eq!(data[food.component::<Position>()].position, position);
+        assert!(data[food.component::<Food>()].rich);
+        assert!(!data.has(food.component::<Creature>()));
+        let position = Vector2::new(500.0, 500.0);
+        assert_eq!(data[vegan.component::<Position>()].position, position);
+        assert!(data.has(vegan.component::<Creature>()));
+        assert!(!data.has(vegan.component::<Food>()));
+    }
+
+    #[test]
+    fn removing_a_component_leaves_the_rest() {
+        let (mut data, entities) = world(1, 0);
+        let e = entities[0];
+        let velocity = data[e.component::<Velocity>()];
+        let removed = Remove::<Velocity>::remove(&mut data, e);
+        assert_eq!(removed.map(|v| v.velocity), Some(velocity.velocity));
+        assert!(!data.has(e.component::<Velocity>()));
+        assert!(data.has(e.component::<Position>()));
+        assert!(data.has(e.component::<Creature>()));
+        assert!(Remove::<Velocity>::remove(&mut data, e).is_none());
+    }
+
+    #[test]
+    fn queries_match_entities_with_every_component() {
+        let (mut data, entities) = world(3, 2);
+        data.delete(entities[1]);
+        let creatures: Vec<_> = data
+            .iter::<(Position, Creature)>()
+            .map(|(e, _)| e)
+            .collect();
+        assert_eq!(creatures, vec![entities[0], entities[2]]);
+        assert_eq!(data.iter::<(Food,)>().count(), 2);
+        assert_eq!(data.iter::<(Food, Creature)>().count(), 0);
+
+        data.for_each_mut::<(Position, Food), _>(|_, _, (position, _)| {
+            position.position.y += 1.0;
+        });
+        assert_eq!(data[entities[3].component::<Position>()].position.y, 301.0);
+        assert_eq!(data[entities[0].component::<Position>()].position.y, 100.0);
+        // the storages are back once it's done
+        assert_eq!(data.iter::<(Position,)>().count(), 4);
+        let mut twice = 0;
+        data.for_each_mut::<(Position, Position), _>(|_, _, _| twice += 1);
+        assert_eq!(twice, 0);
+        assert_eq!(data.iter::<(Position,)>().count(), 4);
+    }
+
+    #[test]
+    fn removing_a_component_later_waits_for_the_commit() {
+        let (mut data, entities) = world(1, 0);
+        let e = entities[0];
+        RemoveLater::<Velocity>::remove_later(&mut data.lazy, e);
+        assert!(data.has(e.component::<Velocity>()));
+        data.commit();
+        assert!(!data.has(e.component::<Velocity>()));
+        assert!(data.has(e.component::<Position>()));
+    }
+
+    #[test]
+    fn deleting_drops_queued_component_removals() {
+        let (mut data, entities) = world(2, 0);
+        RemoveLater::<Velocity>::remove_later(&mut data.lazy, entities[0]);
+        RemoveLater::<Velocity>::remove_later(&mut data.lazy, entities[1]);
+        data.delete(entities[0]);
+        assert_eq!(data.lazy.removed.len(), 1);
+        assert_eq!(data.lazy.removed[0].0, entities[1]);
+    }
+
+    #[test]
+    fn a_frame_goes_on_past_an_entity_deleted_midway() {
+        let (mut data, entities) = world(4, 2);
+        data.insert_resource(DeltaTime(0.01));
+        let creatures = &entities[..4];
+        collision::physics_system(&mut data, entities.clone(), entities.clone()).unwrap();
+        // eaten after physics, one of them already committed away, the later
+        // systems still get handed both
+        let (committed, gone) = (creatures[2], creatures[1]);
+        data.delete(committed);
+        data.lazy.remove(committed);
+        data.commit();
+        data.delete(gone);
+        data.lazy.remove(gone);
+        let inputs = data.get::<Inputs>(creatures[0]).cloned();
+
+        senses::input_system(
+            &mut data,
+            creatures.iter().copied(),
+            entities.iter().copied(),
+            None,
+        )
+        .unwrap();
+        nn::nn_system(&mut data, creatures.iter().copied(), true).unwrap();
+        collision::output_system(&mut data, creatures.iter().copied()).unwrap();
+
+        assert!(data.get::<Creature>(gone).is_none());
+        assert!(data.get::<Creature>(committed).is_none());
+        assert!(data.get::<Inputs>(creatures[0]) != inputs.as_ref());
+        let commit = data.commit();
+        assert_eq!(commit.removed, vec![gone]);
+    }
+
+    #[test]
+    fn pairs_come_back_in_argument_order() {
+        for &(a, b) in &[(0, 2), (2, 0)] {
+            let (mut data, entities) = world(3, 0);
+            let (a, b) = (entities[a], entities[b]);
+            let (pa, pb) = data.get_pair_mut::<Position>(a, b);
+            pa.position.x = 1.0;
+            pb.position.x = 2.0;
+            assert_eq!(data[a.component::<Position>()].position.x, 1.0);
+            assert_eq!(data[b.component::<Position>()].position.x, 2.0);
+            assert_eq!(data[entities[1].component::<Position>()].position.x, 150.0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can't borrow an entity's component twice")]
+    fn pairs_of_one_entity_panic() {
+        let (mut data, entities) = world(1, 0);
+        data.get_pair_mut::<Position>(entities[0], entities[0]);
+    }
+
+    #[test]
+    fn removed_entities_arent_pending() {
+        let (mut data, entities) = world(3, 0);
+        data.delete(entities[0]);
+        data.lazy.remove(entities[0]);
+        // removed at the next commit, even though it wasn't deleted
+        data.lazy.remove(entities[2]);
+        assert!(!data.has_or_pending::<Creature>(entities[0]));
+        assert!(data.has_or_pending::<Creature>(entities[1]));
+        assert!(!data.has_or_pending::<Creature>(entities[2]));
+        assert!(data.has(entities[2].component::<Creature>()));
+        assert!(!data.has_or_pending::<Food>(entities[1]));
+    }
+
+    #[test]
+    fn descriptions_show_components_and_whats_pending() {
+        let (mut data, entities) = world(2, 1);
+        let (creature, other, food) = (entities[0], entities[1], entities[2]);
+        let debug = data.describe(creature);
+        assert!(!debug.pending_delete && !debug.pending_remove);
+        assert_eq!(debug.position.as_deref(), Some("(100, 100)"));
+        assert!(debug.food.is_none());
+        let text = debug.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "entity 0");
+        assert!(lines.contains(&"  position: (100, 100)"));
+        let components = debug
+            .components()
+            .iter()
+            .filter(|(_, c)| c.is_some())
+            .count();
+        assert_eq!(lines.len(), components + 1);
+
+        let text = data.describe(food).to_string();
+        assert!(text.contains("\n  food: Food { nutrition: 1.0, rich: false }"));
+        assert!(!text.contains("creature:"));
+
+        data.delete(creature);
+        data.lazy.remove(other);
+        let debug = data.describe(creature);
+        assert!(debug.pending_delete && !debug.pending_remove);
+        // still there until the commit
+        assert!(debug.creature.is_some());
+        assert!(debug.to_string().starts_with("entity 0 (pending delete)\n"));
+        let debug = data.describe(other);
+        assert!(!debug.pending_delete && debug.pending_remove);
+        assert!(debug
+            .to_string()
+            .starts_with("entity 1 (pending lazy remove)\n"));
+
+        let empty = data.add_entity();
+        assert_eq!(
+            data.describe(empty).to_string(),
+            format!("entity {}\n  no components", empty.idx)
+        );
+    }
+
+    #[test]
+    fn pending_rows_count_as_pending() {
+        let (mut data, _) = world(2, 0);
+        let pending = data.lazy.add_entity();
+        let food = Food {
+            nutrition: 1.0,
+            rich: false,
+        };
+        data.lazy.insert(pending, food);
+        assert!(data.lazy.has(pending.component::<Food>()));
+        assert!(!data.lazy.has(pending.component::<Creature>()));
+
+        let e = data.pending_entity(pending);
+        assert!(data.has_or_pending::<Food>(e));
+        assert!(!data.has_or_pending::<Creature>(e));
+        // past the pending rows there's nothing at all
+        assert!(!data.has_or_pending::<Food>(Entity { idx: e.idx + 1 }));
+
+        let commit = data.commit();
+        assert_eq!(commit.added, vec![e]);
+        assert!(data.has_or_pending::<Food>(e));
+        assert!(data.has(e.component::<Food>()));
+    }
+
+    #[test]
+    fn classify_splits_creatures_from_foods() {
+        let (mut data, entities) = world(2, 2);
+        let empty = data.add_entity();
+        let mixed = [entities[2], entities[0], empty, entities[3], entities[1]];
+        let (creatures, foods) = data.classify(&mixed);
+        assert_eq!(creatures, vec![entities[0], entities[1]]);
+        assert_eq!(foods, vec![entities[2], entities[3]]);
+    }
+
+    #[test]
+    fn compact_keeps_rows_in_order_under_new_handles() {
+        let (mut data, entities) = world(3, 3);
+        for &e in &[entities[0], entities[2], entities[4]] {
+            data.delete(e);
+            data.lazy.remove(e);
+        }
+        data.commit();
+        let kept = [entities[1], entities[3], entities[5]];
+        let before: Vec<_> = kept.iter().map(|e| data.positions[e.idx]).collect();
+        // still marked, a compact mid-frame has to carry this over
+        data.delete(entities[5]);
+
+        let map = data.compact();
+        assert_eq!(data.entity, kept.len());
+        assert!(data.short_storages().is_empty());
+        let handles: Vec<_> = kept.iter().map(|e| map[e]).collect();
+        assert_eq!(
+            handles,
+            (0..kept.len())
+                .map(|idx| Entity { idx })
+                .collect::<Vec<_>>()
+        );
+        let after: Vec<_> = handles.iter().map(|e| data.positions[e.idx]).collect();
+        assert_eq!(before, after);
+        assert!(data.has(handles[0].component::<Creature>()));
+        assert!(data.has(handles[1].component::<Food>()));
+        assert_eq!(data.delete, [handles[2]].iter().copied().collect());
+        assert_eq!(data.hole_ratio(), 0.0);
+    }
+
+    #[test]
+    fn holes_are_counted_as_rows_are_removed() {
+        let (mut data, entities) = world(4, 4);
+        let scanned = |data: &GameData| data.count_holes() as f32 / data.entity as f32;
+        for &e in &[entities[0], entities[5], entities[0]] {
+            data.delete(e);
+            data.lazy.remove(e);
+        }
+        data.commit();
+        assert_eq!(data.hole_ratio(), 2.0 / 8.0);
+        assert_eq!(data.hole_ratio(), scanned(&data));
+
+        // removing a hole again doesn't make it a second one
+        for &e in &[entities[0], entities[1]] {
+            data.delete(e);
+            data.lazy.remove(e);
+        }
+        data.commit();
+        assert_eq!(data.hole_ratio(), 3.0 / 8.0);
+        assert_eq!(data.hole_ratio(), scanned(&data));
+
+        data.compact();
+        assert_eq!(data.hole_ratio(), 0.0);
+        assert_eq!(data.count_holes(), 0);
+    }
+
+    #[test]
+    fn same_worlds_have_no_diff() {
+        let (a, _) = world(3, 2);
+        let (b, _) = world(3, 2);
+        assert_eq!(a.diff(&b), Vec::new());
+    }
+
+    #[test]
+    fn diff_finds_moved_and_missing_components() {
+        let (a, entities) = world(3, 2);
+        let (mut b, _) = world(3, 2);
+        b[entities[1].component::<Position>()].position.x += 1.0;
+        b[entities[0].component::<Position>()].position.x += DIFF_EPSILON * 0.1;
+        Remove::<Velocity>::remove(&mut b, entities[3]);
+        b[entities[2].component::<Creature>()].hunger += 0.5;
+        // zeroes the smallest weight and nothing else
+        let network = &mut b[entities[2].component::<Network>()];
+        let mut threshold = 1e-4;
+        while network.clone().prune(threshold) == 0 {
+            threshold *= 1.1;
+        }
+        assert_eq!(network.prune(threshold), 1);
+
+        let diffs = a.diff(&b);
+        let found: Vec<_> = diffs.iter().map(|d| (d.entity, d.component)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (entities[1], "position"),
+                (entities[2], "creature"),
+                (entities[2], "network"),
+                (entities[3], "velocity")
+            ]
+        );
+        assert!(diffs[1].description.contains("hunger"));
+        assert!(diffs[2].description.starts_with("max weight delta"));
+        assert_eq!(diffs[3].description, "missing in other");
+    }
+}
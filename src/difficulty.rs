@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Adjusts how often food spawns based on how well the vegans are doing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FoodController {
+    /// Food spawns this many times as often as `food_timeout` says
+    pub multiplier: f32,
+    timer: f32,
+}
+
+impl Default for FoodController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FoodController {
+    pub fn new() -> Self {
+        Self {
+            multiplier: 1.0,
+            timer: 0.0,
+        }
+    }
+
+    /// Nudges the multiplier once every sim-second, fewer vegans than the
+    /// target means more food
+    pub fn update(&mut self, config: &Config, vegans: usize, delta: f32) {
+        if !config.adaptive_food {
+            self.multiplier = 1.0;
+            return;
+        }
+
+        self.timer += delta;
+        while self.timer >= 1.0 {
+            self.timer -= 1.0;
+            let ratio = vegans as f32 / config.target_vegans.max(1) as f32;
+            self.multiplier += config.adaptive_gain * (1.0 - ratio);
+            self.multiplier = self
+                .multiplier
+                .max(config.min_food_multiplier)
+                .min(config.max_food_multiplier);
+        }
+    }
+
+    /// The food timeout the spawner should actually use
+    pub fn food_timeout(&self, config: &Config) -> f32 {
+        config.food_timeout / self.multiplier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adaptive() -> Config {
+        Config {
+            adaptive_food: true,
+            target_vegans: 100,
+            adaptive_gain: 0.1,
+            min_food_multiplier: 0.25,
+            max_food_multiplier: 4.0,
+            food_timeout: 2.0,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn fewer_vegans_get_more_food() {
+        let config = adaptive();
+        let mut controller = FoodController::new();
+        // nothing happens until a whole sim-second went by
+        controller.update(&config, 50, 0.5);
+        assert_eq!(controller.multiplier, 1.0);
+        controller.update(&config, 50, 0.5);
+        assert!((controller.multiplier - 1.05).abs() < 1e-6);
+        assert!(controller.food_timeout(&config) < config.food_timeout);
+
+        let mut controller = FoodController::new();
+        controller.update(&config, 150, 1.0);
+        assert!((controller.multiplier - 0.95).abs() < 1e-6);
+        assert!(controller.food_timeout(&config) > config.food_timeout);
+    }
+
+    #[test]
+    fn the_multiplier_stays_in_bounds() {
+        let config = adaptive();
+        let mut controller = FoodController::new();
+        controller.update(&config, 0, 100.0);
+        assert_eq!(controller.multiplier, config.max_food_multiplier);
+        controller.update(&config, 1000, 100.0);
+        assert_eq!(controller.multiplier, config.min_food_multiplier);
+    }
+
+    #[test]
+    fn switched_off_it_leaves_the_rate_alone() {
+        let mut config = adaptive();
+        let mut controller = FoodController::new();
+        controller.update(&config, 0, 10.0);
+        config.adaptive_food = false;
+        controller.update(&config, 0, 10.0);
+        assert_eq!(controller.multiplier, 1.0);
+        assert_eq!(controller.food_timeout(&config), config.food_timeout);
+    }
+}
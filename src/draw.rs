@@ -1,56 +1,447 @@
-use ggez::graphics::{self, Color, DrawMode, DrawParam, Mesh, MeshBuilder};
+use ggez::graphics::{self, Color, DrawMode, DrawParam, Mesh, MeshBuilder, Rect};
 use ggez::{Context, GameResult};
 
-use crate::creature::{Direction, Position};
-use crate::data::Has;
+use nalgebra::Vector2;
+
+use serde::{Deserialize, Serialize};
+
+use crate::collision::{Body, Contacts};
+use crate::config::Config;
+use crate::creature::{ColorDef, Creature, Direction, Position, Velocity};
 use crate::data::{Entity, GameData};
-use crate::DPI_FACTOR;
+use crate::data::{Has, Insert};
+use crate::profile_scope;
+use crate::senses::{self, ViewArc};
+use crate::{Food, DPI_FACTOR, HEIGHT, WIDTH, WORLD_HEIGHT, WORLD_WIDTH};
+
+/// Draws what comes after in world units, the window shows the whole world
+///
+/// This is the only place the simulation gets scaled to the window, nothing
+/// drawn with it should know about `DPI_FACTOR`.
+pub fn world_view(ctx: &mut Context) -> GameResult<()> {
+    graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT))
+}
+
+/// Draws what comes after in window pixels, for text and the rest of the UI
+pub fn screen_view(ctx: &mut Context) -> GameResult<()> {
+    graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, WIDTH, HEIGHT))
+}
+
+/// Where a point of the world shows up in the window
+pub fn to_screen(position: Vector2<f32>) -> Vector2<f32> {
+    position * DPI_FACTOR
+}
+
+/// The point of the world under a spot in the window, for the mouse
+pub fn to_world(config: &Config, position: Vector2<f32>) -> Vector2<f32> {
+    position / config.display_scale
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Shape {
+    /// Plain circle, used for food
+    Circle,
+    /// Circle with a line showing where it's headed
+    Creature,
+}
+
+/// What an entity looks like, `mesh_system` turns this into a `Draw`
+///
+/// Should be stored in an array of structs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Appearance {
+    pub shape: Shape,
+    pub radius: f32,
+    #[serde(with = "ColorDef")]
+    pub color: Color,
+}
+
+impl Appearance {
+    pub fn new(shape: Shape, radius: f32, color: Color) -> Self {
+        Self {
+            shape,
+            radius,
+            color,
+        }
+    }
+}
 
 /// Should be stored in an array of structs
 #[derive(Debug, Clone, PartialEq)]
 pub struct Draw {
     mesh: Mesh,
-    pub color: Color,
 }
 
 impl Draw {
-    pub fn circle(ctx: &mut Context, radius: f32, color: Color) -> GameResult<Self> {
-        let mesh = MeshBuilder::new()
-            .circle(DrawMode::fill(), [0.0, 0.0], radius, 0.25, color)
-            .build(ctx)?;
-        Ok(Self { mesh, color })
+    pub fn new(ctx: &mut Context, appearance: &Appearance) -> GameResult<Self> {
+        let Appearance {
+            shape,
+            radius,
+            color,
+        } = *appearance;
+        let mut builder = MeshBuilder::new();
+        builder.circle(DrawMode::fill(), [0.0, 0.0], radius, 0.25, color);
+        if shape == Shape::Creature {
+            builder.line(&[[0.0, 0.0], [2.0 * radius, 0.0]], 4.0, color)?;
+        }
+        let mesh = builder.build(ctx)?;
+        Ok(Self { mesh })
+    }
+}
+
+/// Builds the meshes of entities that have an `Appearance` but no `Draw` yet
+pub fn mesh_system<I>(ctx: &mut Context, data: &mut GameData, iter: I) -> GameResult<()>
+where
+    I: IntoIterator<Item = Entity>,
+{
+    profile_scope!("meshes");
+    for e in iter {
+        if data.has(e.component::<Appearance>()) && !data.has(e.component::<Draw>()) {
+            let draw = Draw::new(ctx, &data[e.component::<Appearance>()])?;
+            data.insert(e, draw);
+        }
+    }
+    Ok(())
+}
+
+/// Entities slower than this are drawn unrotated if they have no `Direction`
+pub const ROTATION_EPSILON: f32 = 1.0;
+
+/// Uses the `Direction` if there is one, otherwise the angle of the velocity
+pub fn rotation(data: &GameData, e: Entity) -> f32 {
+    if data.has(e.component::<Direction>()) {
+        return data[e.component::<Direction>()].direction;
+    }
+
+    if data.has(e.component::<Velocity>()) {
+        let velocity = data[e.component::<Velocity>()].velocity;
+        if velocity.magnitude_squared() > ROTATION_EPSILON * ROTATION_EPSILON {
+            return velocity.y.atan2(velocity.x);
+        }
+    }
+
+    0.0
+}
+
+/// Multiplies the colors of a creature that's digesting
+pub const DIGESTION_SHADE: f32 = 0.75;
+
+/// Multiplies the mesh colors of an entity, weakened creatures fade to gray
+/// and digesting ones are a bit darker
+pub fn tint(data: &GameData, e: Entity) -> Color {
+    if !data.has(e.component::<Creature>()) {
+        return graphics::WHITE;
+    }
+
+    let creature = data[e.component::<Creature>()];
+    let weakness = creature.weakness(&data.config);
+    let shade = if creature.digesting() {
+        DIGESTION_SHADE
+    } else {
+        1.0
+    };
+    let color = data[e.component::<Appearance>()].color;
+    let gray = (color.r + color.g + color.b) / 3.0;
+    // the mesh already has `color` baked in, so this lerps it towards `gray`
+    let channel = |c: f32| {
+        if c > 0.0 {
+            1.0 + weakness * (gray / c - 1.0)
+        } else {
+            1.0
+        }
+    };
+    Color::new(
+        channel(color.r) * shade,
+        channel(color.g) * shade,
+        channel(color.b) * shade,
+        1.0,
+    )
+}
+
+/// Draws all the food and then all the creatures on top
+pub fn draw_system(ctx: &mut Context, data: &GameData) -> GameResult<()> {
+    world_view(ctx)?;
+    for (e, (position, draw, _)) in data.iter::<(Position, Draw, Food)>() {
+        draw_one(ctx, data, e, position, draw)?;
+    }
+    for (e, (position, draw, _)) in data.iter::<(Position, Draw, Creature)>() {
+        draw_one(ctx, data, e, position, draw)?;
+    }
+    Ok(())
+}
+
+fn draw_one(
+    ctx: &mut Context,
+    data: &GameData,
+    e: Entity,
+    position: &Position,
+    draw: &Draw,
+) -> GameResult<()> {
+    let position = position.position;
+    graphics::draw(
+        ctx,
+        &draw.mesh,
+        DrawParam::new()
+            .color(tint(data, e))
+            .dest([position.x, position.y])
+            .offset([0.0, 0.0])
+            .rotation(rotation(data, e))
+            .scale([1.0, 1.0]),
+    )
+}
+
+/// Skips drawing most frames so updates can run as fast as possible
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Throttle {
+    pub on: bool,
+    frame: usize,
+}
+
+impl Throttle {
+    pub fn toggle(&mut self) {
+        self.on = !self.on;
+        self.frame = 0;
     }
 
-    pub fn creature(ctx: &mut Context, radius: f32, color: Color) -> GameResult<Self> {
-        let mesh = MeshBuilder::new()
-            .circle(DrawMode::fill(), [0.0, 0.0], radius, 0.25, color)
-            .line(&[[0.0, 0.0], [2.0 * radius, 0.0]], 4.0 * DPI_FACTOR, color)?
-            .build(ctx)?;
-        Ok(Self { mesh, color })
+    /// Whether this frame gets drawn, which is only every `divisor`th one
+    /// while throttled
+    pub fn draw_frame(&mut self, divisor: usize) -> bool {
+        if !self.on {
+            return true;
+        }
+
+        let draw = self.frame % divisor.max(1) == 0;
+        self.frame += 1;
+        draw
     }
 }
 
-pub fn draw_system<I>(ctx: &mut Context, data: &GameData, iter: I) -> GameResult<()>
+/// Draws an orange ring around every creature that's lunging
+pub fn draw_lunges<I>(ctx: &mut Context, data: &GameData, iter: I) -> GameResult<()>
 where
     I: IntoIterator<Item = Entity>,
 {
+    world_view(ctx)?;
+    let orange = Color::new(1.0, 0.5, 0.0, 1.0);
+    let mut builder = MeshBuilder::new();
+    let mut empty = true;
     for e in iter {
+        if data[e.component::<Creature>()].lunge <= 0.0 {
+            continue;
+        }
         let position = data[e.component::<Position>()].position;
-        let mesh = &data[e.component::<Draw>()].mesh;
-        let rotation = if data.has(e.component::<Direction>()) {
-            data[e.component::<Direction>()].direction
+        let radius = data[e.component::<Body>()].radius;
+        builder.circle(
+            DrawMode::stroke(2.0),
+            [position.x, position.y],
+            radius + 1.0,
+            0.25,
+            orange,
+        );
+        empty = false;
+    }
+    if empty {
+        return Ok(());
+    }
+
+    let mesh = builder.build(ctx)?;
+    graphics::draw(ctx, &mesh, DrawParam::new())
+}
+
+/// Contact lines are this many times as long as the penetration
+pub const CONTACT_SCALE: f32 = 4.0;
+
+/// Draws every contact as a line along its normal, green if the two bounced
+/// off each other and red if one of them got eaten
+pub fn draw_contacts(ctx: &mut Context, contacts: &Contacts) -> GameResult<()> {
+    if contacts.list.is_empty() {
+        return Ok(());
+    }
+
+    world_view(ctx)?;
+    let mut builder = MeshBuilder::new();
+    for contact in &contacts.list {
+        let color = if contact.eaten {
+            Color::new(1.0, 0.0, 0.0, 1.0)
         } else {
-            0.0
+            Color::new(0.0, 1.0, 0.0, 1.0)
+        };
+        let length = (contact.penetration * CONTACT_SCALE).max(1.0);
+        let half = contact.normal * length * 0.5;
+        let (p1, p2) = (contact.point - half, contact.point + half);
+        builder.line(&[[p1.x, p1.y], [p2.x, p2.y]], 1.0, color)?;
+    }
+    let mesh = builder.build(ctx)?;
+    graphics::draw(ctx, &mesh, DrawParam::new())
+}
+
+/// Segments `draw_view` splits the edge of a view wedge into
+pub const VIEW_ARC_SEGMENTS: usize = 24;
+
+/// Draws the rays of every creature in `creatures` with the wedge they sweep
+/// and a tick where each one sees something, all in one mesh
+///
+/// The rays come from `senses::rays` and the ticks from `senses::look`, so
+/// this shows exactly what the inputs are built from.
+pub fn draw_view<I>(
+    ctx: &mut Context,
+    data: &GameData,
+    creatures: &[Entity],
+    all: I,
+) -> GameResult<()>
+where
+    I: IntoIterator<Item = Entity> + Clone,
+{
+    world_view(ctx)?;
+    let wedge = Color::new(1.0, 1.0, 1.0, 0.1);
+    let line = Color::new(1.0, 1.0, 1.0, 0.3);
+    let tick = Color::new(1.0, 0.84, 0.0, 0.9);
+    let mut builder = MeshBuilder::new();
+    let mut empty = true;
+    for &e in creatures {
+        let rays = senses::rays(data, e);
+        let arc = match ViewArc::of(&rays) {
+            Some(arc) => arc,
+            None => continue,
         };
-        graphics::draw(
-            ctx,
-            mesh,
-            DrawParam::new()
-                .dest([position.x, position.y])
-                .offset([0.0, 0.0])
-                .rotation(rotation)
-                .scale([1.0, 1.0]),
-        )?;
+        if arc.end - arc.start > f32::EPSILON {
+            let point = |angle: f32| {
+                let (y, x) = angle.sin_cos();
+                let p = arc.center + Vector2::new(x, y) * arc.radius;
+                [p.x, p.y]
+            };
+            let step = (arc.end - arc.start) / VIEW_ARC_SEGMENTS as f32;
+            let mut points = vec![[arc.center.x, arc.center.y]];
+            points.extend((0..=VIEW_ARC_SEGMENTS).map(|i| point(arc.start + step * i as f32)));
+            builder.polygon(DrawMode::fill(), &points, wedge)?;
+        }
+
+        let sights = senses::look(data, e, all.clone());
+        for (ray, sight) in rays.iter().zip(sights) {
+            builder.line(&[[ray.p1.x, ray.p1.y], [ray.p2.x, ray.p2.y]], 1.0, line)?;
+            let sight = match sight {
+                Some(sight) => sight,
+                None => continue,
+            };
+            let along = (ray.p2 - ray.p1).normalize();
+            let hit = ray.p1 + along * sight.distance;
+            let across = Vector2::new(-along.y, along.x) * 4.0;
+            let (a, b) = (hit - across, hit + across);
+            builder.line(&[[a.x, a.y], [b.x, b.y]], 2.0, tick)?;
+        }
+        empty = false;
+    }
+    if !empty {
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())?;
     }
     Ok(())
 }
+
+/// Draws the chronicle trace of every creature as a line up to where it is
+/// now, traces are only sampled while the chronicle is on
+pub fn draw_trails(ctx: &mut Context, data: &GameData, creatures: &[Entity]) -> GameResult<()> {
+    world_view(ctx)?;
+    let white = Color::new(1.0, 1.0, 1.0, 0.6);
+    let mut builder = MeshBuilder::new();
+    let mut empty = true;
+    for &e in creatures {
+        let position = data[e.component::<Position>()].position;
+        let mut points: Vec<_> = data
+            .chronicle
+            .trace(e)
+            .iter()
+            .map(|&(_, x, y)| [x, y])
+            .collect();
+        points.push([position.x, position.y]);
+        if points.len() < 2 {
+            continue;
+        }
+        builder.line(&points, 1.0, white)?;
+        empty = false;
+    }
+    if !empty {
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::brain;
+    use crate::creature::{Genome, Kind};
+    use crate::data::Remove;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    fn food(velocity: Vector2<f32>) -> (GameData, Entity) {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let food = Food::plain(&data.config);
+        let e = spawn::food_at(&mut data, Vector2::new(0.0, 0.0), 10.0, food, &mut rng);
+        Remove::<Direction>::remove(&mut data, e);
+        data.insert(e, Velocity::new(velocity.x, velocity.y));
+        (data, e)
+    }
+
+    #[test]
+    fn undirected_entities_face_where_they_go() {
+        let (data, e) = food(Vector2::new(0.0, 5.0));
+        assert_eq!(rotation(&data, e), std::f32::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn slow_entities_arent_rotated() {
+        let (data, e) = food(Vector2::new(ROTATION_EPSILON * 0.5, 0.0));
+        assert_eq!(rotation(&data, e), 0.0);
+    }
+
+    #[test]
+    fn a_direction_wins_over_the_velocity() {
+        let (mut data, e) = food(Vector2::new(0.0, 5.0));
+        data.insert(e, Direction { direction: 1.0 });
+        assert_eq!(rotation(&data, e), 1.0);
+    }
+
+    #[test]
+    fn starving_creatures_fade_to_gray() {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+        let e = spawn::creature_with(
+            &mut data,
+            Vector2::new(0.0, 0.0),
+            Kind::Vegan,
+            &genome,
+            brain,
+        );
+        assert_eq!(tint(&data, e), graphics::WHITE);
+
+        let starve = data[e.component::<Creature>()].starve();
+        data[e.component::<Creature>()].hunger = starve;
+        let tint = tint(&data, e);
+        let color = genome.color;
+        let gray = (color.r + color.g + color.b) / 3.0;
+        for &(c, t) in &[(color.r, tint.r), (color.g, tint.g), (color.b, tint.b)] {
+            if c > 0.0 {
+                assert!((c * t - gray).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn throttling_draws_every_nth_frame() {
+        let mut throttle = Throttle::default();
+        assert!((0..5).all(|_| throttle.draw_frame(3)));
+
+        throttle.toggle();
+        let drawn: Vec<_> = (0..7).map(|_| throttle.draw_frame(3)).collect();
+        assert_eq!(drawn, [true, false, false, true, false, false, true]);
+        // a divisor of 0 draws everything rather than dividing by it
+        assert!(throttle.draw_frame(0) && throttle.draw_frame(0));
+    }
+}
@@ -0,0 +1,223 @@
+use std::iter;
+
+use ggez::GameResult;
+
+use nalgebra::DVector;
+
+use crate::config::Config;
+use crate::creature::{Creature, Kind, DIR_COUNT, LUNGE_OUTPUT};
+use crate::data::{Entity, GameData};
+use crate::nn::{Inputs, Outputs};
+use crate::paint::FoodGrid;
+use crate::senses;
+use crate::time::SimTime;
+use crate::GameState;
+
+/// Real seconds every step of an episode covers
+pub const ENV_STEP: f32 = 1.0 / 60.0;
+/// Reward for every meal of the controlled creature
+pub const EAT_BONUS: f32 = 1.0;
+/// Reward for the controlled creature dying
+pub const DEATH_PENALTY: f32 = -10.0;
+
+/// What the controlled creature senses, its input vector
+pub type Observation = Vec<f32>;
+
+/// A world where one vegan moves where it's told and everyone else follows
+/// their own network, for training that vegan from outside
+///
+/// An episode lasts until the vegan dies or the generation ends. Rewards are
+/// how much hungrier the vegan got, negated, with `EAT_BONUS` for every meal
+/// and `DEATH_PENALTY` for dying.
+pub struct Env {
+    config: Config,
+    game: GameState,
+    creature: Entity,
+    /// Of the controlled creature after the last step
+    hunger: f32,
+    eaten: usize,
+    done: bool,
+}
+
+impl Env {
+    /// An environment playing worlds with `config`, with an episode already
+    /// started from its seed
+    pub fn new(config: Config) -> Self {
+        let seed = config.seed;
+        let mut env = Self {
+            game: GameState::empty(0, config.clone()),
+            config,
+            creature: Entity { idx: 0 },
+            hunger: 0.0,
+            eaten: 0,
+            done: true,
+        };
+        env.reset(seed);
+        env
+    }
+
+    /// Starts a new episode in a fresh world, the same one for the same
+    /// `seed` unless it's 0
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        let mut config = self.config.clone();
+        config.seed = seed;
+        // nothing of an episode should end up on disk, or depend on the
+        // machine
+        config.chronicle = false;
+        config.continuous = false;
+        config.frame_budget = 0.0;
+        let mut game = GameState::empty(0, config);
+        game.data.food_grid = FoodGrid::default();
+        game.populate_with(Vec::new());
+        self.creature = game
+            .creatures
+            .iter()
+            .copied()
+            .find(|&e| game.data[e.component::<Creature>()].kind == Kind::Vegan)
+            .expect("every world starts with vegans");
+        let creature = game.data[self.creature.component::<Creature>()];
+        self.hunger = creature.hunger;
+        self.eaten = creature.eaten;
+        self.done = false;
+        self.game = game;
+        self.sense()
+    }
+
+    /// Moves the controlled creature towards direction `action` for a step,
+    /// returns what it senses after, the reward and whether the episode is
+    /// over
+    pub fn step(&mut self, action: usize) -> GameResult<(Observation, f32, bool)> {
+        assert!(action < DIR_COUNT, "there are {} directions", DIR_COUNT);
+        assert!(!self.done, "the episode is over, reset first");
+        let e = self.creature;
+        self.game
+            .step_with(ENV_STEP, |data| decide(data, e, action))?;
+
+        let mut reward = 0.0;
+        match self.game.data.get::<Creature>(e).copied() {
+            Some(creature) => {
+                reward += self.hunger - creature.hunger;
+                reward += EAT_BONUS * (creature.eaten - self.eaten) as f32;
+                self.hunger = creature.hunger;
+                self.eaten = creature.eaten;
+            }
+            _ => {
+                reward += DEATH_PENALTY;
+                self.done = true;
+            }
+        }
+        let time = SimTime::new(self.game.data.config.sim_speed);
+        // another step would roll over into the next generation
+        if self.game.time + time.advance(ENV_STEP) > time.generation() {
+            self.done = true;
+        }
+        Ok((self.observation(), reward, self.done))
+    }
+
+    /// Senses the world for the controlled creature right away, rather than
+    /// at the next step
+    fn sense(&mut self) -> Observation {
+        let game = &mut self.game;
+        let all: Vec<_> = game.creatures.iter().chain(&game.foods).copied().collect();
+        senses::input_system(&mut game.data, iter::once(self.creature), all, None)
+            .expect("a fresh creature has the inputs it needs");
+        self.observation()
+    }
+
+    /// The inputs of the controlled creature, all zeros once it's dead
+    fn observation(&self) -> Observation {
+        let len = self.config.brain.spec(Kind::Vegan).input_len;
+        match self.game.data.get::<Inputs>(self.creature) {
+            Some(inputs) if !self.done => inputs.input.iter().copied().collect(),
+            _ => vec![0.0; len],
+        }
+    }
+}
+
+/// Sets the outputs of `e` so it heads towards direction `action` and
+/// doesn't lunge, every other direction goes to -inf so neither picking the
+/// strongest output nor sampling can land on it
+fn decide(data: &mut GameData, e: Entity, action: usize) {
+    if let Some(outputs) = data.get_mut::<Outputs>(e) {
+        let len = outputs.output.len();
+        let mut output = DVector::from_element(len, f32::NEG_INFINITY);
+        output[action] = 1.0;
+        if LUNGE_OUTPUT < len {
+            output[LUNGE_OUTPUT] = 0.0;
+        }
+        *outputs = Outputs { output };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::creature::{Direction, Food, Position};
+    use crate::data::Has;
+
+    fn env() -> Env {
+        Env::new(Config {
+            seed: 3,
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn the_action_decides_where_the_creature_heads() {
+        let mut env = env();
+        let e = env.creature;
+        for &action in &[3, 11] {
+            let (observation, _, done) = env.step(action).unwrap();
+            assert!(!done);
+            assert_eq!(
+                observation.len(),
+                env.config.brain.spec(Kind::Vegan).input_len
+            );
+            let angle = (360.0 / DIR_COUNT as f32 * action as f32).to_radians();
+            assert_eq!(env.game.data[e.component::<Direction>()].direction, angle);
+        }
+    }
+
+    #[test]
+    fn eating_is_rewarded() {
+        let mut env = env();
+        let e = env.creature;
+        // a piece of food right where the creature is
+        let food = env.game.foods[0];
+        let position = env.game.data[e.component::<Position>()].position;
+        env.game.data[food.component::<Position>()].position = position;
+        let (_, reward, done) = env.step(0).unwrap();
+        assert!(!done);
+        assert_eq!(env.eaten, 1);
+        assert!(reward > EAT_BONUS * 0.5, "reward {}", reward);
+        assert!(!env.game.data.has(food.component::<Food>()));
+    }
+
+    #[test]
+    fn dying_ends_the_episode() {
+        let mut env = env();
+        let e = env.creature;
+        let starve = env.game.data[e.component::<Creature>()].starve();
+        env.game.data[e.component::<Creature>()].hunger = starve * 2.0;
+        let (observation, reward, done) = env.step(0).unwrap();
+        assert!(done);
+        assert_eq!(reward, DEATH_PENALTY);
+        assert!(observation.iter().all(|&x| x == 0.0));
+
+        let observation = env.reset(3);
+        assert!(!env.done);
+        assert!(observation.iter().any(|&x| x != 0.0));
+    }
+
+    #[test]
+    fn the_generation_ending_ends_the_episode() {
+        let mut env = env();
+        let time = SimTime::new(env.game.data.config.sim_speed);
+        env.game.time = time.generation() - time.advance(ENV_STEP) * 1.5;
+        let (_, _, done) = env.step(0).unwrap();
+        assert!(done);
+        // the world didn't roll over
+        assert_eq!(env.game.generation, 0);
+    }
+}
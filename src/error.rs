@@ -0,0 +1,138 @@
+use std::fmt;
+use std::io;
+
+use ggez::GameError;
+
+/// Everything that can go wrong loading and saving files or setting up the
+/// game, callers decide whether to fall back, go without or give up
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// A file that couldn't be encoded or decoded
+    Serde(String),
+    /// A save from another version of the game
+    FormatVersion {
+        found: u32,
+        expected: u32,
+    },
+    /// A network that doesn't fit the inputs and outputs it's given
+    ShapeMismatch(String),
+    Audio(GameError),
+    /// Meshes that couldn't be built for a restored world
+    Graphics(GameError),
+    /// A step of a world run without a window that failed
+    Simulation(GameError),
+    /// A setting that doesn't exist or can't take a value
+    Config(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Serde(err) => write!(f, "couldn't decode or encode: {}", err),
+            Error::FormatVersion { found, expected } => {
+                write!(f, "saved with version {}, expected {}", found, expected)
+            }
+            Error::ShapeMismatch(problem) => write!(f, "{}", problem),
+            Error::Audio(err) => write!(f, "couldn't play audio: {}", err),
+            Error::Graphics(err) => write!(f, "couldn't build meshes: {}", err),
+            Error::Simulation(err) => write!(f, "couldn't step the world: {}", err),
+            Error::Config(problem) => write!(f, "{}", problem),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        Error::Serde(err.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Error::Serde(err.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(err: toml::ser::Error) -> Self {
+        Error::Serde(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Serde(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use crate::brain;
+    use crate::config::Config;
+    use crate::creature::{Creature, Genome, Kind};
+    use crate::resources::SimRng;
+    use crate::save::{Elites, SAVE_VERSION};
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("ldjam-46-error-{}-{}.bin", name, process::id()))
+    }
+
+    #[test]
+    fn saves_of_other_versions_are_named() {
+        let path = temp_path("version");
+        fs::write(&path, bincode::serialize(&(SAVE_VERSION + 1)).unwrap()).unwrap();
+        let loaded = Elites::load(&path);
+        fs::remove_file(&path).unwrap();
+        match loaded {
+            Err(Error::FormatVersion { found, expected }) => {
+                assert_eq!((found, expected), (SAVE_VERSION + 1, SAVE_VERSION))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_saves_dont_decode() {
+        let mut rng = SimRng::new(3);
+        let kind = Kind::Vegan;
+        let network = brain::new_for(&Config::default(), kind, &mut rng);
+        let elites = Elites {
+            vegans: vec![(Creature::new(kind), Genome::random(kind, &mut rng), network)],
+            carnivores: Vec::new(),
+        };
+        let path = temp_path("truncated");
+        elites.save(&path, 1).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let loaded = Elites::load(&path);
+        fs::remove_file(&path).unwrap();
+        match loaded {
+            Err(Error::Serde(_)) => {}
+            other => panic!("{:?}", other),
+        }
+
+        match Elites::load(&path) {
+            Err(Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+            other => panic!("{:?}", other),
+        }
+    }
+}
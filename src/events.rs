@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::Entity;
+
+/// Events the queue holds by default before it drops the oldest
+pub const EVENT_CAPACITY: usize = 4096;
+
+/// Something that happened in a system, queued on `GameData::events` until
+/// `GameData::drain_events` takes it
+///
+/// Eaten and starved entities are already marked for deletion by the time
+/// anyone sees the event, only their handles are left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEvent {
+    /// A creature ate food or another creature
+    Ate {
+        eater: Entity,
+        eaten: Entity,
+    },
+    /// Two creatures mated, `children` can be 0 for parents past their prime
+    Mated {
+        a: Entity,
+        b: Entity,
+        children: usize,
+    },
+    Starved(Entity),
+    /// A sated creature gave `amount` of its satiety to hungry kin, which got
+    /// less of it after `share_efficiency`
+    Shared {
+        donor: Entity,
+        recipient: Entity,
+        amount: f32,
+    },
+}
+
+/// How many of each event happened, over a generation in `GameState`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct EventTally {
+    pub meals: usize,
+    pub matings: usize,
+    pub children: usize,
+    pub starved: usize,
+    pub shares: usize,
+}
+
+impl EventTally {
+    pub fn record(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::Ate { .. } => self.meals += 1,
+            GameEvent::Mated { children, .. } => {
+                self.matings += 1;
+                self.children += children;
+            }
+            GameEvent::Starved(_) => self.starved += 1,
+            GameEvent::Shared { .. } => self.shares += 1,
+        }
+    }
+}
+
+/// How many of each event an `EventQueue` dropped to stay in its capacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EventDrops {
+    pub meals: usize,
+    pub matings: usize,
+    pub starved: usize,
+    pub shares: usize,
+}
+
+impl EventDrops {
+    fn record(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Ate { .. } => self.meals += 1,
+            GameEvent::Mated { .. } => self.matings += 1,
+            GameEvent::Starved(_) => self.starved += 1,
+            GameEvent::Shared { .. } => self.shares += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.meals + self.matings + self.starved + self.shares
+    }
+}
+
+impl fmt::Display for EventDrops {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} meals, {} matings, {} starvations, {} shares",
+            self.meals, self.matings, self.starved, self.shares
+        )
+    }
+}
+
+/// Events waiting to be drained, once `capacity` of them are waiting every
+/// new one pushes the oldest out and counts it in `dropped`
+///
+/// Whatever is read from here can be incomplete, anything that has to be
+/// exact, like picking the elites, looks at the world instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventQueue {
+    events: VecDeque<GameEvent>,
+    capacity: usize,
+    /// Since the last `clear`
+    pub dropped: EventDrops,
+}
+
+impl EventQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            dropped: EventDrops::default(),
+        }
+    }
+
+    pub fn push(&mut self, event: GameEvent) {
+        if self.capacity == 0 {
+            self.dropped.record(&event);
+            return;
+        }
+        if self.events.len() == self.capacity {
+            if let Some(oldest) = self.events.pop_front() {
+                self.dropped.record(&oldest);
+            }
+        }
+        self.events.push_back(event);
+    }
+
+    /// Takes every event, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = GameEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, dropping the oldest events that don't fit
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.events.len() > capacity {
+            if let Some(oldest) = self.events.pop_front() {
+                self.dropped.record(&oldest);
+            }
+        }
+        self.events.reserve(capacity - self.events.len());
+    }
+
+    /// Drops every event without counting them and resets the counters
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.dropped = EventDrops::default();
+    }
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new(EVENT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starved(idx: usize) -> GameEvent {
+        GameEvent::Starved(Entity { idx })
+    }
+
+    fn ate(idx: usize) -> GameEvent {
+        GameEvent::Ate {
+            eater: Entity { idx },
+            eaten: Entity { idx: idx + 1 },
+        }
+    }
+
+    #[test]
+    fn overflowing_drops_the_oldest_and_counts_them() {
+        let mut queue = EventQueue::new(3);
+        queue.push(ate(0));
+        queue.push(starved(1));
+        queue.push(ate(2));
+        queue.push(starved(3));
+        queue.push(starved(4));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(
+            queue.dropped,
+            EventDrops {
+                meals: 1,
+                matings: 0,
+                starved: 1,
+                shares: 0,
+            }
+        );
+        let left: Vec<_> = queue.drain().collect();
+        assert_eq!(left, vec![ate(2), starved(3), starved(4)]);
+        // draining isn't dropping
+        assert_eq!(queue.dropped.total(), 2);
+    }
+
+    #[test]
+    fn shrinking_drops_the_oldest() {
+        let mut queue = EventQueue::new(4);
+        for idx in 0..4 {
+            queue.push(starved(idx));
+        }
+        queue.set_capacity(1);
+        assert_eq!(queue.dropped.starved, 3);
+        assert_eq!(queue.drain().collect::<Vec<_>>(), vec![starved(3)]);
+    }
+
+    #[test]
+    fn no_capacity_drops_everything() {
+        let mut queue = EventQueue::new(0);
+        queue.push(ate(0));
+        assert!(queue.is_empty());
+        assert_eq!(queue.dropped.meals, 1);
+        queue.clear();
+        assert_eq!(queue.dropped, EventDrops::default());
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use crate::config::{Config, FocusPolicy};
+
+/// How long a frame sleeps while the window is in the background and the
+/// simulation is paused or throttled, so the loop doesn't spin
+pub const BACKGROUND_SLEEP: Duration = Duration::from_millis(20);
+
+/// Whether the window has focus and what that means for the simulation, see
+/// `FocusPolicy`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Focus {
+    focused: bool,
+    /// Set when focus comes back, the frame after covers time spent in the
+    /// background
+    refocused: bool,
+    /// Real seconds since the last throttled update
+    timeout: f32,
+}
+
+impl Default for Focus {
+    fn default() -> Self {
+        Self {
+            focused: true,
+            refocused: false,
+            timeout: 0.0,
+        }
+    }
+}
+
+impl Focus {
+    pub fn set(&mut self, focused: bool) {
+        if focused && !self.focused {
+            self.refocused = true;
+        }
+        self.focused = focused;
+        self.timeout = 0.0;
+    }
+
+    /// Real seconds the simulation should advance by this frame, `None` to
+    /// skip it
+    ///
+    /// The first frame back in focus is skipped too, it's time spent in the
+    /// background and would otherwise show up as lost to clamping.
+    pub fn frame(&mut self, config: &Config, real: f32) -> Option<f32> {
+        if self.refocused {
+            self.refocused = false;
+            return None;
+        }
+        if self.focused {
+            return Some(real);
+        }
+        match config.unfocused {
+            FocusPolicy::Continue => Some(real),
+            FocusPolicy::Pause => None,
+            FocusPolicy::Throttle => {
+                self.timeout += real;
+                let interval = config.unfocused_update_rate.recip();
+                if self.timeout < interval {
+                    return None;
+                }
+                let real = self.timeout;
+                self.timeout = 0.0;
+                Some(real)
+            }
+        }
+    }
+
+    /// Whether the game loop should sleep this frame
+    pub fn idle(&self, config: &Config) -> bool {
+        !self.focused && config.unfocused != FocusPolicy::Continue
+    }
+
+    /// What the music volume gets multiplied by
+    pub fn volume(&self, config: &Config) -> f32 {
+        if self.focused {
+            1.0
+        } else {
+            config.unfocused_volume
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::GameState;
+
+    const FRAME: f32 = 1.0 / 60.0;
+
+    fn config(unfocused: FocusPolicy) -> Config {
+        Config {
+            unfocused,
+            unfocused_update_rate: 5.0,
+            ..Config::default()
+        }
+    }
+
+    /// What `frame` gives for `count` frames in the background
+    fn background(focus: &mut Focus, config: &Config, count: usize) -> Vec<Option<f32>> {
+        focus.set(false);
+        (0..count).map(|_| focus.frame(config, FRAME)).collect()
+    }
+
+    #[test]
+    fn focused_frames_pass_through() {
+        let mut focus = Focus::default();
+        let config = config(FocusPolicy::Pause);
+        assert_eq!(focus.frame(&config, FRAME), Some(FRAME));
+        assert!(!focus.idle(&config));
+        assert_eq!(focus.volume(&config), 1.0);
+    }
+
+    #[test]
+    fn continue_ignores_focus() {
+        let mut focus = Focus::default();
+        let config = config(FocusPolicy::Continue);
+        let frames = background(&mut focus, &config, 3);
+        assert_eq!(frames, vec![Some(FRAME); 3]);
+        assert!(!focus.idle(&config));
+        assert_eq!(focus.volume(&config), config.unfocused_volume);
+    }
+
+    #[test]
+    fn pause_skips_every_background_frame() {
+        let mut focus = Focus::default();
+        let config = config(FocusPolicy::Pause);
+        let frames = background(&mut focus, &config, 30);
+        assert!(frames.iter().all(Option::is_none));
+        assert!(focus.idle(&config));
+    }
+
+    #[test]
+    fn throttle_updates_a_few_times_a_second() {
+        let mut focus = Focus::default();
+        let config = config(FocusPolicy::Throttle);
+        let frames = background(&mut focus, &config, 60);
+        let updates: Vec<_> = frames.iter().flatten().collect();
+        assert_eq!(updates.len(), 5);
+        // each update covers the frames it skipped
+        for &&real in &updates {
+            assert!((real - 0.2).abs() < FRAME, "{}", real);
+        }
+        assert!(focus.idle(&config));
+    }
+
+    #[test]
+    fn background_time_isnt_lost_time() {
+        let config = Config {
+            seed: 3,
+            chronicle: false,
+            ..config(FocusPolicy::Pause)
+        };
+        let mut game = GameState::empty(0, config);
+        let mut focus = Focus::default();
+        background(&mut focus, &game.data.config, 10);
+        focus.set(true);
+        // the first frame back spans however long the window was away
+        for &real in &[30.0, FRAME, FRAME] {
+            if let Some(real) = focus.frame(&game.data.config, real) {
+                game.frame_delta(real);
+            }
+        }
+        assert_eq!(game.lost_time, 0.0);
+        assert_eq!(game.clamped_frames, 0);
+        assert_eq!(focus.frame(&game.data.config, FRAME), Some(FRAME));
+    }
+}
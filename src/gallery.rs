@@ -0,0 +1,296 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect, Text};
+use ggez::{Context, GameResult};
+
+use ordered_float::OrderedFloat;
+
+use crate::brain;
+use crate::config::Config;
+use crate::creature::*;
+use crate::data::GameData;
+use crate::draw;
+use crate::error::Result;
+use crate::nn::Network;
+use crate::resources::SimRng;
+use crate::save::{Elites, RunArchive};
+use crate::spawn;
+use crate::{DPI_FACTOR, HEIGHT, MAX_RADIUS, WIDTH};
+
+/// Height of one row of the gallery, enough for the biggest thumbnail
+pub const ROW_HEIGHT: f32 = 2.0 * MAX_RADIUS * DPI_FACTOR + 8.0 * DPI_FACTOR;
+
+/// One saved elite
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Generation of the save it came from
+    pub generation: usize,
+    pub creature: Creature,
+    pub genome: Genome,
+    pub network: Network,
+}
+
+/// The elites of every save in a directory, newest generation first and the
+/// fittest first within a generation
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Gallery {
+    pub entries: Vec<Entry>,
+    pub selected: usize,
+    /// Saves that couldn't be read, with the reason
+    pub skipped: Vec<String>,
+}
+
+impl Gallery {
+    /// Reads every save in `dir`, saves that are missing, corrupt or from
+    /// another version are skipped instead of failing the whole gallery
+    pub fn scan<P: AsRef<Path>>(dir: P, config: &Config) -> Result<Self> {
+        let archive = RunArchive::open(dir)?;
+        let saves = archive
+            .generations()
+            .map(|meta| (meta.generation, archive.load_generation(meta.generation)))
+            .collect();
+        let mut gallery = Self::from_saves(saves, config);
+        gallery.skipped.extend(archive.problems().iter().cloned());
+        Ok(gallery)
+    }
+
+    pub fn from_saves(saves: Vec<(usize, Result<Elites>)>, config: &Config) -> Self {
+        let mut gallery = Self::default();
+        for (generation, elites) in saves {
+            let elites = match elites {
+                Ok(elites) => elites,
+                Err(err) => {
+                    gallery
+                        .skipped
+                        .push(format!("gen{}.bin: {}", generation, err));
+                    continue;
+                }
+            };
+            for (creature, genome, network) in elites.carnivores.into_iter().chain(elites.vegans) {
+                gallery.entries.push(Entry {
+                    generation,
+                    creature,
+                    genome,
+                    network,
+                });
+            }
+        }
+        gallery.entries.sort_by_key(|entry| {
+            (
+                std::cmp::Reverse(entry.generation),
+                std::cmp::Reverse(OrderedFloat::from(entry.creature.shared_fitness(config))),
+            )
+        });
+        gallery
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn prev(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Spawns a mutated copy of the selected elite somewhere in the world, it
+    /// gets a fresh brain if its own doesn't fit the configured one
+    pub fn inject(&self, data: &mut GameData) {
+        let entry = match self.selected() {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let kind = entry.creature.kind;
+        let rates = data.annealing.rates(&data.config);
+        let mut rng = data.take_resource::<SimRng>();
+        let genome = entry
+            .genome
+            .cross(&entry.genome, &data.config, rates, &mut rng);
+        let network = if data.config.brain.matches(&entry.network, kind) {
+            entry.network.clone()
+        } else {
+            brain::new_for(&data.config, kind, &mut rng)
+        };
+        let position = spawn::random_position(&data.config, genome.radius, &mut rng);
+        data.insert_resource(rng);
+        spawn::creature_with(&mut data.lazy, position, kind, &genome, network);
+    }
+
+    /// Writes the creature and genome of the selected elite to a JSON file
+    pub fn export<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let entry = match self.selected() {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+
+        let encoded = serde_json::to_string_pretty(&(entry.creature, entry.genome))?;
+        fs::write(path, encoded)
+    }
+
+    /// Rows around the selected one that fit on the screen
+    fn visible(&self) -> std::ops::Range<usize> {
+        let rows = ((HEIGHT / ROW_HEIGHT) as usize).max(1);
+        let start = self.selected.saturating_sub(rows / 2);
+        start..(start + rows).min(self.entries.len())
+    }
+
+    pub fn draw(&self, ctx: &mut Context, config: &Config) -> GameResult<()> {
+        draw::screen_view(ctx)?;
+        let background = Rect::new(0.0, 0.0, WIDTH, HEIGHT);
+        let mut builder = MeshBuilder::new();
+        builder.rectangle(
+            DrawMode::fill(),
+            background,
+            Color::new(0.0, 0.0, 0.0, 0.85),
+        );
+
+        let visible = self.visible();
+        for (row, i) in visible.clone().enumerate() {
+            let entry = &self.entries[i];
+            let y = (row as f32 + 0.5) * ROW_HEIGHT;
+            let x = MAX_RADIUS * DPI_FACTOR + 8.0 * DPI_FACTOR;
+            let r = entry.genome.radius * DPI_FACTOR;
+            if i == self.selected {
+                let highlight = Rect::new(0.0, y - ROW_HEIGHT * 0.5, WIDTH, ROW_HEIGHT);
+                builder.rectangle(DrawMode::fill(), highlight, Color::new(1.0, 1.0, 1.0, 0.15));
+            }
+            match entry.creature.kind {
+                Kind::Vegan => {
+                    builder.circle(DrawMode::fill(), [x, y], r, 0.25, entry.genome.color);
+                }
+                Kind::Carnivorous => {
+                    let points = [[x + r, y], [x - r * 0.5, y - r], [x - r * 0.5, y + r]];
+                    builder.polygon(DrawMode::fill(), &points, entry.genome.color)?;
+                }
+            }
+        }
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())?;
+
+        for (row, i) in visible.enumerate() {
+            let entry = &self.entries[i];
+            let c = entry.creature;
+            let text = Text::new(format!(
+                "{:?} from gen {}: life {:.1}, eaten {}, children {}, fitness {:.1}, shared {:.1}",
+                c.kind,
+                entry.generation,
+                c.life,
+                c.eaten,
+                c.children,
+                c.fitness(config),
+                c.shared_fitness(config)
+            ));
+            let (_, h) = text.dimensions(ctx);
+            let dest = [
+                2.0 * MAX_RADIUS * DPI_FACTOR + 16.0 * DPI_FACTOR,
+                (row as f32 + 0.5) * ROW_HEIGHT - h as f32 * 0.5,
+            ];
+            graphics::draw(ctx, &text, DrawParam::new().dest(dest))?;
+        }
+
+        let footer = if self.entries.is_empty() {
+            format!("no elites found, {} saves skipped", self.skipped.len())
+        } else {
+            format!(
+                "{}/{}, Enter spawns a copy, D exports its card, {} saves skipped",
+                self.selected + 1,
+                self.entries.len(),
+                self.skipped.len()
+            )
+        };
+        let text = Text::new(footer);
+        let (w, h) = text.dimensions(ctx);
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::new().dest([WIDTH - w as f32 - 4.0, HEIGHT - h as f32 - 4.0]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::Error;
+    use crate::save::SavedCreature;
+
+    fn elite(kind: Kind, score: usize, rng: &mut SimRng) -> SavedCreature {
+        let mut creature = Creature::new(kind);
+        creature.life = score as f32;
+        creature.eaten = score;
+        creature.children = score;
+        let network = brain::new_for(&Config::default(), kind, rng);
+        (creature, Genome::random(kind, rng), network)
+    }
+
+    fn gallery() -> Gallery {
+        let mut rng = SimRng::new(1);
+        let old = Elites {
+            vegans: vec![elite(Kind::Vegan, 9, &mut rng)],
+            carnivores: Vec::new(),
+        };
+        let new = Elites {
+            vegans: vec![
+                elite(Kind::Vegan, 1, &mut rng),
+                elite(Kind::Vegan, 5, &mut rng),
+            ],
+            carnivores: vec![elite(Kind::Carnivorous, 3, &mut rng)],
+        };
+        let broken = Err(Error::Config("corrupt".to_owned()));
+        Gallery::from_saves(
+            vec![(2, Ok(old)), (4, Ok(new)), (3, broken)],
+            &Config::default(),
+        )
+    }
+
+    #[test]
+    fn newest_and_fittest_come_first() {
+        let gallery = gallery();
+        let order: Vec<_> = gallery
+            .entries
+            .iter()
+            .map(|entry| (entry.generation, entry.creature.eaten))
+            .collect();
+        assert_eq!(order[0].0, 4);
+        assert_eq!(order.last(), Some(&(2, 9)));
+        let config = Config::default();
+        let fitness: Vec<_> = gallery.entries[..3]
+            .iter()
+            .map(|entry| entry.creature.shared_fitness(&config))
+            .collect();
+        assert!(fitness.windows(2).all(|w| w[0] >= w[1]), "{:?}", fitness);
+        assert_eq!(gallery.skipped.len(), 1);
+        assert!(gallery.skipped[0].starts_with("gen3.bin"));
+    }
+
+    #[test]
+    fn the_selection_stays_on_the_list() {
+        let mut gallery = gallery();
+        gallery.prev();
+        assert_eq!(gallery.selected, 0);
+        for _ in 0..10 {
+            gallery.next();
+        }
+        assert_eq!(gallery.selected, gallery.entries.len() - 1);
+        assert!(Gallery::default().selected().is_none());
+    }
+
+    #[test]
+    fn injected_elites_join_at_the_next_commit() {
+        let mut data = GameData::new();
+        let gallery = gallery();
+        gallery.inject(&mut data);
+        assert_eq!(data.creature_count(), 1);
+        Gallery::default().inject(&mut data);
+        assert_eq!(data.creature_count(), 1);
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect, Text};
+use ggez::{Context, GameResult};
+
+use nalgebra::Vector2;
+
+use crate::collision::Body;
+use crate::creature::*;
+use crate::data::{Entity, GameData, Has};
+use crate::draw;
+use crate::HEIGHT;
+
+/// Creatures picked by dragging a rectangle over them
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Group {
+    /// Where the drag started and where the mouse is now, in world units
+    pub drag: Option<(Vector2<f32>, Vector2<f32>)>,
+    pub members: Vec<Entity>,
+    /// Entity indices start over every generation, so a group only lasts one
+    pub generation: usize,
+}
+
+/// Aggregates over the members of a group
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GroupStats {
+    pub count: usize,
+    pub vegans: usize,
+    pub carnivores: usize,
+    pub mean_hunger: f32,
+    pub mean_life: f32,
+    /// Mean genome color distance over all pairs of members
+    pub mean_color_distance: f32,
+}
+
+/// Smallest rectangle with both corners in it
+pub fn rect(a: Vector2<f32>, b: Vector2<f32>) -> Rect {
+    let min = Vector2::new(a.x.min(b.x), a.y.min(b.y));
+    let max = Vector2::new(a.x.max(b.x), a.y.max(b.y));
+    Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+}
+
+impl Group {
+    /// Follows the members to their new handles after `GameData::compact`
+    pub fn remap(&mut self, map: &HashMap<Entity, Entity>) {
+        self.members = self
+            .members
+            .iter()
+            .filter_map(|e| map.get(e).copied())
+            .collect();
+    }
+
+    pub fn start(&mut self, position: Vector2<f32>) {
+        self.drag = Some((position, position));
+    }
+
+    pub fn drag_to(&mut self, position: Vector2<f32>) {
+        if let Some((_, end)) = &mut self.drag {
+            *end = position;
+        }
+    }
+
+    /// Ends the drag and selects every creature whose center is inside it
+    pub fn finish(&mut self, data: &GameData, generation: usize, creatures: &[Entity]) {
+        let (start, end) = match self.drag.take() {
+            Some(drag) => drag,
+            None => return,
+        };
+        let rect = rect(start, end);
+        self.generation = generation;
+        self.members = creatures
+            .iter()
+            .copied()
+            .filter(|e| {
+                let position = data[e.component::<Position>()].position;
+                rect.contains([position.x, position.y])
+            })
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.drag = None;
+        self.members.clear();
+    }
+
+    /// Members that are still alive, forgets everything after a rollover
+    pub fn living(&mut self, data: &GameData, generation: usize) -> Vec<Entity> {
+        if generation != self.generation {
+            self.clear();
+            self.generation = generation;
+        }
+        self.members.retain(|e| data.has(e.component::<Creature>()));
+        self.members.clone()
+    }
+}
+
+pub fn stats(data: &GameData, members: &[Entity]) -> GroupStats {
+    let mut stats = GroupStats {
+        count: members.len(),
+        ..GroupStats::default()
+    };
+    if members.is_empty() {
+        return stats;
+    }
+
+    for e in members {
+        let creature = data[e.component::<Creature>()];
+        match creature.kind {
+            Kind::Vegan => stats.vegans += 1,
+            Kind::Carnivorous => stats.carnivores += 1,
+        }
+        stats.mean_hunger += creature.hunger;
+        stats.mean_life += creature.life;
+    }
+    stats.mean_hunger /= members.len() as f32;
+    stats.mean_life /= members.len() as f32;
+
+    let mut pairs = 0;
+    for (i, a) in members.iter().enumerate() {
+        for b in &members[i + 1..] {
+            let a = data[a.component::<Genome>()].color;
+            let b = data[b.component::<Genome>()].color;
+            stats.mean_color_distance += color_distance(a, b);
+            pairs += 1;
+        }
+    }
+    if pairs > 0 {
+        stats.mean_color_distance /= pairs as f32;
+    }
+    stats
+}
+
+/// Writes the creature and genome of every member to a JSON file
+pub fn export<P: AsRef<Path>>(data: &GameData, members: &[Entity], path: P) -> io::Result<()> {
+    let cards: Vec<(Creature, Genome)> = members
+        .iter()
+        .map(|e| {
+            (
+                data[e.component::<Creature>()],
+                data[e.component::<Genome>()],
+            )
+        })
+        .collect();
+    let encoded = serde_json::to_string_pretty(&cards)?;
+    fs::write(path, encoded)
+}
+
+/// Draws the drag rectangle, a ring around every member and the stats in the
+/// bottom left corner
+pub fn draw(
+    ctx: &mut Context,
+    data: &GameData,
+    group: &Group,
+    members: &[Entity],
+) -> GameResult<()> {
+    draw::world_view(ctx)?;
+    let white = Color::new(1.0, 1.0, 1.0, 1.0);
+    let mut builder = MeshBuilder::new();
+    let mut empty = true;
+    if let Some((start, end)) = group.drag {
+        builder.rectangle(DrawMode::stroke(1.0), rect(start, end), white);
+        empty = false;
+    }
+    for e in members {
+        let position = data[e.component::<Position>()].position;
+        let radius = data[e.component::<Body>()].radius;
+        builder.circle(
+            DrawMode::stroke(1.0),
+            [position.x, position.y],
+            radius + 2.0,
+            0.25,
+            white,
+        );
+        empty = false;
+    }
+    if !empty {
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())?;
+    }
+
+    if members.is_empty() {
+        return Ok(());
+    }
+
+    draw::screen_view(ctx)?;
+    let stats = stats(data, members);
+    let text = Text::new(format!(
+        "group: {} ({} vegans, {} carnivores)\nmean hunger {:.1}, mean life {:.1}\nmean color distance {:.2}",
+        stats.count,
+        stats.vegans,
+        stats.carnivores,
+        stats.mean_hunger,
+        stats.mean_life,
+        stats.mean_color_distance
+    ));
+    let (_, h) = text.dimensions(ctx);
+    graphics::draw(
+        ctx,
+        &text,
+        DrawParam::new().dest([4.0, HEIGHT - h as f32 - 4.0]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::brain;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    /// A vegan, a carnivore and another vegan at `positions`
+    fn world(positions: [Vector2<f32>; 3]) -> (GameData, Vec<Entity>) {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let kinds = [Kind::Vegan, Kind::Carnivorous, Kind::Vegan];
+        let creatures = kinds
+            .iter()
+            .zip(&positions)
+            .map(|(&kind, &position)| {
+                let genome = Genome::random(kind, &mut rng);
+                let brain = brain::new_for(&data.config, kind, &mut rng);
+                spawn::creature_with(&mut data, position, kind, &genome, brain)
+            })
+            .collect();
+        (data, creatures)
+    }
+
+    #[test]
+    fn dragging_selects_whats_inside() {
+        let (data, creatures) = world([
+            Vector2::new(10.0, 10.0),
+            Vector2::new(30.0, 20.0),
+            Vector2::new(80.0, 10.0),
+        ]);
+        let mut group = Group::default();
+        // dragged up and to the left
+        group.start(Vector2::new(50.0, 40.0));
+        group.drag_to(Vector2::new(0.0, 0.0));
+        group.finish(&data, 3, &creatures);
+        assert_eq!(group.drag, None);
+        assert_eq!(group.members, &creatures[..2]);
+        assert_eq!(group.generation, 3);
+    }
+
+    #[test]
+    fn groups_only_last_a_generation() {
+        let origin = Vector2::new(0.0, 0.0);
+        let (mut data, creatures) = world([origin; 3]);
+        let mut group = Group {
+            members: creatures.clone(),
+            generation: 1,
+            ..Group::default()
+        };
+        data.delete(creatures[0]);
+        data.lazy.remove(creatures[0]);
+        data.commit();
+        assert_eq!(group.living(&data, 1), &creatures[1..]);
+        assert!(group.living(&data, 2).is_empty());
+    }
+
+    #[test]
+    fn stats_add_up_the_members() {
+        let origin = Vector2::new(0.0, 0.0);
+        let (mut data, creatures) = world([origin; 3]);
+        for (i, e) in creatures.iter().enumerate() {
+            let creature = &mut data[e.component::<Creature>()];
+            creature.hunger = i as f32;
+            creature.life = 10.0 * i as f32;
+        }
+        let stats = stats(&data, &creatures[..2]);
+        assert_eq!((stats.count, stats.vegans, stats.carnivores), (2, 1, 1));
+        assert_eq!((stats.mean_hunger, stats.mean_life), (0.5, 5.0));
+        let a = data[creatures[0].component::<Genome>()].color;
+        let b = data[creatures[1].component::<Genome>()].color;
+        assert_eq!(stats.mean_color_distance, color_distance(a, b));
+        assert_eq!(super::stats(&data, &[]), GroupStats::default());
+    }
+}
@@ -0,0 +1,401 @@
+use ggez::event::{KeyCode, KeyMods, MouseButton};
+
+use serde::{Deserialize, Serialize};
+
+/// Everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    TogglePanel,
+    ToggleSpecies,
+    ToggleContacts,
+    ToggleLabels,
+    ToggleThrottle,
+    ExportPolicy,
+    DescribeEntity,
+    Famine,
+    ExportGroup,
+    ToggleGallery,
+    TogglePaint,
+    ToggleMortality,
+    ToggleConsole,
+    AnnotatedScreenshot,
+    SaveConfig,
+    PanelNext,
+    PanelPrev,
+    PanelUp,
+    PanelDown,
+}
+
+/// Everything a mouse button can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseAction {
+    /// Drags a rectangle to pick a group of creatures
+    SelectGroup,
+    /// Makes food likelier under the cursor while painting
+    PaintMore,
+    /// Makes food less likely under the cursor while painting
+    PaintLess,
+}
+
+/// Keys that can be named in a binding
+const KEYS: &[KeyCode] = &[
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Key0,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Escape,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Left,
+    KeyCode::Up,
+    KeyCode::Right,
+    KeyCode::Down,
+    KeyCode::Back,
+    KeyCode::Return,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Minus,
+    KeyCode::Equals,
+    KeyCode::Grave,
+];
+
+/// A key together with the modifiers that have to be held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub key: KeyCode,
+    pub mods: KeyMods,
+}
+
+impl Binding {
+    /// Parses things like `"Ctrl+Shift+W"`, key names are the `KeyCode` names
+    pub fn parse(s: &str) -> Option<Self> {
+        let (key, mods) = parse_mods(s)?;
+        let key = *KEYS.iter().find(|k| format!("{:?}", k) == key)?;
+        Some(Self { key, mods })
+    }
+}
+
+/// Parses the modifiers in front of the last part of a binding
+fn parse_mods(s: &str) -> Option<(&str, KeyMods)> {
+    let mut parts: Vec<_> = s.split('+').map(str::trim).collect();
+    let last = parts.pop()?;
+    let mut mods = KeyMods::NONE;
+    for part in parts {
+        mods |= match part {
+            "Ctrl" => KeyMods::CTRL,
+            "Shift" => KeyMods::SHIFT,
+            "Alt" => KeyMods::ALT,
+            "Logo" => KeyMods::LOGO,
+            _ => return None,
+        };
+    }
+    Some((last, mods))
+}
+
+/// A mouse button together with the modifiers that have to be held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseBinding {
+    pub button: MouseButton,
+    pub mods: KeyMods,
+}
+
+impl MouseBinding {
+    /// Parses things like `"Alt+Left"`, buttons are `Left`, `Right` and
+    /// `Middle`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (button, mods) = parse_mods(s)?;
+        let button = match button {
+            "Left" => MouseButton::Left,
+            "Right" => MouseButton::Right,
+            "Middle" => MouseButton::Middle,
+            _ => return None,
+        };
+        Some(Self { button, mods })
+    }
+}
+
+/// Maps every action to a key and every mouse action to a button, see
+/// `Binding::parse` and `MouseBinding::parse` for the format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keybindings {
+    pub quit: String,
+    pub toggle_panel: String,
+    pub toggle_species: String,
+    pub toggle_contacts: String,
+    pub toggle_labels: String,
+    pub toggle_throttle: String,
+    pub export_policy: String,
+    pub describe_entity: String,
+    pub famine: String,
+    pub export_group: String,
+    pub toggle_gallery: String,
+    pub toggle_paint: String,
+    pub toggle_mortality: String,
+    pub toggle_console: String,
+    pub annotated_screenshot: String,
+    pub save_config: String,
+    pub panel_next: String,
+    pub panel_prev: String,
+    pub panel_up: String,
+    pub panel_down: String,
+    pub select_group: String,
+    pub paint_more: String,
+    pub paint_less: String,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            quit: "Escape".to_owned(),
+            toggle_panel: "F2".to_owned(),
+            toggle_species: "S".to_owned(),
+            toggle_contacts: "C".to_owned(),
+            toggle_labels: "I".to_owned(),
+            toggle_throttle: "F4".to_owned(),
+            export_policy: "H".to_owned(),
+            describe_entity: "D".to_owned(),
+            famine: "Shift+X".to_owned(),
+            export_group: "E".to_owned(),
+            toggle_gallery: "G".to_owned(),
+            toggle_paint: "P".to_owned(),
+            toggle_mortality: "M".to_owned(),
+            toggle_console: "Grave".to_owned(),
+            annotated_screenshot: "Shift+F12".to_owned(),
+            save_config: "Ctrl+Shift+W".to_owned(),
+            panel_next: "Tab".to_owned(),
+            panel_prev: "Shift+Tab".to_owned(),
+            panel_up: "Up".to_owned(),
+            panel_down: "Down".to_owned(),
+            select_group: "Ctrl+Left".to_owned(),
+            paint_more: "Left".to_owned(),
+            paint_less: "Right".to_owned(),
+        }
+    }
+}
+
+impl Keybindings {
+    /// Every action with the name of its key
+    pub fn actions(&self) -> [(Action, &str); 20] {
+        [
+            (Action::Quit, &self.quit),
+            (Action::TogglePanel, &self.toggle_panel),
+            (Action::ToggleSpecies, &self.toggle_species),
+            (Action::ToggleContacts, &self.toggle_contacts),
+            (Action::ToggleLabels, &self.toggle_labels),
+            (Action::ToggleThrottle, &self.toggle_throttle),
+            (Action::ExportPolicy, &self.export_policy),
+            (Action::DescribeEntity, &self.describe_entity),
+            (Action::Famine, &self.famine),
+            (Action::ExportGroup, &self.export_group),
+            (Action::ToggleGallery, &self.toggle_gallery),
+            (Action::TogglePaint, &self.toggle_paint),
+            (Action::ToggleMortality, &self.toggle_mortality),
+            (Action::ToggleConsole, &self.toggle_console),
+            (Action::AnnotatedScreenshot, &self.annotated_screenshot),
+            (Action::SaveConfig, &self.save_config),
+            (Action::PanelNext, &self.panel_next),
+            (Action::PanelPrev, &self.panel_prev),
+            (Action::PanelUp, &self.panel_up),
+            (Action::PanelDown, &self.panel_down),
+        ]
+    }
+
+    /// The action bound to a key, modifiers have to match exactly
+    pub fn resolve(&self, key: KeyCode, mods: KeyMods) -> Option<Action> {
+        let pressed = Binding { key, mods };
+        self.actions()
+            .iter()
+            .find(|(_, name)| Binding::parse(name) == Some(pressed))
+            .map(|(action, _)| *action)
+    }
+
+    /// Every mouse action with the name of its button
+    pub fn mouse_actions(&self) -> [(MouseAction, &str); 3] {
+        [
+            (MouseAction::SelectGroup, &self.select_group),
+            (MouseAction::PaintMore, &self.paint_more),
+            (MouseAction::PaintLess, &self.paint_less),
+        ]
+    }
+
+    /// The mouse action bound to a button, modifiers have to match exactly
+    pub fn resolve_mouse(&self, button: MouseButton, mods: KeyMods) -> Option<MouseAction> {
+        let pressed = MouseBinding { button, mods };
+        self.mouse_actions()
+            .iter()
+            .find(|(_, name)| MouseBinding::parse(name) == Some(pressed))
+            .map(|(action, _)| *action)
+    }
+
+    /// Describes every binding that can't be parsed or is used twice
+    pub fn problems(&self) -> Vec<String> {
+        let actions = self.actions();
+        let mut problems = Vec::new();
+        for (i, (action, name)) in actions.iter().enumerate() {
+            let binding = match Binding::parse(name) {
+                Some(binding) => binding,
+                None => {
+                    problems.push(format!("{:?} has an invalid key {:?}", action, name));
+                    continue;
+                }
+            };
+            for (other, other_name) in &actions[..i] {
+                if Binding::parse(other_name) == Some(binding) {
+                    problems.push(format!(
+                        "{:?} and {:?} are both bound to {:?}",
+                        other, action, name
+                    ));
+                }
+            }
+        }
+
+        let mouse_actions = self.mouse_actions();
+        for (i, (action, name)) in mouse_actions.iter().enumerate() {
+            let binding = match MouseBinding::parse(name) {
+                Some(binding) => binding,
+                None => {
+                    problems.push(format!("{:?} has an invalid button {:?}", action, name));
+                    continue;
+                }
+            };
+            for (other, other_name) in &mouse_actions[..i] {
+                if MouseBinding::parse(other_name) == Some(binding) {
+                    problems.push(format!(
+                        "{:?} and {:?} are both bound to {:?}",
+                        other, action, name
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_parse() {
+        assert_eq!(
+            Binding::parse("Ctrl + Shift+W"),
+            Some(Binding {
+                key: KeyCode::W,
+                mods: KeyMods::CTRL | KeyMods::SHIFT,
+            })
+        );
+        assert_eq!(Binding::parse("F12").map(|b| b.mods), Some(KeyMods::NONE));
+        assert_eq!(Binding::parse("Hyper+W"), None);
+        assert_eq!(Binding::parse("Ctrl+"), None);
+        assert_eq!(Binding::parse("w"), None);
+    }
+
+    #[test]
+    fn modifiers_have_to_match_exactly() {
+        let keys = Keybindings::default();
+        assert_eq!(
+            keys.resolve(KeyCode::Tab, KeyMods::NONE),
+            Some(Action::PanelNext)
+        );
+        assert_eq!(
+            keys.resolve(KeyCode::Tab, KeyMods::SHIFT),
+            Some(Action::PanelPrev)
+        );
+        assert_eq!(keys.resolve(KeyCode::Tab, KeyMods::CTRL), None);
+        assert_eq!(keys.resolve(KeyCode::X, KeyMods::NONE), None);
+    }
+
+    #[test]
+    fn the_defaults_have_no_problems() {
+        assert_eq!(Keybindings::default().problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn bad_and_doubled_keys_are_problems() {
+        let keys = Keybindings {
+            toggle_labels: "S".to_owned(),
+            famine: "Shift+Nope".to_owned(),
+            ..Keybindings::default()
+        };
+        let problems = keys.problems();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].contains("ToggleSpecies and ToggleLabels"));
+        assert!(problems[1].contains("Famine has an invalid key"));
+    }
+
+    #[test]
+    fn mouse_bindings_resolve() {
+        assert_eq!(
+            MouseBinding::parse("Alt+Middle"),
+            Some(MouseBinding {
+                button: MouseButton::Middle,
+                mods: KeyMods::ALT,
+            })
+        );
+        assert_eq!(MouseBinding::parse("Ctrl+Wheel"), None);
+
+        let keys = Keybindings::default();
+        let select = keys.resolve_mouse(MouseButton::Left, KeyMods::CTRL);
+        assert_eq!(select, Some(MouseAction::SelectGroup));
+        let paint = keys.resolve_mouse(MouseButton::Left, KeyMods::NONE);
+        assert_eq!(paint, Some(MouseAction::PaintMore));
+        assert_eq!(keys.resolve_mouse(MouseButton::Left, KeyMods::SHIFT), None);
+    }
+
+    #[test]
+    fn bad_and_doubled_buttons_are_problems() {
+        let keys = Keybindings {
+            select_group: "Left".to_owned(),
+            paint_less: "Back".to_owned(),
+            ..Keybindings::default()
+        };
+        let problems = keys.problems();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].contains("SelectGroup and PaintMore"));
+        assert!(problems[1].contains("PaintLess has an invalid button"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use ggez::graphics::{self, DrawParam, Text};
+use ggez::input::keyboard;
+use ggez::{Context, GameResult};
+
+use nalgebra::Vector2;
+
+use crate::collision::Body;
+use crate::creature::Position;
+use crate::data::{Entity, GameData};
+use crate::draw;
+
+/// Draws the entity index above every creature, and above food while shift
+/// is held
+///
+/// Entity indices start over every generation, so the cached texts are
+/// thrown away whenever the generation changes.
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    pub open: bool,
+    generation: usize,
+    cache: HashMap<usize, Text>,
+}
+
+impl Labels {
+    pub fn new(open: bool) -> Self {
+        Self {
+            open,
+            ..Self::default()
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Throws the cached texts away, for when entity indices changed within
+    /// a generation
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// The cached label of an entity, laid out the first time it's needed
+    fn text(&mut self, generation: usize, e: Entity) -> &Text {
+        if generation != self.generation {
+            self.generation = generation;
+            self.cache.clear();
+        }
+        self.cache
+            .entry(e.idx)
+            .or_insert_with(|| Text::new(format!("{}", e.idx)))
+    }
+
+    pub fn draw(
+        &mut self,
+        ctx: &mut Context,
+        data: &GameData,
+        generation: usize,
+        creatures: &[Entity],
+        foods: &[Entity],
+    ) -> GameResult<()> {
+        let foods = if keyboard::is_mod_active(ctx, keyboard::KeyMods::SHIFT) {
+            foods
+        } else {
+            &[]
+        };
+
+        // text drawn in world units would shrink with the window
+        draw::screen_view(ctx)?;
+        for &e in creatures.iter().chain(foods) {
+            let position = data[e.component::<Position>()].position;
+            let radius = data[e.component::<Body>()].radius;
+            let top = draw::to_screen(position - Vector2::new(0.0, radius + 2.0));
+            let text = self.text(generation, e);
+            let (w, h) = text.dimensions(ctx);
+            let dest = [top.x - w as f32 * 0.5, top.y - h as f32];
+            graphics::draw(ctx, text, DrawParam::new().dest(dest))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_new_generation_throws_the_labels_away() {
+        let mut labels = Labels::new(true);
+        assert_eq!(labels.text(0, Entity { idx: 5 }).contents(), "5");
+        labels.text(0, Entity { idx: 6 });
+        labels.text(0, Entity { idx: 5 });
+        assert_eq!(labels.cache.len(), 2);
+
+        assert_eq!(labels.text(1, Entity { idx: 5 }).contents(), "5");
+        assert_eq!(labels.cache.len(), 1);
+        labels.clear();
+        assert!(labels.cache.is_empty());
+    }
+}
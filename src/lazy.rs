@@ -1,16 +1,55 @@
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::ops::{Index, IndexMut};
 
 use crate::collision::*;
 use crate::creature::*;
-use crate::data::{Entity, Insert};
+pub use crate::data::Component;
+use crate::data::{Entity, GameData, Has, Insert, Remove};
 use crate::draw::*;
-use crate::nn::{Desired, Inputs, Network, Outputs};
+use crate::nn::{Desired, Experience, Inputs, Network, Outputs};
+
+/// An entity that only exists in a `LazyUpdate` so far
+///
+/// It becomes an `Entity` with a different index once it's committed, the
+/// index it gets is returned by `GameData::commit`, so this can't be used with
+/// a `GameData`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PendingEntity {
+    pub idx: usize,
+}
+
+impl PendingEntity {
+    /// Only valid for indexing the `LazyUpdate` this came from
+    pub fn component<T>(&self) -> PendingComponent<T> {
+        PendingComponent {
+            idx: self.idx,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Used to index into the corresponding `Vec<T>` in a `LazyUpdate`
+///
+/// Kept apart from `Component` so that indexing a `GameData` with the handle
+/// of a pending entity, which would read some unrelated committed row,
+/// doesn't compile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PendingComponent<T> {
+    idx: usize,
+    _phantom: PhantomData<T>,
+}
 
 /// A collection of lazily evaluated components
+///
+/// `Has` takes components of a `PendingEntity` and doesn't look at `remove`,
+/// which only ever holds committed entities, see `GameData::has_or_pending`
+/// for both.
 #[derive(Debug, PartialEq)]
 pub struct LazyUpdate {
     pub remove: Vec<Entity>,
+    /// Components to take off committed entities, see `RemoveLater`
+    pub removed: Vec<(Entity, Removal)>,
     pub entity: usize,
     pub creatures: Vec<Option<Creature>>,
     pub foods: Vec<Option<Food>>,
@@ -23,6 +62,15 @@ pub struct LazyUpdate {
     pub inputs: Vec<Option<Inputs>>,
     pub outputs: Vec<Option<Outputs>>,
     pub desired: Vec<Option<Desired>>,
+    pub experiences: Vec<Option<Experience>>,
+    pub genomes: Vec<Option<Genome>>,
+    pub appearances: Vec<Option<Appearance>>,
+}
+
+impl Default for LazyUpdate {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LazyUpdate {
@@ -30,6 +78,7 @@ impl LazyUpdate {
         Self {
             entity: 0,
             remove: Vec::new(),
+            removed: Vec::new(),
             creatures: Vec::new(),
             foods: Vec::new(),
             positions: Vec::new(),
@@ -41,10 +90,52 @@ impl LazyUpdate {
             inputs: Vec::new(),
             outputs: Vec::new(),
             desired: Vec::new(),
+            experiences: Vec::new(),
+            genomes: Vec::new(),
+            appearances: Vec::new(),
         }
     }
 
-    pub fn add_entity(&mut self) -> Entity {
+    /// Makes room for `additional` more pending entities in every storage
+    pub fn reserve(&mut self, additional: usize) {
+        self.creatures.reserve(additional);
+        self.foods.reserve(additional);
+        self.positions.reserve(additional);
+        self.velocities.reserve(additional);
+        self.directions.reserve(additional);
+        self.bodies.reserve(additional);
+        self.draw.reserve(additional);
+        self.nns.reserve(additional);
+        self.inputs.reserve(additional);
+        self.outputs.reserve(additional);
+        self.desired.reserve(additional);
+        self.experiences.reserve(additional);
+        self.genomes.reserve(additional);
+        self.appearances.reserve(additional);
+    }
+
+    /// Drops everything pending, keeping the capacity of every storage
+    pub fn clear(&mut self) {
+        self.entity = 0;
+        self.remove.clear();
+        self.removed.clear();
+        self.creatures.clear();
+        self.foods.clear();
+        self.positions.clear();
+        self.velocities.clear();
+        self.directions.clear();
+        self.bodies.clear();
+        self.draw.clear();
+        self.nns.clear();
+        self.inputs.clear();
+        self.outputs.clear();
+        self.desired.clear();
+        self.experiences.clear();
+        self.genomes.clear();
+        self.appearances.clear();
+    }
+
+    pub fn add_entity(&mut self) -> PendingEntity {
         self.creatures.push(None);
         self.foods.push(None);
         self.positions.push(None);
@@ -56,8 +147,11 @@ impl LazyUpdate {
         self.inputs.push(None);
         self.outputs.push(None);
         self.desired.push(None);
+        self.experiences.push(None);
+        self.genomes.push(None);
+        self.appearances.push(None);
 
-        let e = Entity { idx: self.entity };
+        let e = PendingEntity { idx: self.entity };
         self.entity += 1;
         e
     }
@@ -65,275 +159,507 @@ impl LazyUpdate {
     pub fn remove(&mut self, e: Entity) {
         self.remove.push(e);
     }
+
+    /// Points pending removals at the new handles after `GameData::compact`,
+    /// ones of entities without rows are dropped
+    pub fn remap(&mut self, map: &HashMap<Entity, Entity>) {
+        self.remove = self
+            .remove
+            .iter()
+            .filter_map(|e| map.get(e).copied())
+            .collect();
+        self.removed = self
+            .removed
+            .iter()
+            .filter_map(|&(e, removal)| Some((*map.get(&e)?, removal)))
+            .collect();
+    }
 }
 
-/// Used to index into the corresponding `Vec<T>` in a `LazyUpdate`
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Component<T> {
-    idx: usize,
-    _phantom: PhantomData<T>,
+/// Takes one kind of component off an entity
+pub type Removal = fn(&mut GameData, Entity);
+
+/// Takes a component off an entity in the next `GameData::commit`
+pub trait RemoveLater<T> {
+    fn remove_later(&mut self, e: Entity);
+}
+
+impl<T> RemoveLater<T> for LazyUpdate
+where
+    GameData: Remove<T>,
+{
+    fn remove_later(&mut self, e: Entity) {
+        self.removed.push((e, |data, e| {
+            Remove::<T>::remove(data, e);
+        }));
+    }
 }
 
-impl Index<Component<Creature>> for LazyUpdate {
+impl Index<PendingComponent<Creature>> for LazyUpdate {
     type Output = Creature;
 
-    fn index(&self, idx: Component<Creature>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Creature>) -> &Self::Output {
         self.creatures[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Creature>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Creature>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Creature>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Creature>) -> &mut Self::Output {
         self.creatures[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Creature> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Creature) {
+impl Insert<Creature, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Creature) {
         self.creatures[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Food>> for LazyUpdate {
+impl Has<Creature, PendingComponent<Creature>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Creature>) -> bool {
+        self.creatures[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Food>> for LazyUpdate {
     type Output = Food;
 
-    fn index(&self, idx: Component<Food>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Food>) -> &Self::Output {
         self.foods[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Food>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Food>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Food>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Food>) -> &mut Self::Output {
         self.foods[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Food> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Food) {
+impl Insert<Food, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Food) {
         self.foods[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Position>> for LazyUpdate {
+impl Has<Food, PendingComponent<Food>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Food>) -> bool {
+        self.foods[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Position>> for LazyUpdate {
     type Output = Position;
 
-    fn index(&self, idx: Component<Position>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Position>) -> &Self::Output {
         self.positions[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Position>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Position>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Position>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Position>) -> &mut Self::Output {
         self.positions[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Position> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Position) {
+impl Insert<Position, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Position) {
         self.positions[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Velocity>> for LazyUpdate {
+impl Has<Position, PendingComponent<Position>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Position>) -> bool {
+        self.positions[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Velocity>> for LazyUpdate {
     type Output = Velocity;
 
-    fn index(&self, idx: Component<Velocity>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Velocity>) -> &Self::Output {
         self.velocities[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Velocity>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Velocity>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Velocity>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Velocity>) -> &mut Self::Output {
         self.velocities[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Velocity> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Velocity) {
+impl Insert<Velocity, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Velocity) {
         self.velocities[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Direction>> for LazyUpdate {
+impl Has<Velocity, PendingComponent<Velocity>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Velocity>) -> bool {
+        self.velocities[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Direction>> for LazyUpdate {
     type Output = Direction;
 
-    fn index(&self, idx: Component<Direction>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Direction>) -> &Self::Output {
         self.directions[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Direction>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Direction>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Direction>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Direction>) -> &mut Self::Output {
         self.directions[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Direction> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Direction) {
+impl Insert<Direction, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Direction) {
         self.directions[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Body>> for LazyUpdate {
+impl Has<Direction, PendingComponent<Direction>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Direction>) -> bool {
+        self.directions[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Body>> for LazyUpdate {
     type Output = Body;
 
-    fn index(&self, idx: Component<Body>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Body>) -> &Self::Output {
         self.bodies[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Body>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Body>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Body>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Body>) -> &mut Self::Output {
         self.bodies[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Body> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Body) {
+impl Insert<Body, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Body) {
         self.bodies[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Draw>> for LazyUpdate {
+impl Has<Body, PendingComponent<Body>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Body>) -> bool {
+        self.bodies[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Draw>> for LazyUpdate {
     type Output = Draw;
 
-    fn index(&self, idx: Component<Draw>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Draw>) -> &Self::Output {
         self.draw[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Draw>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Draw>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Draw>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Draw>) -> &mut Self::Output {
         self.draw[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Draw> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Draw) {
+impl Insert<Draw, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Draw) {
         self.draw[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Network>> for LazyUpdate {
+impl Has<Draw, PendingComponent<Draw>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Draw>) -> bool {
+        self.draw[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Network>> for LazyUpdate {
     type Output = Network;
 
-    fn index(&self, idx: Component<Network>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Network>) -> &Self::Output {
         self.nns[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Network>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Network>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Network>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Network>) -> &mut Self::Output {
         self.nns[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Network> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Network) {
+impl Insert<Network, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Network) {
         self.nns[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Inputs>> for LazyUpdate {
+impl Has<Network, PendingComponent<Network>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Network>) -> bool {
+        self.nns[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Inputs>> for LazyUpdate {
     type Output = Inputs;
 
-    fn index(&self, idx: Component<Inputs>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Inputs>) -> &Self::Output {
         self.inputs[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Inputs>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Inputs>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Inputs>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Inputs>) -> &mut Self::Output {
         self.inputs[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Inputs> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Inputs) {
+impl Insert<Inputs, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Inputs) {
         self.inputs[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Outputs>> for LazyUpdate {
+impl Has<Inputs, PendingComponent<Inputs>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Inputs>) -> bool {
+        self.inputs[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Outputs>> for LazyUpdate {
     type Output = Outputs;
 
-    fn index(&self, idx: Component<Outputs>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Outputs>) -> &Self::Output {
         self.outputs[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Outputs>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Outputs>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Outputs>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Outputs>) -> &mut Self::Output {
         self.outputs[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Outputs> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Outputs) {
+impl Insert<Outputs, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Outputs) {
         self.outputs[e.idx] = Some(t);
     }
 }
 
-impl Index<Component<Desired>> for LazyUpdate {
+impl Has<Outputs, PendingComponent<Outputs>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Outputs>) -> bool {
+        self.outputs[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Desired>> for LazyUpdate {
     type Output = Desired;
 
-    fn index(&self, idx: Component<Desired>) -> &Self::Output {
+    fn index(&self, idx: PendingComponent<Desired>) -> &Self::Output {
         self.desired[idx.idx]
             .as_ref()
             .expect("entity doesn't have component")
     }
 }
 
-impl IndexMut<Component<Desired>> for LazyUpdate {
-    fn index_mut(&mut self, idx: Component<Desired>) -> &mut Self::Output {
+impl IndexMut<PendingComponent<Desired>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Desired>) -> &mut Self::Output {
         self.desired[idx.idx]
             .as_mut()
             .expect("entity doesn't have component")
     }
 }
 
-impl Insert<Desired> for LazyUpdate {
-    fn insert(&mut self, e: Entity, t: Desired) {
+impl Insert<Desired, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Desired) {
         self.desired[e.idx] = Some(t);
     }
 }
+
+impl Has<Desired, PendingComponent<Desired>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Desired>) -> bool {
+        self.desired[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Experience>> for LazyUpdate {
+    type Output = Experience;
+
+    fn index(&self, idx: PendingComponent<Experience>) -> &Self::Output {
+        self.experiences[idx.idx]
+            .as_ref()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl IndexMut<PendingComponent<Experience>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Experience>) -> &mut Self::Output {
+        self.experiences[idx.idx]
+            .as_mut()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl Insert<Experience, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Experience) {
+        self.experiences[e.idx] = Some(t);
+    }
+}
+
+impl Has<Experience, PendingComponent<Experience>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Experience>) -> bool {
+        self.experiences[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Genome>> for LazyUpdate {
+    type Output = Genome;
+
+    fn index(&self, idx: PendingComponent<Genome>) -> &Self::Output {
+        self.genomes[idx.idx]
+            .as_ref()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl IndexMut<PendingComponent<Genome>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Genome>) -> &mut Self::Output {
+        self.genomes[idx.idx]
+            .as_mut()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl Insert<Genome, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Genome) {
+        self.genomes[e.idx] = Some(t);
+    }
+}
+
+impl Has<Genome, PendingComponent<Genome>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Genome>) -> bool {
+        self.genomes[c.idx].is_some()
+    }
+}
+
+impl Index<PendingComponent<Appearance>> for LazyUpdate {
+    type Output = Appearance;
+
+    fn index(&self, idx: PendingComponent<Appearance>) -> &Self::Output {
+        self.appearances[idx.idx]
+            .as_ref()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl IndexMut<PendingComponent<Appearance>> for LazyUpdate {
+    fn index_mut(&mut self, idx: PendingComponent<Appearance>) -> &mut Self::Output {
+        self.appearances[idx.idx]
+            .as_mut()
+            .expect("entity doesn't have component")
+    }
+}
+
+impl Insert<Appearance, PendingEntity> for LazyUpdate {
+    fn insert(&mut self, e: PendingEntity, t: Appearance) {
+        self.appearances[e.idx] = Some(t);
+    }
+}
+
+impl Has<Appearance, PendingComponent<Appearance>> for LazyUpdate {
+    fn has(&self, c: PendingComponent<Appearance>) -> bool {
+        self.appearances[c.idx].is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::brain;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    #[test]
+    fn pending_and_committed_handles_index_their_own_container() {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+        let committed = spawn::creature_with(
+            &mut data,
+            Vector2::new(10.0, 10.0),
+            Kind::Vegan,
+            &genome,
+            brain.clone(),
+        );
+        let pending = spawn::creature_with(
+            &mut data.lazy,
+            Vector2::new(20.0, 20.0),
+            Kind::Carnivorous,
+            &genome,
+            brain,
+        );
+        // both have index 0, `data[pending.component::<Creature>()]` would
+        // read the committed vegan and doesn't compile
+        assert_eq!(pending.idx, committed.idx);
+        assert!(data.lazy.has(pending.component::<Creature>()));
+        assert_eq!(
+            data.lazy[pending.component::<Creature>()].kind,
+            Kind::Carnivorous
+        );
+        assert_eq!(data[committed.component::<Creature>()].kind, Kind::Vegan);
+
+        data.lazy[pending.component::<Position>()].position.x = 30.0;
+        let added = data.commit().added;
+        assert_eq!(data[added[0].component::<Position>()].position.x, 30.0);
+        assert_eq!(
+            data[added[0].component::<Creature>()].kind,
+            Kind::Carnivorous
+        );
+    }
+}
@@ -1,36 +1,121 @@
-use std::env;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Instant;
 
 use ggez::audio::{SoundSource, Source};
 use ggez::conf::WindowMode;
-use ggez::event::{self, EventHandler};
-use ggez::graphics::{self, Color};
+use ggez::event::{self, EventHandler, KeyCode, KeyMods, MouseButton};
+use ggez::graphics;
+use ggez::input::{keyboard, mouse};
 use ggez::timer;
-use ggez::{Context, ContextBuilder, GameResult};
+use ggez::{Context, ContextBuilder, GameError, GameResult};
 
-use rand::random;
+use image::{imageops, RgbaImage};
 
+use nalgebra::Vector2;
+
+use ordered_float::OrderedFloat;
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use self::audio::BurstDetector;
+use self::budget::{Budget, Degradation, Step};
+use self::checkpoint::CHECKPOINT_DIR;
+use self::chronicle::{Cause, CHRONICLE_PATH};
 use self::collision::Body;
+use self::config::{Config, CONFIG_PATH};
+use self::console::{Command, Console};
+use self::continuous::Recruiter;
 use self::creature::*;
-use self::data::{Entity, GameData, Insert};
-use self::draw::Draw;
-use self::nn::{Desired, Inputs, Network, Outputs};
+use self::data::{CommitResult, Entity, GameData, Insert, WorldSnapshot};
+use self::difficulty::FoodController;
+use self::draw::Throttle;
+use self::error::Error;
+use self::events::{EventTally, GameEvent};
+use self::focus::Focus;
+use self::gallery::Gallery;
+use self::group::Group;
+use self::keys::{Action, MouseAction};
+use self::labels::Labels;
+use self::memory::MemoryWatch;
+use self::mortality::{Mortality, MORTALITY_PATH};
+use self::nutrients::NutrientTally;
+use self::paint::{FoodGrid, FOOD_GRID_PATH, PAINT_RATE};
+use self::panel::Panel;
+use self::population::{Population, POPULATION_PATH};
+use self::resources::{Clock, DeltaTime, SimRng};
+use self::save::{Elites, RunArchive};
+use self::seedbank::{SeedBank, SEED_BANK_PATH};
+use self::species::SPECIES_PATH;
+use self::time::SimTime;
+use self::tournament::TOURNAMENT_PATH;
+use self::ui_state::{UiState, UI_STATE_PATH};
 
+pub mod audio;
+pub mod brain;
+pub mod budget;
+mod check;
+mod checkpoint;
+mod chronicle;
 pub mod collision;
+pub mod config;
+mod console;
+pub mod continuous;
 pub mod creature;
 pub mod data;
+pub mod difficulty;
 pub mod draw;
+mod env;
+pub mod error;
+pub mod events;
+mod focus;
+mod gallery;
+mod group;
+pub mod keys;
+mod labels;
 pub mod lazy;
+mod memory;
+mod mortality;
 pub mod mutate;
 pub mod nn;
+pub mod nutrients;
+pub mod paint;
+pub mod panel;
+pub mod policy;
+mod population;
+mod presets;
+pub mod profile;
+pub mod resources;
+pub mod sanitize;
+mod save;
+mod seedbank;
+pub mod selection;
+pub mod senses;
+mod shutdown;
+pub mod spawn;
+pub mod species;
+pub mod time;
+mod tournament;
+pub mod ui_state;
+mod verify;
 
 pub const TIME_FACTOR: f32 = 2.5;
-pub const GEN_TIME: f32 = 72.0 / TIME_FACTOR;
+pub const GEN_TIME: f32 = time::duration(72.0);
+/// Size of the world in world units, every gameplay length and speed is in
+/// these no matter how big the window is
+pub const WORLD_WIDTH: f32 = 1920.0;
+pub const WORLD_HEIGHT: f32 = 1080.0;
+/// UI pixels per world unit, and the `display_scale` of the window unless
+/// the config says otherwise, only the camera and the UI know about it, see
+/// `draw::world_view`
 pub const DPI_FACTOR: f32 = 1.0 / 3.166;
-pub const WIDTH: f32 = 1920.0 * DPI_FACTOR;
-pub const HEIGHT: f32 = 1080.0 * DPI_FACTOR;
+/// Size of the UI in pixels, stretched over the whole window
+pub const WIDTH: f32 = WORLD_WIDTH * DPI_FACTOR;
+pub const HEIGHT: f32 = WORLD_HEIGHT * DPI_FACTOR;
 pub const FOOD_MIN_RADIUS: f32 = 10.0;
 pub const FOOD_MAX_RADIUS: f32 = 20.0;
 pub const VEGAN_MIN_RADIUS: f32 = 15.0;
@@ -38,16 +123,31 @@ pub const VEGAN_MAX_RADIUS: f32 = 30.0;
 pub const CARNIVORE_MIN_RADIUS: f32 = 7.0;
 pub const CARNIVORE_MAX_RADIUS: f32 = 14.0;
 pub const MAX_RADIUS: f32 = VEGAN_MAX_RADIUS;
-pub const CARNIVORE_SPEED: f32 = 40.0 * TIME_FACTOR;
-pub const VEGAN_SPEED: f32 = 100.0 * TIME_FACTOR;
+pub const CARNIVORE_SPEED: f32 = time::rate(126.6);
+pub const VEGAN_SPEED: f32 = time::rate(316.6);
 pub const TOP_COUNT: usize = 10;
 pub const CREATURE_COUNT: usize = 100;
 pub const FOOD_COUNT: usize = 30;
-pub const FOOD_TIMEOUT: f32 = 1.0 / TIME_FACTOR;
+/// Fraction of dead entity slots after which the storages get compacted
+pub const COMPACT_HOLE_RATIO: f32 = 0.5;
+/// Entity slots a world starts out with, entities aren't reused so this is
+/// the founders and a good while of food spawns
+pub const INITIAL_CAPACITY: usize = CREATURE_COUNT + FOOD_COUNT * 64;
+pub const FOOD_TIMEOUT: f32 = time::duration(1.0);
+/// Spawning is spread over frames so a whole batch doesn't land at once
+pub const MAX_SPAWNS_PER_FRAME: usize = 5;
 pub const CARNIVORE_RATIO: f32 = 0.06;
+/// Food gives up on keeping clear of creatures after this many tries
+pub const FOOD_SPAWN_ATTEMPTS: usize = 8;
+/// A warning is logged every time this many frames in a row get clamped
+pub const CLAMP_WARN_FRAMES: usize = 10;
+/// Where `save world` writes the world if it's given no path
+pub const WORLD_PATH: &str = "world.bin";
 
 enum State {
     Game,
+    /// Browsing saved elites, the world is paused meanwhile
+    Gallery(Gallery),
 }
 
 struct GameState {
@@ -56,303 +156,2632 @@ struct GameState {
     data: GameData,
     foods: Vec<Entity>,
     creatures: Vec<Entity>,
+    /// Centers of the patches rich food spawns in
+    patches: Vec<Vector2<f32>>,
     food_timeout: f32,
+    /// Time left before food spawns again after a famine
+    famine_timeout: f32,
+    pending_food: usize,
+    food_controller: FoodController,
+    species_timeout: f32,
+    /// Births and deaths since the last time `Game` took them
+    events: usize,
+    /// What the systems did this generation, see `GameEvent`
+    tally: EventTally,
+    /// Memory reports of the last few generations
+    memory_watch: MemoryWatch,
+    /// Real seconds dropped by clamping long frames, over the whole run
+    lost_time: f32,
+    /// Frames in a row that got clamped
+    clamped_frames: usize,
+    recruiter: Recruiter,
+    /// How far updates are cut down to stay in `frame_budget`
+    budget: Budget,
+    /// Ordinary genomes of earlier generations, see `SeedBank`
+    seed_bank: SeedBank,
+    /// Whether `finish` ran already
+    finished: bool,
 }
 
-impl GameState {
-    pub fn new(ctx: &mut Context, generation: usize) -> GameResult<Self> {
-        let mut data = GameData::new();
-        let mut foods = Vec::new();
-        let mut creatures = Vec::new();
-        for _ in 0..FOOD_COUNT {
-            let e = data.add_entity();
-            let radius = (FOOD_MIN_RADIUS + random::<f32>() * (FOOD_MAX_RADIUS - FOOD_MIN_RADIUS))
-                * DPI_FACTOR;
-            let color = random::<f32>();
-            let color = Color::new(color, color, color, 1.0);
-            data.insert(e, Food);
-            data.insert(
-                e,
-                Position::new(random::<f32>() * WIDTH, random::<f32>() * HEIGHT),
-            );
-            data.insert(e, Velocity::new(0.0, 0.0));
-            data.insert(e, Body::new(radius, random::<f32>(), random::<f32>()));
-            data.insert(e, Draw::circle(ctx, radius, color)?);
-            foods.push(e)
+/// A whole `GameState` as written by `save_world`, the version comes first so
+/// outdated saves are caught before decoding the rest
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SavedWorld {
+    version: u32,
+    generation: usize,
+    time: f32,
+    foods: Vec<Entity>,
+    creatures: Vec<Entity>,
+    patches: Vec<Vector2<f32>>,
+    food_timeout: f32,
+    famine_timeout: f32,
+    pending_food: usize,
+    food_controller: FoodController,
+    species_timeout: f32,
+    recruiter: Recruiter,
+    tally: EventTally,
+    world: WorldSnapshot,
+}
+
+/// A random spot for food, rich food lands in one of the patches unless the
+/// food grid is painted
+fn food_position(
+    data: &GameData,
+    patches: &[Vector2<f32>],
+    rich: bool,
+    radius: f32,
+    rng: &mut SimRng,
+) -> Vector2<f32> {
+    if let Some(position) = data.food_grid.sample(rng) {
+        spawn::clamp_to_world(position, radius)
+    } else if rich {
+        let center = patches[rng.gen_range(0, patches.len())];
+        let angle = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
+        let distance = rng.gen::<f32>().sqrt() * data.config.rich_patch_radius;
+        let (y, x) = angle.sin_cos();
+        spawn::clamp_to_world(center + Vector2::new(x, y) * distance, radius)
+    } else {
+        spawn::random_position(&data.config, radius, rng)
+    }
+}
+
+/// Whether food at `position` would be at least `food_clearance` away from
+/// the edge of every creature
+fn clear_of(data: &GameData, creatures: &[Entity], position: Vector2<f32>, radius: f32) -> bool {
+    creatures.iter().all(|e| {
+        let creature = data[e.component::<Position>()].position;
+        let reach = data[e.component::<Body>()].radius + radius + data.config.food_clearance;
+        (creature - position).magnitude_squared() >= reach * reach
+    })
+}
+
+/// Spawns a piece of food, some of it is rich and lands in one of the patches
+///
+/// Food that would land on a creature is moved somewhere else, so nobody gets
+/// fed for free, but only `FOOD_SPAWN_ATTEMPTS` times.
+fn spawn_food(data: &mut GameData, patches: &[Vector2<f32>], creatures: &[Entity]) -> Entity {
+    let mut rng = data.take_resource::<SimRng>();
+    let radius = FOOD_MIN_RADIUS + rng.gen::<f32>() * (FOOD_MAX_RADIUS - FOOD_MIN_RADIUS);
+    let rich = !patches.is_empty() && rng.gen::<f32>() < data.config.rich_food_ratio;
+    let food = if rich {
+        Food::rich(&data.config)
+    } else {
+        Food::plain(&data.config)
+    };
+    let mut position = food_position(data, patches, rich, radius, &mut rng);
+    for _ in 1..FOOD_SPAWN_ATTEMPTS {
+        if clear_of(data, creatures, position, radius) {
+            break;
+        }
+        position = food_position(data, patches, rich, radius, &mut rng);
+    }
+    data.nutrients.injected += food.nutrition;
+    let e = spawn::food_at(data, position, radius, food, &mut rng);
+    data.insert_resource(rng);
+    e
+}
+
+/// A world written by `GameState::save_world`
+fn decode_world(encoded: &[u8]) -> error::Result<SavedWorld> {
+    let version: u32 = bincode::deserialize(encoded)?;
+    if version != save::WORLD_VERSION {
+        return Err(Error::FormatVersion {
+            found: version,
+            expected: save::WORLD_VERSION,
+        });
+    }
+    Ok(bincode::deserialize(encoded)?)
+}
+
+/// Flags that take the argument after them as their value
+const VALUE_FLAGS: &[&str] = &[
+    "--resume",
+    "--tournament",
+    "--trace",
+    "--preset",
+    "--record-reference",
+    "--verify-against",
+    "--steps",
+    "--diffs",
+];
+
+/// The value after `flag` on the command line
+fn flag_value(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != flag).nth(1)
+}
+
+/// The config file over the `--preset` one, or over the defaults without
+/// that flag
+fn load_config() -> error::Result<Config> {
+    let base = match flag_value("--preset") {
+        Some(name) => presets::find(&name)
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "there's no preset called {}, --list-presets shows them",
+                    name
+                ))
+            })?
+            .config(),
+        None => Config::default(),
+    };
+    Config::load_over(CONFIG_PATH, base)
+        .map_err(|err| Error::Config(format!("{}: {}", CONFIG_PATH, err)))
+}
+
+/// The first argument that isn't a flag or the value of one, the elites to
+/// start from
+fn elites_arg() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if VALUE_FLAGS.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with("--") {
+            return Some(arg);
         }
+    }
+    None
+}
+
+/// The elites in the save at `path`, or in the newest save if it's a
+/// directory, `None` if there's nothing there
+fn starting_elites(path: &Path) -> error::Result<Option<Elites>> {
+    if path.is_dir() {
+        let archive = RunArchive::open(path)?;
+        for problem in archive.problems() {
+            eprintln!("skipped {}", problem);
+        }
+        match archive.latest() {
+            Some(meta) => {
+                println!(
+                    "starting from generation {} with {} elites",
+                    meta.generation,
+                    meta.elites.unwrap_or(0)
+                );
+                Ok(Some(archive.load_generation(meta.generation)?))
+            }
+            None => Ok(None),
+        }
+    } else if path.exists() {
+        Ok(Some(Elites::load(path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+impl GameState {
+    pub fn new(generation: usize, config: Config) -> Self {
+        let mut game = Self::empty(generation, config);
+        game.seed_bank = SeedBank::load(SEED_BANK_PATH).unwrap_or_else(|err| {
+            eprintln!("couldn't load {}: {}", SEED_BANK_PATH, err);
+            SeedBank::default()
+        });
+        game.populate();
+        game
+    }
 
-        let mut new_count = CREATURE_COUNT;
+    /// A world with nothing in it yet, see `populate`
+    fn empty(generation: usize, config: Config) -> Self {
+        let mut data = GameData::with_capacity(INITIAL_CAPACITY);
+        data.insert_resource(SimRng::new(config.seed));
+        data.events.set_capacity(config.event_capacity);
+        data.config = config;
+        data.food_grid = FoodGrid::load(FOOD_GRID_PATH);
+        Self {
+            generation,
+            time: 0.0,
+            data,
+            foods: Vec::new(),
+            creatures: Vec::new(),
+            patches: Vec::new(),
+            food_timeout: 0.0,
+            famine_timeout: 0.0,
+            pending_food: 0,
+            food_controller: FoodController::new(),
+            species_timeout: 0.0,
+            events: 0,
+            tally: EventTally::default(),
+            memory_watch: MemoryWatch::default(),
+            lost_time: 0.0,
+            clamped_frames: 0,
+            recruiter: Recruiter::default(),
+            budget: Budget::default(),
+            seed_bank: SeedBank::default(),
+            finished: false,
+        }
+    }
 
-        let mut carnivores = (CREATURE_COUNT as f32 * CARNIVORE_RATIO) as usize;
+    /// Starts the next generation in the same world, what `new` does but
+    /// reusing what's been allocated
+    ///
+    /// The storages only ever grow during a generation, so they already have
+    /// room for the next one, `rollover` reports any that reallocate anyway.
+    /// The config, the food grid, the food controller and the run-long stats
+    /// carry over.
+    fn reset(&mut self) {
+        self.generation += 1;
+        self.time = 0.0;
+        self.data.clear();
+        self.foods.clear();
+        self.creatures.clear();
+        self.food_timeout = 0.0;
+        self.famine_timeout = 0.0;
+        self.pending_food = 0;
+        self.species_timeout = 0.0;
+        self.events = 0;
+        self.tally = EventTally::default();
+        self.clamped_frames = 0;
+        self.recruiter = Recruiter::default();
+        self.populate();
+    }
 
-        let mut args = env::args();
-        args.next();
-        if let Some(path) = args.next() {
+    /// Fills an empty world with the patches, the creatures, starting from
+    /// the elites on the command line if there are any, and the food
+    fn populate(&mut self) {
+        let mut sides = Vec::new();
+        if let Some(path) = elites_arg() {
             println!("{:?}", path);
             let path: &Path = path.as_ref();
-            if path.exists() {
-                let encoded = fs::read(path).expect("couldn't load top 10");
-                let top: Vec<(Creature, Network)> =
-                    bincode::deserialize(&encoded).expect("couldn't deserialize top 10");
-
-                new_count -= top.len();
-
-                for (creature, network) in top {
-                    let e = data.add_entity();
-                    let radius = if creature.kind == Kind::Vegan {
-                        (VEGAN_MIN_RADIUS + random::<f32>() * (VEGAN_MAX_RADIUS - VEGAN_MIN_RADIUS))
-                            * DPI_FACTOR
-                    } else {
-                        carnivores -= 1;
-                        (CARNIVORE_MIN_RADIUS
-                            + random::<f32>() * (CARNIVORE_MAX_RADIUS - CARNIVORE_MIN_RADIUS))
-                            * DPI_FACTOR
-                    };
-                    let color = if creature.kind == Kind::Vegan {
-                        Color::new(0.0, random::<f32>(), random::<f32>() * 0.2, 1.0)
-                    } else {
-                        Color::new(random::<f32>(), 0.0, random::<f32>() * 0.2, 1.0)
-                    };
-                    data.insert(e, creature);
-                    data.insert(
-                        e,
-                        Position::new(random::<f32>() * WIDTH, random::<f32>() * HEIGHT),
-                    );
-                    data.insert(e, Velocity::new(0.0, 0.0));
-                    data.insert(e, Direction::new(0.0));
-                    data.insert(e, Body::new(radius, random::<f32>(), random::<f32>()));
-                    data.insert(e, Draw::creature(ctx, radius, color)?);
-                    data.insert(e, network);
-                    data.insert(e, Inputs::new(RAY_COUNT * 2));
-                    data.insert(e, Outputs::new(DIR_COUNT));
-                    data.insert(e, Desired::new(DIR_COUNT));
-                    creatures.push(e)
-                }
+            // a bad save shouldn't keep the game from starting
+            let elites = starting_elites(path).unwrap_or_else(|err| {
+                eprintln!(
+                    "couldn't load elites from {}, starting fresh: {}",
+                    path.display(),
+                    err
+                );
+                None
+            });
+            sides.extend(elites);
+        }
+        self.populate_with(sides);
+    }
+
+    /// Fills an empty world, the way `populate` does, with each of `sides`
+    /// getting an even share of the creature quota, returns the creatures
+    /// spawned for each side
+    fn populate_with(&mut self, sides: Vec<Elites>) -> Vec<Vec<Entity>> {
+        let mut rng = self.data.take_resource::<SimRng>();
+        let config = &self.data.config;
+        self.patches.clear();
+        self.patches.extend(
+            (0..config.rich_patch_count).map(|_| spawn::random_position(config, 0.0, &mut rng)),
+        );
+
+        let mut carnivores = (CREATURE_COUNT as f32 * config.carnivore_ratio) as usize;
+        let mut vegans = CREATURE_COUNT - carnivores;
+        let carnivore_share = carnivores / sides.len().max(1);
+        let vegan_share = vegans / sides.len().max(1);
+
+        let mut spawned = Vec::new();
+        for mut elites in sides {
+            let skipped = elites.retain_matching(&self.data.config.brain);
+            if skipped > 0 {
+                eprintln!(
+                    "skipped {} elites that don't match the configured brain",
+                    skipped
+                );
+            }
+
+            // carnivore elites go first so that they always get their share of the quota
+            let carnivore_elites: Vec<_> = elites
+                .carnivores
+                .into_iter()
+                .take(carnivore_share)
+                .collect();
+            let vegan_elites: Vec<_> = elites.vegans.into_iter().take(vegan_share).collect();
+            carnivores -= carnivore_elites.len();
+            vegans -= vegan_elites.len();
+
+            let mut side = Vec::new();
+            for (mut creature, genome, network) in carnivore_elites.into_iter().chain(vegan_elites)
+            {
+                let position = spawn::random_position(&self.data.config, genome.radius, &mut rng);
+                let e =
+                    spawn::creature_with(&mut self.data, position, creature.kind, &genome, network);
+                // it was seeing things in another world
+                creature.food_distance = f32::INFINITY;
+                self.data.insert(e, creature);
+                self.creatures.push(e);
+                side.push(e);
             }
+            spawned.push(side);
         }
 
-        for _ in 0..new_count {
-            let e = data.add_entity();
-            let color;
+        let new_count = vegans + carnivores;
+
+        self.data.chronicle.generation = self.generation;
+        self.data.annealing.generation = self.generation;
+        // takes the place of the first random founder of its kind
+        let rates = self.data.annealing.rates(&self.data.config);
+        let mut revival = self.seed_bank.revive(&self.data.config, rates, &mut rng);
+        let mut revived = None;
+        for founder in 0..new_count {
             let kind = if carnivores == 0 {
-                color = Color::new(0.0, random::<f32>(), random::<f32>() * 0.2, 1.0);
                 Kind::Vegan
             } else {
                 carnivores -= 1;
-                color = Color::new(random::<f32>(), 0.0, random::<f32>() * 0.2, 1.0);
                 Kind::Carnivorous
             };
-            let radius = if kind == Kind::Vegan {
-                (VEGAN_MIN_RADIUS + random::<f32>() * (VEGAN_MAX_RADIUS - VEGAN_MIN_RADIUS))
-                    * DPI_FACTOR
-            } else {
-                (CARNIVORE_MIN_RADIUS
-                    + random::<f32>() * (CARNIVORE_MAX_RADIUS - CARNIVORE_MIN_RADIUS))
-                    * DPI_FACTOR
+            let kin = match &revival {
+                Some(((creature, _, _), _)) => creature.kind == kind,
+                None => false,
             };
-            data.insert(e, Creature::new(kind));
-            data.insert(
-                e,
-                Position::new(random::<f32>() * WIDTH, random::<f32>() * HEIGHT),
+            let revive = if kin { revival.take() } else { None };
+            if let Some(((_, genome, network), frozen)) = revive {
+                let position = spawn::random_position(&self.data.config, genome.radius, &mut rng);
+                let e = spawn::creature_with(&mut self.data, position, kind, &genome, network);
+                self.creatures.push(e);
+                revived = Some((e, frozen));
+                continue;
+            }
+            let mut genome = Genome::random(kind, &mut rng);
+            if self.data.config.lineage_colors {
+                genome = genome.founder(founder);
+            }
+            let position = spawn::random_position(&self.data.config, genome.radius, &mut rng);
+            let brain = brain::new_for(&self.data.config, kind, &mut rng);
+            let e = spawn::creature_with(&mut self.data, position, kind, &genome, brain);
+            self.creatures.push(e)
+        }
+
+        self.data.insert_resource(rng);
+        for &e in &self.creatures {
+            self.data.chronicle.birth(e);
+        }
+        if let Some((e, frozen)) = revived {
+            self.data.chronicle.revived(e, frozen);
+            println!(
+                "revived a {:?} frozen in generation {}",
+                self.data[e.component::<Creature>()].kind,
+                frozen
             );
-            data.insert(e, Velocity::new(0.0, 0.0));
-            data.insert(e, Direction::new(0.0));
-            data.insert(e, Body::new(radius, random::<f32>(), random::<f32>()));
-            data.insert(e, Draw::creature(ctx, radius, color)?);
-            data.insert(e, Network::new(&[RAY_COUNT * 2, 24, 20, DIR_COUNT]));
-            data.insert(e, Inputs::new(RAY_COUNT * 2));
-            data.insert(e, Outputs::new(DIR_COUNT));
-            data.insert(e, Desired::new(DIR_COUNT));
-            creatures.push(e)
         }
 
+        // after the creatures, so the food can keep clear of them
+        for _ in 0..FOOD_COUNT {
+            self.foods
+                .push(spawn_food(&mut self.data, &self.patches, &self.creatures));
+        }
+        spawned
+    }
+
+    /// Writes the whole world to `path` so it can be resumed mid-generation
+    /// with `--resume`, pending lazy updates are committed first
+    fn save_world<P: AsRef<Path>>(&mut self, path: P) -> error::Result<()> {
+        fs::write(path, bincode::serialize(&self.saved_world())?)?;
+        Ok(())
+    }
+
+    /// Everything `save_world` writes, pending lazy updates are committed
+    /// first
+    fn saved_world(&mut self) -> SavedWorld {
+        let commit = self.data.commit();
+        self.apply_commit(commit);
+        SavedWorld {
+            version: save::WORLD_VERSION,
+            generation: self.generation,
+            time: self.time,
+            foods: self.foods.clone(),
+            creatures: self.creatures.clone(),
+            patches: self.patches.clone(),
+            food_timeout: self.food_timeout,
+            famine_timeout: self.famine_timeout,
+            pending_food: self.pending_food,
+            food_controller: self.food_controller,
+            species_timeout: self.species_timeout,
+            recruiter: self.recruiter,
+            tally: self.tally,
+            world: self.data.snapshot(),
+        }
+    }
+
+    /// Picks up a world written by `save_world`, with the config it was saved
+    /// with rather than the one on disk
+    fn load_world<P: AsRef<Path>>(ctx: &mut Context, path: P) -> error::Result<Self> {
+        let saved = decode_world(&fs::read(path)?)?;
+        let mut game = Self::from_saved(saved, |world| {
+            GameData::restore(ctx, world).map_err(Error::Graphics)
+        })?;
+        game.seed_bank = SeedBank::load(SEED_BANK_PATH).unwrap_or_else(|err| {
+            eprintln!("couldn't load {}: {}", SEED_BANK_PATH, err);
+            SeedBank::default()
+        });
+        Ok(game)
+    }
+
+    /// Everything but the seed bank from a world written by `save_world`,
+    /// `restore` builds the `GameData` from its snapshot
+    fn from_saved<F>(saved: SavedWorld, restore: F) -> error::Result<Self>
+    where
+        F: FnOnce(WorldSnapshot) -> error::Result<GameData>,
+    {
         Ok(Self {
-            generation,
-            time: 0.0,
-            data,
-            foods,
-            creatures,
-            food_timeout: 0.0,
+            generation: saved.generation,
+            time: saved.time,
+            data: restore(saved.world)?,
+            foods: saved.foods,
+            creatures: saved.creatures,
+            patches: saved.patches,
+            food_timeout: saved.food_timeout,
+            famine_timeout: saved.famine_timeout,
+            pending_food: saved.pending_food,
+            food_controller: saved.food_controller,
+            species_timeout: saved.species_timeout,
+            events: 0,
+            tally: saved.tally,
+            memory_watch: MemoryWatch::default(),
+            lost_time: 0.0,
+            clamped_frames: 0,
+            recruiter: saved.recruiter,
+            budget: Budget::default(),
+            seed_bank: SeedBank::default(),
+            finished: false,
         })
     }
-}
 
-impl EventHandler for GameState {
-    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let delta = timer::duration_to_f64(timer::delta(ctx)) as f32;
-        self.time += delta;
+    /// Writes the whole world to `CHECKPOINT_DIR` every `checkpoint_interval`
+    /// generations, keeping the `checkpoint_keep` newest
+    fn checkpoint(&mut self) {
+        let interval = self.data.config.checkpoint_interval;
+        if interval == 0 || self.generation % interval != 0 {
+            return;
+        }
+        let path = checkpoint::path(CHECKPOINT_DIR, self.generation);
+        let written = bincode::serialize(&self.saved_world())
+            .map_err(Error::from)
+            .and_then(|encoded| Ok(checkpoint::write(&path, &encoded)?));
+        if let Err(err) = written {
+            eprintln!("couldn't write {}: {}", path.display(), err);
+            return;
+        }
+        if let Err(err) = checkpoint::prune(CHECKPOINT_DIR, self.data.config.checkpoint_keep) {
+            eprintln!("couldn't prune {}: {}", CHECKPOINT_DIR, err);
+        }
+    }
 
-        if self.time > GEN_TIME {
-            *self = GameState::new(ctx, self.generation + 1)?;
-            return Ok(());
+    /// Drops removed entities from the entity lists and appends added ones
+    /// to the list of their kind
+    ///
+    /// Systems iterate these lists in order, which decides collision
+    /// resolution order and raycast ties, so they are kept sorted by entity
+    /// index no matter when entities got deleted. Removing keeps the order
+    /// and committed entities always have larger indices than existing ones.
+    fn apply_commit(&mut self, commit: CommitResult) {
+        self.events += commit.added.len() + commit.removed.len();
+        let (creatures, foods) = self.data.classify(&commit.added);
+        for &e in &creatures {
+            self.data.chronicle.birth(e);
         }
+        let remove: HashSet<_> = commit.removed.into_iter().collect();
+        self.creatures.retain(|e| !remove.contains(e));
+        self.foods.retain(|e| !remove.contains(e));
+        self.creatures.extend(creatures);
+        self.foods.extend(foods);
 
-        self.food_timeout += delta;
-        if self.food_timeout > FOOD_TIMEOUT {
-            self.food_timeout -= FOOD_TIMEOUT;
-            for _ in 0..FOOD_COUNT {
-                let e = self.data.add_entity();
-                let radius = (FOOD_MIN_RADIUS
-                    + random::<f32>() * (FOOD_MAX_RADIUS - FOOD_MIN_RADIUS))
-                    * DPI_FACTOR;
-                let color = random::<f32>();
-                let color = Color::new(color, color, color, 1.0);
-                self.data.insert(e, Food);
-                self.data.insert(
-                    e,
-                    Position::new(random::<f32>() * WIDTH, random::<f32>() * HEIGHT),
-                );
-                self.data.insert(e, Velocity::new(0.0, 0.0));
-                self.data
-                    .insert(e, Body::new(radius, random::<f32>(), random::<f32>()));
-                self.data.insert(e, Draw::circle(ctx, radius, color)?);
-                self.foods.push(e)
-            }
-        }
-        for e in self.creatures.iter().copied() {
-            self.data[e.component::<Creature>()].timeout -= delta;
-            self.data[e.component::<Creature>()].life += delta;
-            self.data[e.component::<Creature>()].hunger += delta;
-            let starve = match self.data[e.component::<Creature>()].kind {
-                Kind::Carnivorous => CARNIVORE_STARVE,
-                Kind::Vegan => VEGAN_STARVE,
-            };
-            if self.data[e.component::<Creature>()].hunger > starve {
-                self.data.delete(e);
-                self.data.lazy.remove(e);
+        let sorted = |list: &[Entity]| list.windows(2).all(|w| w[0].idx < w[1].idx);
+        debug_assert!(sorted(&self.creatures), "creatures aren't sorted by index");
+        debug_assert!(sorted(&self.foods), "foods aren't sorted by index");
+        if cfg!(debug_assertions) {
+            let problems = check::validate(&self.data, &self.creatures, &self.foods);
+            assert!(problems.is_empty(), "{}", problems.join("\n"));
+        }
+    }
+
+    /// The creature closest to the mouse, if there are any
+    fn creature_near_mouse(&self, ctx: &mut Context) -> Option<Entity> {
+        let mouse = mouse::position(ctx);
+        let mouse = draw::to_world(&self.data.config, Vector2::new(mouse.x, mouse.y));
+        self.creatures.iter().copied().min_by_key(|e| {
+            let position = self.data[e.component::<Position>()].position;
+            OrderedFloat::from((position - mouse).magnitude_squared())
+        })
+    }
+
+    /// Prints every component of the creature closest to the mouse
+    fn describe_entity(&self, ctx: &mut Context) {
+        if let Some(e) = self.creature_near_mouse(ctx) {
+            println!("{}", self.data.describe(e));
+        }
+    }
+
+    /// Writes the policy image of the creature closest to the mouse
+    fn export_policy(&self, ctx: &mut Context) {
+        if let Some(e) = self.creature_near_mouse(ctx) {
+            let path = format!("policy_gen{}_{}.png", self.generation, e.idx);
+            match policy::export(&self.data, e, &path) {
+                Ok(()) => println!("wrote {}", path),
+                Err(err) => eprintln!("couldn't write {}: {}", path, err),
+            }
+        }
+    }
+
+    /// Writes the best creatures of each kind to `gen{N}.bin`, returns the
+    /// path
+    fn save_elites(&mut self) -> error::Result<String> {
+        species::share(&mut self.data, &self.creatures);
+        let mut elites = Elites::select(&self.data, self.creatures.iter().copied(), TOP_COUNT);
+        // the living compete with the hall of fame without joining it, they
+        // only do once they die
+        if self.data.config.continuous {
+            let mut hall = self.data.hall_of_fame.clone();
+            for saved in elites.vegans.into_iter().chain(elites.carnivores) {
+                hall.offer(saved, TOP_COUNT, &self.data.config);
+            }
+            elites = hall;
+        }
+        let threshold = self.data.config.prune_threshold;
+        if threshold > 0.0 {
+            let pruned = elites.prune(threshold);
+            let stats = elites.weight_stats();
+            println!(
+                "pruned {} elite weights, {:.1}% of {} are zero",
+                pruned,
+                stats.sparsity() * 100.0,
+                stats.count
+            );
+        }
+        let path = save::generation_path(self.generation);
+        elites.save(&path, self.generation)?;
+        Ok(path)
+    }
+
+    /// Removes a creature or a piece of food right away
+    fn kill(&mut self, e: Entity) -> Result<String, String> {
+        if self.creatures.contains(&e) {
+            chronicle::death(&mut self.data, e, Cause::Killed);
+            continuous::enshrine(&mut self.data, e);
+            nutrients::recycle(&mut self.data, e);
+        } else if !self.foods.contains(&e) {
+            return Err(format!("no creature or food {}", e.idx));
+        }
+        self.data.delete(e);
+        self.data.lazy.remove(e);
+        let commit = self.data.commit();
+        self.apply_commit(commit);
+        Ok(format!("killed {}", e.idx))
+    }
+
+    /// One line summary of the world for the console
+    fn stats(&self) -> String {
+        let population = Population::count(&self.data, &self.creatures);
+        let deaths: usize = self.data.mortality.deaths().iter().map(|(_, n)| n).sum();
+        format!(
+            "gen {} at {:.1}s: {} vegans, {} carnivores ({:.0}%), {} food (x{:.2}), {} deaths, {} meals, {} matings, {} shares, {} events dropped, {:.1} nutrition spawned, {:.1} recycled, {:.1}s lost",
+            self.generation,
+            self.time,
+            population.vegans,
+            population.carnivores,
+            population.carnivore_ratio() * 100.0,
+            self.foods.len(),
+            self.food_controller.multiplier,
+            deaths,
+            self.tally.meals,
+            self.tally.matings,
+            self.tally.shares,
+            self.data.events.dropped.total(),
+            self.data.nutrients.injected,
+            self.data.nutrients.recycled,
+            self.lost_time
+        )
+    }
+
+    /// Real seconds this frame covers, at most `max_frame_delta` so a stall
+    /// doesn't apply seconds of hunger and movement at once
+    fn frame_delta(&mut self, real: f32) -> f32 {
+        let max = self.data.config.max_frame_delta;
+        if max <= 0.0 || real <= max {
+            self.clamped_frames = 0;
+            return real;
+        }
+
+        self.lost_time += real - max;
+        self.clamped_frames += 1;
+        if self.clamped_frames % CLAMP_WARN_FRAMES == 0 {
+            eprintln!(
+                "{} frames in a row took longer than {:.3}s, {:.1}s lost so far",
+                self.clamped_frames, max, self.lost_time
+            );
+        }
+        max
+    }
+
+    /// Removes all food and stops it from spawning for `famine_duration`
+    fn famine(&mut self) {
+        for e in self.foods.iter().copied() {
+            self.data.delete(e);
+            self.data.lazy.remove(e);
+        }
+        let commit = self.data.commit();
+        self.apply_commit(commit);
+        debug_assert!(self.foods.is_empty(), "food survived the famine");
+
+        self.pending_food = 0;
+        self.food_timeout = 0.0;
+        self.famine_timeout = self.data.config.famine_duration;
+        println!(
+            "famine in generation {}, no food for {:.1}s",
+            self.generation, self.famine_timeout
+        );
+    }
+
+    /// Closes the chronicle and saves the food grid and the elites, only the
+    /// first time so an interrupt and a close don't both save, returns
+    /// whether it did
+    fn finish(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        self.finished = true;
+        self.close_chronicle();
+        self.save_food_grid();
+        if let Err(err) = self.save_elites() {
+            eprintln!("couldn't save generation {}: {}", self.generation, err);
+        }
+        true
+    }
+
+    /// Records the survivors and writes out whatever the chronicle still has
+    fn close_chronicle(&mut self) {
+        for e in self.creatures.clone() {
+            chronicle::death(&mut self.data, e, Cause::Survived);
+        }
+        self.flush_chronicle();
+    }
+
+    fn save_food_grid(&mut self) {
+        if self.data.food_grid.dirty {
+            if let Err(err) = self.data.food_grid.save(FOOD_GRID_PATH) {
+                eprintln!("couldn't write {}: {}", FOOD_GRID_PATH, err);
             }
         }
+    }
+
+    fn flush_chronicle(&mut self) {
+        if let Err(err) = self.data.chronicle.flush(CHRONICLE_PATH) {
+            eprintln!("couldn't write {}: {}", CHRONICLE_PATH, err);
+        }
+    }
 
-        let (_, remove) = self.data.commit();
-        for r in remove {
-            let pos = self.creatures.iter().position(|e| *e == r);
-            if let Some(pos) = pos {
-                self.creatures.remove(pos);
+    /// With `enforce_ratio`, the oldest creatures of every kind that's over
+    /// its quota, other than the ones in `dying`
+    fn surplus(&self, dying: &HashSet<Entity>) -> Vec<Entity> {
+        let mut surplus = Vec::new();
+        for &kind in &[Kind::Vegan, Kind::Carnivorous] {
+            let mut living: Vec<_> = self
+                .creatures
+                .iter()
+                .copied()
+                .filter(|e| !dying.contains(e))
+                .filter(|e| self.data[e.component::<Creature>()].kind == kind)
+                .collect();
+            let over = continuous::surplus(&self.data.config, kind, living.len());
+            if over == 0 {
                 continue;
             }
+            // stable, so the lower index goes first between equals
+            living.sort_by(|a, b| {
+                let life = |e: &Entity| self.data[e.component::<Creature>()].life;
+                life(b).total_cmp(&life(a))
+            });
+            surplus.extend(living.into_iter().take(over));
+        }
+        surplus
+    }
+
+    /// Spawns the recruits that are due, mutated copies of the hall of fame
+    /// or fresh creatures while it has none of a kind
+    fn recruit(&mut self, delta: f32) {
+        for &kind in &[Kind::Vegan, Kind::Carnivorous] {
+            let count = self
+                .data
+                .living_creatures()
+                .filter(|e| self.data[e.component::<Creature>()].kind == kind)
+                .count();
+            let due = self.recruiter.due(&self.data.config, kind, count, delta);
+            let mut rng = self.data.take_resource::<SimRng>();
+            for _ in 0..due {
+                let hall = match kind {
+                    Kind::Vegan => &self.data.hall_of_fame.vegans,
+                    Kind::Carnivorous => &self.data.hall_of_fame.carnivores,
+                };
+                let (genome, brain) = if hall.is_empty() {
+                    (
+                        Genome::random(kind, &mut rng),
+                        brain::new_for(&self.data.config, kind, &mut rng),
+                    )
+                } else {
+                    let (_, genome, network) = &hall[rng.gen_range(0, hall.len())];
+                    let rates = self.data.annealing.rates(&self.data.config);
+                    (
+                        genome.cross(genome, &self.data.config, rates, &mut rng),
+                        network.clone(),
+                    )
+                };
+                let position = spawn::random_position(&self.data.config, genome.radius, &mut rng);
+                spawn::creature_with(&mut self.data.lazy, position, kind, &genome, brain);
+            }
+            self.data.insert_resource(rng);
+        }
+    }
+
+    /// Prints how much nutrition the world spawned and how much the dead gave
+    /// back this generation
+    fn report_nutrients(&self) {
+        let nutrients = self.data.nutrients;
+        println!(
+            "nutrition in generation {}: {:.1} spawned, {:.1} recycled",
+            self.generation, nutrients.injected, nutrients.recycled
+        );
+    }
+
+    /// Appends how this generation ended to the per-generation CSVs
+    fn append_stats(&self) {
+        if let Err(err) = self
+            .data
+            .mortality
+            .append_csv(MORTALITY_PATH, self.generation)
+        {
+            eprintln!("couldn't write {}: {}", MORTALITY_PATH, err);
+        }
+        let population = Population::count(&self.data, &self.creatures);
+        if let Err(err) = population.append_csv(POPULATION_PATH, self.generation) {
+            eprintln!("couldn't write {}: {}", POPULATION_PATH, err);
+        }
+        // as of the last clustering, at most a sim-second ago
+        let k = self.data.config.species_count;
+        let species = species::stats(&self.data, &self.creatures, k);
+        if let Err(err) = species::append_csv(SPECIES_PATH, self.generation, &species) {
+            eprintln!("couldn't write {}: {}", SPECIES_PATH, err);
+        }
+    }
+
+    /// Ends a generation in continuous mode, the world carries on and only
+    /// the stats and saves start over
+    fn epoch(&mut self) {
+        self.append_stats();
+        match self.save_elites() {
+            Ok(path) => println!("wrote {}", path),
+            Err(err) => eprintln!("couldn't save generation {}: {}", self.generation, err),
+        }
+        self.freeze_seeds();
+        println!(
+            "{} matings refused in generation {}",
+            self.data.refused_matings, self.generation
+        );
+        let dropped = self.data.events.dropped;
+        if dropped.total() > 0 {
+            eprintln!(
+                "event queue full in generation {}, dropped {}, event stats are incomplete",
+                self.generation, dropped
+            );
+        }
+        self.report_nutrients();
+        self.next_epoch();
+    }
+
+    /// Starts the stats of the next generation over and brings back a seed,
+    /// the world itself carries on
+    fn next_epoch(&mut self) {
+        self.data.mortality = Mortality::default();
+        self.data.refused_matings = 0;
+        self.data.nutrients = NutrientTally::default();
+        self.data.annealing.diversity = Some(species::diversity(&self.data, &self.creatures));
+
+        self.time -= SimTime::new(self.data.config.sim_speed).generation();
+        self.generation += 1;
+        self.data.chronicle.generation = self.generation;
+        self.data.annealing.generation = self.generation;
+        self.revive_seed();
+    }
+
+    /// Freezes a few ordinary creatures into the seed bank if this generation
+    /// is due and writes the bank to `SEED_BANK_PATH`
+    fn freeze_seeds(&mut self) {
+        if !SeedBank::due(&self.data.config, self.generation) {
+            return;
+        }
+        let mut rng = self.data.take_resource::<SimRng>();
+        let frozen = self
+            .seed_bank
+            .freeze(&self.data, &self.creatures, self.generation, &mut rng);
+        self.data.insert_resource(rng);
+        if frozen == 0 {
+            return;
+        }
+        println!(
+            "froze {} creatures of generation {}, {} seeds in the bank",
+            frozen,
+            self.generation,
+            self.seed_bank.seeds.len()
+        );
+        if let Err(err) = self.seed_bank.save(SEED_BANK_PATH) {
+            eprintln!("couldn't write {}: {}", SEED_BANK_PATH, err);
+        }
+    }
+
+    /// In continuous mode, maybe brings back a seed at the start of a
+    /// generation, alongside the recruits
+    ///
+    /// Spawned right away rather than lazily so the chronicle can note where
+    /// it came from, it still gets the largest index so far.
+    fn revive_seed(&mut self) {
+        let rates = self.data.annealing.rates(&self.data.config);
+        let mut rng = self.data.take_resource::<SimRng>();
+        let revival = self.seed_bank.revive(&self.data.config, rates, &mut rng);
+        let position = revival.as_ref().map(|((_, genome, _), _)| {
+            spawn::random_position(&self.data.config, genome.radius, &mut rng)
+        });
+        self.data.insert_resource(rng);
+        let (((creature, genome, network), frozen), position) = match (revival, position) {
+            (Some(revival), Some(position)) => (revival, position),
+            _ => return,
+        };
+        let kind = creature.kind;
+        let e = spawn::creature_with(&mut self.data, position, kind, &genome, network);
+        self.creatures.push(e);
+        self.events += 1;
+        self.data.chronicle.birth(e);
+        self.data.chronicle.revived(e, frozen);
+        println!("revived a {:?} frozen in generation {}", kind, frozen);
+    }
+
+    /// Replaces this generation with a fresh one
+    fn rollover(&mut self) {
+        self.close_chronicle();
+        self.append_stats();
+        // for the next run, the next generation keeps the one in memory
+        self.save_food_grid();
+        let report = self.data.memory_report();
+        println!("memory in generation {}: {}", self.generation, report);
+        let before = report.storages.clone();
+        let leaks = self.memory_watch.record(report);
+        if cfg!(debug_assertions) {
+            for name in leaks {
+                eprintln!(
+                    "storage {} grew faster than the entity count over {} generations",
+                    name,
+                    memory::LEAK_GENERATIONS
+                );
+            }
+        }
+
+        println!(
+            "{} matings refused in generation {}",
+            self.data.refused_matings, self.generation
+        );
+        self.report_nutrients();
+        self.freeze_seeds();
+        let diversity = species::diversity(&self.data, &self.creatures);
+        self.reset();
+        self.data.annealing.diversity = Some(diversity);
+        let rates = self.data.annealing.rates(&self.data.config);
+        println!(
+            "mutation in generation {}: chance {:.3}, magnitude {:.3}, diversity {:.3}",
+            self.generation, rates.chance, rates.magnitude, diversity
+        );
+
+        // the whole point of resetting in place, see `reset`
+        let after = self.data.memory_report().storages;
+        for (old, new) in before.iter().zip(&after) {
+            if new.capacity != old.capacity {
+                eprintln!(
+                    "storage {} reallocated during the rollover, {} to {} slots",
+                    new.name, old.capacity, new.capacity
+                );
+            }
+        }
+        self.checkpoint();
+    }
+}
+
+impl GameState {
+    /// Squeezes the holes out of the storages with `GameData::compact` and
+    /// follows the entity lists to the new handles, returns the mapping for
+    /// lists kept outside
+    fn compact(&mut self) -> HashMap<Entity, Entity> {
+        let map = self.data.compact();
+        let remap = |list: &mut Vec<Entity>| {
+            *list = list.iter().filter_map(|e| map.get(e).copied()).collect();
+        };
+        remap(&mut self.creatures);
+        remap(&mut self.foods);
+        map
+    }
+
+    /// Advances the simulation by a frame that took `real` seconds, clamped
+    /// by `frame_delta`
+    fn advance(&mut self, real: f32) -> GameResult<()> {
+        let real = self.frame_delta(real);
+        self.step(real)
+    }
+
+    /// Advances the simulation by `real` seconds, everything `update` does
+    /// that doesn't need a window
+    fn step(&mut self, real: f32) -> GameResult<()> {
+        self.step_with(real, |_| {})
+    }
+
+    /// `step`, with `decide` run on the world once the networks have set
+    /// their outputs and before the outputs move anyone, see `env::Env`
+    fn step_with<F>(&mut self, real: f32, decide: F) -> GameResult<()>
+    where
+        F: FnOnce(&mut GameData),
+    {
+        let start = Instant::now();
+        let time = SimTime::new(self.data.config.sim_speed);
+        let delta = time.advance(real);
+        self.data.insert_resource(DeltaTime(delta));
+        self.time += delta;
+        self.data.insert_resource(Clock(self.time));
+        self.data.chronicle.time = self.time;
+
+        if self.time > time.generation() {
+            if !self.data.config.continuous {
+                self.rollover();
+                return Ok(());
+            }
+            self.epoch();
+        }
+
+        let vegans = self
+            .creatures
+            .iter()
+            .filter(|e| self.data[e.component::<Creature>()].kind == Kind::Vegan)
+            .count();
+        self.food_controller
+            .update(&self.data.config, vegans, delta);
+
+        self.species_timeout += delta;
+        if self.species_timeout > 1.0 {
+            self.species_timeout -= 1.0;
+            let k = self.data.config.species_count;
+            species::cluster(&mut self.data, &self.creatures, k);
+        }
+
+        let food_timeout = self.food_controller.food_timeout(&self.data.config);
+        self.famine_timeout = (self.famine_timeout - delta).max(0.0);
+        if self.famine_timeout <= 0.0 {
+            self.food_timeout += delta;
+        }
+        if self.food_timeout > food_timeout {
+            self.food_timeout -= food_timeout;
+            self.pending_food += FOOD_COUNT;
+        }
+        if self.pending_food > 0 {
+            let count = self.pending_food.min(MAX_SPAWNS_PER_FRAME);
+            self.pending_food -= count;
+            self.data.reserve(count);
+            for _ in 0..count {
+                let e = spawn_food(&mut self.data, &self.patches, &self.creatures);
+                self.foods.push(e);
+            }
+        }
+        let mut starved = Vec::new();
+        let mut retired = Vec::new();
+        self.data
+            .for_each_mut::<(Creature,), _>(|data, e, (creature,)| {
+                creature.timeout -= delta;
+                creature.life += delta;
+                let crowding = creature.crowding_hunger(&data.config);
+                creature.hunger += delta * (data.config.metabolism + crowding);
+                creature.last_hit = (creature.last_hit - delta).max(0.0);
+                creature.eat_cooldown = (creature.eat_cooldown - delta).max(0.0);
+                creature.lunge = (creature.lunge - delta).max(0.0);
+                creature.lunge_cooldown = (creature.lunge_cooldown - delta).max(0.0);
+                creature.digest(delta);
+                if creature.hunger > creature.starve() {
+                    starved.push(e);
+                } else if data.config.continuous
+                    && creature.life > continuous::max_life(&data.config, creature.kind)
+                {
+                    retired.push(e);
+                }
+            });
+        if self.data.config.continuous {
+            let dying: HashSet<_> = starved.iter().chain(&retired).copied().collect();
+            retired.extend(self.surplus(&dying));
+        }
+        let deaths = starved
+            .into_iter()
+            .map(|e| (e, Cause::Starved))
+            .chain(retired.into_iter().map(|e| (e, Cause::Survived)));
+        for (e, cause) in deaths {
+            if cause == Cause::Starved {
+                self.data.events.push(GameEvent::Starved(e));
+            }
+            chronicle::death(&mut self.data, e, cause);
+            continuous::enshrine(&mut self.data, e);
+            nutrients::recycle(&mut self.data, e);
+            self.data.delete(e);
+            self.data.lazy.remove(e);
+        }
+        if self.data.config.continuous {
+            self.recruit(delta);
         }
 
+        let commit = self.data.commit();
+        self.apply_commit(commit);
+
         collision::physics_system(
-            ctx,
             &mut self.data,
             self.creatures.iter().chain(&self.foods).copied(),
             self.creatures.iter().chain(&self.foods).copied(),
         )?;
 
-        let (add, remove) = self.data.commit();
-        for r in remove {
-            let pos = self.creatures.iter().position(|e| *e == r);
-            if let Some(pos) = pos {
-                self.creatures.remove(pos);
-                continue;
-            }
-            let pos = self.foods.iter().position(|e| *e == r);
-            if let Some(pos) = pos {
-                self.foods.remove(pos);
-                continue;
+        let all = self.creatures.iter().chain(&self.foods).copied();
+        for issue in sanitize::sanitize_system(&mut self.data, all) {
+            // out of range values are just clamped, the rest are bugs
+            debug_assert!(!issue.is_bug(), "{}", issue);
+            if issue.is_bug() {
+                eprintln!("{}", issue);
             }
         }
-        self.creatures.extend(add);
 
-        collision::input_system(
+        let commit = self.data.commit();
+        self.apply_commit(commit);
+
+        let parity = self.budget.ray_parity(&self.data.config);
+        let train = !self
+            .budget
+            .is_active(&self.data.config, Degradation::NoTraining);
+        senses::input_system(
             &mut self.data,
             self.creatures.iter().copied(),
             self.creatures.iter().chain(&self.foods).copied(),
+            parity,
         )?;
-        nn::nn_system(&mut self.data, self.creatures.iter().copied())?;
+        nn::nn_system(&mut self.data, self.creatures.iter().copied(), train)?;
+        decide(&mut self.data);
         collision::output_system(&mut self.data, self.creatures.iter().copied())?;
 
+        chronicle::sample(&mut self.data, &self.creatures);
+        if self.data.chronicle.full() {
+            self.flush_chronicle();
+        }
+
+        for event in self.data.drain_events() {
+            self.tally.record(&event);
+        }
+
+        let spent = start.elapsed().as_secs_f32();
+        match self.budget.record(&self.data.config, spent, real) {
+            Some(Step::Degraded(degradation)) => {
+                println!("updates over budget, degrading: {}", degradation.name())
+            }
+            Some(Step::Restored(degradation)) => {
+                println!(
+                    "updates back under budget, restoring: {}",
+                    degradation.name()
+                )
+            }
+            None => {}
+        }
+
         Ok(())
     }
+}
+
+impl EventHandler for GameState {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.advance(timer::duration_to_f64(timer::delta(ctx)) as f32)
+    }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
         graphics::clear(ctx, graphics::BLACK);
 
-        draw::draw_system(ctx, &self.data, self.foods.iter().copied())?;
-        draw::draw_system(ctx, &self.data, self.creatures.iter().copied())?;
+        let entities = self.foods.iter().chain(&self.creatures).copied();
+        draw::mesh_system(ctx, &mut self.data, entities)?;
+        draw::draw_system(ctx, &self.data)?;
+        draw::draw_lunges(ctx, &self.data, self.creatures.iter().copied())?;
 
-        graphics::present(ctx)?;
         Ok(())
     }
 
     fn quit_event(&mut self, _ctx: &mut Context) -> bool {
-        // filler
-        let mut top = vec![(Creature::new(Kind::Vegan), Network::new(&[1, 1])); TOP_COUNT];
-        for e in self.creatures.iter().copied() {
-            for top in top.iter_mut() {
-                if self.data[e.component::<Creature>()].life > top.0.life {
-                    *top = (
-                        self.data[e.component::<Creature>()],
-                        self.data[e.component::<Network>()].clone(),
-                    );
-                    break;
-                }
-            }
-        }
+        self.finish();
+        false
+    }
+}
 
-        let encoded: Vec<u8> = bincode::serialize(&top).expect("couldn't serialize top 10");
+/// Seconds between window title updates
+pub const TITLE_INTERVAL: f32 = 1.0;
 
-        fs::write(format!("gen{}.bin", self.generation), &encoded).expect("couldn't save top 10");
+/// Window title with the vitals of the simulation, the time left of a
+/// famine if there is one and what's been cut down to stay in budget
+fn title(game: &GameState, throttle: Option<usize>, fps: f64, frame_time: f64) -> String {
+    let mut title = format!(
+        "ldjam-46 — gen {} | {} creatures | {} food | {:.0} fps | {:.1} ms/frame",
+        game.generation,
+        game.creatures.len(),
+        game.foods.len(),
+        fps,
+        frame_time * 1000.0
+    );
+    if game.data.config.adaptive_food {
+        title += &format!(" | food x{:.2}", game.food_controller.multiplier);
+    }
+    if game.famine_timeout > 0.0 {
+        title += &format!(" | famine {:.0}s", game.famine_timeout.ceil());
+    }
+    if let Some(divisor) = throttle {
+        title += &format!(" | drawing 1/{}", divisor);
+    }
+    let degraded = game.budget.active(&game.data.config);
+    if !degraded.is_empty() {
+        let names: Vec<_> = degraded.iter().map(|d| d.name()).collect();
+        title += &format!(" | degraded: {}", names.join(", "));
+    }
+    title
+}
 
-        false
+/// What gets drawn on top of the world, see `Game::draw_overlays`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Overlays {
+    contacts: bool,
+    /// Labels on every creature and piece of food
+    labels: bool,
+    /// Labels on the group members only
+    member_labels: bool,
+    /// Trails of the group members
+    trails: bool,
+    species: bool,
+    mortality: bool,
+    panel: bool,
+}
+
+impl Overlays {
+    /// The overlays open in `ui`, `annotate` adds labels and trails for the
+    /// group members whatever `ui` says, without changing `ui` itself
+    fn of(ui: UiState, annotate: bool) -> Self {
+        Self {
+            contacts: ui.show_contacts,
+            labels: ui.show_labels,
+            member_labels: annotate && !ui.show_labels,
+            trails: annotate,
+            species: ui.show_species,
+            mortality: ui.show_mortality,
+            panel: ui.show_panel,
+        }
     }
 }
 
 struct Game {
-    _sound: Source,
+    /// `None` if there's no audio, the game works fine without
+    music: Option<Source>,
+    title_timeout: f32,
+    bursts: BurstDetector,
     game: GameState,
     state: State,
+    panel: Panel,
+    labels: Labels,
+    throttle: Throttle,
+    group: Group,
+    /// Button that started the group drag, releasing it ends the drag
+    group_button: Option<MouseButton>,
+    /// Whether the mouse paints the food grid
+    painting: bool,
+    /// Button held down while painting and whether it adds or takes away
+    paint_button: Option<(MouseButton, f32)>,
+    show_species: bool,
+    show_contacts: bool,
+    show_mortality: bool,
+    console: Console,
+    /// Whether the window has focus, see `FocusPolicy`
+    focus: Focus,
 }
 
 impl Game {
-    pub fn new(ctx: &mut Context) -> GameResult<Game> {
-        let mut sound = Source::new(ctx, "/ldjam.mp3")?;
-        sound.set_repeat(true);
-        sound.play()?;
-        Ok(Game {
-            _sound: sound,
-            game: GameState::new(ctx, 0)?,
+    pub fn new(ctx: &mut Context, game: GameState) -> Game {
+        let music = start_music(ctx)
+            .map_err(|err| eprintln!("{}, playing without music", err))
+            .ok();
+        Self::with_music(music, game)
+    }
+
+    /// Everything `new` sets up apart from the music, which needs a context
+    fn with_music(music: Option<Source>, game: GameState) -> Game {
+        let ui = UiState::load(UI_STATE_PATH).unwrap_or_else(|err| {
+            eprintln!("couldn't load {}: {}", UI_STATE_PATH, err);
+            UiState::default()
+        });
+        Game {
+            music,
+            title_timeout: 0.0,
+            bursts: BurstDetector::new(),
+            game,
             state: State::Game,
-        })
+            panel: Panel {
+                open: ui.show_panel,
+                ..Panel::default()
+            },
+            labels: Labels::new(ui.show_labels),
+            throttle: Throttle::default(),
+            group: Group::default(),
+            group_button: None,
+            painting: false,
+            paint_button: None,
+            show_species: ui.show_species,
+            show_contacts: ui.show_contacts,
+            show_mortality: ui.show_mortality,
+            console: Console::default(),
+            focus: Focus::default(),
+        }
     }
 }
 
-impl EventHandler for Game {
-    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        match self.state {
-            State::Game => self.game.update(ctx),
+fn start_music(ctx: &mut Context) -> error::Result<Source> {
+    let mut sound = Source::new(ctx, "/ldjam.mp3").map_err(Error::Audio)?;
+    sound.set_repeat(true);
+    sound.play().map_err(Error::Audio)?;
+    Ok(sound)
+}
+
+impl Game {
+    /// Writes the members of the group to a JSON file
+    fn export_group(&mut self) {
+        let members = self.group.living(&self.game.data, self.game.generation);
+        if members.is_empty() {
+            return;
         }
-    }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        match self.state {
-            State::Game => self.game.draw(ctx),
+        let path = format!("group_gen{}_{}.json", self.game.generation, members[0].idx);
+        match group::export(&self.game.data, &members, &path) {
+            Ok(()) => println!("wrote {}", path),
+            Err(err) => eprintln!("couldn't write {}: {}", path, err),
         }
     }
+}
 
-    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+impl Game {
+    /// Saves what closing the window saves if a shutdown was asked for,
+    /// returns whether it was
+    fn interrupted(&mut self) -> bool {
+        if !shutdown::requested() {
+            return false;
+        }
+
+        self.finish();
+        self.game.finish();
+        true
+    }
+
+    /// The parts of quitting that are `Game`'s own
+    fn finish(&mut self) {
+        // whatever got recorded of a trace that's still going
+        profile::finish();
+        if let Err(err) = self.ui_state().save(UI_STATE_PATH) {
+            eprintln!("couldn't write {}: {}", UI_STATE_PATH, err);
+        }
+    }
+
+    /// Which overlays are open right now
+    fn ui_state(&self) -> UiState {
+        UiState {
+            show_panel: self.panel.open,
+            show_species: self.show_species,
+            show_contacts: self.show_contacts,
+            show_labels: self.labels.open,
+            show_mortality: self.show_mortality,
+        }
+    }
+
+    /// Draws `overlays` on top of the world, the group itself always is
+    fn draw_overlays(&mut self, ctx: &mut Context, overlays: Overlays) -> GameResult<()> {
+        let game = &self.game;
+        if overlays.contacts {
+            draw::draw_contacts(ctx, &game.data.contacts)?;
+        }
+
+        let members = self.group.living(&game.data, game.generation);
+        if overlays.labels {
+            self.labels.draw(
+                ctx,
+                &game.data,
+                game.generation,
+                &game.creatures,
+                &game.foods,
+            )?;
+        } else if overlays.member_labels {
+            self.labels
+                .draw(ctx, &game.data, game.generation, &members, &[])?;
+        }
+        if overlays.trails {
+            draw::draw_trails(ctx, &game.data, &members)?;
+        }
+        group::draw(ctx, &game.data, &self.group, &members)?;
+        let all = game.creatures.iter().chain(&game.foods).copied();
+        draw::draw_view(ctx, &game.data, &members, all)?;
+
+        if overlays.species {
+            let k = game.data.config.species_count;
+            species::draw_overlay(ctx, &game.data, &game.creatures, k)?;
+        }
+
+        if overlays.mortality {
+            game.data.mortality.draw(ctx)?;
+        }
+
+        if overlays.panel {
+            self.panel.draw(ctx, &game.data.config)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a frame with the group annotated to a canvas and writes it to
+    /// a PNG, the window never shows it
+    fn annotated_screenshot(&mut self, ctx: &mut Context) -> GameResult<String> {
+        let canvas = graphics::Canvas::with_window_size(ctx)?;
+        graphics::set_canvas(ctx, Some(&canvas));
+        let drawn = self
+            .game
+            .draw(ctx)
+            .and_then(|()| self.draw_overlays(ctx, Overlays::of(self.ui_state(), true)));
+        graphics::set_canvas(ctx, None);
+        drawn?;
+
+        let image = canvas.image();
+        let (width, height) = (u32::from(image.width()), u32::from(image.height()));
+        let pixels = image.to_rgba8(ctx)?;
+        let image = RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| GameError::RenderError("screenshot has the wrong size".to_owned()))?;
+        // canvases are stored upside down
+        let image = imageops::flip_vertical(&image);
+        let path = format!(
+            "screenshot_gen{}_{:.0}.png",
+            self.game.generation, self.game.time
+        );
+        image
+            .save(&path)
+            .map_err(|err| GameError::ResourceLoadError(err.to_string()))?;
+        Ok(path)
+    }
+
+    /// Runs a line typed into the console and prints what happened
+    fn run_command(&mut self, line: &str) {
+        match console::parse(line).and_then(|command| self.execute(command)) {
+            Ok(message) => self.console.print(message),
+            Err(err) => self.console.print(format!("error: {}", err)),
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> Result<String, String> {
+        let game = &mut self.game;
+        match command {
+            Command::SpawnFood(count) => {
+                for _ in 0..count {
+                    let e = spawn_food(&mut game.data, &game.patches, &game.creatures);
+                    game.foods.push(e);
+                }
+                Ok(format!("spawned {} food", count))
+            }
+            Command::SpawnCreatures { kind, count, at } => {
+                let mut rng = game.data.take_resource::<SimRng>();
+                for _ in 0..count {
+                    let genome = Genome::random(kind, &mut rng);
+                    let position = match at {
+                        Some(at) => spawn::clamp_to_world(at, genome.radius),
+                        None => spawn::random_position(&game.data.config, genome.radius, &mut rng),
+                    };
+                    let brain = brain::new_for(&game.data.config, kind, &mut rng);
+                    spawn::creature_with(&mut game.data.lazy, position, kind, &genome, brain);
+                }
+                game.data.insert_resource(rng);
+                Ok(format!("spawned {} {:?}", count, kind))
+            }
+            Command::Kill(idx) => game.kill(Entity { idx }),
+            Command::Set(name, value) => {
+                game.data
+                    .config
+                    .set(&name, &value)
+                    .map_err(|err| err.to_string())?;
+                self.panel.dirty = true;
+                Ok(format!("{} = {}", name, value))
+            }
+            Command::SaveWorld(path) => game
+                .save_world(&path)
+                .map(|()| format!("wrote {}", path))
+                .map_err(|err| err.to_string()),
+            Command::Save => game
+                .save_elites()
+                .map(|path| format!("wrote {}", path))
+                .map_err(|err| err.to_string()),
+            Command::Stats => Ok(game.stats()),
+            Command::Select(idx) => {
+                let e = Entity { idx };
+                if !game.creatures.contains(&e) {
+                    return Err(format!("no creature {}", idx));
+                }
+                self.group.clear();
+                self.group.members.push(e);
+                self.group.generation = game.generation;
+                Ok(format!("selected {}", idx))
+            }
+        }
+    }
+}
+
+impl EventHandler for Game {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        if self.interrupted() {
+            process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+        }
+
+        // contacts are only collected while somebody is looking at them
+        self.game.data.contacts.enabled = self.show_contacts;
+        match self.state {
+            State::Game => {
+                let real = timer::duration_to_f64(timer::delta(ctx)) as f32;
+                if let Some(real) = self.focus.frame(&self.game.data.config, real) {
+                    self.game.advance(real)?;
+                }
+                // rollovers start from empty storages anyway, this is for
+                // long generations and continuous mode
+                if self.game.data.hole_ratio() > COMPACT_HOLE_RATIO {
+                    let map = self.game.compact();
+                    self.group.remap(&map);
+                    self.labels.clear();
+                }
+            }
+            State::Gallery(_) => {}
+        }
+
+        // real time, painting shouldn't get faster with the sim speed
+        if let Some((_, sign)) = self.paint_button {
+            let delta = timer::duration_to_f64(timer::delta(ctx)) as f32;
+            let position = mouse::position(ctx);
+            let config = &self.game.data.config;
+            let position = draw::to_world(config, Vector2::new(position.x, position.y));
+            self.game
+                .data
+                .food_grid
+                .paint(position, sign * PAINT_RATE * delta);
+        }
+
+        let now = timer::duration_to_f64(timer::time_since_start(ctx)) as f32;
+        let events = std::mem::take(&mut self.game.events);
+        self.bursts.record(now, events);
+        let config = &self.game.data.config;
+        let burst = self.bursts.burst(config, now);
+        if let Some(music) = &mut self.music {
+            music.set_volume(audio::music_volume(config, burst) * self.focus.volume(config));
+        }
+
+        // real time, so the title keeps ticking at any sim speed
+        self.title_timeout += timer::duration_to_f64(timer::delta(ctx)) as f32;
+        if self.title_timeout > TITLE_INTERVAL {
+            self.title_timeout %= TITLE_INTERVAL;
+            let throttle = if self.throttle.on {
+                Some(self.game.data.config.render_divisor)
+            } else {
+                None
+            };
+            let title = title(
+                &self.game,
+                throttle,
+                timer::fps(ctx),
+                timer::duration_to_f64(timer::average_delta(ctx)),
+            );
+            graphics::set_window_title(ctx, &title);
+        }
+
+        if self.focus.idle(&self.game.data.config) {
+            timer::sleep(focus::BACKGROUND_SLEEP);
+        }
+
+        profile::end_frame();
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        // skipping present too means vsync doesn't hold updates back
+        if !self
+            .throttle
+            .draw_frame(self.game.data.config.render_divisor)
+        {
+            return Ok(());
+        }
+
+        match self.state {
+            State::Game | State::Gallery(_) => self.game.draw(ctx)?,
+        }
+
+        if self.painting {
+            self.game.data.food_grid.draw(ctx)?;
+        }
+
+        self.draw_overlays(ctx, Overlays::of(self.ui_state(), false))?;
+
+        if let State::Gallery(gallery) = &self.state {
+            gallery.draw(ctx, &self.game.data.config)?;
+        }
+
+        if self.console.open {
+            self.console.draw(ctx)?;
+        }
+
+        graphics::present(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymods: KeyMods,
+        _repeat: bool,
+    ) {
+        // the console gets every key while it's open, typing goes through
+        // `text_input_event`
+        if self.console.open {
+            match keycode {
+                KeyCode::Return => {
+                    if let Some(line) = self.console.submit() {
+                        self.run_command(&line);
+                    }
+                }
+                KeyCode::Back => self.console.backspace(),
+                KeyCode::Up => self.console.older(),
+                KeyCode::Down => self.console.newer(),
+                _ => {
+                    let action = self.game.data.config.keybindings.resolve(keycode, keymods);
+                    if matches!(action, Some(Action::Quit) | Some(Action::ToggleConsole)) {
+                        self.console.toggle();
+                    }
+                }
+            }
+            return;
+        }
+
+        if let State::Gallery(gallery) = &mut self.state {
+            let data = &mut self.game.data;
+            match keycode {
+                KeyCode::Up => gallery.prev(),
+                KeyCode::Down => gallery.next(),
+                KeyCode::Return => gallery.inject(data),
+                KeyCode::D => {
+                    let generation = gallery.selected().map_or(0, |entry| entry.generation);
+                    let path = format!("card_gen{}_{}.json", generation, gallery.selected);
+                    match gallery.export(&path) {
+                        Ok(()) => println!("wrote {}", path),
+                        Err(err) => eprintln!("couldn't write {}: {}", path, err),
+                    }
+                }
+                _ => {
+                    let action = data.config.keybindings.resolve(keycode, keymods);
+                    if matches!(action, Some(Action::Quit) | Some(Action::ToggleGallery)) {
+                        self.state = State::Game;
+                    }
+                }
+            }
+            return;
+        }
+
+        let config = &mut self.game.data.config;
+        let action = match config.keybindings.resolve(keycode, keymods) {
+            Some(action) => action,
+            None => return,
+        };
+        match action {
+            // escape gets rid of the group before it quits
+            Action::Quit if !self.group.members.is_empty() => self.group.clear(),
+            Action::Quit => event::quit(ctx),
+            Action::TogglePanel => self.panel.toggle(),
+            Action::ToggleSpecies => self.show_species = !self.show_species,
+            Action::ToggleContacts => self.show_contacts = !self.show_contacts,
+            Action::ToggleLabels => self.labels.toggle(),
+            Action::ToggleMortality => self.show_mortality = !self.show_mortality,
+            Action::ToggleConsole => self.console.toggle(),
+            Action::AnnotatedScreenshot => match self.annotated_screenshot(ctx) {
+                Ok(path) => println!("wrote {}", path),
+                Err(err) => eprintln!("couldn't take screenshot: {}", err),
+            },
+            Action::ToggleThrottle => self.throttle.toggle(),
+            Action::ExportPolicy => self.game.export_policy(ctx),
+            Action::DescribeEntity => self.game.describe_entity(ctx),
+            Action::Famine => self.game.famine(),
+            Action::ExportGroup => self.export_group(),
+            Action::TogglePaint => {
+                self.painting = !self.painting;
+                self.paint_button = None;
+            }
+            Action::ToggleGallery => match Gallery::scan(".", config) {
+                Ok(gallery) => {
+                    for skipped in &gallery.skipped {
+                        eprintln!("gallery skipped {}", skipped);
+                    }
+                    self.state = State::Gallery(gallery);
+                }
+                Err(err) => eprintln!("couldn't list saves: {}", err),
+            },
+            Action::SaveConfig => self.panel.persist(config, CONFIG_PATH),
+            Action::PanelNext if self.panel.open => self.panel.next(),
+            Action::PanelPrev if self.panel.open => self.panel.prev(),
+            Action::PanelUp if self.panel.open => self.panel.step(config, 1.0),
+            Action::PanelDown if self.panel.open => self.panel.step(config, -1.0),
+            Action::PanelNext | Action::PanelPrev | Action::PanelUp | Action::PanelDown => {}
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        // the key that opens the console shouldn't end up in it
+        if self.console.open && !character.is_control() && character != '`' && character != '~' {
+            self.console.type_char(character);
+        }
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        let keybindings = &self.game.data.config.keybindings;
+        let mods = keyboard::active_mods(ctx);
+        match keybindings.resolve_mouse(button, mods) {
+            Some(MouseAction::SelectGroup) => {
+                let position = draw::to_world(&self.game.data.config, Vector2::new(x, y));
+                self.group.start(position);
+                self.group_button = Some(button);
+            }
+            Some(MouseAction::PaintMore) if self.painting => {
+                self.paint_button = Some((button, 1.0));
+            }
+            Some(MouseAction::PaintLess) if self.painting => {
+                self.paint_button = Some((button, -1.0));
+            }
+            Some(MouseAction::PaintMore) | Some(MouseAction::PaintLess) | None => {}
+        }
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) {
+        self.focus.set(gained);
+    }
+
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        self.group
+            .drag_to(draw::to_world(&self.game.data.config, Vector2::new(x, y)));
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        // modifiers may be let go before the button, so only the button counts
+        if self.paint_button.is_some_and(|(held, _)| held == button) {
+            self.paint_button = None;
+        }
+        if self.group_button == Some(button) {
+            self.group_button = None;
+            self.group
+                .drag_to(draw::to_world(&self.game.data.config, Vector2::new(x, y)));
+            let game = &self.game;
+            self.group
+                .finish(&game.data, game.generation, &game.creatures);
+        }
+    }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        self.finish();
         match self.state {
-            State::Game => self.game.quit_event(ctx),
+            State::Game | State::Gallery(_) => self.game.quit_event(ctx),
+        }
+    }
+}
+
+/// Exit code when the config or the window can't be set up
+pub const SETUP_EXIT_CODE: i32 = 2;
+
+/// Builds a fresh world and checks it, returns the exit code
+fn self_check() -> i32 {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("couldn't load the config: {}", err);
+            return SETUP_EXIT_CODE;
+        }
+    };
+    let game = GameState::new(0, config);
+    let mut problems = check::validate_fresh(&game.data, &game.creatures, &game.foods);
+    // `--resume` relies on this, meshes aside
+    let snapshot = game.data.snapshot();
+    let decoded = bincode::serialize(&snapshot)
+        .and_then(|encoded| bincode::deserialize::<WorldSnapshot>(&encoded));
+    match decoded {
+        Ok(decoded) if decoded == snapshot => {}
+        Ok(_) => problems.push("world snapshot changed going through bincode".to_owned()),
+        Err(err) => problems.push(format!("world snapshot doesn't round trip: {}", err)),
+    }
+    if problems.is_empty() {
+        println!(
+            "self check passed: {} creatures, {} foods",
+            game.creatures.len(),
+            game.foods.len()
+        );
+        return 0;
+    }
+
+    eprintln!("self check found {} problems:", problems.len());
+    for problem in &problems {
+        eprintln!("  {}", problem);
+    }
+    1
+}
+
+/// Prints how the creatures in the chronicle died, returns the exit code
+fn chronicle_summary() -> i32 {
+    let records = match chronicle::load(CHRONICLE_PATH) {
+        Ok(records) => records,
+        Err(err) => {
+            eprintln!("couldn't read {}: {}", CHRONICLE_PATH, err);
+            return 1;
+        }
+    };
+
+    println!("{} creatures in {}", records.len(), CHRONICLE_PATH);
+    for (cause, count, life) in chronicle::summarize(&records) {
+        println!("  {}: {} (mean life {:.1}s)", cause, count, life);
+    }
+    0
+}
+
+/// Ranks the generations saved in `dir` by playing their elites against
+/// each other, returns the exit code
+fn tournament(dir: &str) -> i32 {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("couldn't load the config: {}", err);
+            return SETUP_EXIT_CODE;
+        }
+    };
+    let standings = match tournament::run(dir, &config) {
+        Ok(standings) => standings,
+        Err(err) => {
+            eprintln!("couldn't open {}: {}", dir, err);
+            return SETUP_EXIT_CODE;
+        }
+    };
+    if standings.len() < 2 {
+        eprintln!("a tournament needs at least two generations in {}", dir);
+        return 1;
+    }
+
+    for (rank, standing) in standings.iter().enumerate() {
+        println!(
+            "{:>3}. generation {}: {:.0} ({}-{}-{})",
+            rank + 1,
+            standing.generation,
+            standing.rating,
+            standing.wins,
+            standing.draws,
+            standing.losses
+        );
+    }
+    if let Err(err) = tournament::write_csv(&standings, TOURNAMENT_PATH) {
+        eprintln!("couldn't write {}: {}", TOURNAMENT_PATH, err);
+        return 1;
+    }
+    0
+}
+
+/// Plays an episode of `env::Env` picking directions at random, prints how
+/// long it lasted and what it earned
+fn random_agent() -> i32 {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("couldn't load the config: {}", err);
+            return SETUP_EXIT_CODE;
+        }
+    };
+    let mut rng = SimRng::new(config.seed);
+    let mut env = env::Env::new(config);
+    let (mut steps, mut total) = (0, 0.0);
+    loop {
+        let (_, reward, done) = match env.step(rng.gen_range(0, DIR_COUNT)) {
+            Ok(step) => step,
+            Err(err) => {
+                eprintln!("couldn't step the world: {}", err);
+                return 1;
+            }
+        };
+        steps += 1;
+        total += reward;
+        if done {
+            break;
+        }
+    }
+    println!("episode over after {} steps, reward {:.2}", steps, total);
+    0
+}
+
+/// The number after `flag` on the command line, `default` without it
+fn count_flag(flag: &str, default: usize) -> error::Result<usize> {
+    match flag_value(flag) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| Error::Config(format!("{} takes a count, not {}", flag, value))),
+        None => Ok(default),
+    }
+}
+
+/// The newest checkpoint for `--resume-latest`, `None` starts from the
+/// elites as usual
+fn latest_checkpoint() -> Option<PathBuf> {
+    match checkpoint::latest(CHECKPOINT_DIR) {
+        Ok(Some(path)) => {
+            println!("resuming from {}", path.display());
+            Some(path)
+        }
+        Ok(None) => {
+            println!("no checkpoints in {}, starting over", CHECKPOINT_DIR);
+            None
+        }
+        Err(err) => {
+            eprintln!(
+                "couldn't look for checkpoints in {}: {}",
+                CHECKPOINT_DIR, err
+            );
+            None
         }
     }
 }
 
+/// Runs a seeded world for `--steps` steps and writes it to `path` for
+/// `--verify-against`
+fn record_reference(path: &str) -> i32 {
+    let recorded = load_config().and_then(|config| {
+        let steps = count_flag("--steps", verify::VERIFY_STEPS)?;
+        verify::record(&config, steps, path)?;
+        Ok(steps)
+    });
+    match recorded {
+        Ok(steps) => {
+            println!("recorded {} steps to {}", steps, path);
+            0
+        }
+        Err(err) => {
+            eprintln!("couldn't record {}: {}", path, err);
+            SETUP_EXIT_CODE
+        }
+    }
+}
+
+/// Runs the world in the reference at `path` again and prints the first
+/// `--diffs` differences, fails if there are any
+fn verify_against(path: &str) -> i32 {
+    let verified = count_flag("--diffs", verify::VERIFY_DIFFS)
+        .and_then(|shown| Ok((shown, verify::verify(path)?)));
+    let (shown, (steps, diffs)) = match verified {
+        Ok(verified) => verified,
+        Err(err) => {
+            eprintln!("couldn't verify against {}: {}", path, err);
+            return SETUP_EXIT_CODE;
+        }
+    };
+    if diffs.is_empty() {
+        println!("{} steps match {}", steps, path);
+        return 0;
+    }
+
+    eprintln!("{} differences after {} steps:", diffs.len(), steps);
+    for diff in diffs.iter().take(shown) {
+        eprintln!("  {}", diff);
+    }
+    if diffs.len() > shown {
+        eprintln!("  and {} more", diffs.len() - shown);
+    }
+    1
+}
+
 fn main() {
+    if std::env::args().any(|arg| arg == "--self-check") {
+        process::exit(self_check());
+    }
+    if let Some(dir) = flag_value("--tournament") {
+        process::exit(tournament(&dir));
+    }
+    if std::env::args().any(|arg| arg == "--random-agent") {
+        process::exit(random_agent());
+    }
+    if let Some(path) = flag_value("--record-reference") {
+        process::exit(record_reference(&path));
+    }
+    if let Some(path) = flag_value("--verify-against") {
+        process::exit(verify_against(&path));
+    }
+    if std::env::args().any(|arg| arg == "--chronicle-summary") {
+        process::exit(chronicle_summary());
+    }
+    if std::env::args().any(|arg| arg == "--list-presets") {
+        for preset in presets::PRESETS {
+            println!("{}: {}", preset.name, preset.summary);
+        }
+        return;
+    }
+
+    // a broken config is better fixed than silently replaced
+    let config = load_config().unwrap_or_else(|err| {
+        eprintln!("couldn't load the config: {}", err);
+        process::exit(SETUP_EXIT_CODE);
+    });
     let (mut ctx, mut event_loop) =
         ContextBuilder::new("ldjam-46", "Szymon \"pi\" Walter <waltersz@protonmail.com>")
             .window_mode(WindowMode {
-                // NOTE: the `* DPI_FACTOR` part should only be necessary on my fucked laptop
-                width: WIDTH,
-                height: HEIGHT,
+                width: WORLD_WIDTH * config.display_scale,
+                height: WORLD_HEIGHT * config.display_scale,
                 ..Default::default()
             })
             .build()
-            .expect("couldn't build game context");
+            .unwrap_or_else(|err| {
+                eprintln!("couldn't open a window: {}", err);
+                process::exit(SETUP_EXIT_CODE);
+            });
 
-    let mut game = Game::new(&mut ctx).expect("couldn't create game");
+    let resume = flag_value("--resume").map(PathBuf::from).or_else(|| {
+        if std::env::args().any(|arg| arg == "--resume-latest") {
+            latest_checkpoint()
+        } else {
+            None
+        }
+    });
+    let game = match resume {
+        // resuming is asked for, starting over instead would be a surprise
+        Some(path) => GameState::load_world(&mut ctx, &path).unwrap_or_else(|err| {
+            eprintln!("couldn't resume {}: {}", path.display(), err);
+            process::exit(SETUP_EXIT_CODE);
+        }),
+        // numbering goes on from the elites started from, so their run's
+        // saves don't get written over
+        None => {
+            let generation = elites_arg().map_or(0, |path| save::next_generation(path.as_ref()));
+            GameState::new(generation, config)
+        }
+    };
+    let mut game = Game::new(&mut ctx, game);
+    shutdown::install();
+    if let Some(path) = flag_value("--trace") {
+        profile::start(path, game.game.data.config.trace_frames);
+    }
 
     if let Err(err) = event::run(&mut ctx, &mut event_loop, &mut game) {
         eprintln!("{}", err);
         process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A fresh world that keeps everything off the disk, like a tournament
+    /// match
+    fn headless(seed: u64) -> GameState {
+        let config = Config {
+            seed,
+            chronicle: false,
+            frame_budget: 0.0,
+            ..Config::default()
+        };
+        let mut game = GameState::empty(0, config);
+        game.populate_with(Vec::new());
+        game
+    }
+
+    fn sorted(list: &[Entity]) -> bool {
+        list.windows(2).all(|w| w[0].idx < w[1].idx)
+    }
+
+    #[test]
+    fn reseeding_keeps_the_carnivore_quota() {
+        for &enforce_ratio in &[false, true] {
+            let mut game = GameState::empty(
+                0,
+                Config {
+                    seed: 3,
+                    chronicle: false,
+                    enforce_ratio,
+                    ..Config::default()
+                },
+            );
+            // more carnivore elites than the quota has room for
+            let mut elites = Elites::default();
+            let mut rng = SimRng::new(4);
+            for _ in 0..CREATURE_COUNT {
+                let network = brain::new_for(&game.data.config, Kind::Carnivorous, &mut rng);
+                let genome = Genome::random(Kind::Carnivorous, &mut rng);
+                elites
+                    .carnivores
+                    .push((Creature::new(Kind::Carnivorous), genome, network));
+            }
+            let sides = game.populate_with(vec![elites]);
+
+            let quota = (CREATURE_COUNT as f32 * game.data.config.carnivore_ratio) as usize;
+            let population = Population::count(&game.data, &game.creatures);
+            assert_eq!(game.creatures.len(), CREATURE_COUNT);
+            assert_eq!(population.carnivores, quota);
+            assert_eq!(sides[0].len(), quota);
+        }
+    }
+
+    /// A continuous world with `extra` carnivores over the quota, each older
+    /// than the last, and a third of its vegans gone
+    fn skewed(enforce_ratio: bool, extra: usize) -> GameState {
+        let mut game = headless(21);
+        game.data.config.continuous = true;
+        game.data.config.enforce_ratio = enforce_ratio;
+        let mut rng = SimRng::new(22);
+        for _ in 0..extra {
+            let kind = Kind::Carnivorous;
+            let genome = Genome::random(kind, &mut rng);
+            let position = spawn::random_position(&game.data.config, genome.radius, &mut rng);
+            let brain = brain::new_for(&game.data.config, kind, &mut rng);
+            let e = spawn::creature_with(&mut game.data, position, kind, &genome, brain);
+            game.creatures.push(e);
+        }
+        for (i, &e) in game.creatures.iter().enumerate() {
+            game.data[e.component::<Creature>()].life = i as f32 * 0.01;
+        }
+        let vegans: Vec<_> = game
+            .creatures
+            .iter()
+            .copied()
+            .filter(|e| game.data[e.component::<Creature>()].kind == Kind::Vegan)
+            .step_by(3)
+            .collect();
+        for e in vegans {
+            game.kill(e).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn enforced_ratios_retire_the_oldest_surplus() {
+        let mut game = skewed(true, 20);
+        let quota = continuous::quota(&game.data.config, Kind::Carnivorous);
+        let carnivores: Vec<_> = game
+            .creatures
+            .iter()
+            .copied()
+            .filter(|e| game.data[e.component::<Creature>()].kind == Kind::Carnivorous)
+            .collect();
+        let vegans = Population::count(&game.data, &game.creatures).vegans;
+        game.step(1.0 / 60.0).unwrap();
+
+        let population = Population::count(&game.data, &game.creatures);
+        assert_eq!(population.carnivores, quota);
+        // the ones spawned last were the oldest
+        let (young, old) = carnivores.split_at(carnivores.len() - 20);
+        assert!(young.iter().all(|e| game.creatures.contains(e)));
+        assert!(!old.iter().any(|e| game.creatures.contains(e)));
+        assert_eq!(game.data.mortality.carnivores[Cause::Survived.index()], 20);
+
+        // recruits fill the vegans all the way up to their quota
+        assert_eq!(
+            continuous::floor(&game.data.config, Kind::Vegan),
+            continuous::quota(&game.data.config, Kind::Vegan)
+        );
+        // a long step has a few of them due at once
+        game.step(2.0).unwrap();
+        let population = Population::count(&game.data, &game.creatures);
+        assert!(population.vegans > vegans);
+        assert!(population.carnivores <= quota);
+    }
+
+    #[test]
+    fn unenforced_ratios_drift() {
+        let mut game = skewed(false, 20);
+        let quota = continuous::quota(&game.data.config, Kind::Carnivorous);
+        game.step(1.0 / 60.0).unwrap();
+        let population = Population::count(&game.data, &game.creatures);
+        assert!(population.carnivores > quota);
+        assert!(population.carnivore_ratio() > game.data.config.carnivore_ratio);
+        assert!(game.stats().contains(&format!(
+            "{} carnivores ({:.0}%)",
+            population.carnivores,
+            population.carnivore_ratio() * 100.0
+        )));
+    }
+
+    #[test]
+    fn entity_lists_stay_sorted_by_index() {
+        let mut game = headless(7);
+        for step in 0..10 {
+            // deaths all over the lists, the next commit adds food after them
+            let dead: Vec<_> = game.creatures.iter().copied().step_by(7).collect();
+            for e in dead.into_iter().chain(game.foods.first().copied()) {
+                game.data.delete(e);
+                game.data.lazy.remove(e);
+            }
+            game.step(1.0 / 60.0).unwrap();
+            assert!(sorted(&game.creatures), "creatures after step {}", step);
+            assert!(sorted(&game.foods), "foods after step {}", step);
+        }
+    }
+
+    #[test]
+    fn food_spawns_a_few_at_a_time() {
+        let mut game = scenario();
+        let step = 1.0 / 60.0;
+        // the timeout runs out during the next step
+        game.food_timeout = game.food_controller.food_timeout(&game.data.config);
+        let foods = game.foods.len();
+        game.step(step).unwrap();
+        assert_eq!(game.pending_food, FOOD_COUNT - MAX_SPAWNS_PER_FRAME);
+        assert_eq!(game.foods.len(), foods + MAX_SPAWNS_PER_FRAME);
+
+        let frames = (FOOD_COUNT - 1) / MAX_SPAWNS_PER_FRAME;
+        for _ in 1..frames {
+            game.step(step).unwrap();
+        }
+        assert!(game.pending_food > 0);
+        game.step(step).unwrap();
+        assert_eq!(game.pending_food, 0);
+        assert_eq!(game.foods.len(), foods + FOOD_COUNT);
+    }
+
+    #[test]
+    fn rich_food_lands_in_its_patches() {
+        let mut data = GameData::new();
+        data.food_grid = FoodGrid::default();
+        let mut rng = SimRng::new(9);
+        let patch = Vector2::new(WORLD_WIDTH * 0.5, WORLD_HEIGHT * 0.5);
+        let radius = data.config.rich_patch_radius;
+        for _ in 0..100 {
+            let position = food_position(&data, &[patch], true, 5.0, &mut rng);
+            assert!((position - patch).magnitude() <= radius);
+        }
+        // plain food goes anywhere
+        let far = (0..100)
+            .map(|_| food_position(&data, &[patch], false, 5.0, &mut rng))
+            .any(|position| (position - patch).magnitude() > radius);
+        assert!(far);
+    }
+
+    #[test]
+    fn populating_a_new_world_doesnt_reallocate() {
+        let config = Config {
+            seed: 2,
+            chronicle: false,
+            ..Config::default()
+        };
+        let mut game = GameState::empty(0, config);
+        let capacities = |game: &GameState| {
+            let report = game.data.memory_report();
+            report
+                .storages
+                .iter()
+                // contacts aren't entity storages
+                .filter(|storage| storage.name != "contacts")
+                .map(|storage| (storage.name, storage.capacity))
+                .collect::<Vec<_>>()
+        };
+        let before = capacities(&game);
+        assert!(before
+            .iter()
+            .all(|&(_, capacity)| capacity >= INITIAL_CAPACITY));
+        game.populate_with(Vec::new());
+        assert!(game.data.memory_report().entities >= CREATURE_COUNT);
+        assert_eq!(capacities(&game), before);
+    }
+
+    #[test]
+    fn console_commands_reach_the_game() {
+        let mut game = Game::with_music(None, headless(17));
+        let run = |game: &mut Game, line: &str| {
+            game.run_command(line);
+            game.console.output.last().unwrap().clone()
+        };
+
+        let foods = game.game.foods.len();
+        assert_eq!(run(&mut game, "spawn food 3"), "spawned 3 food");
+        assert_eq!(game.game.foods.len(), foods + 3);
+
+        assert_eq!(
+            run(&mut game, "spawn vegan 2 at 100 120"),
+            "spawned 2 Vegan"
+        );
+        let added = game.game.data.commit().added;
+        assert_eq!(added.len(), 2);
+        for e in added {
+            assert_eq!(game.game.data[e.component::<Creature>()].kind, Kind::Vegan);
+            let position = game.game.data[e.component::<Position>()].position;
+            assert_eq!(position, Vector2::new(100.0, 120.0));
+        }
+
+        let e = game.game.creatures[0];
+        assert_eq!(
+            run(&mut game, &format!("select {}", e.idx)),
+            format!("selected {}", e.idx)
+        );
+        assert_eq!(game.group.members, vec![e]);
+        assert_eq!(
+            run(&mut game, &format!("kill {}", e.idx)),
+            format!("killed {}", e.idx)
+        );
+        assert!(!game.game.creatures.contains(&e));
+
+        assert_eq!(run(&mut game, "set sim_speed 3"), "sim_speed = 3");
+        assert_eq!(game.game.data.config.sim_speed, 3.0);
+        assert!(game.panel.dirty);
+
+        let stats = game.game.stats();
+        assert_eq!(run(&mut game, "stats"), stats);
+
+        let path = std::env::temp_dir().join(format!("console_world_{}.bin", process::id()));
+        let line = format!("save world {}", path.display());
+        assert_eq!(run(&mut game, &line), format!("wrote {}", path.display()));
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn console_errors_are_printed() {
+        let mut game = Game::with_music(None, headless(18));
+        let lines = [
+            ("jump", "error: unknown command jump"),
+            ("kill 99999", "error: no creature or food 99999"),
+            ("select 99999", "error: no creature 99999"),
+            ("spawn food many", "error: many isn't a valid count"),
+        ];
+        for (line, error) in lines.iter() {
+            game.run_command(line);
+            assert_eq!(game.console.output.last().unwrap(), error);
+        }
+        let set = "set no_such_setting 1";
+        game.run_command(set);
+        assert!(game.console.output.last().unwrap().starts_with("error: "));
+    }
+
+    #[test]
+    fn startles_wear_off() {
+        let mut game = scenario();
+        let vegan = game.creatures[1];
+        let startle_time = game.data.config.startle_time;
+        game.data[vegan.component::<Creature>()].last_hit = startle_time;
+        let delta = SimTime::new(game.data.config.sim_speed).advance(1.0 / 60.0);
+        game.step_with(1.0 / 60.0, |_| {}).unwrap();
+        let last_hit = game.data[vegan.component::<Creature>()].last_hit;
+        assert!(
+            (last_hit - (startle_time - delta)).abs() < 1e-5,
+            "{}",
+            last_hit
+        );
+
+        // and stop at zero
+        game.data[vegan.component::<Creature>()].last_hit = delta * 0.5;
+        game.step_with(1.0 / 60.0, |_| {}).unwrap();
+        assert_eq!(game.data[vegan.component::<Creature>()].last_hit, 0.0);
+    }
+
+    #[test]
+    fn interrupts_save_once() {
+        /// Moves into a temp dir for what the interrupt writes, goes back and
+        /// takes the interrupt back on drop, even when an assert fails
+        struct Sandbox {
+            previous: PathBuf,
+            dir: PathBuf,
+        }
+
+        impl Drop for Sandbox {
+            fn drop(&mut self) {
+                shutdown::reset();
+                env::set_current_dir(&self.previous).unwrap();
+                let _ = fs::remove_dir_all(&self.dir);
+            }
+        }
+
+        let dir = env::temp_dir().join(format!("ldjam-46-interrupt-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let previous = env::current_dir().unwrap();
+        env::set_current_dir(&dir).unwrap();
+        let _sandbox = Sandbox { previous, dir };
+
+        let mut game = Game::with_music(None, headless(19));
+        let path = save::generation_path(game.game.generation);
+        let survivors = |game: &Game| {
+            let mortality = game.game.data.mortality;
+            let i = Cause::Survived.index();
+            mortality.vegans[i] + mortality.carnivores[i]
+        };
+
+        shutdown::request();
+        assert!(game.interrupted());
+        let saved = Elites::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(saved.is_ok());
+        assert_eq!(survivors(&game), game.game.creatures.len());
+
+        // a close on top of the interrupt
+        assert!(game.interrupted());
+        assert!(!game.game.finish());
+        assert!(!Path::new(&path).exists());
+        assert_eq!(survivors(&game), game.game.creatures.len());
+        assert!(Path::new(UI_STATE_PATH).exists());
+    }
+
+    #[test]
+    fn famines_clear_food_until_they_end() {
+        let mut game = scenario();
+        game.data.config.famine_duration = 0.5;
+        game.data.config.food_timeout = 0.1;
+        game.famine();
+        assert!(game.foods.is_empty());
+        assert_eq!(game.data.iter::<(Food,)>().count(), 0);
+        assert_eq!(game.famine_timeout, 0.5);
+
+        let delta = SimTime::new(game.data.config.sim_speed).advance(1.0 / 60.0);
+        while game.famine_timeout > 0.0 {
+            game.step(1.0 / 60.0).unwrap();
+            assert!(game.foods.is_empty(), "{}", game.famine_timeout);
+        }
+        let steps = (0.1 / delta).ceil() as usize + 1;
+        for _ in 0..steps {
+            game.step(1.0 / 60.0).unwrap();
+        }
+        assert!(!game.foods.is_empty());
+        assert_eq!(game.data.iter::<(Food,)>().count(), game.foods.len());
+    }
+
+    #[test]
+    fn annotating_only_adds_the_group_overlays() {
+        let closed = UiState::default();
+        assert_eq!(
+            Overlays::of(closed, true),
+            Overlays {
+                member_labels: true,
+                trails: true,
+                ..Overlays::of(closed, false)
+            }
+        );
+        assert!(!Overlays::of(closed, false).member_labels);
+        assert!(!Overlays::of(closed, false).trails);
+
+        // everything that's open stays open, labels on everything win
+        let open = UiState {
+            show_panel: true,
+            show_species: true,
+            show_contacts: true,
+            show_labels: true,
+            show_mortality: true,
+        };
+        let annotated = Overlays::of(open, true);
+        assert!(annotated.labels && !annotated.member_labels && annotated.trails);
+        assert!(annotated.contacts && annotated.species && annotated.mortality && annotated.panel);
+    }
+
+    #[test]
+    fn toggles_are_saved_and_restored() {
+        let mut game = Game::with_music(None, headless(20));
+        game.show_species = true;
+        game.labels.open = true;
+        game.panel.open = false;
+        let before = game.ui_state();
+
+        let path = std::env::temp_dir().join(format!("ldjam-46-ui-{}.json", process::id()));
+        before.save(&path).unwrap();
+        let loaded = UiState::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), before);
+        assert!(before.show_species && before.show_labels && !before.show_panel);
+    }
+
+    #[test]
+    fn the_title_shows_the_vitals() {
+        let mut game = scenario();
+        game.generation = 14;
+        assert_eq!(
+            title(&game, None, 58.4, 0.0042),
+            "ldjam-46 — gen 14 | 5 creatures | 10 food | 58 fps | 4.2 ms/frame"
+        );
+        game.famine_timeout = 2.2;
+        let throttled = title(&game, Some(3), 58.4, 0.0042);
+        assert!(
+            throttled.ends_with("ms/frame | famine 3s | drawing 1/3"),
+            "{}",
+            throttled
+        );
+
+        game.data.config.adaptive_food = true;
+        game.food_controller.multiplier = 1.5;
+        let title = title(&game, None, 58.4, 0.0042);
+        assert!(
+            title.ends_with("ms/frame | food x1.50 | famine 3s"),
+            "{}",
+            title
+        );
+        assert!(game.stats().contains("10 food (x1.50)"), "{}", game.stats());
+    }
+
+    #[test]
+    fn food_spawns_clear_of_creatures() {
+        let mut game = scenario();
+        let e = game.creatures[0];
+        let position = game.data[e.component::<Position>()].position;
+        let reach = game.data[e.component::<Body>()].radius + 5.0 + game.data.config.food_clearance;
+        let creatures = [e];
+        let offset = |d: f32| position + Vector2::new(d, 0.0);
+        assert!(!clear_of(&game.data, &creatures, offset(reach - 0.1), 5.0));
+        assert!(clear_of(&game.data, &creatures, offset(reach + 0.1), 5.0));
+
+        for _ in 0..50 {
+            let food = spawn_food(&mut game.data, &game.patches, &game.creatures);
+            let position = game.data[food.component::<Position>()].position;
+            let radius = game.data[food.component::<Body>()].radius;
+            assert!(clear_of(&game.data, &game.creatures, position, radius));
+        }
+    }
+
+    #[test]
+    fn an_empty_world_plays_without_panicking() {
+        let config = Config {
+            seed: 5,
+            chronicle: false,
+            frame_budget: 0.0,
+            ..Config::default()
+        };
+        let mut game = GameState::empty(0, config);
+        game.data.food_grid = FoodGrid::default();
+        for _ in 0..120 {
+            game.step(1.0 / 60.0).unwrap();
+        }
+        assert!(game.creatures.is_empty());
+        assert!(!game.foods.is_empty());
+        assert_eq!(species::diversity(&game.data, &game.creatures), 0.0);
+        assert!(title(&game, None, 60.0, 0.001).contains("| 0 creatures |"));
+    }
+
+    #[test]
+    fn long_frames_are_clamped_and_counted() {
+        let mut game = headless(9);
+        game.data.config.max_frame_delta = 0.25;
+        assert_eq!(game.frame_delta(0.1), 0.1);
+        assert_eq!(game.frame_delta(2.0), 0.25);
+        assert_eq!(game.frame_delta(1.25), 0.25);
+        assert_eq!(game.clamped_frames, 2);
+        assert!((game.lost_time - 2.75).abs() < 1e-6);
+        assert_eq!(game.frame_delta(0.2), 0.2);
+        assert_eq!(game.clamped_frames, 0);
+
+        game.data.config.max_frame_delta = 0.0;
+        assert_eq!(game.frame_delta(5.0), 5.0);
+        assert!((game.lost_time - 2.75).abs() < 1e-6);
+    }
+
+    #[test]
+    fn a_resumed_world_plays_out_like_the_one_it_was_saved_from() {
+        let mut game = headless(11);
+        // a whole generation in a few dozen steps, and no more creatures than
+        // it started with to keep them quick
+        game.data.config.sim_speed = 40.0;
+        game.data.config.max_creatures = CREATURE_COUNT;
+        let step = 1.0 / 60.0;
+        game.step(step).unwrap();
+        game.step(step).unwrap();
+        let encoded = bincode::serialize(&game.saved_world()).unwrap();
+        let saved = decode_world(&encoded).unwrap();
+        let mut resumed =
+            GameState::from_saved(saved, |world| Ok(GameData::from_snapshot(world))).unwrap();
+
+        let time = SimTime::new(game.data.config.sim_speed);
+        let mut steps = 0;
+        // up to the end of the generation, a rollover would write to the disk
+        while game.time + time.advance(step) <= time.generation() {
+            game.step(step).unwrap();
+            resumed.step(step).unwrap();
+            steps += 1;
+            assert!(
+                game.saved_world() == resumed.saved_world(),
+                "worlds differ after {} steps: {:?}",
+                steps,
+                game.data.diff(&resumed.data)
+            );
+        }
+        assert!(steps > 40);
+    }
+
+    const SCENARIO_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/scenario_checksums.txt"
+    );
+    const SCENARIO_STEPS: usize = 300;
+
+    /// A seeded world of 5 creatures, one of them a carnivore, and 10 food,
+    /// without the food grid painted on disk
+    fn scenario() -> GameState {
+        let config = Config {
+            seed: 46,
+            chronicle: false,
+            frame_budget: 0.0,
+            ..Config::default()
+        };
+        let mut game = GameState::empty(0, config);
+        game.data.food_grid = FoodGrid::default();
+        let mut rng = game.data.take_resource::<SimRng>();
+        for i in 0..5 {
+            let kind = if i == 0 {
+                Kind::Carnivorous
+            } else {
+                Kind::Vegan
+            };
+            let genome = Genome::random(kind, &mut rng);
+            let position = spawn::random_position(&game.data.config, genome.radius, &mut rng);
+            let brain = brain::new_for(&game.data.config, kind, &mut rng);
+            let e = spawn::creature_with(&mut game.data, position, kind, &genome, brain);
+            game.creatures.push(e);
+        }
+        game.data.insert_resource(rng);
+        for _ in 0..10 {
+            let e = spawn_food(&mut game.data, &game.patches, &game.creatures);
+            game.foods.push(e);
+        }
+        game
+    }
+
+    /// The checksums of the scenario after every one of its steps, with the
+    /// window at `display_scale`
+    fn scenario_checksums(display_scale: f32) -> Vec<Vec<(&'static str, u64)>> {
+        let mut game = scenario();
+        game.data.config.display_scale = display_scale;
+        (0..SCENARIO_STEPS)
+            .map(|_| {
+                game.step(1.0 / 60.0).unwrap();
+                game.data.checksums()
+            })
+            .collect()
+    }
+
+    /// Run with `REGEN_FIXTURES=1` to write the checksums again after a
+    /// change to the simulation that's on purpose
+    #[test]
+    fn scenario_matches_its_golden_checksums() {
+        let steps = scenario_checksums(DPI_FACTOR);
+        if std::env::var_os("REGEN_FIXTURES").is_some() {
+            let names: Vec<_> = steps[0].iter().map(|&(name, _)| name).collect();
+            let mut fixture = format!(
+                "# checksums after every step of `scenario` in main.rs, written by\n\
+                 # REGEN_FIXTURES=1 cargo test scenario_matches_its_golden_checksums\n\
+                 # step {}\n",
+                names.join(" ")
+            );
+            for (step, checksums) in steps.iter().enumerate() {
+                let hashes: Vec<_> = checksums
+                    .iter()
+                    .map(|(_, hash)| format!("{:016x}", hash))
+                    .collect();
+                fixture.push_str(&format!("{} {}\n", step + 1, hashes.join(" ")));
+            }
+            fs::create_dir_all(Path::new(SCENARIO_PATH).parent().unwrap()).unwrap();
+            fs::write(SCENARIO_PATH, fixture).unwrap();
+            return;
+        }
+
+        let fixture = fs::read_to_string(SCENARIO_PATH).unwrap();
+        let expected: Vec<Vec<u64>> = fixture
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                line.split_whitespace()
+                    .skip(1)
+                    .map(|hash| u64::from_str_radix(hash, 16).unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(expected.len(), SCENARIO_STEPS);
+        for (step, (expected, checksums)) in expected.iter().zip(&steps).enumerate() {
+            let differ: Vec<_> = checksums
+                .iter()
+                .zip(expected)
+                .filter(|((_, hash), expected)| hash != *expected)
+                .map(|((name, _), _)| *name)
+                .collect();
+            assert!(
+                differ.is_empty(),
+                "step {} is the first to differ from {}, in {}. \
+                 --record-reference and --verify-against show which entities differ.",
+                step + 1,
+                SCENARIO_PATH,
+                differ.join(", ")
+            );
+        }
+    }
+
+    /// Gameplay is in world units, so a bigger window plays out the same
+    #[test]
+    fn the_display_scale_doesnt_change_the_run() {
+        assert_eq!(scenario_checksums(DPI_FACTOR), scenario_checksums(1.0));
+    }
+}
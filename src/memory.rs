@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+
+/// Capacity growing by more than this fraction on top of the entity count is
+/// flagged as a leak
+pub const LEAK_GROWTH: f32 = 0.5;
+/// Number of generations the growth is measured over
+pub const LEAK_GENERATIONS: usize = 5;
+
+/// For values that own nothing outside of their storage
+pub fn no_heap<T>(_: &T) -> usize {
+    0
+}
+
+/// Memory used by one storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageReport {
+    pub name: &'static str,
+    pub len: usize,
+    /// Slots that hold a value
+    pub occupied: usize,
+    pub capacity: usize,
+    /// Estimate of the bytes used, including what the values point to
+    pub bytes: usize,
+}
+
+impl StorageReport {
+    /// Reports a storage of optional values, `heap` estimates the bytes a
+    /// value owns outside of the storage itself
+    pub fn of<T, F>(name: &'static str, storage: &Vec<Option<T>>, heap: F) -> Self
+    where
+        F: Fn(&T) -> usize,
+    {
+        let owned: usize = storage.iter().flatten().map(heap).sum();
+        Self {
+            name,
+            len: storage.len(),
+            occupied: storage.iter().filter(|t| t.is_some()).count(),
+            capacity: storage.capacity(),
+            bytes: storage.capacity() * mem::size_of::<Option<T>>() + owned,
+        }
+    }
+
+    /// Reports a plain list whose values own nothing else
+    pub fn list<T>(name: &'static str, list: &Vec<T>) -> Self {
+        Self {
+            name,
+            len: list.len(),
+            occupied: list.len(),
+            capacity: list.capacity(),
+            bytes: list.capacity() * mem::size_of::<T>(),
+        }
+    }
+}
+
+/// Memory used by every storage of a world
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MemoryReport {
+    pub entities: usize,
+    pub storages: Vec<StorageReport>,
+}
+
+impl MemoryReport {
+    pub fn bytes(&self) -> usize {
+        self.storages.iter().map(|s| s.bytes).sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} entities, {} KiB:",
+            self.entities,
+            self.bytes() / 1024
+        )?;
+        for s in &self.storages {
+            write!(
+                f,
+                " {} {}/{}/{} {}B",
+                s.name, s.occupied, s.len, s.capacity, s.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Names of the storages whose capacity grew from `old` to `new` by more than
+/// `LEAK_GROWTH` on top of the growth of the entity count
+pub fn leaks(old: &MemoryReport, new: &MemoryReport) -> Vec<&'static str> {
+    let entities = (new.entities as f32 / old.entities.max(1) as f32).max(1.0);
+    new.storages
+        .iter()
+        .filter(|s| {
+            let before = match old.storages.iter().find(|o| o.name == s.name) {
+                Some(o) if o.capacity > 0 => o.capacity,
+                _ => return false,
+            };
+            s.capacity as f32 / before as f32 > entities * (1.0 + LEAK_GROWTH)
+        })
+        .map(|s| s.name)
+        .collect()
+}
+
+/// Keeps the reports of the last few generations to find storages that keep
+/// growing
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MemoryWatch {
+    history: VecDeque<MemoryReport>,
+}
+
+impl MemoryWatch {
+    /// Adds the report of a generation, returns the storages that leaked
+    /// over the last `LEAK_GENERATIONS` generations
+    pub fn record(&mut self, report: MemoryReport) -> Vec<&'static str> {
+        self.history.push_back(report);
+        if self.history.len() > LEAK_GENERATIONS + 1 {
+            self.history.pop_front();
+        }
+        if self.history.len() <= LEAK_GENERATIONS {
+            return Vec::new();
+        }
+
+        leaks(&self.history[0], &self.history[self.history.len() - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(entities: usize, capacities: &[(&'static str, usize)]) -> MemoryReport {
+        MemoryReport {
+            entities,
+            storages: capacities
+                .iter()
+                .map(|&(name, capacity)| StorageReport {
+                    name,
+                    len: 0,
+                    occupied: 0,
+                    capacity,
+                    bytes: capacity,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn storages_are_measured() {
+        let mut storage: Vec<Option<Vec<u8>>> = Vec::with_capacity(4);
+        storage.push(Some(vec![0; 3]));
+        storage.push(None);
+        let report = StorageReport::of("bytes", &storage, |v| v.len());
+        assert_eq!((report.len, report.occupied), (2, 1));
+        let slot = mem::size_of::<Option<Vec<u8>>>();
+        assert_eq!(report.bytes, report.capacity * slot + 3);
+    }
+
+    #[test]
+    fn growth_beyond_the_entities_is_a_leak() {
+        let old = report(100, &[("position", 100), ("contacts", 10)]);
+        // twice the entities make room for twice the positions
+        let new = report(200, &[("position", 200), ("contacts", 40)]);
+        assert_eq!(leaks(&old, &new), vec!["contacts"]);
+        // shrinking worlds don't make growth look worse
+        let new = report(50, &[("position", 100), ("contacts", 14)]);
+        assert!(leaks(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn the_watch_waits_for_enough_generations() {
+        let mut watch = MemoryWatch::default();
+        for generation in 0..LEAK_GENERATIONS {
+            let capacity = 10 << generation;
+            assert!(watch
+                .record(report(100, &[("events", capacity)]))
+                .is_empty());
+        }
+        let capacity = 10 << LEAK_GENERATIONS;
+        assert_eq!(
+            watch.record(report(100, &[("events", capacity)])),
+            vec!["events"]
+        );
+        // a generation that didn't grow pushes the oldest report out
+        assert_eq!(
+            watch.record(report(100, &[("events", capacity)])),
+            vec!["events"]
+        );
+        assert_eq!(watch.history.len(), LEAK_GENERATIONS + 1);
+        assert_eq!(watch.history[0].storages[0].capacity, 20);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect, Text};
+use ggez::{Context, GameResult};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chronicle::{Cause, CAUSE_NAMES};
+use crate::creature::Kind;
+use crate::draw;
+use crate::{DPI_FACTOR, HEIGHT, WIDTH};
+
+pub const MORTALITY_PATH: &str = "mortality.csv";
+/// Width of the mortality bar when it's full
+pub const BAR_WIDTH: f32 = 400.0 * DPI_FACTOR;
+pub const BAR_HEIGHT: f32 = 40.0 * DPI_FACTOR;
+
+/// Color of every cause in the mortality bar, in the order of `CAUSE_NAMES`
+const CAUSE_COLORS: [(f32, f32, f32); CAUSE_NAMES.len()] = [
+    (0.9, 0.8, 0.2),
+    (0.9, 0.2, 0.2),
+    (0.5, 0.5, 0.9),
+    (0.6, 0.6, 0.6),
+    (0.8, 0.3, 0.8),
+    (0.2, 0.8, 0.3),
+];
+
+/// How many creatures of every kind ended up each way this generation,
+/// counted whether or not the chronicle is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Mortality {
+    pub vegans: [usize; CAUSE_NAMES.len()],
+    pub carnivores: [usize; CAUSE_NAMES.len()],
+}
+
+impl Mortality {
+    pub fn record(&mut self, kind: Kind, cause: Cause) {
+        match kind {
+            Kind::Vegan => self.vegans[cause.index()] += 1,
+            Kind::Carnivorous => self.carnivores[cause.index()] += 1,
+        }
+    }
+
+    /// Both kinds together, survivors are left out since they didn't die
+    pub fn deaths(&self) -> Vec<(&'static str, usize)> {
+        let survived = Cause::Survived.index();
+        CAUSE_NAMES
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != survived)
+            .map(|(i, &name)| (name, self.vegans[i] + self.carnivores[i]))
+            .collect()
+    }
+
+    /// Appends one row per kind and cause, writes the header first if the
+    /// file is new
+    pub fn append_csv<P: AsRef<Path>>(&self, path: P, generation: usize) -> io::Result<()> {
+        let path = path.as_ref();
+        let new = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if new {
+            writeln!(file, "generation,kind,cause,count")?;
+        }
+        for (kind, counts) in &[("vegan", self.vegans), ("carnivore", self.carnivores)] {
+            for (name, count) in CAUSE_NAMES.iter().zip(counts) {
+                writeln!(file, "{},{},{},{}", generation, kind, name, count)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stacked bar of the deaths so far in the bottom right corner, with the
+    /// count of every cause above it
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        draw::screen_view(ctx)?;
+        let deaths = self.deaths();
+        let total: usize = deaths.iter().map(|(_, count)| count).sum();
+        let x = WIDTH - BAR_WIDTH - 4.0;
+        let y = HEIGHT - BAR_HEIGHT - 4.0;
+
+        let mut builder = MeshBuilder::new();
+        let outline = Rect::new(x, y, BAR_WIDTH, BAR_HEIGHT);
+        builder.rectangle(
+            DrawMode::stroke(DPI_FACTOR),
+            outline,
+            Color::new(1.0, 1.0, 1.0, 1.0),
+        );
+        let mut left = x;
+        for (i, (_, count)) in deaths.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            let width = BAR_WIDTH * *count as f32 / total as f32;
+            let (r, g, b) = CAUSE_COLORS[i];
+            let rect = Rect::new(left, y, width, BAR_HEIGHT);
+            builder.rectangle(DrawMode::fill(), rect, Color::new(r, g, b, 1.0));
+            left += width;
+        }
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())?;
+
+        let mut left = x;
+        for (i, (name, count)) in deaths.iter().enumerate() {
+            let text = Text::new(format!("{} {}", name, count));
+            let (w, h) = text.dimensions(ctx);
+            let (r, g, b) = CAUSE_COLORS[i];
+            graphics::draw(
+                ctx,
+                &text,
+                DrawParam::new()
+                    .dest([left, y - h as f32 - 2.0])
+                    .color(Color::new(r, g, b, 1.0)),
+            )?;
+            left += w as f32 + 8.0 * DPI_FACTOR;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn deaths_add_up_both_kinds_without_survivors() {
+        let mut mortality = Mortality::default();
+        mortality.record(Kind::Vegan, Cause::Eaten { by: 3 });
+        mortality.record(Kind::Carnivorous, Cause::Starved);
+        mortality.record(Kind::Vegan, Cause::Eaten { by: 9 });
+        mortality.record(Kind::Vegan, Cause::Survived);
+
+        assert_eq!(mortality.vegans[Cause::Survived.index()], 1);
+        let deaths = mortality.deaths();
+        assert_eq!(deaths.len(), CAUSE_NAMES.len() - 1);
+        assert!(deaths.iter().all(|&(name, _)| name != "survived"));
+        assert_eq!(deaths[0], ("starved", 1));
+        assert_eq!(deaths[1], ("eaten", 2));
+        assert_eq!(deaths.iter().map(|(_, count)| count).sum::<usize>(), 3);
+    }
+
+    #[test]
+    fn the_csv_header_is_written_once() {
+        let path = env::temp_dir().join(format!("ldjam-46-mortality-{}.csv", process::id()));
+        let _ = fs::remove_file(&path);
+        let mut mortality = Mortality::default();
+        mortality.record(Kind::Carnivorous, Cause::Culled);
+        mortality.append_csv(&path, 0).unwrap();
+        mortality.append_csv(&path, 1).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 2 * 2 * CAUSE_NAMES.len());
+        assert_eq!(lines[0], "generation,kind,cause,count");
+        assert_eq!(
+            lines.iter().filter(|l| l.starts_with("generation")).count(),
+            1
+        );
+        assert!(lines.contains(&"1,carnivore,culled,1"));
+        assert!(lines.contains(&"0,vegan,culled,0"));
+    }
+}
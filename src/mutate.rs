@@ -2,42 +2,187 @@ use ggez::graphics::Color;
 
 use nalgebra::{DMatrix, DVector, Vector2};
 
-use rand::random;
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Mutation parameters in effect while mating
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rates {
+    pub chance: f32,
+    pub magnitude: f32,
+}
+
+/// Where a run is in the mutation schedule
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Annealing {
+    pub generation: usize,
+    /// Mean pairwise looks distance of the last generation, if there was one
+    pub diversity: Option<f32>,
+}
+
+impl Annealing {
+    /// The configured rates decayed by `mutation_decay` every generation down
+    /// to the floors, and heated back up towards the configured rates while
+    /// the diversity is below `reheat_diversity`
+    pub fn rates(&self, config: &Config) -> Rates {
+        let decay = config.mutation_decay.powi(self.generation as i32);
+        let heat = match self.diversity {
+            Some(diversity) if config.reheat_diversity > 0.0 => {
+                (1.0 - diversity / config.reheat_diversity).clamp(0.0, 1.0)
+            }
+            _ => 0.0,
+        };
+        let anneal = |initial: f32, floor: f32| {
+            let cooled = (initial * decay).max(floor.min(initial));
+            cooled + (initial - cooled) * heat
+        };
+        Rates {
+            chance: anneal(config.mutation_chance, config.min_mutation_chance),
+            magnitude: anneal(config.mutation_magnitude, config.min_mutation_magnitude),
+        }
+    }
+}
+
+impl Annealing {
+    /// `mating_threshold` decayed by `mating_threshold_decay` every
+    /// generation
+    pub fn mating_threshold(&self, config: &Config) -> f32 {
+        config.mating_threshold * config.mating_threshold_decay.powi(self.generation as i32)
+    }
+}
 
 pub trait Mutate {
-    fn mutate(&self, other: &Self, factor: f32, chance: f32, mutation: f32) -> Self;
+    fn mutate<R: Rng>(
+        &self,
+        other: &Self,
+        factor: f32,
+        chance: f32,
+        mutation: f32,
+        rng: &mut R,
+    ) -> Self;
 }
 
 impl Mutate for f32 {
-    fn mutate(&self, other: &Self, factor: f32, chance: f32, mutation: f32) -> Self {
+    fn mutate<R: Rng>(
+        &self,
+        other: &Self,
+        factor: f32,
+        chance: f32,
+        mutation: f32,
+        rng: &mut R,
+    ) -> Self {
         let mut result = *self * factor + *other * (1.0 - factor);
-        if random::<f32>() < chance {
-            result *= random::<f32>() * 2.0 * mutation + (1.0 - mutation);
+        if rng.gen::<f32>() < chance {
+            result *= rng.gen::<f32>() * 2.0 * mutation + (1.0 - mutation);
         }
         result
     }
 }
 
 impl Mutate for Color {
-    fn mutate(&self, other: &Self, factor: f32, chance: f32, mutation: f32) -> Self {
-        let r = self.r.mutate(&other.r, factor, chance, mutation);
-        let g = self.g.mutate(&other.g, factor, chance, mutation);
-        let b = self.b.mutate(&other.b, factor, chance, mutation);
-        let a = self.a.mutate(&other.a, factor, chance, mutation);
+    fn mutate<R: Rng>(
+        &self,
+        other: &Self,
+        factor: f32,
+        chance: f32,
+        mutation: f32,
+        rng: &mut R,
+    ) -> Self {
+        let r = self.r.mutate(&other.r, factor, chance, mutation, rng);
+        let g = self.g.mutate(&other.g, factor, chance, mutation, rng);
+        let b = self.b.mutate(&other.b, factor, chance, mutation, rng);
+        let a = self.a.mutate(&other.a, factor, chance, mutation, rng);
         Color::new(r, g, b, a)
     }
 }
 
+/// Lowest saturation and value a lineage color can mutate to, grays would lose
+/// their hue
+pub const MIN_SHADE: f32 = 0.35;
+
+/// Hue, saturation and value of a color, all from 0.0 to 1.0
+pub fn hsv(color: Color) -> (f32, f32, f32) {
+    let max = color.r.max(color.g).max(color.b);
+    let min = color.r.min(color.g).min(color.b);
+    let range = max - min;
+    let hue = if range <= 0.0 {
+        0.0
+    } else if max == color.r {
+        ((color.g - color.b) / range).rem_euclid(6.0)
+    } else if max == color.g {
+        (color.b - color.r) / range + 2.0
+    } else {
+        (color.r - color.g) / range + 4.0
+    };
+    let saturation = if max > 0.0 { range / max } else { 0.0 };
+    (hue / 6.0, saturation, max)
+}
+
+pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Color {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as usize {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    Color::new(r + m, g + m, b + m, 1.0)
+}
+
+/// Like `Mutate for Color`, but the child gets exactly `hue` and only its
+/// saturation and value are mixed and mutated
+pub fn mutate_shade<R: Rng>(
+    a: Color,
+    b: Color,
+    hue: f32,
+    factor: f32,
+    chance: f32,
+    mutation: f32,
+    rng: &mut R,
+) -> Color {
+    let (_, sa, va) = hsv(a);
+    let (_, sb, vb) = hsv(b);
+    let saturation = sa
+        .mutate(&sb, factor, chance, mutation, rng)
+        .clamp(MIN_SHADE, 1.0);
+    let value = va
+        .mutate(&vb, factor, chance, mutation, rng)
+        .clamp(MIN_SHADE, 1.0);
+    from_hsv(hue, saturation, value)
+}
+
 impl Mutate for Vector2<f32> {
-    fn mutate(&self, other: &Self, factor: f32, chance: f32, mutation: f32) -> Self {
-        let x = self.x.mutate(&other.x, factor, chance, mutation);
-        let y = self.y.mutate(&other.y, factor, chance, mutation);
+    fn mutate<R: Rng>(
+        &self,
+        other: &Self,
+        factor: f32,
+        chance: f32,
+        mutation: f32,
+        rng: &mut R,
+    ) -> Self {
+        let x = self.x.mutate(&other.x, factor, chance, mutation, rng);
+        let y = self.y.mutate(&other.y, factor, chance, mutation, rng);
         Vector2::new(x, y)
     }
 }
 
 impl Mutate for DMatrix<f32> {
-    fn mutate(&self, other: &Self, factor: f32, chance: f32, mutation: f32) -> Self {
+    fn mutate<R: Rng>(
+        &self,
+        other: &Self,
+        factor: f32,
+        chance: f32,
+        mutation: f32,
+        rng: &mut R,
+    ) -> Self {
         assert_eq!(self.nrows(), other.nrows());
         assert_eq!(self.ncols(), other.ncols());
 
@@ -45,7 +190,7 @@ impl Mutate for DMatrix<f32> {
 
         for i in 0..self.nrows() {
             for j in 0..self.ncols() {
-                result[(i, j)] = self[(i, j)].mutate(&other[(i, j)], factor, chance, mutation);
+                result[(i, j)] = self[(i, j)].mutate(&other[(i, j)], factor, chance, mutation, rng);
             }
         }
 
@@ -54,15 +199,84 @@ impl Mutate for DMatrix<f32> {
 }
 
 impl Mutate for DVector<f32> {
-    fn mutate(&self, other: &Self, factor: f32, chance: f32, mutation: f32) -> Self {
+    fn mutate<R: Rng>(
+        &self,
+        other: &Self,
+        factor: f32,
+        chance: f32,
+        mutation: f32,
+        rng: &mut R,
+    ) -> Self {
         assert_eq!(self.nrows(), other.nrows());
 
         let mut result = DVector::zeros(self.nrows());
 
         for i in 0..self.nrows() {
-            result[i] = self[i].mutate(&other[i], factor, chance, mutation);
+            result[i] = self[i].mutate(&other[i], factor, chance, mutation, rng);
         }
 
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::resources::SimRng;
+
+    #[test]
+    fn colors_round_trip_through_hsv() {
+        for &(hue, saturation, value) in &[(0.0, 1.0, 1.0), (0.3, 0.5, 0.8), (0.9, 0.35, 0.4)] {
+            let (h, s, v) = hsv(from_hsv(hue, saturation, value));
+            assert!((h - hue).abs() < 1e-5, "{} {}", h, hue);
+            assert!((s - saturation).abs() < 1e-5 && (v - value).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn shades_keep_their_hue_and_never_go_gray() {
+        let mut rng = SimRng::new(1);
+        let a = from_hsv(0.6, 0.4, 0.4);
+        let b = from_hsv(0.1, 1.0, 1.0);
+        for _ in 0..100 {
+            // always mutating, and by a lot
+            let child = mutate_shade(a, b, 0.6, 0.5, 1.0, 5.0, &mut rng);
+            let (hue, saturation, value) = hsv(child);
+            assert!((hue - 0.6).abs() < 1e-4);
+            assert!(saturation >= MIN_SHADE - 1e-5 && value >= MIN_SHADE - 1e-5);
+        }
+    }
+
+    #[test]
+    fn mutation_cools_down_and_reheats() {
+        let config = Config {
+            mutation_chance: 0.2,
+            mutation_magnitude: 0.4,
+            mutation_decay: 0.5,
+            min_mutation_chance: 0.02,
+            min_mutation_magnitude: 0.1,
+            reheat_diversity: 0.4,
+            ..Config::default()
+        };
+        let rates = |generation, diversity| {
+            let annealing = Annealing {
+                generation,
+                diversity,
+            };
+            let Rates { chance, magnitude } = annealing.rates(&config);
+            (chance, magnitude)
+        };
+        let close =
+            |(a, b): (f32, f32), (c, d): (f32, f32)| (a - c).abs() < 1e-6 && (b - d).abs() < 1e-6;
+        assert!(close(rates(0, None), (0.2, 0.4)));
+        assert!(close(rates(1, None), (0.1, 0.2)));
+        // down to the floors and no further
+        assert!(close(rates(20, None), (0.02, 0.1)));
+        // diverse enough, nothing to reheat
+        assert!(close(rates(20, Some(0.5)), (0.02, 0.1)));
+        // half as diverse as wanted heats halfway back up
+        assert!(close(rates(20, Some(0.2)), (0.11, 0.25)));
+        assert!(close(rates(20, Some(0.0)), (0.2, 0.4)));
+    }
+}
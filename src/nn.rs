@@ -1,23 +1,55 @@
+use std::collections::VecDeque;
 use std::iter;
+use std::mem;
 
 use ggez::GameResult;
 
 use nalgebra::{DMatrix, DVector};
 
+use ordered_float::OrderedFloat;
+
 use serde::{Deserialize, Serialize};
 
 use rand::prelude::*;
 use rand_distr::StandardNormal;
 
+use crate::brain;
+use crate::creature::DIR_COUNT;
 use crate::data::{Entity, GameData};
+use crate::profile_scope;
 
 pub fn sigmoid(n: f32) -> f32 {
     (1.0 + n.exp()).recip()
 }
 
+/// Derivative of `sigmoid`, which falls as `n` grows
 pub fn sigmoid_der(n: f32) -> f32 {
     let sig = sigmoid(n);
-    sig * (1.0 - sig)
+    -sig * (1.0 - sig)
+}
+
+/// Nonlinearity applied after every layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activation {
+    Sigmoid,
+    Tanh,
+}
+
+impl Activation {
+    pub fn apply(self, n: f32) -> f32 {
+        match self {
+            Activation::Sigmoid => sigmoid(n),
+            Activation::Tanh => n.tanh(),
+        }
+    }
+
+    pub fn derivative(self, n: f32) -> f32 {
+        match self {
+            Activation::Sigmoid => sigmoid_der(n),
+            Activation::Tanh => 1.0 - n.tanh() * n.tanh(),
+        }
+    }
 }
 
 pub fn cost(result: &DVector<f32>, desired: &DVector<f32>) -> f32 {
@@ -36,7 +68,41 @@ pub fn nabla_w_l(act: &DVector<f32>, delta: &DVector<f32>) -> DMatrix<f32> {
     output
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Below this temperature `sample` is a plain argmax
+pub const TEMPERATURE_EPSILON: f32 = 1e-3;
+
+/// Index of the largest value, 0 if there are no values
+pub fn argmax(values: &DVector<f32>) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, x)| OrderedFloat::from(**x))
+        .map_or(0, |(index, _)| index)
+}
+
+/// Picks an index from the softmax of `values / temperature`, `r` should be
+/// uniformly distributed in 0..1
+pub fn sample(values: &DVector<f32>, temperature: f32, r: f32) -> usize {
+    if temperature < TEMPERATURE_EPSILON || values.is_empty() {
+        return argmax(values);
+    }
+
+    let max = values.max();
+    let weights: Vec<_> = values
+        .iter()
+        .map(|x| ((x - max) / temperature).exp())
+        .collect();
+    let mut left = r * weights.iter().sum::<f32>();
+    for (i, w) in weights.iter().enumerate() {
+        if left < *w {
+            return i;
+        }
+        left -= w;
+    }
+    weights.len() - 1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Inputs {
     pub input: DVector<f32>,
 }
@@ -49,7 +115,7 @@ impl Inputs {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Outputs {
     pub output: DVector<f32>,
 }
@@ -62,7 +128,7 @@ impl Outputs {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Desired {
     pub desired: DVector<f32>,
 }
@@ -75,6 +141,89 @@ impl Desired {
     }
 }
 
+/// Step size of the training `imprint` does
+pub const IMPRINT_RATE: f32 = 0.5;
+
+/// Recent inputs of a creature and the direction it picked for each, newest
+/// last
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Experience {
+    pub samples: VecDeque<(DVector<f32>, usize)>,
+}
+
+impl Experience {
+    /// Remembers a sample, forgetting the oldest ones past `capacity`
+    pub fn record(&mut self, input: DVector<f32>, direction: usize, capacity: usize) {
+        self.samples.push_back((input, direction));
+        while self.samples.len() > capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        let inputs: usize = self
+            .samples
+            .iter()
+            .map(|(input, _)| input.len() * mem::size_of::<f32>())
+            .sum();
+        self.samples.capacity() * mem::size_of::<(DVector<f32>, usize)>() + inputs
+    }
+}
+
+/// Trains `network` for `steps` passes to pick the directions in `experience`,
+/// going around the samples in order
+///
+/// Only the direction outputs get a target, the rest are trained towards what
+/// the network already outputs so they stay as they are.
+pub fn imprint(network: &mut Network, experience: &Experience, steps: usize) {
+    if experience.samples.is_empty() {
+        return;
+    }
+
+    for (input, direction) in experience.samples.iter().cycle().take(steps) {
+        let output = network.feedforward(input);
+        let mut desired = output.clone();
+        for (i, target) in desired.iter_mut().take(DIR_COUNT).enumerate() {
+            *target = if i == *direction { 1.0 } else { 0.0 };
+        }
+        network.update(input, &desired, IMPRINT_RATE);
+    }
+}
+
+/// Summary of the weights of one or more networks
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WeightStats {
+    pub count: usize,
+    /// Weights that are exactly zero
+    pub zeros: usize,
+    pub mean_abs: f32,
+}
+
+impl WeightStats {
+    /// Fraction of the weights that are zero
+    pub fn sparsity(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.zeros as f32 / self.count as f32
+    }
+
+    /// Combines the stats of two sets of weights
+    pub fn merge(self, other: Self) -> Self {
+        let count = self.count + other.count;
+        let mean_abs = if count == 0 {
+            0.0
+        } else {
+            (self.mean_abs * self.count as f32 + other.mean_abs * other.count as f32) / count as f32
+        };
+        Self {
+            count,
+            zeros: self.zeros + other.zeros,
+            mean_abs,
+        }
+    }
+}
+
 /// Rnn-ish thing, not scientifically gud
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Network {
@@ -82,17 +231,25 @@ pub struct Network {
     cache_prev: DVector<f32>,
     weights: Vec<DMatrix<f32>>,
     biases: Vec<DVector<f32>>,
+    activation: Activation,
+    /// Recurrent networks get their last output as extra inputs
+    recurrent: bool,
 }
 
 impl Network {
-    pub fn new(layers: &[usize]) -> Network {
+    pub fn new<R: Rng>(
+        layers: &[usize],
+        activation: Activation,
+        recurrent: bool,
+        rng: &mut R,
+    ) -> Network {
         let last = *layers.last().unwrap();
+        let state = if recurrent { last } else { 0 };
         let mut weights = Vec::with_capacity(layers.len() - 1);
         let mut biases = Vec::with_capacity(layers.len() - 1);
-        let iter = iter::once(layers[0] + last)
+        let iter = iter::once(layers[0] + state)
             .chain(layers[1..layers.len() - 1].iter().copied())
             .zip(layers[1..].iter().copied());
-        let mut rng = thread_rng();
         for (input, output) in iter {
             let mut vec = Vec::with_capacity(output * input);
             for _ in 0..output * input {
@@ -105,33 +262,174 @@ impl Network {
             }
             biases.push(DVector::from_vec(vec));
         }
-        let cache_next = DVector::zeros(last);
-        let cache_prev = DVector::zeros(last);
+        let cache_next = DVector::zeros(state);
+        let cache_prev = DVector::zeros(state);
         Network {
             cache_next,
             cache_prev,
             weights,
             biases,
+            activation,
+            recurrent,
+        }
+    }
+
+    /// Shifts the biases of the first `outputs` outputs so their mean
+    /// pre-activation over `samples` random inputs in 0..1 is zero, which
+    /// leaves none of them stronger than the others on average
+    ///
+    /// Only the last layer changes and it's linear before the activation, so
+    /// this is exact for the samples drawn and doesn't need any training.
+    pub fn calibrate<R: Rng>(&mut self, outputs: usize, samples: usize, rng: &mut R) {
+        if samples == 0 {
+            return;
+        }
+
+        let activation = self.activation;
+        let last = self.weights.len() - 1;
+        let width = self.weights[0].ncols();
+        let mut mean = DVector::zeros(self.output_len());
+        for _ in 0..samples {
+            // the recurrent inputs are random too, they're whatever the last
+            // output was
+            let mut layer = DVector::from_fn(width, |_, _| rng.gen::<f32>());
+            for (w, b) in self.weights[..last].iter().zip(&self.biases) {
+                layer = (w * layer + b).map(|n| activation.apply(n));
+            }
+            mean += &self.weights[last] * layer + &self.biases[last];
+        }
+        mean /= samples as f32;
+        for (b, m) in self.biases[last].iter_mut().zip(mean.iter()).take(outputs) {
+            *b -= m;
         }
     }
 
+    /// Sizes of the layers, not counting the recurrent inputs
+    pub fn layers(&self) -> Vec<usize> {
+        let state = self.cache_next.len();
+        iter::once(self.weights[0].ncols() - state)
+            .chain(self.weights.iter().map(|w| w.nrows()))
+            .collect()
+    }
+
+    /// Bytes taken by the weights, biases and recurrent state
+    pub fn parameter_bytes(&self) -> usize {
+        let floats = self.cache_next.len()
+            + self.cache_prev.len()
+            + self.weights.iter().map(|w| w.len()).sum::<usize>()
+            + self.biases.iter().map(|b| b.len()).sum::<usize>();
+        floats * mem::size_of::<f32>()
+    }
+
+    /// Zeroes every weight whose magnitude is below `threshold`, returns how
+    /// many were zeroed, biases are left alone
+    pub fn prune(&mut self, threshold: f32) -> usize {
+        let mut pruned = 0;
+        for w in &mut self.weights {
+            for x in w.iter_mut() {
+                if *x != 0.0 && x.abs() < threshold {
+                    *x = 0.0;
+                    pruned += 1;
+                }
+            }
+        }
+        pruned
+    }
+
+    pub fn weight_stats(&self) -> WeightStats {
+        let weights = || self.weights.iter().flat_map(|w| w.iter());
+        let count = weights().count();
+        let zeros = weights().filter(|x| **x == 0.0).count();
+        let sum: f32 = weights().map(|x| x.abs()).sum();
+        WeightStats {
+            count,
+            zeros,
+            mean_abs: if count == 0 { 0.0 } else { sum / count as f32 },
+        }
+    }
+
+    /// Mean absolute difference of every `stride`th weight of two networks
+    /// of the same shape, a cheap stand-in for how differently they think
+    pub fn weight_distance(&self, other: &Network, stride: usize) -> f32 {
+        let pairs = self
+            .weights
+            .iter()
+            .zip(&other.weights)
+            .flat_map(|(a, b)| a.iter().zip(b.iter()))
+            .step_by(stride.max(1));
+        let (sum, count) = pairs.fold((0.0, 0), |(sum, count), (a, b)| {
+            (sum + (a - b).abs(), count + 1)
+        });
+        if count == 0 {
+            return 0.0;
+        }
+        sum / count as f32
+    }
+
+    /// Number of inputs, not counting the recurrent ones
+    pub fn input_len(&self) -> usize {
+        self.weights[0].ncols() - self.cache_next.len()
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.weights[self.weights.len() - 1].nrows()
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+
+    pub fn recurrent(&self) -> bool {
+        self.recurrent
+    }
+
+    /// Largest absolute difference between the parameters of two networks,
+    /// infinite if their layers don't match
+    pub fn max_delta(&self, other: &Network) -> f32 {
+        let shapes = |n: &Network| {
+            n.weights
+                .iter()
+                .map(|w| w.shape())
+                .chain(n.biases.iter().map(|b| b.shape()))
+                .collect::<Vec<_>>()
+        };
+        if shapes(self) != shapes(other) {
+            return f32::INFINITY;
+        }
+
+        let weights = self
+            .weights
+            .iter()
+            .zip(&other.weights)
+            .map(|(a, b)| (a - b).amax());
+        let biases = self
+            .biases
+            .iter()
+            .zip(&other.biases)
+            .map(|(a, b)| (a - b).amax());
+        weights.chain(biases).fold(0.0, f32::max)
+    }
+
     pub fn feedforward(&mut self, layer: &DVector<f32>) -> DVector<f32> {
         let layer = self.cache_next.iter().chain(layer).copied().collect();
         let mut layer = DVector::from_vec(layer);
+        let activation = self.activation;
         for (w, b) in self.weights.iter().zip(&self.biases) {
             let result = w * layer + b;
-            layer = result.map(sigmoid);
+            layer = result.map(|n| activation.apply(n));
+        }
+        // `update` has to see the same recurrent inputs as this pass did
+        if self.recurrent {
+            self.cache_prev = std::mem::replace(&mut self.cache_next, layer.clone());
         }
-        self.cache_next = layer.clone();
         layer
     }
 
     pub fn update(&mut self, input: &DVector<f32>, desired: &DVector<f32>, eta: f32) {
+        profile_scope!("backprop");
         let layer = self.cache_prev.iter().chain(input).copied().collect();
         let layer = DVector::from_vec(layer);
 
-        self.cache_prev = input.clone();
-
         let mut nabla_b = Vec::new();
         let mut nabla_w = Vec::new();
         self.backprop(&mut nabla_b, &mut nabla_w, &layer, desired);
@@ -160,23 +458,24 @@ impl Network {
         let mut activation = 0;
 
         let mut zs = Vec::with_capacity(self.weights.len());
+        let function = self.activation;
 
         for (w, b) in self.weights.iter().zip(&self.biases) {
             let z = w * &activations[activation] + b;
-            activations.push(z.map(sigmoid));
+            activations.push(z.map(|n| function.apply(n)));
             activation += 1;
             zs.push(z);
         }
 
         let tmp1 = &activations[activation] - desired;
-        let tmp2 = zs.last().unwrap().map(sigmoid_der);
+        let tmp2 = zs.last().unwrap().map(|n| function.derivative(n));
         let delta = tmp1.component_mul(&tmp2);
         nabla_w.push(nabla_w_l(&activations[activations.len() - 2], &delta));
         nabla_b.push(delta);
         let len = self.weights.len();
         for l in 2..len + 1 {
             let z = &zs[len - l];
-            let der = z.map(sigmoid_der);
+            let der = z.map(|n| function.derivative(n));
             let tmp = self.weights[len - l + 1].transpose();
             let a = tmp * &nabla_b[l - 2];
             let delta = a.component_mul(&der);
@@ -188,21 +487,142 @@ impl Network {
     }
 }
 
-pub fn nn_system<I>(data: &mut GameData, entities: I) -> GameResult<()>
+/// Runs the networks of every creature, and trains them on their desired
+/// outputs if `train` is set
+pub fn nn_system<I>(data: &mut GameData, entities: I, train: bool) -> GameResult<()>
 where
     I: IntoIterator<Item = Entity>,
 {
+    profile_scope!("networks");
     for e in entities {
+        // eaten earlier in the frame
+        if data.get::<Network>(e).is_none() {
+            continue;
+        }
+        if let Err(problem) = brain::check_shape(data, e) {
+            panic!("{}", problem);
+        }
+
         let input = data[e.component::<Inputs>()].input.clone();
         let desired = data[e.component::<Desired>()].desired.clone();
         let network = &mut data[e.component::<Network>()];
 
         let output = network.feedforward(&input);
 
-        let cost = cost(&output, &desired);
-        network.update(&input, &desired, cost);
+        if train {
+            let cost = cost(&output, &desired);
+            network.update(&input, &desired, cost);
+        }
 
         data[e.component::<Outputs>()].output = output;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn argmax_picks_the_largest() {
+        assert_eq!(argmax(&DVector::from_vec(vec![0.1, 0.7, -2.0, 0.3])), 1);
+        assert_eq!(argmax(&DVector::from_vec(Vec::new())), 0);
+    }
+
+    #[test]
+    fn cold_sampling_is_argmax() {
+        let values = DVector::from_vec(vec![0.2, 0.1, 0.9, 0.4]);
+        for i in 0..10 {
+            let r = i as f32 / 10.0;
+            assert_eq!(sample(&values, 0.0, r), argmax(&values));
+        }
+        assert_eq!(sample(&DVector::from_vec(Vec::new()), 1.0, 0.5), 0);
+    }
+
+    #[test]
+    fn sampling_follows_the_softmax() {
+        // the softmax at temperature 1 is 1/4 and 3/4
+        let values = DVector::from_vec(vec![0.0, 3.0f32.ln()]);
+        let steps = 1000;
+        let firsts = (0..steps)
+            .filter(|i| sample(&values, 1.0, *i as f32 / steps as f32) == 0)
+            .count();
+        assert!((firsts as f32 / steps as f32 - 0.25).abs() < 0.01);
+        assert_eq!(sample(&values, 1.0, 0.999_999), 1);
+    }
+
+    #[test]
+    fn new_networks_have_the_asked_layers() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let network = Network::new(&[4, 6, 3], Activation::Sigmoid, false, &mut rng);
+        assert_eq!(network.layers(), vec![4, 6, 3]);
+        assert_eq!(network.input_len(), 4);
+        assert_eq!(network.output_len(), 3);
+    }
+
+    #[test]
+    fn updates_lower_the_cost() {
+        for &activation in &[Activation::Sigmoid, Activation::Tanh] {
+            let mut rng = StdRng::seed_from_u64(3);
+            let mut network = Network::new(&[4, 6, 3], activation, false, &mut rng);
+            let input = DVector::from_vec(vec![0.1, 0.9, 0.4, 0.6]);
+            let desired = DVector::from_vec(vec![0.0, 0.5, 0.0]);
+            let before = cost(&network.feedforward(&input), &desired);
+            for _ in 0..20 {
+                network.feedforward(&input);
+                network.update(&input, &desired, 0.5);
+            }
+            let after = cost(&network.feedforward(&input), &desired);
+            assert!(
+                after < before,
+                "{:?}: {} isn't below {}",
+                activation,
+                after,
+                before
+            );
+        }
+    }
+
+    #[test]
+    fn pruning_zeroes_small_weights() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut network = Network::new(&[4, 6, 3], Activation::Sigmoid, false, &mut rng);
+        let before = network.weight_stats();
+        assert_eq!(before.count, 4 * 6 + 6 * 3);
+        let small = network
+            .weights
+            .iter()
+            .flat_map(|w| w.iter())
+            .filter(|x| x.abs() < 0.5)
+            .count();
+        assert_eq!(network.prune(0.5), small);
+        let after = network.weight_stats();
+        assert_eq!(after.zeros, before.zeros + small);
+        assert!((after.sparsity() - after.zeros as f32 / after.count as f32).abs() < 1e-6);
+        // nothing left to prune at the same threshold
+        assert_eq!(network.prune(0.5), 0);
+    }
+
+    #[test]
+    fn merged_stats_weigh_by_count() {
+        let a = WeightStats {
+            count: 1,
+            zeros: 1,
+            mean_abs: 0.0,
+        };
+        let b = WeightStats {
+            count: 3,
+            zeros: 0,
+            mean_abs: 2.0,
+        };
+        let merged = a.merge(b);
+        assert_eq!((merged.count, merged.zeros, merged.mean_abs), (4, 1, 1.5));
+        assert_eq!(merged.sparsity(), 0.25);
+        assert_eq!(
+            WeightStats::default()
+                .merge(WeightStats::default())
+                .sparsity(),
+            0.0
+        );
+    }
+}
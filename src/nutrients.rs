@@ -0,0 +1,147 @@
+use std::f32::consts::TAU;
+
+use nalgebra::Vector2;
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::creature::{Creature, Food, Position};
+use crate::data::{Entity, GameData};
+use crate::resources::SimRng;
+use crate::spawn;
+use crate::FOOD_MIN_RADIUS;
+
+/// Radius of the pellets the dead leave behind
+pub const PELLET_RADIUS: f32 = FOOD_MIN_RADIUS * 0.5;
+
+/// Nutrition that came into the world this generation, as food spawned by
+/// the world or as pellets given back by the dead
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct NutrientTally {
+    pub injected: f32,
+    pub recycled: f32,
+}
+
+/// How many pellets a creature that took in `consumed` nutrition leaves
+/// behind and the nutrition of each, together they carry exactly
+/// `recycled_fraction` of it
+pub fn pellets(config: &Config, consumed: f32) -> (usize, f32) {
+    let total = consumed * config.recycled_fraction;
+    if total <= 0.0 || config.pellet_nutrition <= 0.0 || config.max_pellets == 0 {
+        return (0, 0.0);
+    }
+    let count = ((total / config.pellet_nutrition).ceil() as usize).clamp(1, config.max_pellets);
+    (count, total / count as f32)
+}
+
+/// A random spot at most `radius` away from `center`, moved inside the world
+/// if it's too close to the edge for a body of `body_radius`
+pub fn scatter<R: Rng>(
+    center: Vector2<f32>,
+    radius: f32,
+    body_radius: f32,
+    rng: &mut R,
+) -> Vector2<f32> {
+    let angle = rng.gen::<f32>() * TAU;
+    // uniform over the disc rather than bunched up in the middle
+    let distance = radius * rng.gen::<f32>().sqrt();
+    let offset = Vector2::new(angle.cos(), angle.sin()) * distance;
+    spawn::clamp_to_world(center + offset, body_radius)
+}
+
+/// Gives a share of what `e` took in over its life back to the world as food
+/// pellets around where it died, does nothing unless `nutrient_cycling` is on
+///
+/// Has to be called before `e` is deleted. The pellets are spawned lazily,
+/// the next commit hands them out as food.
+pub fn recycle(data: &mut GameData, e: Entity) {
+    if !data.config.nutrient_cycling {
+        return;
+    }
+    let (consumed, center) = match (data.get::<Creature>(e), data.get::<Position>(e)) {
+        (Some(creature), Some(position)) => (creature.consumed, position.position),
+        _ => return,
+    };
+
+    let (count, nutrition) = pellets(&data.config, consumed);
+    let mut rng = data.take_resource::<SimRng>();
+    for _ in 0..count {
+        let position = scatter(center, data.config.pellet_scatter, PELLET_RADIUS, &mut rng);
+        let food = Food {
+            nutrition,
+            rich: false,
+        };
+        spawn::food_at(&mut data.lazy, position, PELLET_RADIUS, food, &mut rng);
+    }
+    data.insert_resource(rng);
+    data.nutrients.recycled += nutrition * count as f32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{WORLD_HEIGHT, WORLD_WIDTH};
+
+    fn config(recycled_fraction: f32, pellet_nutrition: f32, max_pellets: usize) -> Config {
+        Config {
+            recycled_fraction,
+            pellet_nutrition,
+            max_pellets,
+            ..Config::default()
+        }
+    }
+
+    /// Nutrition all the pellets of a creature that took in `consumed` carry
+    fn returned(config: &Config, consumed: f32) -> f32 {
+        let (count, nutrition) = pellets(config, consumed);
+        count as f32 * nutrition
+    }
+
+    #[test]
+    fn pellets_carry_the_recycled_fraction() {
+        let config = config(0.5, 0.1, 8);
+        assert_eq!(pellets(&config, 0.6).0, 3);
+        assert!((returned(&config, 0.6) - 0.3).abs() < 1e-6);
+        // a sliver still gets a pellet of its own
+        assert_eq!(pellets(&config, 0.01).0, 1);
+        assert!((returned(&config, 0.01) - 0.005).abs() < 1e-6);
+    }
+
+    #[test]
+    fn the_pellet_cap_makes_pellets_richer() {
+        let config = config(0.5, 0.1, 4);
+        let (count, nutrition) = pellets(&config, 10.0);
+        assert_eq!(count, 4);
+        assert!((nutrition - 1.25).abs() < 1e-6);
+        assert!((returned(&config, 10.0) - 5.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn nothing_to_give_back_leaves_no_pellets() {
+        assert_eq!(pellets(&config(0.5, 0.1, 8), 0.0), (0, 0.0));
+        assert_eq!(pellets(&config(0.0, 0.1, 8), 3.0), (0, 0.0));
+        assert_eq!(pellets(&config(0.5, 0.0, 8), 3.0), (0, 0.0));
+        assert_eq!(pellets(&config(0.5, 0.1, 0), 3.0), (0, 0.0));
+    }
+
+    #[test]
+    fn pellets_scatter_close_by_and_inside_the_world() {
+        let mut rng = SimRng::new(8);
+        let center = Vector2::new(WORLD_WIDTH * 0.5, WORLD_HEIGHT * 0.5);
+        for _ in 0..200 {
+            let position = scatter(center, 30.0, PELLET_RADIUS, &mut rng);
+            assert!((position - center).magnitude() <= 30.0 + 1e-3);
+        }
+
+        let corner = Vector2::new(1.0, WORLD_HEIGHT - 1.0);
+        for _ in 0..200 {
+            let position = scatter(corner, 30.0, PELLET_RADIUS, &mut rng);
+            assert!(position.x >= PELLET_RADIUS && position.x <= WORLD_WIDTH - PELLET_RADIUS);
+            assert!(position.y >= PELLET_RADIUS && position.y <= WORLD_HEIGHT - PELLET_RADIUS);
+            assert!((position - corner).magnitude() <= 30.0 + PELLET_RADIUS * 2.0);
+        }
+    }
+}
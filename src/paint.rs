@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::Path;
+
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+
+use nalgebra::Vector2;
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::draw;
+use crate::error::{Error, Result};
+use crate::{WORLD_HEIGHT, WORLD_WIDTH};
+
+pub const FOOD_GRID_PATH: &str = "food_grid.bin";
+/// Side of a cell of the food grid
+pub const PAINT_CELL: f32 = 40.0;
+/// Weight added per second while painting over a cell
+pub const PAINT_RATE: f32 = 4.0;
+/// Highest weight a cell can be painted to, a plain cell has 1.0
+pub const MAX_PAINT: f32 = 8.0;
+
+/// How likely food is to spawn in every cell of the world, painted by hand
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FoodGrid {
+    pub columns: usize,
+    pub rows: usize,
+    pub weights: Vec<f32>,
+    /// Whether there's painting that hasn't been saved
+    #[serde(skip)]
+    pub dirty: bool,
+}
+
+impl Default for FoodGrid {
+    fn default() -> Self {
+        let columns = (WORLD_WIDTH / PAINT_CELL).ceil() as usize;
+        let rows = (WORLD_HEIGHT / PAINT_CELL).ceil() as usize;
+        Self {
+            columns,
+            rows,
+            weights: vec![1.0; columns * rows],
+            dirty: false,
+        }
+    }
+}
+
+impl FoodGrid {
+    /// Falls back to a plain grid if there is none or it doesn't fit the world
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::default();
+        }
+
+        let grid = fs::read(path)
+            .map_err(Error::from)
+            .and_then(|encoded| Ok(bincode::deserialize::<FoodGrid>(&encoded)?));
+        match grid {
+            Ok(grid) if grid.weights.len() == Self::default().weights.len() => grid,
+            Ok(_) => {
+                eprintln!("{} is for another world size, ignoring it", path.display());
+                Self::default()
+            }
+            Err(err) => {
+                eprintln!("couldn't load {}: {}", path.display(), err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let encoded = bincode::serialize(self)?;
+        fs::write(path, &encoded)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Whether every cell has the same weight, food spawns as usual then
+    pub fn is_uniform(&self) -> bool {
+        self.weights.windows(2).all(|w| w[0] == w[1])
+    }
+
+    fn cell(&self, position: Vector2<f32>) -> Option<usize> {
+        if position.x < 0.0 || position.y < 0.0 {
+            return None;
+        }
+        let column = (position.x / PAINT_CELL) as usize;
+        let row = (position.y / PAINT_CELL) as usize;
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        Some(row * self.columns + column)
+    }
+
+    /// Adds `amount` to the cell under `position`, keeping it within 0.0 and
+    /// `MAX_PAINT`
+    pub fn paint(&mut self, position: Vector2<f32>, amount: f32) {
+        if let Some(i) = self.cell(position) {
+            self.weights[i] = (self.weights[i] + amount).clamp(0.0, MAX_PAINT);
+            self.dirty = true;
+        }
+    }
+
+    /// A random spot picked by the weights, `None` if the grid is uniform or
+    /// painted all the way down to nothing
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<Vector2<f32>> {
+        let total: f32 = self.weights.iter().sum();
+        if self.is_uniform() || total <= 0.0 {
+            return None;
+        }
+
+        let mut target = rng.gen::<f32>() * total;
+        // rounding can leave a bit of `target` after the last cell
+        let mut picked = self.weights.len() - 1;
+        for (i, weight) in self.weights.iter().enumerate() {
+            if target < *weight {
+                picked = i;
+                break;
+            }
+            target -= weight;
+        }
+        let column = (picked % self.columns) as f32;
+        let row = (picked / self.columns) as f32;
+        Some(Vector2::new(
+            (column + rng.gen::<f32>()) * PAINT_CELL,
+            (row + rng.gen::<f32>()) * PAINT_CELL,
+        ))
+    }
+
+    /// Tints every cell from transparent for nothing to green for `MAX_PAINT`
+    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
+        draw::world_view(ctx)?;
+        let mut builder = MeshBuilder::new();
+        for (i, weight) in self.weights.iter().enumerate() {
+            let column = (i % self.columns) as f32;
+            let row = (i / self.columns) as f32;
+            let rect = Rect::new(
+                column * PAINT_CELL,
+                row * PAINT_CELL,
+                PAINT_CELL,
+                PAINT_CELL,
+            );
+            let alpha = weight / MAX_PAINT * 0.5;
+            builder.rectangle(DrawMode::fill(), rect, Color::new(0.0, 1.0, 0.0, alpha));
+        }
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::resources::SimRng;
+
+    #[test]
+    fn painting_stays_within_bounds() {
+        let mut grid = FoodGrid::default();
+        let spot = Vector2::new(PAINT_CELL * 1.5, PAINT_CELL * 2.5);
+        grid.paint(spot, 100.0);
+        assert!(grid.dirty);
+        assert_eq!(grid.weights[2 * grid.columns + 1], MAX_PAINT);
+        grid.paint(spot, -100.0);
+        assert_eq!(grid.weights[2 * grid.columns + 1], 0.0);
+
+        // painting outside the world does nothing
+        let mut grid = FoodGrid::default();
+        grid.paint(Vector2::new(-1.0, 10.0), 1.0);
+        grid.paint(Vector2::new(WORLD_WIDTH * 2.0, 10.0), 1.0);
+        assert!(!grid.dirty);
+        assert!(grid.is_uniform());
+    }
+
+    #[test]
+    fn samples_land_in_painted_cells() {
+        let mut rng = SimRng::new(1);
+        let mut grid = FoodGrid::default();
+        assert_eq!(grid.sample(&mut rng), None);
+
+        grid.weights.iter_mut().for_each(|w| *w = 0.0);
+        assert_eq!(grid.sample(&mut rng), None);
+
+        let spot = Vector2::new(PAINT_CELL * 3.5, PAINT_CELL * 1.5);
+        grid.paint(spot, 1.0);
+        for _ in 0..20 {
+            let position = grid.sample(&mut rng).unwrap();
+            assert_eq!(grid.cell(position), grid.cell(spot));
+        }
+    }
+}
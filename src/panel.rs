@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use ggez::graphics::{self, Color, DrawMode, DrawParam, Mesh, Rect, Text};
+use ggez::{Context, GameResult};
+
+use crate::config::Config;
+use crate::draw;
+
+/// A single row of the parameter panel
+pub struct Param {
+    pub name: &'static str,
+    pub get: fn(&Config) -> f32,
+    pub set: fn(&mut Config, f32),
+    pub step: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+pub const PARAMS: &[Param] = &[
+    Param {
+        name: "mutation chance",
+        get: |c| c.mutation_chance,
+        set: |c, v| c.mutation_chance = v,
+        step: 0.01,
+        min: 0.0,
+        max: 1.0,
+    },
+    Param {
+        name: "mutation magnitude",
+        get: |c| c.mutation_magnitude,
+        set: |c, v| c.mutation_magnitude = v,
+        step: 0.01,
+        min: 0.0,
+        max: 1.0,
+    },
+    Param {
+        name: "mutation decay",
+        get: |c| c.mutation_decay,
+        set: |c, v| c.mutation_decay = v,
+        step: 0.01,
+        min: 0.5,
+        max: 1.0,
+    },
+    Param {
+        name: "food timeout",
+        get: |c| c.food_timeout,
+        set: |c, v| c.food_timeout = v,
+        step: 0.05,
+        min: 0.05,
+        max: 10.0,
+    },
+    Param {
+        name: "metabolism",
+        get: |c| c.metabolism,
+        set: |c, v| c.metabolism = v,
+        step: 0.1,
+        min: 0.0,
+        max: 10.0,
+    },
+    Param {
+        name: "sim speed",
+        get: |c| c.sim_speed,
+        set: |c, v| c.sim_speed = v,
+        step: 0.25,
+        min: 0.25,
+        max: 8.0,
+    },
+];
+
+/// In-game panel for tuning the config while the game is running
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Panel {
+    pub open: bool,
+    pub focus: usize,
+    /// Set when the config was changed since it was last saved
+    pub dirty: bool,
+}
+
+impl Panel {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn next(&mut self) {
+        self.focus = (self.focus + 1) % PARAMS.len();
+    }
+
+    pub fn prev(&mut self) {
+        self.focus = (self.focus + PARAMS.len() - 1) % PARAMS.len();
+    }
+
+    /// Steps the focused parameter `steps` times, negative steps go down
+    pub fn step(&mut self, config: &mut Config, steps: f32) {
+        let param = &PARAMS[self.focus];
+        let value = (param.get)(config) + param.step * steps;
+        (param.set)(config, value.max(param.min).min(param.max));
+        self.dirty = true;
+    }
+
+    /// Writes the config to `path` if anything changed, a failed write isn't
+    /// retried until the next change
+    pub fn persist<P: AsRef<Path>>(&mut self, config: &Config, path: P) {
+        if self.dirty {
+            let path = path.as_ref();
+            if let Err(err) = config.save(path) {
+                eprintln!("couldn't write {}: {}", path.display(), err);
+            }
+            self.dirty = false;
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context, config: &Config) -> GameResult<()> {
+        draw::screen_view(ctx)?;
+        const MARGIN: f32 = 4.0;
+
+        let mut y = MARGIN;
+        for (i, param) in PARAMS.iter().enumerate() {
+            let text = Text::new(format!("{}: {:.2}", param.name, (param.get)(config)));
+            let (w, h) = text.dimensions(ctx);
+            if i == self.focus {
+                let rect = Rect::new(0.0, y, w as f32 + 2.0 * MARGIN, h as f32);
+                let highlight = Mesh::new_rectangle(
+                    ctx,
+                    DrawMode::fill(),
+                    rect,
+                    Color::new(1.0, 1.0, 1.0, 0.25),
+                )?;
+                graphics::draw(ctx, &highlight, DrawParam::new())?;
+            }
+            graphics::draw(ctx, &text, DrawParam::new().dest([MARGIN, y]))?;
+            y += h as f32;
+        }
+        if self.dirty {
+            let text = Text::new("unsaved, ctrl+shift+w to save");
+            graphics::draw(ctx, &text, DrawParam::new().dest([MARGIN, y]))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn focus_wraps_around() {
+        let mut panel = Panel::default();
+        panel.prev();
+        assert_eq!(panel.focus, PARAMS.len() - 1);
+        panel.next();
+        assert_eq!(panel.focus, 0);
+    }
+
+    #[test]
+    fn stepping_stays_in_range() {
+        let mut config = Config::default();
+        let mut panel = Panel::default();
+        for (focus, param) in PARAMS.iter().enumerate() {
+            panel.focus = focus;
+            panel.step(&mut config, 1000.0);
+            assert_eq!((param.get)(&config), param.max, "{}", param.name);
+            panel.step(&mut config, -1000.0);
+            assert_eq!((param.get)(&config), param.min, "{}", param.name);
+        }
+        assert!(panel.dirty);
+    }
+
+    #[test]
+    fn persisting_writes_only_changes() {
+        let path = env::temp_dir().join(format!("ldjam-46-panel-{}.toml", process::id()));
+        let mut config = Config::default();
+        let mut panel = Panel::default();
+        panel.persist(&config, &path);
+        assert!(!path.exists());
+
+        panel.step(&mut config, 1.0);
+        panel.persist(&config, &path);
+        assert!(!panel.dirty);
+        let saved = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved.unwrap(), config);
+    }
+}
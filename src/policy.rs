@@ -0,0 +1,134 @@
+use std::io;
+use std::path::Path;
+
+use image::{Rgb, RgbImage};
+
+use nalgebra::{Rotation2, Vector2};
+
+use crate::collision::Body;
+use crate::creature::*;
+use crate::data::{Entity, GameData, Insert};
+use crate::nn::{self, Network};
+use crate::resources::SimRng;
+use crate::senses::{build_inputs, heading_index};
+use crate::spawn;
+use crate::species::species_color;
+use crate::{FOOD_MAX_RADIUS, FOOD_MIN_RADIUS};
+
+/// Width and height of a policy image in pixels
+pub const POLICY_SIZE: u32 = 64;
+/// The image covers food offsets from minus this to this on both axes
+pub const POLICY_RANGE: f32 = 200.0;
+
+/// Which way a creature would go for every position of a single piece of
+/// food around it
+///
+/// The image is in the creature's frame, it faces right. The hue of a pixel
+/// is the chosen direction relative to the heading, pixels covered by the
+/// creature itself are black.
+pub fn heatmap(data: &GameData, e: Entity) -> RgbImage {
+    let creature = data[e.component::<Creature>()];
+    let genome = data[e.component::<Genome>()];
+    let direction = data[e.component::<Direction>()].direction;
+    let network = data[e.component::<Network>()].clone();
+
+    let mut scene = GameData::new();
+    scene.config = data.config.clone();
+    let mut rng = scene.take_resource::<SimRng>();
+    let this = spawn::creature_with(
+        &mut scene,
+        Vector2::zeros(),
+        creature.kind,
+        &genome,
+        network.clone(),
+    );
+    scene.insert(this, creature);
+    scene.insert(this, Direction::new(direction));
+    let radius = (FOOD_MIN_RADIUS + FOOD_MAX_RADIUS) * 0.5;
+    let food = spawn::food_at(
+        &mut scene,
+        Vector2::zeros(),
+        radius,
+        Food::plain(&data.config),
+        &mut rng,
+    );
+
+    let rotation = Rotation2::new(direction);
+    let heading = heading_index(direction);
+    let body = scene[this.component::<Body>()].radius;
+    RgbImage::from_fn(POLICY_SIZE, POLICY_SIZE, |x, y| {
+        let f = |p: u32| (p as f32 + 0.5) / POLICY_SIZE as f32 * 2.0 - 1.0;
+        let offset = Vector2::new(f(x), f(y)) * POLICY_RANGE;
+        if offset.magnitude() < body {
+            return Rgb([0, 0, 0]);
+        }
+
+        scene[food.component::<Position>()].position = rotation * offset;
+        let inputs = build_inputs(&scene, this, vec![this, food], &mut rng);
+        let output = network.clone().feedforward(&inputs);
+        let directions = output.rows(0, DIR_COUNT).into_owned();
+        let index = (nn::argmax(&directions) + DIR_COUNT - heading) % DIR_COUNT;
+        let color = species_color(index, DIR_COUNT);
+        let channel = |c: f32| (c * 255.0) as u8;
+        Rgb([channel(color.r), channel(color.g), channel(color.b)])
+    })
+}
+
+/// Writes the policy image of a creature to a PNG
+pub fn export<P: AsRef<Path>>(data: &GameData, e: Entity, path: P) -> io::Result<()> {
+    heatmap(data, e).save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::brain;
+    use crate::nn::Inputs;
+    use crate::senses::input_system;
+    use crate::{WORLD_HEIGHT, WORLD_WIDTH};
+
+    /// A vegan in the middle of the world and one piece of food `offset`
+    /// away in its frame
+    fn live(offset: Vector2<f32>) -> (GameData, Entity, Entity) {
+        let mut data = GameData::new();
+        data.config.input_noise = 0.0;
+        data.config.ray_dropout = 0.0;
+        let mut rng = data.take_resource::<SimRng>();
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Vegan, &mut SimRng::new(5));
+        let center = Vector2::new(WORLD_WIDTH, WORLD_HEIGHT) * 0.5;
+        let e = spawn::creature_with(&mut data, center, Kind::Vegan, &genome, brain);
+        let direction = 0.7;
+        data.insert(e, Direction::new(direction));
+        let radius = (FOOD_MIN_RADIUS + FOOD_MAX_RADIUS) * 0.5;
+        let position = center + Rotation2::new(direction) * offset;
+        let food = Food::plain(&data.config);
+        let food = spawn::food_at(&mut data, position, radius, food, &mut rng);
+        data.insert_resource(rng);
+        (data, e, food)
+    }
+
+    #[test]
+    fn synthesized_inputs_match_the_live_ones() {
+        let offset = |p: u32| ((p as f32 + 0.5) / POLICY_SIZE as f32 * 2.0 - 1.0) * POLICY_RANGE;
+        let (data, e, _) = live(Vector2::zeros());
+        let image = heatmap(&data, e);
+        for &(x, y) in &[(48, 32), (40, 10), (10, 50), (60, 60), (33, 20)] {
+            let (mut data, e, food) = live(Vector2::new(offset(x), offset(y)));
+            let mut rng = SimRng::new(1);
+            let built = build_inputs(&data, e, vec![e, food], &mut rng);
+            input_system(&mut data, vec![e], vec![e, food], None).unwrap();
+            let input = &data[e.component::<Inputs>()].input;
+            assert_eq!(&built, input);
+
+            let output = data[e.component::<Network>()].clone().feedforward(input);
+            let chosen = nn::argmax(&output.rows(0, DIR_COUNT).into_owned());
+            let heading = heading_index(data[e.component::<Direction>()].direction);
+            let color = species_color((chosen + DIR_COUNT - heading) % DIR_COUNT, DIR_COUNT);
+            let channel = |c: f32| (c * 255.0) as u8;
+            let expected = Rgb([channel(color.r), channel(color.g), channel(color.b)]);
+            assert_eq!(image.get_pixel(x, y), &expected, "{} {}", x, y);
+        }
+    }
+}
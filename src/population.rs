@@ -0,0 +1,84 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::creature::{Creature, Kind};
+use crate::data::{Entity, GameData};
+
+pub const POPULATION_PATH: &str = "population.csv";
+
+/// How many creatures of every kind are alive, shows how far the ratio of
+/// the kinds drifted from the configured one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Population {
+    pub vegans: usize,
+    pub carnivores: usize,
+}
+
+impl Population {
+    pub fn count(data: &GameData, creatures: &[Entity]) -> Self {
+        let mut population = Self::default();
+        for e in creatures {
+            match data[e.component::<Creature>()].kind {
+                Kind::Vegan => population.vegans += 1,
+                Kind::Carnivorous => population.carnivores += 1,
+            }
+        }
+        population
+    }
+
+    /// Share of the creatures that are carnivores, 0.0 if there are none
+    pub fn carnivore_ratio(&self) -> f32 {
+        let total = self.vegans + self.carnivores;
+        if total == 0 {
+            return 0.0;
+        }
+        self.carnivores as f32 / total as f32
+    }
+
+    /// Appends a row for `generation`, writes the header first if the file
+    /// is new
+    pub fn append_csv<P: AsRef<Path>>(&self, path: P, generation: usize) -> io::Result<()> {
+        let path = path.as_ref();
+        let new = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if new {
+            writeln!(file, "generation,vegans,carnivores,carnivore_ratio")?;
+        }
+        writeln!(
+            file,
+            "{},{},{},{:.3}",
+            generation,
+            self.vegans,
+            self.carnivores,
+            self.carnivore_ratio()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn ratios_are_recorded_every_generation() {
+        let path = env::temp_dir().join(format!("ldjam-46-population-{}.csv", process::id()));
+        let _ = fs::remove_file(&path);
+        let skewed = Population {
+            vegans: 30,
+            carnivores: 70,
+        };
+        Population::default().append_csv(&path, 0).unwrap();
+        skewed.append_csv(&path, 1).unwrap();
+        let written = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written.unwrap(),
+            "generation,vegans,carnivores,carnivore_ratio\n0,0,0,0.000\n1,30,70,0.700\n"
+        );
+    }
+}
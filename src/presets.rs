@@ -0,0 +1,124 @@
+use crate::config::Config;
+use crate::creature::VEGAN_NUTRITION;
+use crate::{CREATURE_COUNT, FOOD_TIMEOUT};
+
+/// A named starting point for the config, `config.toml` still goes on top
+#[derive(Debug, Clone, Copy)]
+pub struct Preset {
+    pub name: &'static str,
+    pub summary: &'static str,
+    /// Changes the defaults into the preset
+    apply: fn(&mut Config),
+}
+
+impl Preset {
+    pub fn config(&self) -> Config {
+        let mut config = Config::default();
+        (self.apply)(&mut config);
+        config
+    }
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "classic",
+        summary: "the defaults",
+        apply: |_| {},
+    },
+    Preset {
+        name: "predator_pressure",
+        summary: "more carnivores and faster ones",
+        apply: |config| {
+            config.carnivore_ratio = 0.15;
+            config.carnivore_speed = 1.3;
+            config.target_vegans = CREATURE_COUNT - (CREATURE_COUNT as f32 * 0.15) as usize;
+        },
+    },
+    Preset {
+        name: "scarcity",
+        summary: "little food that spawns slowly",
+        apply: |config| {
+            config.food_timeout = FOOD_TIMEOUT * 2.5;
+            config.food_nutrition = VEGAN_NUTRITION * 0.6;
+            config.rich_food_ratio = 0.0;
+            config.rich_patch_count = 0;
+        },
+    },
+    Preset {
+        name: "garden",
+        summary: "lots of food in a few patches and no carnivores",
+        apply: |config| {
+            config.carnivore_ratio = 0.0;
+            config.target_vegans = CREATURE_COUNT;
+            config.food_timeout = FOOD_TIMEOUT * 0.5;
+            config.rich_patch_count = 4;
+            config.rich_food_ratio = 0.6;
+            config.rich_patch_radius = 120.0;
+        },
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use crate::check;
+    use crate::creature::{Creature, Kind};
+    use crate::GameState;
+
+    fn populated(config: Config) -> GameState {
+        let config = Config {
+            seed: 19,
+            chronicle: false,
+            ..config
+        };
+        let mut game = GameState::empty(0, config);
+        game.populate_with(Vec::new());
+        game
+    }
+
+    #[test]
+    fn every_preset_makes_a_valid_world() {
+        for preset in PRESETS {
+            let game = populated(preset.config());
+            let problems = check::validate_fresh(&game.data, &game.creatures, &game.foods);
+            assert!(problems.is_empty(), "{}: {:?}", preset.name, problems);
+            assert_eq!(find(preset.name).map(|p| p.name), Some(preset.name));
+        }
+        assert!(find("nonsense").is_none());
+    }
+
+    #[test]
+    fn the_garden_has_no_carnivores() {
+        let game = populated(find("garden").unwrap().config());
+        assert!(game
+            .creatures
+            .iter()
+            .all(|e| game.data[e.component::<Creature>()].kind == Kind::Vegan));
+        assert_eq!(game.creatures.len(), CREATURE_COUNT);
+    }
+
+    #[test]
+    fn the_config_file_goes_over_the_preset() {
+        let path = env::temp_dir().join(format!("ldjam-46-preset-{}.toml", process::id()));
+        fs::write(&path, "carnivore_ratio = 0.05\n").unwrap();
+        let preset = find("predator_pressure").unwrap();
+        let config = Config::load_over(&path, preset.config());
+        fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.carnivore_ratio, 0.05);
+        // what the file leaves out stays the preset's
+        assert_eq!(config.carnivore_speed, 1.3);
+        assert_eq!(config.target_vegans, preset.config().target_vegans);
+        assert_ne!(config.carnivore_speed, Config::default().carnivore_speed);
+    }
+}
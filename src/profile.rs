@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static PROFILER: RefCell<Option<Profiler>> = const { RefCell::new(None) };
+}
+
+/// Times the rest of the enclosing scope under a name while tracing, costs
+/// a branch otherwise
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _scope = if $crate::profile::enabled() {
+            Some($crate::profile::Scope::new($name))
+        } else {
+            None
+        };
+    };
+}
+
+/// One complete event of the chrome trace-event format, times in
+/// microseconds since tracing started
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct TraceEvent {
+    name: &'static str,
+    cat: &'static str,
+    ph: &'static str,
+    ts: f64,
+    dur: f64,
+    pid: u32,
+    tid: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    trace_events: &'a [TraceEvent],
+    display_time_unit: &'static str,
+}
+
+/// Time spent under one marker name over the whole trace
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Total {
+    time: Duration,
+    count: usize,
+}
+
+struct Profiler {
+    path: String,
+    start: Instant,
+    frames_left: usize,
+    events: Vec<TraceEvent>,
+    totals: BTreeMap<&'static str, Total>,
+}
+
+impl Profiler {
+    fn record(&mut self, name: &'static str, start: Instant, end: Instant) {
+        let elapsed = end - start;
+        self.events.push(TraceEvent {
+            name,
+            cat: "ldjam-46",
+            ph: "X",
+            ts: (start - self.start).as_secs_f64() * 1e6,
+            dur: elapsed.as_secs_f64() * 1e6,
+            pid: 0,
+            tid: 0,
+        });
+        let total = self.totals.entry(name).or_default();
+        total.time += elapsed;
+        total.count += 1;
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let file = BufWriter::new(File::create(&self.path)?);
+        let trace = Trace {
+            trace_events: &self.events,
+            display_time_unit: "ms",
+        };
+        serde_json::to_writer(file, &trace)?;
+        Ok(())
+    }
+}
+
+/// A running marker, records itself when dropped
+pub struct Scope {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Scope {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let end = Instant::now();
+        PROFILER.with(|profiler| {
+            if let Some(profiler) = profiler.borrow_mut().as_mut() {
+                profiler.record(self.name, self.start, end);
+            }
+        });
+    }
+}
+
+/// Whether markers are being recorded
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Starts recording markers on this thread for `frames` frames, after which
+/// the trace is written to `path`
+pub fn start(path: String, frames: usize) {
+    PROFILER.with(|profiler| {
+        *profiler.borrow_mut() = Some(Profiler {
+            path,
+            start: Instant::now(),
+            frames_left: frames,
+            events: Vec::new(),
+            totals: BTreeMap::new(),
+        });
+    });
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Counts down the frames of the trace, finishing it after the last one
+pub fn end_frame() {
+    if !enabled() {
+        return;
+    }
+    let done = PROFILER.with(|profiler| match profiler.borrow_mut().as_mut() {
+        Some(profiler) => {
+            profiler.frames_left = profiler.frames_left.saturating_sub(1);
+            profiler.frames_left == 0
+        }
+        None => true,
+    });
+    if done {
+        finish();
+    }
+}
+
+/// Stops recording, writes the trace and prints the time spent under every
+/// marker, does nothing if there's no trace going
+pub fn finish() {
+    ENABLED.store(false, Ordering::Relaxed);
+    let profiler = match PROFILER.with(|profiler| profiler.borrow_mut().take()) {
+        Some(profiler) => profiler,
+        None => return,
+    };
+    match profiler.write() {
+        Ok(()) => println!(
+            "wrote {} markers to {}",
+            profiler.events.len(),
+            profiler.path
+        ),
+        Err(err) => eprintln!("couldn't write {}: {}", profiler.path, err),
+    }
+    for (name, total) in &profiler.totals {
+        println!(
+            "  {}: {:.2} ms over {} calls",
+            name,
+            total.time.as_secs_f64() * 1000.0,
+            total.count
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn profiler(path: String) -> Profiler {
+        Profiler {
+            path,
+            start: Instant::now(),
+            frames_left: 1,
+            events: Vec::new(),
+            totals: BTreeMap::new(),
+        }
+    }
+
+    /// A frame of 10ms with two updates of 3ms and 2ms inside it, recorded in
+    /// the order scopes drop, innermost first
+    fn frame(profiler: &mut Profiler, at: Duration) {
+        let start = profiler.start + at;
+        profiler.record("update", start + ms(1), start + ms(4));
+        profiler.record("update", start + ms(5), start + ms(7));
+        profiler.record("frame", start, start + ms(10));
+    }
+
+    #[test]
+    fn totals_add_up_per_marker() {
+        let mut profiler = profiler(String::new());
+        frame(&mut profiler, ms(0));
+        frame(&mut profiler, ms(20));
+        assert_eq!(
+            profiler.totals["update"],
+            Total {
+                time: ms(10),
+                count: 4
+            }
+        );
+        assert_eq!(
+            profiler.totals["frame"],
+            Total {
+                time: ms(20),
+                count: 2
+            }
+        );
+
+        // inner markers sit inside the outer one they ran under
+        let outer = &profiler.events[2];
+        for inner in &profiler.events[..2] {
+            assert!(inner.ts >= outer.ts);
+            assert!(inner.ts + inner.dur <= outer.ts + outer.dur + 1e-6);
+        }
+        assert!((profiler.events[5].ts - 20_000.0).abs() < 1e-6);
+        assert!((profiler.events[5].dur - 10_000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn traces_are_complete_chrome_events() {
+        let path = env::temp_dir().join(format!("ldjam-46-trace-{}.json", process::id()));
+        let mut profiler = profiler(path.to_string_lossy().into_owned());
+        frame(&mut profiler, ms(0));
+        profiler.write().unwrap();
+        let written = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+
+        let trace: serde_json::Value = serde_json::from_str(&written.unwrap()).unwrap();
+        assert_eq!(trace["displayTimeUnit"], "ms");
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 3);
+        for event in events {
+            assert_eq!(event["ph"], "X");
+            assert_eq!(event["cat"], "ldjam-46");
+            assert!(event["ts"].is_f64() && event["dur"].is_f64());
+            assert!(event["pid"].is_u64() && event["tid"].is_u64());
+        }
+        assert_eq!(events[2]["name"], "frame");
+        assert_eq!(events[2]["dur"], 10_000.0);
+    }
+}
@@ -0,0 +1,102 @@
+use rand::{Error, RngCore, SeedableRng};
+
+use rand_chacha::ChaCha20Rng;
+
+use serde::{Deserialize, Serialize};
+
+/// Sim seconds the current step covers, written once at the start of
+/// `GameState::step`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DeltaTime(pub f32);
+
+/// Sim seconds into the generation, written along with `DeltaTime`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Clock(pub f32);
+
+/// The rng every system draws from instead of the thread's, so the same
+/// steps from the same world with a fixed `seed` play out the same
+///
+/// It's saved with the world as its seed and how far it got, so a resumed
+/// world goes on drawing the same numbers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RngState", into = "RngState")]
+pub struct SimRng {
+    seed: u64,
+    rng: ChaCha20Rng,
+}
+
+impl SimRng {
+    /// Seeded with `seed`, or with one from the os if it's 0
+    pub fn new(seed: u64) -> Self {
+        let seed = if seed == 0 { rand::random() } else { seed };
+        Self {
+            seed,
+            rng: ChaCha20Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// A fixed seed, it's only what's left in `GameData` while a system has taken
+/// the real one out
+impl Default for SimRng {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            rng: ChaCha20Rng::seed_from_u64(0),
+        }
+    }
+}
+
+/// Two rngs are the same if they start from the same seed and have drawn as
+/// many words
+impl PartialEq for SimRng {
+    fn eq(&self, other: &Self) -> bool {
+        RngState::from(self.clone()) == RngState::from(other.clone())
+    }
+}
+
+/// A `SimRng` as it's saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct RngState {
+    seed: u64,
+    /// 32 bit words drawn so far, this won't get past 64 bits
+    words: u64,
+}
+
+impl From<SimRng> for RngState {
+    fn from(rng: SimRng) -> Self {
+        Self {
+            seed: rng.seed,
+            words: rng.rng.get_word_pos() as u64,
+        }
+    }
+}
+
+impl From<RngState> for SimRng {
+    fn from(state: RngState) -> Self {
+        let mut rng = ChaCha20Rng::seed_from_u64(state.seed);
+        rng.set_word_pos(state.words as u128);
+        Self {
+            seed: state.seed,
+            rng,
+        }
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
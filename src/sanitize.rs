@@ -0,0 +1,202 @@
+use std::fmt;
+
+use crate::chronicle::{self, Cause};
+use crate::collision::Body;
+use crate::continuous;
+use crate::creature::*;
+use crate::data::{Entity, GameData, Has};
+
+/// Lightest a body can be while still being pushed around
+pub const MIN_MASS: f32 = 0.01;
+
+/// What `sanitize_system` did about a bad value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remedy {
+    /// The value was out of range and got moved back into it
+    Clamped,
+    /// The value was NaN or infinite and got reset
+    Reset,
+    /// There was no sensible value, so the entity was deleted
+    Despawned,
+}
+
+/// A bad value found by `sanitize_system`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Issue {
+    pub entity: Entity,
+    pub field: &'static str,
+    pub remedy: Remedy,
+}
+
+impl Issue {
+    /// NaNs and infinities are bugs, values out of range are just extreme
+    pub fn is_bug(&self) -> bool {
+        self.remedy != Remedy::Clamped
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "entity {}: bad {}, {:?}",
+            self.entity.idx, self.field, self.remedy
+        )
+    }
+}
+
+/// Finds NaNs, infinities and out of range values and fixes them before
+/// they spread to other entities through collisions and raycasts
+pub fn sanitize_system<I>(data: &mut GameData, iter: I) -> Vec<Issue>
+where
+    I: IntoIterator<Item = Entity>,
+{
+    let mut issues = Vec::new();
+    for e in iter {
+        if let Some((field, remedy)) = sanitize(data, e) {
+            if remedy == Remedy::Despawned {
+                if data.has(e.component::<Creature>()) {
+                    chronicle::death(data, e, Cause::Despawned);
+                    continuous::enshrine(data, e);
+                }
+                data.delete(e);
+                data.lazy.remove(e);
+            }
+            issues.push(Issue {
+                entity: e,
+                field,
+                remedy,
+            });
+        }
+    }
+    issues
+}
+
+/// Fixes the first bad value of an entity
+fn sanitize(data: &mut GameData, e: Entity) -> Option<(&'static str, Remedy)> {
+    if !data.has(e.component::<Body>()) {
+        return None;
+    }
+
+    let position = data[e.component::<Position>()].position;
+    if !position.x.is_finite() || !position.y.is_finite() {
+        return Some(("position", Remedy::Despawned));
+    }
+
+    let velocity = &mut data[e.component::<Velocity>()].velocity;
+    if !velocity.x.is_finite() || !velocity.y.is_finite() {
+        velocity.fill(0.0);
+        return Some(("velocity", Remedy::Reset));
+    }
+
+    let body = data[e.component::<Body>()];
+    if !body.radius.is_finite() || body.radius <= 0.0 {
+        return Some(("radius", Remedy::Despawned));
+    }
+    if !body.mass.is_finite() || !body.restitution.is_finite() || !body.opacity.is_finite() {
+        return Some(("body", Remedy::Despawned));
+    }
+    if body.mass < 0.0 || body.rmass < 0.0 {
+        data[e.component::<Body>()] =
+            Body::new(body.radius, MIN_MASS, body.restitution).with_opacity(body.opacity);
+        return Some(("mass", Remedy::Clamped));
+    }
+
+    if !data.has(e.component::<Creature>()) {
+        return None;
+    }
+
+    let creature = &mut data[e.component::<Creature>()];
+    for (field, value) in [
+        ("hunger", creature.hunger),
+        ("life", creature.life),
+        ("timeout", creature.timeout),
+    ] {
+        if !value.is_finite() {
+            return Some((field, Remedy::Despawned));
+        }
+    }
+    // one full stomach of reserves is plenty
+    let min_hunger = -creature.starve();
+    if creature.hunger < min_hunger {
+        creature.hunger = min_hunger;
+        return Some(("hunger", Remedy::Clamped));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::Vector2;
+
+    use crate::brain;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    /// Three vegans and a piece of food, all of them fine for now
+    fn world() -> (GameData, Vec<Entity>) {
+        let mut data = GameData::new();
+        data.config.chronicle = false;
+        let mut rng = SimRng::new(1);
+        let mut entities: Vec<_> = (0..3)
+            .map(|i| {
+                let genome = Genome::random(Kind::Vegan, &mut rng);
+                let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+                let position = Vector2::new(100.0 * (i + 1) as f32, 100.0);
+                spawn::creature_with(&mut data, position, Kind::Vegan, &genome, brain)
+            })
+            .collect();
+        let food = Food::plain(&data.config);
+        entities.push(spawn::food_at(
+            &mut data,
+            Vector2::new(50.0, 50.0),
+            5.0,
+            food,
+            &mut rng,
+        ));
+        (data, entities)
+    }
+
+    #[test]
+    fn fine_worlds_are_left_alone() {
+        let (mut data, entities) = world();
+        assert!(sanitize_system(&mut data, entities).is_empty());
+    }
+
+    #[test]
+    fn bad_values_are_fixed_or_despawned() {
+        let (mut data, entities) = world();
+        let (a, b, c, food) = (entities[0], entities[1], entities[2], entities[3]);
+        data[a.component::<Velocity>()].velocity.x = f32::NAN;
+        data[b.component::<Body>()].mass = -1.0;
+        let starve = data[c.component::<Creature>()].starve();
+        data[c.component::<Creature>()].hunger = -starve * 3.0;
+        data[food.component::<Position>()].position.y = f32::INFINITY;
+
+        let issues = sanitize_system(&mut data, entities);
+        let found: Vec<_> = issues
+            .iter()
+            .map(|issue| (issue.entity, issue.field, issue.remedy))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (a, "velocity", Remedy::Reset),
+                (b, "mass", Remedy::Clamped),
+                (c, "hunger", Remedy::Clamped),
+                (food, "position", Remedy::Despawned),
+            ]
+        );
+        assert_eq!(
+            data[a.component::<Velocity>()].velocity,
+            Vector2::new(0.0, 0.0)
+        );
+        assert_eq!(data[b.component::<Body>()].mass, MIN_MASS);
+        assert_eq!(data[c.component::<Creature>()].hunger, -starve);
+        assert!(!data.has_or_pending::<Food>(food));
+        assert_eq!(issues.iter().filter(|issue| issue.is_bug()).count(), 2);
+    }
+}
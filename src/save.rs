@@ -0,0 +1,450 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::brain::BrainConfig;
+use crate::config::Config;
+use crate::creature::{Creature, Genome, Kind};
+use crate::data::{Entity, GameData};
+use crate::error::{Error, Result};
+use crate::nn::{Network, WeightStats};
+use crate::selection;
+
+/// Bumped every time the layout of `Elites` or `Header` changes
+pub const SAVE_VERSION: u32 = 19;
+/// Bumped every time the layout of a world save or of `Config` changes, see
+/// `GameState::save_world`
+pub const WORLD_VERSION: u32 = 18;
+/// Bytes of a `Header` in a save
+const HEADER_LEN: u64 = 4 + 8 + 8 + 8;
+
+pub type SavedCreature = (Creature, Genome, Network);
+
+/// Comes before the elites in a save, so they can be counted without reading
+/// them, every version so far starts with the version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    /// Counted over every run that picked up where the last one stopped
+    generation: u64,
+    vegans: u64,
+    carnivores: u64,
+}
+
+/// The best creatures of a generation, ranked separately per kind
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Elites {
+    pub vegans: Vec<SavedCreature>,
+    pub carnivores: Vec<SavedCreature>,
+}
+
+/// Name of the save `GameState::quit_event` writes for a generation
+pub fn generation_path(generation: usize) -> String {
+    format!("gen{}.bin", generation)
+}
+
+/// The generation in the name of a save, see `generation_path`
+pub fn save_generation(name: &str) -> Option<usize> {
+    name.strip_prefix("gen")?.strip_suffix(".bin")?.parse().ok()
+}
+
+impl Elites {
+    /// Picks the top `k` vegans and the top `k` carnivores by their shared
+    /// fitness, see `species::share`
+    pub fn select<I>(data: &GameData, creatures: I, k: usize) -> Self
+    where
+        I: IntoIterator<Item = Entity>,
+    {
+        let mut vegans = Vec::new();
+        let mut carnivores = Vec::new();
+        for e in creatures {
+            match data[e.component::<Creature>()].kind {
+                Kind::Vegan => vegans.push(e),
+                Kind::Carnivorous => carnivores.push(e),
+            }
+        }
+
+        Self {
+            vegans: top(data, vegans, k),
+            carnivores: top(data, carnivores, k),
+        }
+    }
+
+    /// Adds a creature to the ones of its kind, keeping the `k` best by shared
+    /// fitness, which makes this a rolling hall of fame
+    pub fn offer(&mut self, saved: SavedCreature, k: usize, config: &Config) {
+        let list = match saved.0.kind {
+            Kind::Vegan => &mut self.vegans,
+            Kind::Carnivorous => &mut self.carnivores,
+        };
+        let candidates = std::mem::take(list)
+            .into_iter()
+            .chain(iter::once(saved))
+            .map(|saved| {
+                let fitness = saved.0.shared_fitness(config);
+                (saved, fitness)
+            });
+        *list = selection::top_k(candidates, k)
+            .into_iter()
+            .map(|(saved, _)| saved)
+            .collect();
+    }
+
+    /// Reports missing, corrupt and outdated saves instead of panicking
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let encoded = fs::read(path)?;
+        let version: u32 = bincode::deserialize(&encoded)?;
+        if version != SAVE_VERSION {
+            return Err(Error::FormatVersion {
+                found: version,
+                expected: SAVE_VERSION,
+            });
+        }
+
+        let mut reader = &encoded[..];
+        let _: Header = bincode::deserialize_from(&mut reader)?;
+        Ok(bincode::deserialize_from(&mut reader)?)
+    }
+
+    /// Drops the elites whose networks don't fit the configured brain,
+    /// returns how many were dropped
+    pub fn retain_matching(&mut self, brain: &BrainConfig) -> usize {
+        let before = self.vegans.len() + self.carnivores.len();
+        self.vegans
+            .retain(|(creature, _, network)| brain.matches(network, creature.kind));
+        self.carnivores
+            .retain(|(creature, _, network)| brain.matches(network, creature.kind));
+        before - self.vegans.len() - self.carnivores.len()
+    }
+
+    /// Prunes the networks of every elite, see `Network::prune`, returns how
+    /// many weights were zeroed
+    pub fn prune(&mut self, threshold: f32) -> usize {
+        self.vegans
+            .iter_mut()
+            .chain(&mut self.carnivores)
+            .map(|(_, _, network)| network.prune(threshold))
+            .sum()
+    }
+
+    /// Weight stats over the networks of every elite
+    pub fn weight_stats(&self) -> WeightStats {
+        self.vegans
+            .iter()
+            .chain(&self.carnivores)
+            .map(|(_, _, network)| network.weight_stats())
+            .fold(WeightStats::default(), WeightStats::merge)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, generation: usize) -> Result<()> {
+        let header = Header {
+            version: SAVE_VERSION,
+            generation: generation as u64,
+            vegans: self.vegans.len() as u64,
+            carnivores: self.carnivores.len() as u64,
+        };
+        let mut encoded = bincode::serialize(&header)?;
+        bincode::serialize_into(&mut encoded, self)?;
+        fs::write(path, &encoded)?;
+        Ok(())
+    }
+}
+
+/// What the header of a save says about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenerationMeta {
+    /// From the header, or from the name for saves of other versions
+    pub generation: usize,
+    pub path: PathBuf,
+    /// When the file was last written, if the file system knows
+    pub modified: Option<SystemTime>,
+    pub version: u32,
+    /// Number of elites, only known for saves of the current version
+    pub elites: Option<usize>,
+}
+
+impl GenerationMeta {
+    /// Reads just the header of a save, `generation` is used if the header
+    /// is of another version
+    pub fn read(generation: usize, path: PathBuf) -> Result<Self> {
+        let file = File::open(&path)?;
+        let modified = file.metadata().and_then(|m| m.modified()).ok();
+        let mut header = Vec::new();
+        file.take(HEADER_LEN).read_to_end(&mut header)?;
+        let version: u32 = bincode::deserialize(&header)?;
+        let (generation, elites) = if version == SAVE_VERSION {
+            let header: Header = bincode::deserialize(&header)?;
+            let elites = (header.vegans + header.carnivores) as usize;
+            (header.generation as usize, Some(elites))
+        } else {
+            (generation, None)
+        };
+        Ok(Self {
+            generation,
+            path,
+            modified,
+            version,
+            elites,
+        })
+    }
+}
+
+/// Every save in a directory, only their headers are read until a
+/// generation gets loaded
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RunArchive {
+    generations: Vec<GenerationMeta>,
+    problems: Vec<String>,
+}
+
+impl RunArchive {
+    /// Finds the saves in `dir`, ones whose header can't be read end up in
+    /// `problems` instead of failing the whole scan
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut archive = Self::default();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if let Some(generation) = save_generation(name) {
+                let problem = |err| format!("{}: {}", path.display(), err);
+                match GenerationMeta::read(generation, path.clone()) {
+                    Ok(meta) => archive.generations.push(meta),
+                    Err(err) => archive.problems.push(problem(err)),
+                }
+            }
+        }
+        archive.generations.sort_by_key(|meta| meta.generation);
+        Ok(archive)
+    }
+
+    /// Oldest first, saves of other versions included
+    pub fn generations(&self) -> impl Iterator<Item = &GenerationMeta> {
+        self.generations.iter()
+    }
+
+    /// Saves that couldn't be read at all
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    /// The newest save of the current version
+    pub fn latest(&self) -> Option<&GenerationMeta> {
+        self.generations
+            .iter()
+            .rev()
+            .find(|meta| meta.version == SAVE_VERSION)
+    }
+
+    pub fn load_generation(&self, generation: usize) -> Result<Elites> {
+        let meta = self
+            .generations
+            .iter()
+            .find(|meta| meta.generation == generation)
+            .ok_or_else(|| {
+                let err = format!("there is no save of generation {}", generation);
+                Error::Io(io::Error::new(io::ErrorKind::NotFound, err))
+            })?;
+        Elites::load(&meta.path)
+    }
+}
+
+/// The newest generation saved at `path`, a save or a directory of them,
+/// `None` if there's nothing readable there
+///
+/// Saves of other versions count too, going by their names, so a run that
+/// can't load them still doesn't write over them.
+pub fn latest_generation(path: &Path) -> Option<usize> {
+    if path.is_dir() {
+        let archive = RunArchive::open(path).ok()?;
+        return archive.generations().map(|meta| meta.generation).max();
+    }
+
+    let named = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(save_generation);
+    match GenerationMeta::read(named.unwrap_or(0), path.to_owned()) {
+        Ok(meta) if meta.version == SAVE_VERSION => Some(meta.generation),
+        _ => named,
+    }
+}
+
+/// The generation a run starting from the saves at `path` numbers its first
+/// one, past the newest saved there
+pub fn next_generation(path: &Path) -> usize {
+    latest_generation(path).map_or(0, |latest| latest + 1)
+}
+
+fn top(data: &GameData, entities: Vec<Entity>, k: usize) -> Vec<SavedCreature> {
+    selection::top_k_by(data, entities, k, selection::shared_fitness)
+        .into_iter()
+        .map(|(e, _)| saved(data, e))
+        .collect()
+}
+
+/// What a save keeps of a creature
+pub fn saved(data: &GameData, e: Entity) -> SavedCreature {
+    (
+        data[e.component::<Creature>()],
+        data[e.component::<Genome>()],
+        data[e.component::<Network>()].clone(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    use nalgebra::Vector2;
+
+    use crate::brain;
+    use crate::creature::Fitness;
+    use crate::data::Insert;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    /// A creature that did `score` of everything, so it ranks by `score`
+    /// whichever fitness its kind uses
+    fn elite(kind: Kind, score: usize, rng: &mut SimRng) -> SavedCreature {
+        let mut creature = Creature::new(kind);
+        creature.life = score as f32;
+        creature.eaten = score;
+        creature.children = score;
+        let network = brain::new_for(&Config::default(), kind, rng);
+        (creature, Genome::random(kind, rng), network)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("ldjam-46-{}-{}.bin", name, process::id()))
+    }
+
+    #[test]
+    fn selection_ranks_each_kind_on_its_own() {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(5);
+        let kinds = [
+            (Kind::Vegan, 1),
+            (Kind::Carnivorous, 2),
+            (Kind::Vegan, 9),
+            (Kind::Vegan, 4),
+            (Kind::Carnivorous, 1),
+        ];
+        let mut creatures = Vec::new();
+        for &(kind, score) in &kinds {
+            let (creature, genome, network) = elite(kind, score, &mut rng);
+            let e = spawn::creature_with(&mut data, Vector2::new(0.0, 0.0), kind, &genome, network);
+            data.insert(e, creature);
+            creatures.push(e);
+        }
+
+        let elites = Elites::select(&data, creatures.clone(), 2);
+        let scores =
+            |list: &[SavedCreature]| -> Vec<_> { list.iter().map(|s| s.0.eaten).collect() };
+        assert_eq!(scores(&elites.vegans), vec![9, 4]);
+        assert_eq!(scores(&elites.carnivores), vec![2, 1]);
+        assert!(elites
+            .carnivores
+            .iter()
+            .all(|s| s.0.kind == Kind::Carnivorous));
+
+        // vegans that lived long but had few children now rank last
+        data.config.vegan_fitness = Fitness::Children;
+        for (&e, &(kind, score)) in creatures.iter().zip(&kinds) {
+            if kind == Kind::Vegan {
+                data[e.component::<Creature>()].children = 10 - score;
+            }
+        }
+        let elites = Elites::select(&data, creatures, 2);
+        let lives = |list: &[SavedCreature]| -> Vec<_> { list.iter().map(|s| s.0.life).collect() };
+        assert_eq!(lives(&elites.vegans), vec![1.0, 4.0]);
+        assert_eq!(scores(&elites.carnivores), vec![2, 1]);
+    }
+
+    #[test]
+    fn saves_round_trip() {
+        let mut rng = SimRng::new(6);
+        let elites = Elites {
+            vegans: vec![elite(Kind::Vegan, 3, &mut rng)],
+            carnivores: vec![elite(Kind::Carnivorous, 1, &mut rng)],
+        };
+        let path = temp_path("elites");
+        elites.save(&path, 4).unwrap();
+        let loaded = Elites::load(&path);
+        let meta = GenerationMeta::read(0, path.clone());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), elites);
+        let meta = meta.unwrap();
+        assert_eq!((meta.generation, meta.elites), (4, Some(2)));
+    }
+
+    #[test]
+    fn numbering_goes_on_after_the_latest_save() {
+        let dir = env::temp_dir().join(format!("ldjam-46-numbering-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut rng = SimRng::new(8);
+        let elites = Elites {
+            vegans: vec![elite(Kind::Vegan, 1, &mut rng)],
+            carnivores: Vec::new(),
+        };
+        // the header wins over the name
+        let renamed = dir.join("gen3.bin");
+        elites.save(&renamed, 17).unwrap();
+        assert_eq!(latest_generation(&renamed), Some(17));
+        assert_eq!(next_generation(&renamed), 18);
+        assert_eq!(next_generation(&dir), 18);
+
+        // other versions only have their names to go by
+        let old = bincode::serialize(&(SAVE_VERSION - 1)).unwrap();
+        let named = dir.join(generation_path(21));
+        fs::write(&named, &old).unwrap();
+        let unnamed = dir.join("elites.bin");
+        fs::write(&unnamed, &old).unwrap();
+        assert_eq!(latest_generation(&named), Some(21));
+        assert_eq!(next_generation(&named), 22);
+        assert_eq!(latest_generation(&unnamed), None);
+        assert_eq!(next_generation(&unnamed), 0);
+        assert_eq!(next_generation(&dir), 22);
+        assert_eq!(next_generation(&dir.join("missing.bin")), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archives_list_what_they_can_read() {
+        let dir = env::temp_dir().join(format!("ldjam-46-archive-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut rng = SimRng::new(7);
+        let elites = Elites {
+            vegans: vec![elite(Kind::Vegan, 2, &mut rng)],
+            carnivores: Vec::new(),
+        };
+        elites.save(dir.join(generation_path(2)), 2).unwrap();
+        // a save of an old version only has its version to go by
+        let old = bincode::serialize(&(SAVE_VERSION - 1)).unwrap();
+        fs::write(dir.join(generation_path(5)), old).unwrap();
+        fs::write(dir.join(generation_path(9)), b"").unwrap();
+        fs::write(dir.join("notes.txt"), b"not a save").unwrap();
+
+        let archive = RunArchive::open(&dir).unwrap();
+        let generations: Vec<_> = archive
+            .generations()
+            .map(|meta| (meta.generation, meta.elites))
+            .collect();
+        assert_eq!(generations, vec![(2, Some(1)), (5, None)]);
+        assert_eq!(archive.problems().len(), 1);
+        assert!(archive.problems()[0].contains("gen9.bin"));
+        assert_eq!(archive.latest().map(|meta| meta.generation), Some(2));
+        assert_eq!(archive.load_generation(2).unwrap(), elites);
+        assert!(archive.load_generation(5).is_err());
+        assert!(archive.load_generation(7).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
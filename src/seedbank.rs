@@ -0,0 +1,269 @@
+use std::fs;
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::creature::{Creature, Kind};
+use crate::data::{Entity, GameData};
+use crate::error::{Error, Result};
+use crate::mutate::Rates;
+use crate::save::{self, SavedCreature};
+use crate::selection;
+use crate::TOP_COUNT;
+
+pub const SEED_BANK_PATH: &str = "seedbank.bin";
+/// Bumped whenever the layout of the seed bank changes, see `SAVE_VERSION`
+pub const SEED_BANK_VERSION: u32 = 1;
+
+/// A creature frozen in the seed bank and the generation it lived in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Seed {
+    pub generation: usize,
+    pub creature: SavedCreature,
+}
+
+/// Genomes and brains of ordinary creatures kept around so genes that
+/// selection would have lost can come back later
+///
+/// Every `seed_bank_interval` generations a few creatures that aren't among
+/// the elites of their kind get frozen. Once `seed_bank_size` are kept, each
+/// new seed replaces a random one, so older seeds get rarer but never run
+/// out all at once.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SeedBank {
+    pub seeds: Vec<Seed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedBank {
+    version: u32,
+    bank: SeedBank,
+}
+
+impl SeedBank {
+    /// An empty bank if there's no file yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let saved: SavedBank = bincode::deserialize(&fs::read(path)?)?;
+        if saved.version != SEED_BANK_VERSION {
+            return Err(Error::FormatVersion {
+                found: saved.version,
+                expected: SEED_BANK_VERSION,
+            });
+        }
+        Ok(saved.bank)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let saved = SavedBank {
+            version: SEED_BANK_VERSION,
+            bank: self.clone(),
+        };
+        fs::write(path, bincode::serialize(&saved)?)?;
+        Ok(())
+    }
+
+    /// Adds a seed, replacing a random one if the bank is full
+    pub fn offer<R: Rng>(&mut self, seed: Seed, size: usize, rng: &mut R) {
+        if size == 0 {
+            return;
+        }
+        if self.seeds.len() < size {
+            self.seeds.push(seed);
+        } else {
+            self.seeds.truncate(size);
+            let victim = rng.gen_range(0, size);
+            self.seeds[victim] = seed;
+        }
+    }
+
+    /// Whether `generation` is one where seeds get frozen
+    pub fn due(config: &Config, generation: usize) -> bool {
+        config.seed_bank_interval > 0 && generation % config.seed_bank_interval == 0
+    }
+
+    /// Freezes `seed_bank_samples` random creatures of every kind that aren't
+    /// among its elites, returns how many were frozen
+    pub fn freeze<R: Rng>(
+        &mut self,
+        data: &GameData,
+        creatures: &[Entity],
+        generation: usize,
+        rng: &mut R,
+    ) -> usize {
+        let config = &data.config;
+        let mut frozen = 0;
+        for &kind in &[Kind::Vegan, Kind::Carnivorous] {
+            let of_kind = creatures
+                .iter()
+                .copied()
+                .filter(|e| data[e.component::<Creature>()].kind == kind);
+            let elites: Vec<_> =
+                selection::top_k_by(data, of_kind.clone(), TOP_COUNT, selection::shared_fitness)
+                    .into_iter()
+                    .map(|(e, _)| e)
+                    .collect();
+            let ordinary: Vec<_> = of_kind.filter(|e| !elites.contains(e)).collect();
+            let picked = ordinary.choose_multiple(rng, config.seed_bank_samples);
+            for &e in picked {
+                let seed = Seed {
+                    generation,
+                    creature: save::saved(data, e),
+                };
+                self.offer(seed, config.seed_bank_size, rng);
+                frozen += 1;
+            }
+        }
+        frozen
+    }
+
+    /// With `seed_revival_chance`, a mutated copy of a random seed whose brain
+    /// fits the configured one, along with the generation it was frozen in
+    pub fn revive<R: Rng>(
+        &self,
+        config: &Config,
+        rates: Rates,
+        rng: &mut R,
+    ) -> Option<(SavedCreature, usize)> {
+        if rng.gen::<f32>() >= config.seed_revival_chance {
+            return None;
+        }
+        let fitting: Vec<_> = self
+            .seeds
+            .iter()
+            .filter(|seed| {
+                let (creature, _, network) = &seed.creature;
+                config.brain.matches(network, creature.kind)
+            })
+            .collect();
+        let seed = fitting.choose(rng)?;
+        let (creature, genome, network) = &seed.creature;
+        let genome = genome.cross(genome, config, rates, rng);
+        let creature = Creature::new(creature.kind);
+        Some(((creature, genome, network.clone()), seed.generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::check;
+    use crate::resources::SimRng;
+    use crate::GameState;
+
+    fn seed(generation: usize, game: &GameState) -> Seed {
+        Seed {
+            generation,
+            creature: save::saved(&game.data, game.creatures[0]),
+        }
+    }
+
+    fn fresh() -> GameState {
+        let config = Config {
+            seed: 13,
+            chronicle: false,
+            ..Config::default()
+        };
+        let mut game = GameState::empty(0, config);
+        game.populate_with(Vec::new());
+        game
+    }
+
+    #[test]
+    fn seeds_get_frozen_every_interval() {
+        let mut config = Config {
+            seed_bank_interval: 5,
+            ..Config::default()
+        };
+        let due: Vec<_> = (1..=15).filter(|&g| SeedBank::due(&config, g)).collect();
+        assert_eq!(due, vec![5, 10, 15]);
+        config.seed_bank_interval = 0;
+        assert!(!(0..20).any(|g| SeedBank::due(&config, g)));
+    }
+
+    #[test]
+    fn a_full_bank_replaces_a_seed() {
+        let game = fresh();
+        let mut bank = SeedBank::default();
+        let mut rng = SimRng::new(2);
+        for generation in 0..10 {
+            bank.offer(seed(generation, &game), 4, &mut rng);
+            assert_eq!(bank.seeds.len(), (generation + 1).min(4));
+        }
+        // the newest always gets in, older ones only if they were spared
+        assert!(bank.seeds.iter().any(|seed| seed.generation == 9));
+        assert!(bank.seeds.iter().all(|seed| seed.generation < 10));
+
+        bank.offer(seed(10, &game), 0, &mut rng);
+        assert!(bank.seeds.iter().all(|seed| seed.generation != 10));
+    }
+
+    #[test]
+    fn elites_stay_out_of_the_bank() {
+        let mut game = fresh();
+        for (i, &e) in game.creatures.iter().enumerate() {
+            game.data[e.component::<Creature>()].eaten = i;
+        }
+        let mut bank = SeedBank::default();
+        let mut rng = SimRng::new(3);
+        let frozen = bank.freeze(&game.data, &game.creatures, 5, &mut rng);
+        // kinds with no more creatures than elites have nothing to give
+        let ordinary = |kind| {
+            let count = game
+                .creatures
+                .iter()
+                .filter(|e| game.data[e.component::<Creature>()].kind == kind)
+                .count();
+            count
+                .saturating_sub(TOP_COUNT)
+                .min(game.data.config.seed_bank_samples)
+        };
+        let expected = ordinary(Kind::Vegan) + ordinary(Kind::Carnivorous);
+        assert!(expected > 0);
+        assert_eq!(frozen, expected);
+        assert_eq!(bank.seeds.len(), expected);
+
+        let vegans = game
+            .creatures
+            .iter()
+            .copied()
+            .filter(|e| game.data[e.component::<Creature>()].kind == Kind::Vegan);
+        let elites: Vec<_> =
+            selection::top_k_by(&game.data, vegans, TOP_COUNT, selection::shared_fitness)
+                .into_iter()
+                .map(|(e, _)| save::saved(&game.data, e).2)
+                .collect();
+        assert!(bank
+            .seeds
+            .iter()
+            .all(|seed| seed.generation == 5 && !elites.contains(&seed.creature.2)));
+    }
+
+    #[test]
+    fn revived_seeds_are_whole_creatures() {
+        let mut game = fresh();
+        let mut rng = SimRng::new(4);
+        game.seed_bank
+            .freeze(&game.data, &game.creatures, 5, &mut rng);
+        assert!(!game.seed_bank.seeds.is_empty());
+        game.data.config.seed_revival_chance = 1.0;
+        let before = game.creatures.len();
+        game.revive_seed();
+        assert_eq!(game.creatures.len(), before + 1);
+        let problems = check::validate(&game.data, &game.creatures, &game.foods);
+        assert!(problems.is_empty(), "{:?}", problems);
+
+        // nothing comes back when the chance never comes up
+        game.data.config.seed_revival_chance = 0.0;
+        game.revive_seed();
+        assert_eq!(game.creatures.len(), before + 1);
+    }
+}
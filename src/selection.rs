@@ -0,0 +1,158 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use ordered_float::OrderedFloat;
+
+use crate::creature::Creature;
+use crate::data::{Entity, GameData};
+
+/// Picks the `k` entities with the highest `metric`, best first
+///
+/// Keeps at most `k` candidates around, so this is O(n log k). Ties are
+/// broken in favour of the lower entity index.
+pub fn top_k_by<I, F>(data: &GameData, entities: I, k: usize, metric: F) -> Vec<(Entity, f32)>
+where
+    I: IntoIterator<Item = Entity>,
+    F: Fn(&GameData, Entity) -> f32,
+{
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for e in entities {
+        let score = OrderedFloat::from(metric(data, e));
+        // the heap is a min-heap of the candidates, so the worst one gets popped
+        heap.push(Reverse((score, Reverse(e.idx))));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((score, Reverse(idx)))| (Entity { idx }, score.into_inner()))
+        .collect()
+}
+
+/// Keeps the `k` items with the highest score, best first, ties go to the
+/// earlier item
+pub fn top_k<T, I>(items: I, k: usize) -> Vec<(T, f32)>
+where
+    I: IntoIterator<Item = (T, f32)>,
+{
+    let mut items: Vec<_> = items.into_iter().map(Some).collect();
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (i, item) in items.iter().enumerate() {
+        let score = item.as_ref().map_or(0.0, |(_, score)| *score);
+        heap.push(Reverse((OrderedFloat::from(score), Reverse(i))));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .filter_map(|Reverse((_, Reverse(i)))| items[i].take())
+        .collect()
+}
+
+/// Picks the `k` entities with the lowest `metric`, worst first
+pub fn bottom_k_by<I, F>(data: &GameData, entities: I, k: usize, metric: F) -> Vec<(Entity, f32)>
+where
+    I: IntoIterator<Item = Entity>,
+    F: Fn(&GameData, Entity) -> f32,
+{
+    top_k_by(data, entities, k, |data, e| -metric(data, e))
+        .into_iter()
+        .map(|(e, score)| (e, -score))
+        .collect()
+}
+
+pub fn life(data: &GameData, e: Entity) -> f32 {
+    data[e.component::<Creature>()].life
+}
+
+pub fn eaten(data: &GameData, e: Entity) -> f32 {
+    data[e.component::<Creature>()].eaten as f32
+}
+
+pub fn children(data: &GameData, e: Entity) -> f32 {
+    data[e.component::<Creature>()].children as f32
+}
+
+/// Combination of the other metrics using the weights from the config
+pub fn weighted(data: &GameData, e: Entity) -> f32 {
+    data[e.component::<Creature>()].weighted(&data.config)
+}
+
+/// Whichever metric is configured for the kind of the creature
+pub fn fitness(data: &GameData, e: Entity) -> f32 {
+    data[e.component::<Creature>()].fitness(&data.config)
+}
+
+/// `fitness` divided by the niche `species::share` found last
+pub fn shared_fitness(data: &GameData, e: Entity) -> f32 {
+    data[e.component::<Creature>()].shared_fitness(&data.config)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// Scores with plenty of ties, there are only 8 different values
+    fn scores(n: usize, seed: u64) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| rng.gen_range(0, 8) as f32 - 4.0).collect()
+    }
+
+    /// What `top_k` and `top_k_by` should pick, by sorting everything
+    fn reference(scores: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let mut sorted: Vec<_> = scores.iter().copied().enumerate().collect();
+        sorted.sort_by(|(i, a), (j, b)| b.partial_cmp(a).unwrap().then(i.cmp(j)));
+        sorted.truncate(k);
+        sorted
+    }
+
+    #[test]
+    fn top_k_by_matches_sorting() {
+        let data = GameData::new();
+        for seed in 0..20 {
+            let scores = scores(50, seed);
+            for &k in &[0, 1, 5, 50, 80] {
+                let entities = (0..scores.len()).map(|idx| Entity { idx });
+                let picked: Vec<_> = top_k_by(&data, entities, k, |_, e| scores[e.idx])
+                    .into_iter()
+                    .map(|(e, score)| (e.idx, score))
+                    .collect();
+                assert_eq!(picked, reference(&scores, k), "seed {} k {}", seed, k);
+            }
+        }
+    }
+
+    #[test]
+    fn top_k_matches_sorting() {
+        for seed in 0..20 {
+            let scores = scores(50, seed);
+            for &k in &[0, 1, 5, 50, 80] {
+                let items = scores.iter().copied().enumerate();
+                assert_eq!(
+                    top_k(items, k),
+                    reference(&scores, k),
+                    "seed {} k {}",
+                    seed,
+                    k
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bottom_k_by_is_worst_first() {
+        let data = GameData::new();
+        let scores = [3.0, -1.0, 2.0, -1.0, 5.0];
+        let entities = (0..scores.len()).map(|idx| Entity { idx });
+        let picked: Vec<_> = bottom_k_by(&data, entities, 3, |_, e| scores[e.idx])
+            .into_iter()
+            .map(|(e, score)| (e.idx, score))
+            .collect();
+        assert_eq!(picked, vec![(1, -1.0), (3, -1.0), (2, 2.0)]);
+    }
+}
@@ -0,0 +1,561 @@
+use std::f32::consts::PI;
+
+use ggez::GameResult;
+
+use nalgebra::{DVector, Vector2};
+
+use rand::Rng;
+
+use rand_distr::StandardNormal;
+
+use crate::collision::{occluder, raycast_all, Body, Ray};
+use crate::config::Config;
+use crate::creature::*;
+use crate::data::{Entity, GameData, Has};
+use crate::nn::{Desired, Inputs};
+use crate::profile_scope;
+use crate::resources::{Clock, SimRng};
+use crate::time::SimTime;
+use crate::WORLD_WIDTH;
+
+pub const VIEW_DISTANCE: f32 = WORLD_WIDTH;
+/// How food looks to a carnivore desperate enough to eat it
+pub const SCAVENGE_SIGHT: f32 = 0.5;
+/// How rich food looks to a vegan, plain food is 1.0
+pub const RICH_FOOD_SIGHT: f32 = 0.9;
+
+/// Index of the output closest to a heading in radians
+pub fn heading_index(direction: f32) -> usize {
+    let step = 360.0 / DIR_COUNT as f32;
+    let heading = (direction.to_degrees() / step).round() as isize;
+    heading.rem_euclid(DIR_COUNT as isize) as usize
+}
+
+/// What a creature sees along one of its rays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sight {
+    pub entity: Entity,
+    pub distance: f32,
+    /// How the thing looks, from -1.0 for danger to 1.0 for whatever it's after
+    pub kind: f32,
+}
+
+/// How `other` looks to the creature `this`
+pub fn sight_kind(data: &GameData, this: Entity, other: Entity) -> f32 {
+    let creature = data[this.component::<Creature>()];
+    match creature.kind {
+        Kind::Vegan => {
+            if data.has(other.component::<Food>()) {
+                if data[other.component::<Food>()].rich {
+                    RICH_FOOD_SIGHT
+                } else {
+                    1.0
+                }
+            } else if data[other.component::<Creature>()].kind == Kind::Vegan {
+                0.7
+            } else {
+                -1.0
+            }
+        }
+        Kind::Carnivorous => {
+            if data.has(other.component::<Food>()) {
+                if creature.desperate(&data.config) {
+                    SCAVENGE_SIGHT
+                } else {
+                    0.0
+                }
+            } else if data[other.component::<Creature>()].kind == Kind::Vegan {
+                1.0
+            } else {
+                0.7
+            }
+        }
+    }
+}
+
+/// How far views reach at `time` sim seconds into a generation, 1.0 by day
+/// and `night_view` by night, nights are the second half of every day
+pub fn daylight(config: &Config, time: f32) -> f32 {
+    if config.day_length <= 0.0 || time.rem_euclid(config.day_length) < config.day_length * 0.5 {
+        1.0
+    } else {
+        config.night_view
+    }
+}
+
+/// Which way a creature looks, how wide and how far, angles in radians
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    pub heading: f32,
+    pub fov: f32,
+    pub distance: f32,
+}
+
+impl View {
+    /// The view of a creature right now, the FOV of its kind and its view
+    /// distance in the current light
+    pub fn of(data: &GameData, e: Entity) -> Self {
+        let config = &data.config;
+        let fov = match data[e.component::<Creature>()].kind {
+            Kind::Vegan => config.vegan_fov,
+            Kind::Carnivorous => config.carnivore_fov,
+        };
+        let light = daylight(config, data.resource::<Clock>().0);
+        Self {
+            heading: data[e.component::<Direction>()].direction,
+            fov: fov.to_radians(),
+            distance: data[e.component::<Genome>()].view_distance * light,
+        }
+    }
+}
+
+/// The rays a creature casts, in input order, spread evenly over its view
+pub fn rays(data: &GameData, e: Entity) -> Vec<Ray> {
+    let p1 = data[e.component::<Position>()].position;
+    let view = View::of(data, e);
+    (0..RAY_COUNT)
+        .map(|i| {
+            let f = i as f32 / (RAY_COUNT as f32 - 1.0);
+            let d = view.heading + view.fov * (f - 0.5);
+            let (y, x) = d.sin_cos();
+            let p2 = Vector2::new(x, y) * view.distance;
+            Ray::new(p1, p2)
+        })
+        .collect()
+}
+
+/// Casts every ray of a creature, `None` where a ray hit nothing
+pub fn look<I>(data: &GameData, e: Entity, all: I) -> Vec<Option<Sight>>
+where
+    I: IntoIterator<Item = Entity> + Clone,
+{
+    look_some(data, e, all, |_| true)
+}
+
+/// Like `look`, but only casts the rays `cast` picks by index, the others
+/// come back `None` too
+pub fn look_some<I, F>(data: &GameData, e: Entity, all: I, cast: F) -> Vec<Option<Sight>>
+where
+    I: IntoIterator<Item = Entity> + Clone,
+    F: Fn(usize) -> bool,
+{
+    rays(data, e)
+        .iter()
+        .enumerate()
+        .map(|(i, ray)| {
+            if !cast(i) {
+                return None;
+            }
+            let hits = raycast_all(data, ray, e, all.clone());
+            occluder(data, &hits).map(|(other, distance)| Sight {
+                entity: other,
+                distance,
+                kind: sight_kind(data, e, other),
+            })
+        })
+        .collect()
+}
+
+/// The wedge a set of rays sweeps, angles in radians
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewArc {
+    pub center: Vector2<f32>,
+    pub start: f32,
+    /// At least `start`, the same if every ray points the same way
+    pub end: f32,
+    /// Length of the longest ray
+    pub radius: f32,
+}
+
+impl ViewArc {
+    /// The wedge of rays cast from the same point, like the ones from `rays`,
+    /// measured from the rays themselves so it shows what `look` casts
+    pub fn of(rays: &[Ray]) -> Option<Self> {
+        let first = rays.first()?;
+        let angle = |ray: &Ray| {
+            let d = ray.p2 - ray.p1;
+            d.y.atan2(d.x)
+        };
+        let base = angle(first);
+        let (mut low, mut high, mut radius) = (0.0f32, 0.0f32, 0.0f32);
+        for ray in rays {
+            // relative to the first ray, so a wedge across the negative x
+            // axis doesn't wrap around
+            let offset = (angle(ray) - base + PI).rem_euclid(2.0 * PI) - PI;
+            low = low.min(offset);
+            high = high.max(offset);
+            radius = radius.max((ray.p2 - ray.p1).magnitude());
+        }
+        Some(Self {
+            center: first.p1,
+            start: base + low,
+            end: base + high,
+            radius,
+        })
+    }
+}
+
+/// How fast `other` is moving away from `this`, from -1.0 for charging at it
+/// at full speed to 1.0 for fleeing at full speed
+pub fn radial_velocity(data: &GameData, this: Entity, other: Entity) -> f32 {
+    let offset =
+        data[other.component::<Position>()].position - data[this.component::<Position>()].position;
+    let distance = offset.magnitude();
+    if distance <= 0.0 {
+        return 0.0;
+    }
+
+    let relative =
+        data[other.component::<Velocity>()].velocity - data[this.component::<Velocity>()].velocity;
+    let speed = relative.dot(&(offset / distance));
+    (speed / SimTime::max_speed()).clamp(-1.0, 1.0)
+}
+
+/// Turns what a creature sees and how it's doing into its network inputs
+pub fn encode<R: Rng>(
+    data: &GameData,
+    e: Entity,
+    sights: &[Option<Sight>],
+    rng: &mut R,
+) -> DVector<f32> {
+    let view_distance = data[e.component::<Genome>()].view_distance;
+    let creature = data[e.component::<Creature>()];
+    let mut inputs = vec![1.0; INPUT_COUNT];
+    for (i, sight) in sights.iter().enumerate() {
+        let channels = &mut inputs[i * RAY_CHANNELS..(i + 1) * RAY_CHANNELS];
+        let dropped = data.config.ray_dropout > 0.0 && rng.gen::<f32>() < data.config.ray_dropout;
+        if let (Some(sight), false) = (sight, dropped) {
+            channels[0] = sight.kind;
+            channels[1] = sight.distance / view_distance;
+            channels[2] = radial_velocity(data, e, sight.entity);
+        } else {
+            channels[2] = 0.0;
+        }
+    }
+    inputs[STARTLE_INPUT] = creature.last_hit / data.config.startle_time;
+    let weakness = creature.weakness(&data.config);
+    inputs[WEAKENED_INPUT] = if weakness > 0.0 { 1.0 } else { 0.0 };
+    inputs[LUNGE_INPUT] = if data.config.lunge_duration > 0.0 {
+        creature.lunge / data.config.lunge_duration
+    } else {
+        0.0
+    };
+    inputs[DIGESTION_INPUT] = if data.config.digestion_duration > 0.0 {
+        creature.digestion / data.config.digestion_duration
+    } else {
+        0.0
+    };
+
+    let sigma = data.config.input_noise;
+    if sigma > 0.0 {
+        for input in &mut inputs {
+            *input += sigma * rng.sample::<f32, _>(StandardNormal);
+        }
+    }
+    DVector::from_vec(inputs)
+}
+
+/// Whether a creature still has everything `look` and `encode` read
+fn senses_whole(data: &GameData, e: Entity) -> bool {
+    data.get::<Creature>(e).is_some()
+        && data.get::<Position>(e).is_some()
+        && data.get::<Velocity>(e).is_some()
+        && data.get::<Direction>(e).is_some()
+        && data.get::<Body>(e).is_some()
+        && data.get::<Genome>(e).is_some()
+        && data.get::<Inputs>(e).is_some()
+}
+
+/// The inputs a creature would get from the entities in `all`
+pub fn build_inputs<I, R>(data: &GameData, e: Entity, all: I, rng: &mut R) -> DVector<f32>
+where
+    I: IntoIterator<Item = Entity> + Clone,
+    R: Rng,
+{
+    encode(data, e, &look(data, e, all), rng)
+}
+
+/// Builds the inputs of every creature, with `parity` only the rays whose
+/// index has that parity are cast and the others keep their last inputs
+pub fn input_system<I1, I2>(
+    data: &mut GameData,
+    creatures: I1,
+    all: I2,
+    parity: Option<usize>,
+) -> GameResult<()>
+where
+    I1: IntoIterator<Item = Entity>,
+    I2: IntoIterator<Item = Entity> + Clone,
+{
+    profile_scope!("senses");
+    let mut rng = data.take_resource::<SimRng>();
+    for e in creatures {
+        // eaten earlier in the frame, there's nothing left to sense with
+        let desired_len = match data.get::<Desired>(e) {
+            Some(desired) if senses_whole(data, e) => desired.desired.len(),
+            _ => continue,
+        };
+        let cast = |i: usize| match parity {
+            Some(parity) => i % 2 == parity,
+            None => true,
+        };
+        let sights = look_some(data, e, all.clone(), cast);
+        let mut inputs = encode(data, e, &sights, &mut rng);
+        let last = &data[e.component::<Inputs>()].input;
+        if parity.is_some() && last.len() == inputs.len() {
+            for i in (0..RAY_COUNT).filter(|&i| !cast(i)) {
+                let channels = i * RAY_CHANNELS..(i + 1) * RAY_CHANNELS;
+                for j in channels {
+                    inputs[j] = last[j];
+                }
+            }
+        }
+
+        let mut has_desired = false;
+        // carnivores have a lunge output after the directions, which is left
+        // for evolution to figure out
+        let mut desired = vec![0.0; desired_len];
+        let mut food_distance = f32::INFINITY;
+        for (i, sight) in sights.iter().enumerate() {
+            if let Some(sight) = sight {
+                if data.has(sight.entity.component::<Food>()) && sight.kind > 0.0 {
+                    food_distance = food_distance.min(sight.distance);
+                }
+
+                has_desired = true;
+                let j = i * (DIR_COUNT as f32 / RAY_COUNT as f32).round() as usize;
+                desired[j] = sight.kind;
+            }
+        }
+
+        let heading = heading_index(data[e.component::<Direction>()].direction);
+        let last_food = data[e.component::<Creature>()].food_distance;
+        data[e.component::<Creature>()].food_distance = food_distance;
+        let shaping = data.config.shaping_weight;
+        if shaping > 0.0 && last_food.is_finite() && food_distance.is_finite() {
+            // a nudge for whatever brought it closer to food, events stay in charge
+            let progress = last_food - food_distance;
+            if progress != 0.0 && desired[heading] == 0.0 {
+                desired[heading] = shaping * progress.signum();
+                has_desired = true;
+            }
+        }
+        if data[e.component::<Creature>()].last_hit > 0.0 {
+            // whatever it was doing got it hit, so discourage it
+            desired[heading] = -1.0;
+            has_desired = true;
+        }
+        if has_desired {
+            data[e.component::<Desired>()].desired = DVector::from_vec(desired);
+        }
+        data[e.component::<Inputs>()].input = inputs;
+    }
+    data.insert_resource(rng);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::brain;
+    use crate::spawn;
+
+    /// A vegan at the origin heading towards output 1, with a piece of food
+    /// 100 units ahead that every one of its rays sees
+    fn vegan_facing_food() -> (GameData, Entity, Entity) {
+        let mut data = GameData::new();
+        let mut rng = SimRng::new(1);
+        let genome = Genome::random(Kind::Vegan, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+        let origin = Vector2::new(0.0, 0.0);
+        let vegan = spawn::creature_with(&mut data, origin, Kind::Vegan, &genome, brain);
+        let direction = (360.0 / DIR_COUNT as f32).to_radians();
+        data[vegan.component::<Direction>()].direction = direction;
+        let (y, x) = direction.sin_cos();
+        let food = Food::plain(&data.config);
+        let food = spawn::food_at(&mut data, Vector2::new(x, y) * 100.0, 5.0, food, &mut rng);
+        data.insert_resource(rng);
+        (data, vegan, food)
+    }
+
+    fn desired(data: &mut GameData, vegan: Entity, food: Entity) -> Vec<f32> {
+        input_system(data, vec![vegan], vec![vegan, food], None).unwrap();
+        data[vegan.component::<Desired>()]
+            .desired
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn getting_closer_to_food_is_encouraged() {
+        let (mut data, vegan, food) = vegan_facing_food();
+        data.config.shaping_weight = 0.25;
+        assert_eq!(
+            heading_index(data[vegan.component::<Direction>()].direction),
+            1
+        );
+        // nothing to compare with yet
+        assert_eq!(desired(&mut data, vegan, food)[1], 0.0);
+        let distance = data[vegan.component::<Creature>()].food_distance;
+        assert!(distance.is_finite());
+
+        data[vegan.component::<Creature>()].food_distance = distance + 10.0;
+        assert_eq!(desired(&mut data, vegan, food)[1], 0.25);
+        data[vegan.component::<Creature>()].food_distance = distance - 10.0;
+        assert_eq!(desired(&mut data, vegan, food)[1], -0.25);
+
+        data.config.shaping_weight = 0.0;
+        data[vegan.component::<Creature>()].food_distance = distance + 10.0;
+        assert_eq!(desired(&mut data, vegan, food)[1], 0.0);
+    }
+
+    #[test]
+    fn headings_round_and_wrap() {
+        let step = (360.0 / DIR_COUNT as f32).to_radians();
+        assert_eq!(heading_index(0.0), 0);
+        assert_eq!(heading_index(step * 2.4), 2);
+        assert_eq!(heading_index(-step), DIR_COUNT - 1);
+        assert_eq!(heading_index(step * (DIR_COUNT + 3) as f32), 3);
+    }
+
+    #[test]
+    fn inputs_encode_what_is_seen() {
+        let (mut data, vegan, food) = vegan_facing_food();
+        data.config.ray_dropout = 0.0;
+        data.config.input_noise = 0.0;
+        let mut rng = SimRng::new(2);
+        let view_distance = data[vegan.component::<Genome>()].view_distance;
+        let inputs = build_inputs(&data, vegan, vec![vegan, food], &mut rng);
+        assert_eq!(inputs.len(), INPUT_COUNT);
+        let sights = look(&data, vegan, vec![vegan, food]);
+        for (ray, sight) in sights.iter().enumerate() {
+            let sight = sight.unwrap();
+            assert_eq!(sight.entity, food);
+            let channels = &inputs.as_slice()[ray * RAY_CHANNELS..(ray + 1) * RAY_CHANNELS];
+            assert_eq!(channels, &[1.0, sight.distance / view_distance, 0.0][..]);
+        }
+        assert_eq!(inputs[STARTLE_INPUT], 0.0);
+        assert_eq!(inputs[WEAKENED_INPUT], 0.0);
+
+        // nothing to see, and hungry enough to be weakened
+        let starve = data[vegan.component::<Creature>()].starve();
+        data[vegan.component::<Creature>()].hunger = starve;
+        let inputs = build_inputs(&data, vegan, vec![vegan], &mut rng);
+        assert_eq!(&inputs.as_slice()[..RAY_CHANNELS], &[1.0, 1.0, 0.0][..]);
+        assert_eq!(inputs[WEAKENED_INPUT], 1.0);
+    }
+
+    #[test]
+    fn startled_creatures_feel_it() {
+        let (mut data, vegan, food) = vegan_facing_food();
+        data.config.input_noise = 0.0;
+        data[vegan.component::<Creature>()].last_hit = data.config.startle_time * 0.5;
+        input_system(&mut data, vec![vegan], vec![vegan, food], None).unwrap();
+        assert_eq!(data[vegan.component::<Inputs>()].input[STARTLE_INPUT], 0.5);
+    }
+
+    #[test]
+    fn approaching_things_read_negative() {
+        let (mut data, vegan, food) = vegan_facing_food();
+        let max = SimTime::max_speed();
+        assert_eq!(radial_velocity(&data, vegan, food), 0.0);
+
+        // the vegan heads straight for the food at half the top speed
+        let position = data[food.component::<Position>()].position;
+        data[vegan.component::<Velocity>()].velocity = position.normalize() * max * 0.5;
+        assert!((radial_velocity(&data, vegan, food) + 0.5).abs() < 1e-5);
+        // moving sideways doesn't count
+        let sideways = Vector2::new(-position.y, position.x).normalize() * max;
+        data[vegan.component::<Velocity>()].velocity = sideways;
+        assert!(radial_velocity(&data, vegan, food).abs() < 1e-5);
+        // and running away faster than anything is clamped
+        data[vegan.component::<Velocity>()].velocity = -position.normalize() * max * 3.0;
+        assert_eq!(radial_velocity(&data, vegan, food), 1.0);
+        assert_eq!(radial_velocity(&data, vegan, vegan), 0.0);
+    }
+
+    #[test]
+    fn dropped_rays_read_like_misses() {
+        let (mut data, vegan, food) = vegan_facing_food();
+        data.config.ray_dropout = 1.0;
+        let inputs = build_inputs(&data, vegan, vec![vegan, food], &mut SimRng::new(2));
+        for ray in 0..RAY_COUNT {
+            let channels = &inputs.as_slice()[ray * RAY_CHANNELS..(ray + 1) * RAY_CHANNELS];
+            assert_eq!(channels, &[1.0, 1.0, 0.0][..]);
+        }
+    }
+
+    #[test]
+    fn noise_is_small_and_seeded() {
+        let (mut data, vegan, food) = vegan_facing_food();
+        let all = vec![vegan, food];
+        let clean = build_inputs(&data, vegan, all.clone(), &mut SimRng::new(2));
+        data.config.input_noise = 0.01;
+        let noisy = build_inputs(&data, vegan, all.clone(), &mut SimRng::new(2));
+        assert_ne!(clean, noisy);
+        assert!((&noisy - &clean).amax() < 0.1);
+        assert_eq!(noisy, build_inputs(&data, vegan, all, &mut SimRng::new(2)));
+    }
+
+    fn arc(data: &GameData, e: Entity) -> ViewArc {
+        ViewArc::of(&rays(data, e)).unwrap()
+    }
+
+    #[test]
+    fn each_kind_looks_over_its_own_fov() {
+        let (mut data, vegan, _) = vegan_facing_food();
+        data.config.vegan_fov = 90.0;
+        data.config.carnivore_fov = 30.0;
+        let mut rng = SimRng::new(3);
+        let genome = Genome::random(Kind::Carnivorous, &mut rng);
+        let brain = brain::new_for(&data.config, Kind::Carnivorous, &mut rng);
+        let origin = Vector2::new(0.0, 0.0);
+        let carnivore = spawn::creature_with(&mut data, origin, Kind::Carnivorous, &genome, brain);
+        data[carnivore.component::<Direction>()].direction = 1.0;
+
+        for (e, fov) in [(vegan, 90.0f32), (carnivore, 30.0)] {
+            let view = View::of(&data, e);
+            let arc = arc(&data, e);
+            assert!((arc.start - (view.heading - fov.to_radians() / 2.0)).abs() < 1e-4);
+            assert!((arc.end - (view.heading + fov.to_radians() / 2.0)).abs() < 1e-4);
+            let view_distance = data[e.component::<Genome>()].view_distance;
+            assert!((arc.radius - view_distance).abs() < 1e-2);
+        }
+
+        data.config.vegan_fov = 0.0;
+        let arc = arc(&data, vegan);
+        assert_eq!(arc.start, arc.end);
+    }
+
+    #[test]
+    fn nights_shorten_views() {
+        let (mut data, vegan, _) = vegan_facing_food();
+        data.config.vegan_fov = 60.0;
+        data.config.day_length = 10.0;
+        data.config.night_view = 0.25;
+        let view_distance = data[vegan.component::<Genome>()].view_distance;
+
+        for (time, light) in [
+            (0.0, 1.0),
+            (4.9, 1.0),
+            (5.0, 0.25),
+            (9.9, 0.25),
+            (12.0, 1.0),
+        ] {
+            data.insert_resource(Clock(time));
+            let arc = arc(&data, vegan);
+            assert!(
+                (arc.radius - view_distance * light).abs() < 1e-2,
+                "{}",
+                time
+            );
+            assert!((arc.end - arc.start - 60f32.to_radians()).abs() < 1e-4);
+        }
+
+        data.config.day_length = 0.0;
+        data.insert_resource(Clock(7.0));
+        assert!((arc(&data, vegan).radius - view_distance).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What the process exits with after saving on an interrupt
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn handle(_signal: libc::c_int) {
+    if request() {
+        // a second one means the save is taking too long for somebody
+        unsafe { libc::_exit(INTERRUPTED_EXIT_CODE) };
+    }
+}
+
+/// Turns SIGINT and SIGTERM into a shutdown request instead of killing the
+/// process right away
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Asks for a shutdown, returns whether one was asked for already
+pub fn request() -> bool {
+    REQUESTED.swap(true, Ordering::SeqCst)
+}
+
+/// Whether a shutdown was asked for
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Takes back a request, for tests that interrupt a game
+#[cfg(test)]
+pub fn reset() {
+    REQUESTED.store(false, Ordering::SeqCst);
+}
@@ -0,0 +1,186 @@
+use ggez::graphics::Color;
+
+use nalgebra::Vector2;
+
+use rand::Rng;
+
+use crate::collision::{Body, FOOD_OPACITY};
+use crate::config::Config;
+use crate::creature::*;
+use crate::data::{Entity, GameData, Insert};
+use crate::draw::{Appearance, Shape};
+use crate::lazy::{LazyUpdate, PendingEntity};
+use crate::nn::{Desired, Experience, Inputs, Network, Outputs};
+use crate::{WORLD_HEIGHT, WORLD_WIDTH};
+
+/// Something entities can be spawned into, lets the same helpers work both
+/// right away and lazily, `E` is the handle of the spawned entities
+pub trait World<E>:
+    Insert<Creature, E>
+    + Insert<Food, E>
+    + Insert<Position, E>
+    + Insert<Velocity, E>
+    + Insert<Direction, E>
+    + Insert<Body, E>
+    + Insert<Appearance, E>
+    + Insert<Genome, E>
+    + Insert<Network, E>
+    + Insert<Inputs, E>
+    + Insert<Outputs, E>
+    + Insert<Desired, E>
+    + Insert<Experience, E>
+{
+    fn add_entity(&mut self) -> E;
+
+    /// Adds an entity and hands out a builder to give it components
+    fn build_entity(&mut self) -> EntityBuilder<'_, Self, E>
+    where
+        Self: Sized,
+    {
+        let e = self.add_entity();
+        EntityBuilder { world: self, e }
+    }
+}
+
+/// A freshly added entity getting its components one `with` at a time, works
+/// with anything that can `Insert` them
+pub struct EntityBuilder<'a, W, E> {
+    world: &'a mut W,
+    e: E,
+}
+
+impl<'a, W, E: Copy> EntityBuilder<'a, W, E> {
+    pub fn with<T>(self, t: T) -> Self
+    where
+        W: Insert<T, E>,
+    {
+        self.world.insert(self.e, t);
+        self
+    }
+
+    pub fn build(self) -> E {
+        self.e
+    }
+}
+
+impl World<Entity> for GameData {
+    fn add_entity(&mut self) -> Entity {
+        GameData::add_entity(self)
+    }
+}
+
+impl World<PendingEntity> for LazyUpdate {
+    fn add_entity(&mut self) -> PendingEntity {
+        LazyUpdate::add_entity(self)
+    }
+}
+
+/// Moves a body of `radius` fully inside the world, anything closer to the
+/// edge could get wrapped around right after spawning
+pub fn clamp_to_world(position: Vector2<f32>, radius: f32) -> Vector2<f32> {
+    let clamp = |x: f32, max: f32| x.max(radius).min(max - radius);
+    Vector2::new(
+        clamp(position.x, WORLD_WIDTH),
+        clamp(position.y, WORLD_HEIGHT),
+    )
+}
+
+/// A random spot for a body of `radius` in the central `spawn_area_fraction`
+/// of the world
+pub fn random_position<R: Rng>(config: &Config, radius: f32, rng: &mut R) -> Vector2<f32> {
+    let fraction = config.spawn_area_fraction.clamp(0.0, 1.0);
+    let mut f = |size: f32| size * (0.5 + (rng.gen::<f32>() - 0.5) * fraction);
+    clamp_to_world(Vector2::new(f(WORLD_WIDTH), f(WORLD_HEIGHT)), radius)
+}
+
+/// Spawns a creature with everything it needs except for its mesh, which
+/// `draw::mesh_system` builds later
+pub fn creature_with<E: Copy, W: World<E>>(
+    world: &mut W,
+    pos: Vector2<f32>,
+    kind: Kind,
+    genome: &Genome,
+    brain: Network,
+) -> E {
+    let outputs = brain.output_len();
+    world
+        .build_entity()
+        .with(Creature::new(kind))
+        .with(Position::new(pos.x, pos.y))
+        .with(Velocity::new(0.0, 0.0))
+        .with(Direction::new(0.0))
+        .with(Body::new(genome.radius, genome.mass, genome.restitution))
+        .with(Appearance::new(
+            Shape::Creature,
+            genome.radius,
+            genome.color,
+        ))
+        .with(*genome)
+        .with(Inputs::new(INPUT_COUNT))
+        .with(Outputs::new(outputs))
+        .with(Desired::new(outputs))
+        .with(Experience::default())
+        .with(brain)
+        .build()
+}
+
+/// Spawns a piece of food, rich food is golden and plain food a random gray
+pub fn food_at<E: Copy, W: World<E>, R: Rng>(
+    world: &mut W,
+    pos: Vector2<f32>,
+    radius: f32,
+    food: Food,
+    rng: &mut R,
+) -> E {
+    let color = if food.rich {
+        RICH_FOOD_COLOR
+    } else {
+        let color = rng.gen::<f32>();
+        Color::new(color, color, color, 1.0)
+    };
+    let body = Body::new(radius, rng.gen::<f32>(), rng.gen::<f32>()).with_opacity(FOOD_OPACITY);
+    world
+        .build_entity()
+        .with(food)
+        .with(Position::new(pos.x, pos.y))
+        .with(Velocity::new(0.0, 0.0))
+        .with(body)
+        .with(Appearance::new(Shape::Circle, radius, color))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::resources::SimRng;
+
+    #[test]
+    fn bodies_are_clamped_inside_the_world() {
+        let clamped = clamp_to_world(Vector2::new(-5.0, WORLD_HEIGHT + 5.0), 10.0);
+        assert_eq!(clamped, Vector2::new(10.0, WORLD_HEIGHT - 10.0));
+        let inside = Vector2::new(50.0, 60.0);
+        assert_eq!(clamp_to_world(inside, 10.0), inside);
+    }
+
+    #[test]
+    fn spawns_stay_in_the_spawn_area() {
+        let config = Config {
+            spawn_area_fraction: 0.5,
+            ..Config::default()
+        };
+        let mut rng = SimRng::new(1);
+        for _ in 0..200 {
+            let position = random_position(&config, 10.0, &mut rng);
+            assert!((position.x - WORLD_WIDTH * 0.5).abs() <= WORLD_WIDTH * 0.25);
+            assert!((position.y - WORLD_HEIGHT * 0.5).abs() <= WORLD_HEIGHT * 0.25);
+        }
+
+        // the whole world still keeps bodies off the edges
+        let config = Config::default();
+        for _ in 0..200 {
+            let position = random_position(&config, 10.0, &mut rng);
+            assert_eq!(clamp_to_world(position, 10.0), position);
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Text};
+use ggez::{Context, GameResult};
+
+use crate::collision::Body;
+use crate::creature::{Creature, Genome, Position};
+use crate::data::{Entity, GameData};
+use crate::draw;
+use crate::draw::Appearance;
+use crate::time::SimTime;
+use crate::{MAX_RADIUS, WIDTH};
+
+pub const SPECIES_PATH: &str = "species.csv";
+
+/// Number of k-means rounds per clustering pass
+pub const SPECIES_ITERATIONS: usize = 8;
+
+/// Position of a creature in "looks" space, every axis is roughly in 0..1
+///
+/// Speed isn't looks, but it's the one heritable gene that changes how a
+/// creature gets by, so it counts too.
+pub fn embed(data: &GameData, e: Entity) -> [f32; 5] {
+    let radius = data[e.component::<Body>()].radius / MAX_RADIUS;
+    let color = data[e.component::<Appearance>()].color;
+    let speed = data
+        .get::<Genome>(e)
+        .map_or(0.0, |genome| genome.speed / SimTime::max_speed());
+    [radius, color.r, color.g, color.b, speed]
+}
+
+fn distance2(a: &[f32; 5], b: &[f32; 5]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Mean distance in looks space over all pairs of creatures
+pub fn diversity(data: &GameData, creatures: &[Entity]) -> f32 {
+    let points: Vec<_> = creatures.iter().map(|e| embed(data, *e)).collect();
+    let mut sum = 0.0;
+    let mut pairs = 0;
+    for (i, a) in points.iter().enumerate() {
+        for b in &points[i + 1..] {
+            sum += distance2(a, b).sqrt();
+            pairs += 1;
+        }
+    }
+    if pairs == 0 {
+        return 0.0;
+    }
+    sum / pairs as f32
+}
+
+/// Sets the niche of every creature to the number of creatures of its kind
+/// within `sharing_radius` in looks space, or to 1 if fitness sharing is off
+pub fn share(data: &mut GameData, creatures: &[Entity]) {
+    let radius2 = data.config.sharing_radius * data.config.sharing_radius;
+    let sharing = data.config.fitness_sharing;
+    let points: Vec<_> = creatures
+        .iter()
+        .map(|e| (data[e.component::<Creature>()].kind, embed(data, *e)))
+        .collect();
+    for (e, (kind, point)) in creatures.iter().zip(&points) {
+        let niche = if sharing {
+            points
+                .iter()
+                .filter(|(other, p)| other == kind && distance2(point, p) <= radius2)
+                .count()
+        } else {
+            1
+        };
+        data[e.component::<Creature>()].niche = niche;
+    }
+}
+
+/// Assigns every creature to one of `k` species with k-means
+///
+/// The initial centroids are spread evenly over `creatures` in order, so the
+/// result only depends on the world and the order of the creatures.
+pub fn cluster(data: &mut GameData, creatures: &[Entity], k: usize) {
+    if creatures.is_empty() || k == 0 {
+        return;
+    }
+
+    let points: Vec<_> = creatures.iter().map(|e| embed(data, *e)).collect();
+    let k = k.min(points.len());
+    let mut centroids: Vec<_> = (0..k).map(|i| points[i * points.len() / k]).collect();
+    let mut species = vec![0; points.len()];
+
+    for _ in 0..SPECIES_ITERATIONS {
+        for (point, species) in points.iter().zip(species.iter_mut()) {
+            let mut best = f32::INFINITY;
+            for (i, centroid) in centroids.iter().enumerate() {
+                let dist = distance2(point, centroid);
+                if dist < best {
+                    best = dist;
+                    *species = i;
+                }
+            }
+        }
+
+        let mut sums = vec![([0.0; 5], 0); k];
+        for (point, species) in points.iter().zip(&species) {
+            let (sum, count) = &mut sums[*species];
+            for (sum, x) in sum.iter_mut().zip(point) {
+                *sum += x;
+            }
+            *count += 1;
+        }
+        for (centroid, (sum, count)) in centroids.iter_mut().zip(sums) {
+            // empty clusters keep their old centroid
+            if count > 0 {
+                for (c, s) in centroid.iter_mut().zip(&sum) {
+                    *c = s / count as f32;
+                }
+            }
+        }
+    }
+
+    for (e, species) in creatures.iter().zip(species) {
+        data[e.component::<Creature>()].species = species;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeciesStats {
+    pub count: usize,
+    pub mean_fitness: f32,
+}
+
+/// Counts and mean fitness of each of the `k` species
+pub fn stats(data: &GameData, creatures: &[Entity], k: usize) -> Vec<SpeciesStats> {
+    let mut stats = vec![
+        SpeciesStats {
+            count: 0,
+            mean_fitness: 0.0,
+        };
+        k
+    ];
+    for e in creatures {
+        let creature = data[e.component::<Creature>()];
+        if let Some(stats) = stats.get_mut(creature.species) {
+            stats.count += 1;
+            stats.mean_fitness += creature.fitness(&data.config);
+        }
+    }
+    for stats in stats.iter_mut() {
+        if stats.count > 0 {
+            stats.mean_fitness /= stats.count as f32;
+        }
+    }
+    stats
+}
+
+/// Appends a row per species for `generation`, writes the header first if
+/// the file is new
+pub fn append_csv<P: AsRef<Path>>(
+    path: P,
+    generation: usize,
+    stats: &[SpeciesStats],
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let new = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if new {
+        writeln!(file, "generation,species,count,mean_fitness")?;
+    }
+    for (species, stats) in stats.iter().enumerate() {
+        writeln!(
+            file,
+            "{},{},{},{:.3}",
+            generation, species, stats.count, stats.mean_fitness
+        )?;
+    }
+    Ok(())
+}
+
+/// Evenly spaced hues, so that neighbouring species are easy to tell apart
+pub fn species_color(species: usize, k: usize) -> Color {
+    let hue = species as f32 / k.max(1) as f32 * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    Color::new(r, g, b, 1.0)
+}
+
+/// Rings around every creature tinted by species, plus a per-species summary
+pub fn draw_overlay(
+    ctx: &mut Context,
+    data: &GameData,
+    creatures: &[Entity],
+    k: usize,
+) -> GameResult<()> {
+    if !creatures.is_empty() {
+        draw::world_view(ctx)?;
+        let mut builder = MeshBuilder::new();
+        for e in creatures {
+            let position = data[e.component::<Position>()].position;
+            let radius = data[e.component::<Body>()].radius;
+            let species = data[e.component::<Creature>()].species;
+            builder.circle(
+                DrawMode::stroke(2.0),
+                [position.x, position.y],
+                radius + 3.0,
+                0.25,
+                species_color(species, k),
+            );
+        }
+        let mesh = builder.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::new())?;
+    }
+
+    draw::screen_view(ctx)?;
+    let mut y = 4.0;
+    for (species, stats) in stats(data, creatures, k).iter().enumerate() {
+        if stats.count == 0 {
+            continue;
+        }
+        let text = Text::new(format!(
+            "{}: {} ({:.1})",
+            species, stats.count, stats.mean_fitness
+        ));
+        let (w, h) = text.dimensions(ctx);
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::new()
+                .dest([WIDTH - w as f32 - 4.0, y])
+                .color(species_color(species, k)),
+        )?;
+        y += h as f32;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use nalgebra::Vector2;
+
+    use super::*;
+    use crate::brain;
+    use crate::creature::Kind;
+    use crate::resources::SimRng;
+    use crate::spawn;
+
+    /// `count` vegans that all look like `genome` and move at `speed`
+    fn population(data: &mut GameData, genome: Genome, speed: f32, count: usize) -> Vec<Entity> {
+        let mut rng = SimRng::new(1);
+        let genome = Genome { speed, ..genome };
+        (0..count)
+            .map(|i| {
+                let brain = brain::new_for(&data.config, Kind::Vegan, &mut rng);
+                let position = Vector2::new(100.0 + 10.0 * i as f32, 100.0);
+                spawn::creature_with(data, position, Kind::Vegan, &genome, brain)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn speed_separates_lookalikes() {
+        let mut data = GameData::new();
+        let genome = Genome::random(Kind::Vegan, &mut SimRng::new(3));
+        let max = SimTime::max_speed();
+        let slow = population(&mut data, genome, max * 0.1, 5);
+        let fast = population(&mut data, genome, max * 0.9, 5);
+        assert!((embed(&data, fast[0])[4] - 0.9).abs() < 1e-5);
+
+        let all: Vec<_> = slow.iter().chain(&fast).copied().collect();
+        cluster(&mut data, &all, 2);
+        let species = |e: &Entity| data[e.component::<Creature>()].species;
+        assert!(slow.iter().all(|e| species(e) == species(&slow[0])));
+        assert!(fast.iter().all(|e| species(e) == species(&fast[0])));
+        assert_ne!(species(&slow[0]), species(&fast[0]));
+    }
+
+    #[test]
+    fn stats_count_each_species() {
+        let mut data = GameData::new();
+        let genome = Genome::random(Kind::Vegan, &mut SimRng::new(3));
+        let creatures = population(&mut data, genome, SimTime::max_speed() * 0.5, 3);
+        for (e, &(species, eaten)) in creatures.iter().zip(&[(0, 2), (0, 4), (2, 1)]) {
+            let creature = &mut data[e.component::<Creature>()];
+            creature.species = species;
+            creature.eaten = eaten;
+        }
+        let fitness = |e: &Entity| data[e.component::<Creature>()].fitness(&data.config);
+        let stats = stats(&data, &creatures, 3);
+        assert_eq!(
+            stats.iter().map(|s| s.count).collect::<Vec<_>>(),
+            vec![2, 0, 1]
+        );
+        let mean = (fitness(&creatures[0]) + fitness(&creatures[1])) / 2.0;
+        assert!((stats[0].mean_fitness - mean).abs() < 1e-5);
+        assert_eq!(stats[1].mean_fitness, 0.0);
+        assert_eq!(stats[2].mean_fitness, fitness(&creatures[2]));
+    }
+
+    #[test]
+    fn species_are_recorded_every_generation() {
+        let path = env::temp_dir().join(format!("ldjam-46-species-{}.csv", process::id()));
+        let _ = fs::remove_file(&path);
+        let stats = |count, mean_fitness| SpeciesStats {
+            count,
+            mean_fitness,
+        };
+        append_csv(&path, 0, &[stats(3, 1.5), stats(0, 0.0)]).unwrap();
+        append_csv(&path, 1, &[stats(1, 2.25), stats(2, 0.5)]).unwrap();
+        let written = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written.unwrap(),
+            "generation,species,count,mean_fitness\n\
+             0,0,3,1.500\n0,1,0,0.000\n\
+             1,0,1,2.250\n1,1,2,0.500\n"
+        );
+    }
+
+    #[test]
+    fn there_are_at_most_as_many_species_as_creatures() {
+        let mut data = GameData::new();
+        let genome = Genome::random(Kind::Vegan, &mut SimRng::new(3));
+        let creatures = population(&mut data, genome, SimTime::max_speed() * 0.5, 2);
+        cluster(&mut data, &creatures, 6);
+        assert!(creatures
+            .iter()
+            .all(|e| data[e.component::<Creature>()].species < 2));
+        // nothing to cluster is fine too
+        cluster(&mut data, &[], 6);
+    }
+
+    #[test]
+    fn lookalikes_share_their_fitness() {
+        let mut data = GameData::new();
+        let genome = Genome::random(Kind::Vegan, &mut SimRng::new(3));
+        let max = SimTime::max_speed();
+        let crowd = population(&mut data, genome, max * 0.1, 4);
+        let loner = population(&mut data, genome, max * 0.9, 1);
+        let all: Vec<_> = crowd.iter().chain(&loner).copied().collect();
+        let niche = |data: &GameData, e: &Entity| data[e.component::<Creature>()].niche;
+
+        share(&mut data, &all);
+        assert!(all.iter().all(|e| niche(&data, e) == 1));
+
+        data.config.fitness_sharing = true;
+        share(&mut data, &all);
+        assert!(crowd.iter().all(|e| niche(&data, e) == 4));
+        assert_eq!(niche(&data, &loner[0]), 1);
+        let creature = Creature {
+            life: 8.0,
+            eaten: 2,
+            children: 1,
+            ..data[crowd[0].component::<Creature>()]
+        };
+        assert!(creature.fitness(&data.config) > 0.0);
+        let shared = creature.shared_fitness(&data.config);
+        assert!((shared * 4.0 - creature.fitness(&data.config)).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::creature::*;
+use crate::{CARNIVORE_SPEED, GEN_TIME, TIME_FACTOR, VEGAN_SPEED};
+
+/// Sim seconds for a duration of `base` seconds at a `TIME_FACTOR` of 1.0
+pub const fn duration(base: f32) -> f32 {
+    base / TIME_FACTOR
+}
+
+/// Speed or per-second rate in sim seconds for `base` at a `TIME_FACTOR` of
+/// 1.0
+pub const fn rate(base: f32) -> f32 {
+    base * TIME_FACTOR
+}
+
+/// How fast sim time passes, every tick goes through this so the runtime
+/// speed is only applied once
+///
+/// All the scaled values are in sim seconds, so they don't change with the
+/// multiplier, only how much sim time a frame covers does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimTime {
+    /// Sim seconds per real second, `sim_speed` from the config
+    pub multiplier: f32,
+}
+
+impl SimTime {
+    pub fn new(multiplier: f32) -> Self {
+        Self { multiplier }
+    }
+
+    /// Sim seconds that pass during `real` seconds
+    pub fn advance(&self, real: f32) -> f32 {
+        real * self.multiplier
+    }
+
+    /// Sim seconds in a generation
+    pub fn generation(&self) -> f32 {
+        GEN_TIME
+    }
+
+    /// Time a creature has to wait between mating
+    pub fn mate_timeout(kind: Kind) -> f32 {
+        match kind {
+            Kind::Vegan => VEGAN_TIMEOUT,
+            Kind::Carnivorous => CARNIVORE_TIMEOUT,
+        }
+    }
+
+    /// Hunger at which a creature starves
+    pub fn starve(kind: Kind) -> f32 {
+        match kind {
+            Kind::Vegan => VEGAN_STARVE,
+            Kind::Carnivorous => CARNIVORE_STARVE,
+        }
+    }
+
+    /// Speed a fresh creature of a kind starts out with
+    pub fn top_speed(kind: Kind) -> f32 {
+        match kind {
+            Kind::Vegan => VEGAN_SPEED,
+            Kind::Carnivorous => CARNIVORE_SPEED,
+        }
+    }
+
+    /// Fastest any kind starts out
+    pub fn max_speed() -> f32 {
+        CARNIVORE_SPEED.max(VEGAN_SPEED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaling_undoes_itself() {
+        // a rate over a duration is the same number of things at any factor
+        assert!((rate(2.0) * duration(3.0) - 6.0).abs() < 1e-4);
+        assert_eq!(duration(rate(5.0)), 5.0);
+    }
+
+    #[test]
+    fn the_multiplier_only_scales_the_tick() {
+        let slow = SimTime::new(1.0);
+        let fast = SimTime::new(4.0);
+        assert_eq!(fast.advance(0.5), 4.0 * slow.advance(0.5));
+        assert_eq!(fast.generation(), slow.generation());
+        assert_eq!(
+            SimTime::starve(Kind::Vegan),
+            Creature::new(Kind::Vegan).starve()
+        );
+        assert!(SimTime::max_speed() >= SimTime::top_speed(Kind::Vegan));
+        assert!(SimTime::max_speed() >= SimTime::top_speed(Kind::Carnivorous));
+    }
+}
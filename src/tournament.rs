@@ -0,0 +1,328 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use ggez::GameResult;
+
+use rand::seq::SliceRandom;
+
+use crate::config::Config;
+use crate::creature::Creature;
+use crate::data::{Entity, Has, Insert};
+use crate::error::Result;
+use crate::resources::SimRng;
+use crate::save::{Elites, RunArchive, SAVE_VERSION};
+use crate::time::SimTime;
+use crate::GameState;
+
+pub const TOURNAMENT_PATH: &str = "tournament.csv";
+/// Real seconds every step of a match covers
+pub const MATCH_STEP: f32 = 1.0 / 60.0;
+/// Steps a match runs for, unless the generation ends first
+pub const MATCH_STEPS: usize = 60 * 60;
+/// Pairs of generations played at most, a random sample of them beyond
+pub const MAX_PAIRS: usize = 64;
+/// Rating every generation starts the tournament with
+pub const INITIAL_RATING: f32 = 1500.0;
+/// How far a single match moves the ratings
+pub const ELO_K: f32 = 32.0;
+
+/// How a generation did over the whole tournament
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub generation: usize,
+    pub rating: f32,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    /// Surviving fitness summed over its matches
+    pub score: f32,
+}
+
+impl Standing {
+    pub fn new(generation: usize) -> Self {
+        Self {
+            generation,
+            rating: INITIAL_RATING,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            score: 0.0,
+        }
+    }
+
+    fn record(&mut self, outcome: f32, score: f32) {
+        if outcome > 0.5 {
+            self.wins += 1;
+        } else if outcome < 0.5 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+        self.score += score;
+    }
+}
+
+/// Chance a side rated `rating` beats one rated `other`
+pub fn expected(rating: f32, other: f32) -> f32 {
+    1.0 / (1.0 + 10f32.powf((other - rating) / 400.0))
+}
+
+/// Ratings of `a` and `b` after a match `a` got `outcome` out of, 1.0 for a
+/// win, 0.5 for a draw and 0.0 for a loss
+pub fn elo(a: f32, b: f32, outcome: f32) -> (f32, f32) {
+    let change = ELO_K * (outcome - expected(a, b));
+    (a + change, b - change)
+}
+
+/// What the first side gets out of a match with these scores
+pub fn outcome(a: f32, b: f32) -> f32 {
+    if a > b {
+        1.0
+    } else if a < b {
+        0.0
+    } else {
+        0.5
+    }
+}
+
+/// Pits the elites of every pair of generations saved in `dir` against each
+/// other, best rated first
+///
+/// Saves that can't be loaded or have no elites matching the configured
+/// brain sit the tournament out.
+pub fn run<P: AsRef<Path>>(dir: P, config: &Config) -> Result<Vec<Standing>> {
+    let archive = RunArchive::open(dir)?;
+    for problem in archive.problems() {
+        eprintln!("skipped {}", problem);
+    }
+
+    let mut entrants = Vec::new();
+    for meta in archive.generations() {
+        if meta.version != SAVE_VERSION {
+            eprintln!(
+                "skipped generation {}, saved with version {}",
+                meta.generation, meta.version
+            );
+            continue;
+        }
+        let mut elites = match archive.load_generation(meta.generation) {
+            Ok(elites) => elites,
+            Err(err) => {
+                eprintln!("skipped generation {}: {}", meta.generation, err);
+                continue;
+            }
+        };
+        let skipped = elites.retain_matching(&config.brain);
+        if elites.vegans.is_empty() && elites.carnivores.is_empty() {
+            eprintln!(
+                "skipped generation {}, none of its elites match the configured brain",
+                meta.generation
+            );
+            continue;
+        }
+        if skipped > 0 {
+            eprintln!(
+                "skipped {} elites of generation {} that don't match the configured brain",
+                skipped, meta.generation
+            );
+        }
+        entrants.push((meta.generation, elites));
+    }
+
+    let mut standings: Vec<_> = entrants.iter().map(|&(g, _)| Standing::new(g)).collect();
+    for (a, b) in pairs(entrants.len(), config.seed) {
+        let (left, right) = (&entrants[a], &entrants[b]);
+        let (score_a, score_b) = match play(config, left.1.clone(), right.1.clone()) {
+            Ok(scores) => scores,
+            Err(err) => {
+                eprintln!("match of {} against {} failed: {}", left.0, right.0, err);
+                continue;
+            }
+        };
+        println!(
+            "generation {} against {}: {:.1} to {:.1}",
+            left.0, right.0, score_a, score_b
+        );
+
+        let outcome = outcome(score_a, score_b);
+        let (rating_a, rating_b) = elo(standings[a].rating, standings[b].rating, outcome);
+        standings[a].rating = rating_a;
+        standings[b].rating = rating_b;
+        standings[a].record(outcome, score_a);
+        standings[b].record(1.0 - outcome, score_b);
+    }
+
+    standings.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+    Ok(standings)
+}
+
+/// Every pair of `count` entrants, or `MAX_PAIRS` of them picked by `seed`
+/// when there are more, in order
+fn pairs(count: usize, seed: u64) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for a in 0..count {
+        for b in a + 1..count {
+            pairs.push((a, b));
+        }
+    }
+    if pairs.len() > MAX_PAIRS {
+        pairs.shuffle(&mut SimRng::new(seed));
+        pairs.truncate(MAX_PAIRS);
+        pairs.sort_unstable();
+    }
+    pairs
+}
+
+/// Runs a match in a fresh world with the elites of `a` and `b` sharing the
+/// creature quota, returns the fitness of each side's survivors
+///
+/// Entrants start out like newborns so only what they do in the match
+/// counts. Matches only play out the same every time with a `seed` in the
+/// config.
+pub fn play(config: &Config, a: Elites, b: Elites) -> GameResult<(f32, f32)> {
+    let mut config = config.clone();
+    // nothing of a match should end up on disk, or depend on the machine
+    config.chronicle = false;
+    config.continuous = false;
+    config.frame_budget = 0.0;
+
+    let mut game = GameState::empty(0, config);
+    let mut sides = game.populate_with(vec![a, b]);
+    for &e in sides.iter().flatten() {
+        let kind = game.data[e.component::<Creature>()].kind;
+        game.data.insert(e, Creature::new(kind));
+    }
+
+    let time = SimTime::new(game.data.config.sim_speed);
+    for _ in 0..MATCH_STEPS {
+        // past the end the world would roll over into the next generation
+        if game.time + time.advance(MATCH_STEP) > time.generation() {
+            break;
+        }
+        game.step(MATCH_STEP)?;
+        for side in &mut sides {
+            side.retain(|&e| survived(&game, e));
+        }
+    }
+
+    let score = |side: &[Entity]| -> f32 {
+        side.iter()
+            .map(|e| game.data[e.component::<Creature>()].fitness(&game.data.config))
+            .sum()
+    };
+    Ok((score(&sides[0]), score(&sides[1])))
+}
+
+/// Whether the entrant `e` is still alive, entities get reused so a newborn
+/// in a dead entrant's place doesn't count, it's younger than the match
+fn survived(game: &GameState, e: Entity) -> bool {
+    game.data.has(e.component::<Creature>())
+        && game.data[e.component::<Creature>()].life + MATCH_STEP >= game.time
+}
+
+/// Writes the ranking table, best rated first as `run` returns it
+pub fn write_csv<P: AsRef<Path>>(standings: &[Standing], path: P) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "rank,generation,rating,wins,draws,losses,score")?;
+    for (rank, standing) in standings.iter().enumerate() {
+        writeln!(
+            file,
+            "{},{},{:.1},{},{},{},{:.1}",
+            rank + 1,
+            standing.generation,
+            standing.rating,
+            standing.wins,
+            standing.draws,
+            standing.losses,
+            standing.score
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::brain;
+    use crate::creature::{Genome, Kind};
+
+    /// A generation of one vegan and one carnivore
+    fn tiny(config: &Config, seed: u64) -> Elites {
+        let mut rng = SimRng::new(seed);
+        let mut saved = |kind| {
+            let network = brain::new_for(config, kind, &mut rng);
+            (Creature::new(kind), Genome::random(kind, &mut rng), network)
+        };
+        Elites {
+            vegans: vec![saved(Kind::Vegan)],
+            carnivores: vec![saved(Kind::Carnivorous)],
+        }
+    }
+
+    #[test]
+    fn elo_moves_both_sides_by_the_same_bounded_amount() {
+        for &(a, b) in &[(1500.0, 1500.0), (1700.0, 1400.0), (1200.0, 1900.0)] {
+            for &outcome in &[0.0, 0.5, 1.0] {
+                let (new_a, new_b) = elo(a, b, outcome);
+                assert!(((new_a - a) + (new_b - b)).abs() < 1e-3);
+                assert!((new_a - a).abs() <= ELO_K);
+            }
+        }
+        // the favourite gains less from a win than the underdog would
+        let (favourite, _) = elo(1700.0, 1400.0, 1.0);
+        let (underdog, _) = elo(1400.0, 1700.0, 1.0);
+        assert!(favourite - 1700.0 < underdog - 1400.0);
+        assert!(favourite > 1700.0);
+    }
+
+    #[test]
+    fn equal_ratings_expect_a_draw() {
+        assert_eq!(expected(1500.0, 1500.0), 0.5);
+        assert!((expected(1900.0, 1500.0) + expected(1500.0, 1900.0) - 1.0).abs() < 1e-6);
+        assert!(expected(1900.0, 1500.0) > 0.9);
+        assert_eq!(elo(1500.0, 1500.0, 0.5), (1500.0, 1500.0));
+        assert_eq!(elo(1500.0, 1500.0, 1.0), (1516.0, 1484.0));
+    }
+
+    #[test]
+    fn outcomes_go_by_the_scores() {
+        assert_eq!(outcome(3.0, 1.0), 1.0);
+        assert_eq!(outcome(1.0, 3.0), 0.0);
+        assert_eq!(outcome(2.0, 2.0), 0.5);
+
+        let mut standing = Standing::new(4);
+        standing.record(1.0, 3.0);
+        standing.record(0.5, 2.0);
+        standing.record(0.0, 1.0);
+        assert_eq!((standing.wins, standing.draws, standing.losses), (1, 1, 1));
+        assert_eq!(standing.score, 6.0);
+    }
+
+    #[test]
+    fn seeded_matches_play_out_the_same() {
+        let config = Config {
+            seed: 12,
+            // the generation ends after a handful of steps
+            sim_speed: 400.0,
+            ..Config::default()
+        };
+        let (a, b) = (tiny(&config, 1), tiny(&config, 2));
+        let first = play(&config, a.clone(), b.clone()).unwrap();
+        let second = play(&config, a, b).unwrap();
+        assert_eq!(first, second);
+        assert!(first.0 >= 0.0 && first.1 >= 0.0);
+    }
+
+    #[test]
+    fn seeded_pairs_are_picked_the_same() {
+        assert_eq!(pairs(4, 0).len(), 6);
+        // 13 entrants make 78 pairs
+        let first = pairs(13, 7);
+        assert_eq!(first.len(), MAX_PAIRS);
+        assert_eq!(first, pairs(13, 7));
+        assert_ne!(first, pairs(13, 8));
+        assert!(first.windows(2).all(|w| w[0] < w[1]));
+    }
+}
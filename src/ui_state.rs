@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+pub const UI_STATE_PATH: &str = "ui_state.json";
+
+/// Which overlays were open, kept between runs
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    pub show_panel: bool,
+    pub show_species: bool,
+    pub show_contacts: bool,
+    pub show_labels: bool,
+    pub show_mortality: bool,
+}
+
+impl UiState {
+    /// Falls back to everything closed if there is no saved state
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let encoded = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&encoded)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let encoded = serde_json::to_string_pretty(self)?;
+        fs::write(path, &encoded)?;
+        Ok(())
+    }
+}
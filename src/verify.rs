@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::data::{ComponentDiff, GameData, WorldSnapshot};
+use crate::error::{Error, Result};
+use crate::paint::{FoodGrid, FOOD_GRID_PATH};
+use crate::save::WORLD_VERSION;
+use crate::time::SimTime;
+use crate::GameState;
+
+/// Real seconds every step of a reference run covers
+pub const VERIFY_STEP: f32 = 1.0 / 60.0;
+/// Steps `--record-reference` runs for without `--steps`
+pub const VERIFY_STEPS: usize = 600;
+/// Differences `--verify-against` prints without `--diffs`
+pub const VERIFY_DIFFS: usize = 20;
+
+/// Where a seeded run got to after some steps, written by
+/// `--record-reference` and checked by `--verify-against`, the version comes
+/// first like in a saved world
+#[derive(Serialize, Deserialize)]
+struct Reference {
+    version: u32,
+    steps: usize,
+    world: WorldSnapshot,
+}
+
+/// Runs a fresh world with `config` for `steps` steps of `VERIFY_STEP`, the
+/// way a tournament match does, and commits what's pending at the end
+///
+/// Only a seeded config gives the same world every time, and the run has to
+/// end before the generation does, since a rollover writes to the disk.
+fn run(config: &Config, food_grid: &FoodGrid, steps: usize) -> Result<GameState> {
+    if config.seed == 0 {
+        return Err(Error::Config(
+            "a reference run needs a seed, 0 draws a fresh one every time".to_owned(),
+        ));
+    }
+    let time = SimTime::new(config.sim_speed);
+    if time.advance(VERIFY_STEP) * steps as f32 > time.generation() {
+        return Err(Error::Config(format!(
+            "{} steps go past the end of a generation",
+            steps
+        )));
+    }
+
+    let mut config = config.clone();
+    config.chronicle = false;
+    config.frame_budget = 0.0;
+    let mut game = GameState::empty(0, config);
+    game.data.food_grid = food_grid.clone();
+    game.populate_with(Vec::new());
+    for _ in 0..steps {
+        game.step(VERIFY_STEP).map_err(Error::Simulation)?;
+    }
+    let commit = game.data.commit();
+    game.apply_commit(commit);
+    Ok(game)
+}
+
+/// Runs a seeded world for `steps` steps and writes where it got to
+pub fn record<P: AsRef<Path>>(config: &Config, steps: usize, path: P) -> Result<()> {
+    let game = run(config, &FoodGrid::load(FOOD_GRID_PATH), steps)?;
+    let reference = Reference {
+        version: WORLD_VERSION,
+        steps,
+        world: game.data.snapshot(),
+    };
+    fs::write(path, bincode::serialize(&reference)?)?;
+    Ok(())
+}
+
+/// Runs the world written by `record` again, with the config and the food
+/// grid it was written with, and compares where it gets to, returns how many
+/// steps that took and every difference
+pub fn verify<P: AsRef<Path>>(path: P) -> Result<(usize, Vec<ComponentDiff>)> {
+    let encoded = fs::read(path)?;
+    let version: u32 = bincode::deserialize(&encoded)?;
+    if version != WORLD_VERSION {
+        return Err(Error::FormatVersion {
+            found: version,
+            expected: WORLD_VERSION,
+        });
+    }
+
+    let reference: Reference = bincode::deserialize(&encoded)?;
+    let expected = GameData::from_snapshot(reference.world);
+    let game = run(&expected.config, &expected.food_grid, reference.steps)?;
+    Ok((reference.steps, game.data.diff(&expected)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    use crate::creature::Position;
+    use crate::data::Entity;
+
+    fn seeded() -> Config {
+        Config {
+            seed: 7,
+            ..Config::default()
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("ldjam-46-{}-{}.bin", name, process::id()))
+    }
+
+    #[test]
+    fn a_recorded_run_verifies() {
+        let path = temp_path("verified");
+        record(&seeded(), 3, &path).unwrap();
+        let (steps, diffs) = verify(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(steps, 3);
+        assert!(diffs.is_empty(), "{:?}", diffs);
+    }
+
+    #[test]
+    fn a_changed_reference_doesnt_verify() {
+        let path = temp_path("changed");
+        record(&seeded(), 2, &path).unwrap();
+        let mut reference: Reference = bincode::deserialize(&fs::read(&path).unwrap()).unwrap();
+        let mut world = GameData::from_snapshot(reference.world);
+        let e = Entity { idx: 0 };
+        world[e.component::<Position>()].position.x += 1.0;
+        reference.world = world.snapshot();
+        fs::write(&path, bincode::serialize(&reference).unwrap()).unwrap();
+
+        let (_, diffs) = verify(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!((diffs[0].entity, diffs[0].component), (e, "position"));
+    }
+
+    #[test]
+    fn an_unseeded_run_cant_be_a_reference() {
+        let path = temp_path("unseeded");
+        assert!(record(&Config::default(), 1, &path).is_err());
+        assert!(!path.exists());
+    }
+}